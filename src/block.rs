@@ -8,6 +8,18 @@ pub enum Type {
     Rock = 3,
     Ice = 4,
     Water = 5,
+    Snow = 6,
+    Mud = 7,
+    DryGrass = 8,
+    Coal = 9,
+    Iron = 10,
+    Copper = 11,
+    Gold = 12,
+    Crystal = 13,
+    Lava = 14,
+    Dripstone = 15,
+    Log = 16,
+    Leaves = 17,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -61,7 +73,7 @@ impl Block {
     }
 
     pub fn is_solid(&self) -> bool {
-        !matches!(self.ty, Type::Inactive | Type::Water)
+        !matches!(self.ty, Type::Inactive | Type::Water | Type::Lava)
     }
 
     pub fn color(&self) -> wgpu::Color {
@@ -91,6 +103,78 @@ impl Block {
                 b: 1.0,
                 a: 0.5,
             },
+            Type::Snow => wgpu::Color {
+                r: 0.95,
+                g: 0.96,
+                b: 1.0,
+                a: 1.0,
+            },
+            Type::Mud => wgpu::Color {
+                r: 0.25,
+                g: 0.2,
+                b: 0.1,
+                a: 1.0,
+            },
+            Type::DryGrass => wgpu::Color {
+                r: 0.7,
+                g: 0.65,
+                b: 0.25,
+                a: 1.0,
+            },
+            Type::Coal => wgpu::Color {
+                r: 0.08,
+                g: 0.08,
+                b: 0.08,
+                a: 1.0,
+            },
+            Type::Iron => wgpu::Color {
+                r: 0.55,
+                g: 0.4,
+                b: 0.32,
+                a: 1.0,
+            },
+            Type::Copper => wgpu::Color {
+                r: 0.72,
+                g: 0.42,
+                b: 0.2,
+                a: 1.0,
+            },
+            Type::Gold => wgpu::Color {
+                r: 0.92,
+                g: 0.76,
+                b: 0.2,
+                a: 1.0,
+            },
+            Type::Crystal => wgpu::Color {
+                r: 0.65,
+                g: 0.35,
+                b: 0.95,
+                a: 1.0,
+            },
+            Type::Lava => wgpu::Color {
+                r: 1.0,
+                g: 0.35,
+                b: 0.05,
+                a: 1.0,
+            },
+            Type::Dripstone => wgpu::Color {
+                r: 0.45,
+                g: 0.36,
+                b: 0.3,
+                a: 1.0,
+            },
+            Type::Log => wgpu::Color {
+                r: 0.33,
+                g: 0.22,
+                b: 0.1,
+                a: 1.0,
+            },
+            Type::Leaves => wgpu::Color {
+                r: 0.16,
+                g: 0.45,
+                b: 0.14,
+                a: 1.0,
+            },
             Type::Inactive => wgpu::Color::TRANSPARENT,
         }
     }
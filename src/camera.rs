@@ -1,5 +1,7 @@
 use std::{f32::consts::FRAC_PI_2, time::Duration};
 
+use crate::collision::CollisionShape;
+use crate::entities::EntityManager;
 use crate::terrain::WATER_LEVEL;
 use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Vec3};
@@ -10,6 +12,29 @@ const GRAVITY: f32 = 25.0;
 const PLAYER_HEIGHT: f32 = 1.8;
 const SPEED: f32 = 6.0;
 const JUMP_VELOCITY: f32 = 10.0;
+/// How far behind (or in front of) the player the third-person camera sits.
+const BOOM_LENGTH: f32 = 4.0;
+/// Gap kept between the camera and any block the boom runs into.
+const BOOM_MARGIN: f32 = 0.25;
+const BOOM_STEP: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewMode {
+    FirstPerson,
+    ThirdPerson,
+    /// Looking back at the player's face.
+    Front,
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            ViewMode::FirstPerson => ViewMode::ThirdPerson,
+            ViewMode::ThirdPerson => ViewMode::Front,
+            ViewMode::Front => ViewMode::FirstPerson,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
@@ -21,6 +46,10 @@ pub struct Uniform {
     fog_start_sq: f32,
     fog_end_sq: f32,
     is_underwater: f32,
+    // debug view, unused, unused, unused
+    debug: [u32; 4],
+    // decoration fade start, decoration fade end, unused, unused
+    decoration_fade: [f32; 4],
 }
 
 impl Uniform {
@@ -33,15 +62,31 @@ impl Uniform {
             fog_start_sq: 0.0,
             fog_end_sq: 0.0,
             is_underwater: 0.0,
+            debug: [0; 4],
+            decoration_fade: [0.0; 4],
         }
     }
 
+    pub fn set_water_level(&mut self, level: f32) {
+        self.water_level = level;
+    }
+
+    pub fn set_debug_view(&mut self, mode: u32) {
+        self.debug[0] = mode;
+    }
+
+    /// Ground cover shrinks away between `start` and `end` blocks from the
+    /// camera.
+    pub fn set_decoration_fade(&mut self, start: f32, end: f32) {
+        self.decoration_fade = [start, end, 0.0, 0.0];
+    }
+
     pub fn update_view_proj(&mut self, camera: &Camera, is_underwater: bool) {
         let vp = camera.projection.matrix() * camera.matrix();
         self.view_proj = *vp.as_ref();
         self.inv_view_proj = *vp.inverse().as_ref();
-        let visual_pos = camera.visual_position();
-        self.view_pos = [visual_pos.x, visual_pos.y, visual_pos.z, 1.0];
+        let eye = camera.eye_position();
+        self.view_pos = [eye.x, eye.y, eye.z, 1.0];
 
         self.fog_start_sq = camera.fog_start * camera.fog_start;
         self.fog_end_sq = camera.fog_end * camera.fog_end;
@@ -83,6 +128,10 @@ pub struct Camera {
     pitch: f32,
     velocity: Vec3,
     step_offset: f32,
+    view_mode: ViewMode,
+    // Current length of the camera boom, which is shortened when it would
+    // otherwise end up inside a block.
+    boom: f32,
     pub projection: Projection,
     pub fog_start: f32,
     pub fog_end: f32,
@@ -106,6 +155,8 @@ impl Camera {
             pitch,
             velocity: Vec3::ZERO,
             step_offset: 0.0,
+            view_mode: ViewMode::FirstPerson,
+            boom: 0.0,
             projection,
             fog_start,
             fog_end,
@@ -126,6 +177,73 @@ impl Camera {
         p
     }
 
+    /// The bottom of the player's collision box, smoothed over steps like
+    /// the eye.
+    pub fn feet_position(&self) -> Vec3 {
+        self.visual_position() - Vec3::new(0.0, PLAYER_HEIGHT, 0.0)
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn view_mode(&self) -> ViewMode {
+        self.view_mode
+    }
+
+    pub fn cycle_view_mode(&mut self) -> ViewMode {
+        self.view_mode = self.view_mode.next();
+        self.boom = 0.0;
+        self.view_mode
+    }
+
+    /// Where the view is rendered from. This is the player's eye in first
+    /// person, otherwise the end of the camera boom.
+    pub fn eye_position(&self) -> Vec3 {
+        self.visual_position() + self.boom_direction() * self.boom
+    }
+
+    fn boom_direction(&self) -> Vec3 {
+        match self.view_mode {
+            ViewMode::FirstPerson => Vec3::ZERO,
+            ViewMode::ThirdPerson => -self.forward(),
+            ViewMode::Front => self.forward(),
+        }
+    }
+
+    fn view_direction(&self) -> Vec3 {
+        match self.view_mode {
+            ViewMode::Front => -self.forward(),
+            _ => self.forward(),
+        }
+    }
+
+    /// Extends the camera boom, pulling it in when it would clip into the
+    /// world. The boom snaps in immediately but eases back out so the view
+    /// doesn't jump when passing close to a wall.
+    pub fn update_view(&mut self, chunks: &crate::chunks::Chunks, dt: Duration) {
+        if self.view_mode == ViewMode::FirstPerson {
+            self.boom = 0.0;
+            return;
+        }
+        let target = boom_length(self.visual_position(), self.boom_direction(), |p| {
+            chunks.is_solid_at(p)
+        });
+        if target < self.boom {
+            self.boom = target;
+        } else {
+            self.boom += (target - self.boom) * (1.0 - f32::exp(-4.0 * dt.as_secs_f32()));
+        }
+    }
+
     pub fn save_state(&self, config: &mut crate::config::WorldConfig) {
         config.camera = Some(crate::config::CameraConfig {
             position: [self.position.x, self.position.y, self.position.z],
@@ -141,16 +259,19 @@ impl Camera {
     }
 
     fn matrix(&self) -> Mat4 {
-        Mat4::look_to_rh(self.visual_position(), self.forward(), Vec3::Y)
+        Mat4::look_to_rh(self.eye_position(), self.view_direction(), Vec3::Y)
     }
 
+    /// What the player is looking at, if it's within `max_distance`.
     pub fn raycast(
         &self,
         chunks: &crate::chunks::Chunks,
+        trees: &EntityManager,
         max_distance: f32,
-    ) -> Option<(glam::IVec3, glam::IVec3)> {
+    ) -> Option<Target> {
         let origin = self.visual_position();
         let dir = self.forward();
+        let tree = trees.raycast(origin, dir, max_distance);
 
         let mut x = origin.x.floor() as i32;
         let mut y = origin.y.floor() as i32;
@@ -196,9 +317,13 @@ impl Camera {
         let mut normal = glam::IVec3::ZERO;
 
         while current_distance <= max_distance {
+            // A tree in front of the block hides it
+            if let Some((t, shapes)) = tree.filter(|(t, _)| *t < current_distance) {
+                return Some(Target::Tree(origin + dir * t, shapes.to_vec()));
+            }
             let pos = glam::IVec3::new(x, y, z);
             if chunks.is_solid_at(pos) {
-                return Some((pos, normal));
+                return Some(Target::Block(pos, normal));
             }
 
             if t_max_x < t_max_y {
@@ -228,10 +353,15 @@ impl Camera {
             }
         }
 
-        None
+        tree.map(|(t, shapes)| Target::Tree(origin + dir * t, shapes.to_vec()))
     }
 
-    pub fn update_physics(&mut self, chunks: &crate::chunks::Chunks, dt: Duration) {
+    pub fn update_physics(
+        &mut self,
+        chunks: &crate::chunks::Chunks,
+        trees: &EntityManager,
+        dt: Duration,
+    ) {
         let chunk_x = (self.position.x.floor() as i32).div_euclid(16);
         let chunk_z = (self.position.z.floor() as i32).div_euclid(16);
         if chunk_x < 0 || chunk_z < 0 {
@@ -256,6 +386,14 @@ impl Camera {
         let radius = 0.3;
         let height_below = PLAYER_HEIGHT;
         let height_above = 0.15;
+        let hits_tree = |pos: Vec3| {
+            let bottom = pos - Vec3::Y * (height_below - radius);
+            let top = pos + Vec3::Y * (height_above - radius);
+            trees.collides(bottom, top, radius)
+        };
+        let collides = |pos: Vec3| {
+            check_collision(pos, radius, height_below, height_above, chunks) || hits_tree(pos)
+        };
 
         // apply gravity
         self.velocity.y -= GRAVITY * dt;
@@ -271,16 +409,20 @@ impl Camera {
                 self.position.y = (self.position.y + height_above).floor() - 0.001 - height_above;
             }
             self.velocity.y = 0.0;
+        } else if hits_tree(self.position) {
+            // Trees don't line up with the blocks, so stop short of them
+            self.position.y -= d.y;
+            self.velocity.y = 0.0;
         }
 
         // X axis
         self.position.x += d.x;
-        if check_collision(self.position, radius, height_below, height_above, chunks) {
+        if collides(self.position) {
             let mut stepped = false;
             let mut test_pos = self.position;
             for _ in 1..=10 {
                 test_pos.y += 0.1;
-                if !check_collision(test_pos, radius, height_below, height_above, chunks) {
+                if !collides(test_pos) {
                     let diff = test_pos.y - self.position.y;
                     self.position = test_pos;
                     self.step_offset -= diff;
@@ -296,12 +438,12 @@ impl Camera {
 
         // Z axis
         self.position.z += d.z;
-        if check_collision(self.position, radius, height_below, height_above, chunks) {
+        if collides(self.position) {
             let mut stepped = false;
             let mut test_pos = self.position;
             for _ in 1..=10 {
                 test_pos.y += 0.1;
-                if !check_collision(test_pos, radius, height_below, height_above, chunks) {
+                if !collides(test_pos) {
                     let diff = test_pos.y - self.position.y;
                     self.position = test_pos;
                     self.step_offset -= diff;
@@ -317,6 +459,20 @@ impl Camera {
     }
 }
 
+/// Walks out from `pivot` along `dir` and returns how far the camera can go
+/// before it would get too close to a solid block.
+fn boom_length(pivot: Vec3, dir: Vec3, is_solid: impl Fn(glam::IVec3) -> bool) -> f32 {
+    let mut t = 0.0;
+    while t < BOOM_LENGTH + BOOM_MARGIN {
+        let p = pivot + dir * t;
+        if is_solid(p.floor().as_ivec3()) {
+            return (t - BOOM_MARGIN).max(0.0);
+        }
+        t += BOOM_STEP;
+    }
+    BOOM_LENGTH
+}
+
 fn check_collision(
     pos: Vec3,
     radius: f32,
@@ -343,6 +499,15 @@ fn check_collision(
     false
 }
 
+/// Something the player is looking at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A block, and which way the face that's hit looks.
+    Block(glam::IVec3, glam::IVec3),
+    /// A point on a tree, and the shapes the whole tree collides with.
+    Tree(Vec3, Vec<CollisionShape>),
+}
+
 pub struct Controller {
     amount_left: f32,
     amount_right: f32,
@@ -480,3 +645,37 @@ impl Controller {
         camera.pitch = camera.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boom_length_open_air() {
+        let len = boom_length(Vec3::new(0.5, 10.5, 0.5), Vec3::NEG_X, |_| false);
+        assert_eq!(len, BOOM_LENGTH);
+    }
+
+    #[test]
+    fn test_boom_pulls_in_before_wall() {
+        // Wall two blocks behind the player
+        let pivot = Vec3::new(0.5, 10.5, 0.5);
+        let len = boom_length(pivot, Vec3::NEG_X, |p| p.x <= -2);
+        assert!(len < 1.5 - BOOM_MARGIN + BOOM_STEP, "boom was {len}");
+        assert!(len > 1.5 - BOOM_MARGIN - BOOM_STEP, "boom was {len}");
+        assert!(pivot.x - len > -1.0);
+
+        // Standing against the wall collapses the boom entirely
+        let len = boom_length(pivot, Vec3::NEG_X, |p| p.x <= -1);
+        assert!(len < 0.5, "boom was {len}");
+    }
+
+    #[test]
+    fn test_view_mode_cycles() {
+        let mut camera = Camera::new(Vec3::ZERO, 0.0, 0.0, 1.0, 75.0, 3);
+        assert_eq!(camera.eye_position(), camera.visual_position());
+        assert_eq!(camera.cycle_view_mode(), ViewMode::ThirdPerson);
+        assert_eq!(camera.cycle_view_mode(), ViewMode::Front);
+        assert_eq!(camera.cycle_view_mode(), ViewMode::FirstPerson);
+    }
+}
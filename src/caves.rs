@@ -0,0 +1,244 @@
+use crate::{block, terrain::BEDROCK_LEVEL};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use std::fmt;
+
+/// Caves this close to bedrock are flooded with lava.
+pub const LAVA_LEVEL: f64 = BEDROCK_LEVEL + 8.0;
+
+/// Caverns only open this far below the surface, so they never break through it.
+const CAVERN_MIN_DEPTH: f64 = 24.0;
+/// Caverns grow to full size over this much more depth.
+const CAVERN_FADE_DEPTH: f64 = 16.0;
+const CAVERN_THRESHOLD: f64 = 0.3;
+
+/// The water table sits around this height, rising and falling by the swing.
+const AQUIFER_LEVEL: f64 = 24.0;
+const AQUIFER_SWING: f64 = 16.0;
+
+/// The deepest a ravine cuts, at the heart of its run.
+const RAVINE_DEPTH: f64 = 40.0;
+/// Half the width of a ravine at the surface, in units of tunnel noise.
+const RAVINE_WIDTH: f64 = 0.03;
+/// Ravines only cut through columns that stand this far above their water.
+pub(crate) const RAVINE_SHORE: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CaveKind {
+    Tunnel,
+    Cavern,
+    Lake,
+    Lava,
+    Ravine,
+}
+
+impl fmt::Display for CaveKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl CaveKind {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "tunnel" | "tunnels" => Some(CaveKind::Tunnel),
+            "cavern" | "caverns" => Some(CaveKind::Cavern),
+            "lake" | "aquifer" => Some(CaveKind::Lake),
+            "lava" => Some(CaveKind::Lava),
+            "ravine" | "ravines" => Some(CaveKind::Ravine),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [CaveKind; 5] = [
+        CaveKind::Tunnel,
+        CaveKind::Cavern,
+        CaveKind::Lake,
+        CaveKind::Lava,
+        CaveKind::Ravine,
+    ];
+
+    /// What the cave is filled with.
+    pub fn block(&self) -> block::Type {
+        match self {
+            CaveKind::Lake => block::Type::Water,
+            CaveKind::Lava => block::Type::Lava,
+            CaveKind::Tunnel | CaveKind::Cavern | CaveKind::Ravine => block::Type::Inactive,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CaveTerrain {
+    cheese_noise: Fbm<Perlin>,
+    spaghetti_noise: Fbm<Perlin>,
+    cavern_noise: Fbm<Perlin>,
+    water_table_noise: Perlin,
+    ravine_mask_noise: Perlin,
+}
+
+impl CaveTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            cheese_noise: Fbm::<Perlin>::new(seed).set_frequency(0.015).set_octaves(3),
+            spaghetti_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(0.01)
+                .set_octaves(2),
+            cavern_noise: Fbm::<Perlin>::new(seed.wrapping_add(2))
+                .set_frequency(0.008)
+                .set_octaves(2),
+            water_table_noise: Perlin::new(seed.wrapping_add(3)),
+            ravine_mask_noise: Perlin::new(seed.wrapping_add(5)),
+        }
+    }
+
+    pub(crate) fn is_cave(&self, point: [f64; 3], surface_height: f64) -> bool {
+        // Prevent caves from eating the bottom of the world
+        if point[1] <= BEDROCK_LEVEL {
+            return false;
+        }
+
+        let depth = surface_height - point[1];
+        if depth < 10.0 {
+            return false;
+        }
+
+        let depth_factor = if depth < 50.0 {
+            (50.0 - depth) / 40.0
+        } else {
+            0.0
+        };
+
+        let cheese_val = self.cheese_noise.get(point);
+        let cheese_is_cave = cheese_val > (0.4 + depth_factor * 0.4);
+
+        let spaghetti_val = self.spaghetti_noise.get(point).abs();
+        let spaghetti_is_cave = spaghetti_val < (0.05 - depth_factor * 0.04);
+
+        cheese_is_cave || spaghetti_is_cave || self.is_cavern(point, depth)
+    }
+
+    /// Big open chambers, squashed so they're wider than they are tall.
+    fn is_cavern(&self, point: [f64; 3], depth: f64) -> bool {
+        if depth < CAVERN_MIN_DEPTH {
+            return false;
+        }
+        let fade = ((CAVERN_MIN_DEPTH + CAVERN_FADE_DEPTH - depth) / CAVERN_FADE_DEPTH).max(0.0);
+        let squashed = [point[0], point[1] * 2.0, point[2]];
+        self.cavern_noise.get(squashed) > CAVERN_THRESHOLD + fade * 0.3
+    }
+
+    /// Height of the local water table. Caverns below it hold lakes.
+    fn water_table(&self, x: f64, z: f64) -> f64 {
+        AQUIFER_LEVEL + AQUIFER_SWING * self.water_table_noise.get([x * 0.004, z * 0.004])
+    }
+
+    /// Tunnels that have split open up to the surface, deepest and widest
+    /// where the mask noise is strongest. A ravine follows the tunnel running
+    /// under its floor and narrows down into it, so the two always meet.
+    fn is_ravine(&self, point: [f64; 3], depth: f64) -> bool {
+        let mask = self
+            .ravine_mask_noise
+            .get([point[0] * 0.0015, point[2] * 0.0015]);
+        let strength = ((mask - 0.2) / 0.2).clamp(0.0, 1.0);
+        let max_depth = RAVINE_DEPTH * strength;
+        if depth <= 0.0 || depth >= max_depth {
+            return false;
+        }
+        let width = RAVINE_WIDTH * strength * (1.0 - depth / max_depth).sqrt();
+        let floor = point[1] + depth - max_depth;
+        self.spaghetti_noise.get([point[0], floor, point[2]]).abs() < width
+    }
+
+    /// What, if anything, is carved out at a point. Ravines only open where
+    /// the column is `dry`, so they never drain the sea.
+    pub(crate) fn cave_at(
+        &self,
+        point: [f64; 3],
+        surface_height: f64,
+        dry: bool,
+    ) -> Option<CaveKind> {
+        if point[1] <= BEDROCK_LEVEL {
+            return None;
+        }
+        let depth = surface_height - point[1];
+        let kind = if dry && self.is_ravine(point, depth) {
+            CaveKind::Ravine
+        } else if self.is_cavern(point, depth) {
+            CaveKind::Cavern
+        } else if self.is_cave(point, surface_height) {
+            CaveKind::Tunnel
+        } else {
+            return None;
+        };
+
+        if point[1] <= LAVA_LEVEL {
+            Some(CaveKind::Lava)
+        } else if kind == CaveKind::Cavern && point[1] < self.water_table(point[0], point[2]) {
+            Some(CaveKind::Lake)
+        } else {
+            Some(kind)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cave_kind_names_round_trip() {
+        for kind in CaveKind::ALL {
+            assert_eq!(CaveKind::from_str(&kind.to_string()), Some(kind));
+        }
+        assert_eq!(CaveKind::from_str("aquifer"), Some(CaveKind::Lake));
+        assert_eq!(CaveKind::from_str("grotto"), None);
+    }
+
+    #[test]
+    fn test_lava_stays_near_bedrock() {
+        let caves = CaveTerrain::new(42);
+        for x in (0..512).step_by(3) {
+            for z in (0..512).step_by(7) {
+                for y in 0..80 {
+                    let point = [x as f64, y as f64, z as f64];
+                    match caves.cave_at(point, 100.0, true) {
+                        Some(CaveKind::Lava) => assert!(point[1] <= LAVA_LEVEL),
+                        Some(CaveKind::Lake) => {
+                            assert!(point[1] < AQUIFER_LEVEL + AQUIFER_SWING)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ravines_open_onto_tunnels() {
+        let caves = CaveTerrain::new(42);
+        let surface = 100.0;
+        let (mut ravines, mut connected) = (0, 0);
+        for x in (0..2048).step_by(4) {
+            for z in (0..2048).step_by(4) {
+                // Down to the bottom of the ravine, if the column has one
+                let Some(bottom) = (0..100)
+                    .rev()
+                    .take_while(|&y| {
+                        caves.cave_at([x as f64, y as f64, z as f64], surface, true)
+                            == Some(CaveKind::Ravine)
+                    })
+                    .last()
+                else {
+                    continue;
+                };
+                ravines += 1;
+                let below = [x as f64, bottom as f64 - 1.0, z as f64];
+                if caves.cave_at(below, surface, true) == Some(CaveKind::Tunnel) {
+                    connected += 1;
+                }
+            }
+        }
+        assert!(ravines > 0, "Should find a ravine");
+        assert!(connected > 0, "Ravines should cut into the tunnels");
+    }
+}
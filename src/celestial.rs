@@ -0,0 +1,167 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3;
+
+use crate::config::CelestialConfig;
+
+/// Works out where the sun, moon and stars are in the sky for the configured
+/// planet on a given date.
+///
+/// Directions use the world axes: +X is east, +Y is up and -Z is north.
+pub struct Celestial {
+    config: CelestialConfig,
+    // Days elapsed since the world was created, taken from the world clock.
+    days: f64,
+}
+
+impl Celestial {
+    pub fn new(config: CelestialConfig) -> Self {
+        Self { config, days: 0.0 }
+    }
+
+    pub fn set_days(&mut self, days: f64) {
+        self.days = days;
+    }
+
+    /// Fraction of the day in [0, 1), where 0 is midnight and 0.5 is noon.
+    fn time_of_day(&self) -> f32 {
+        self.days.fract() as f32
+    }
+
+    /// Fraction of the year in [0, 1), starting at the spring equinox.
+    fn year_fraction(&self) -> f32 {
+        (self.days / self.config.year_length_days.max(1.0) as f64).fract() as f32
+    }
+
+    /// Fraction of the lunar cycle in [0, 1), where 0 is a new moon and 0.5
+    /// is a full moon.
+    pub fn moon_phase(&self) -> f32 {
+        (self.days / self.config.lunar_cycle_days.max(1.0) as f64).fract() as f32
+    }
+
+    /// How much of the moon's face is lit, from 0 at new moon to 1 at full.
+    pub fn moon_illumination(&self) -> f32 {
+        (1.0 - (self.moon_phase() * TAU).cos()) * 0.5
+    }
+
+    /// The sun's angle north of the celestial equator, in radians.
+    pub fn sun_declination(&self) -> f32 {
+        self.config.axial_tilt.to_radians() * (self.year_fraction() * TAU).sin()
+    }
+
+    /// Angle the sky has turned around the celestial pole since midnight.
+    pub fn sidereal_angle(&self) -> f32 {
+        self.time_of_day() * TAU
+    }
+
+    pub fn latitude(&self) -> f32 {
+        self.config.latitude.to_radians()
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        let hour_angle = (self.time_of_day() - 0.5) * TAU;
+        self.direction(hour_angle, self.sun_declination())
+    }
+
+    /// The moon lags the sun by its phase, so it rises later each day and is
+    /// opposite the sun when full.
+    pub fn moon_direction(&self) -> Vec3 {
+        let phase_angle = self.moon_phase() * TAU;
+        let hour_angle = (self.time_of_day() - 0.5) * TAU - phase_angle;
+        let declination = self.sun_declination() * phase_angle.cos();
+        self.direction(hour_angle, declination)
+    }
+
+    fn direction(&self, hour_angle: f32, declination: f32) -> Vec3 {
+        let latitude = self.latitude();
+        let up = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+        Vec3::new(east, up, -north).normalize_or_zero()
+    }
+}
+
+/// Converts the console's angle convention (0 at sunrise, PI/2 at noon) to a
+/// fraction of the day.
+pub fn time_of_day_from_radians(t: f32) -> f32 {
+    ((t + PI / 2.0) / TAU).rem_euclid(1.0)
+}
+
+/// Converts a fraction of the day to the console's angle convention, in
+/// (-PI, PI].
+pub fn time_of_day_to_radians(t: f32) -> f32 {
+    let angle = t * TAU - PI / 2.0;
+    if angle > PI {
+        angle - TAU
+    } else {
+        angle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn celestial() -> Celestial {
+        Celestial::new(CelestialConfig::default())
+    }
+
+    #[test]
+    fn test_sun_rises_in_the_east_and_peaks_at_noon() {
+        let mut c = celestial();
+        c.set_days(0.25);
+        let sunrise = c.sun_direction();
+        c.set_days(0.5);
+        let noon = c.sun_direction();
+        c.set_days(0.0);
+        let midnight = c.sun_direction();
+
+        assert!(sunrise.x > 0.9, "sunrise was {sunrise}");
+        assert!(noon.y > 0.5, "noon was {noon}");
+        assert!(midnight.y < -0.5, "midnight was {midnight}");
+        // At a northern latitude the noon sun is in the south (+Z).
+        assert!(noon.z > 0.0);
+    }
+
+    #[test]
+    fn test_summer_sun_is_higher_than_winter() {
+        let config = CelestialConfig::default();
+        let year = config.year_length_days as f64;
+        let mut c = Celestial::new(config);
+
+        c.days = year * 0.25 + 0.5;
+        let summer = c.sun_direction().y;
+        c.days = year * 0.75 + 0.5;
+        let winter = c.sun_direction().y;
+
+        assert!(c.sun_declination() < 0.0);
+        assert!(summer > winter + 0.3, "summer {summer} winter {winter}");
+    }
+
+    #[test]
+    fn test_full_moon_opposes_sun() {
+        let config = CelestialConfig::default();
+        let cycle = config.lunar_cycle_days as f64;
+        let mut c = Celestial::new(config);
+
+        c.days = cycle * 0.5;
+        assert!((c.moon_phase() - 0.5).abs() < 1e-4);
+        assert!(c.moon_illumination() > 0.99);
+        assert!(c.sun_direction().dot(c.moon_direction()) < -0.9);
+
+        c.days = cycle * 2.0;
+        assert!(c.moon_illumination() < 0.01);
+        assert!(c.sun_direction().dot(c.moon_direction()) > 0.9);
+    }
+
+    #[test]
+    fn test_time_radians_round_trip() {
+        for t in [-1.5f32, 0.0, 0.5, 1.5, 2.6, 3.1] {
+            let back = time_of_day_to_radians(time_of_day_from_radians(t));
+            assert!((back - t).abs() < 1e-4, "{t} became {back}");
+        }
+        assert!((time_of_day_from_radians(std::f32::consts::FRAC_PI_2) - 0.5).abs() < 1e-6);
+    }
+}
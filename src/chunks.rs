@@ -1,6 +1,8 @@
 use crate::{
     block::{self, Block},
-    terrain::{Biome, WorldTerrain, BEDROCK_LEVEL, WATER_LEVEL},
+    generator::TerrainGenerator,
+    terrain::BEDROCK_LEVEL,
+    voxel_trees::VoxelTrees,
 };
 use glam::{IVec2, IVec3, UVec2, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
@@ -24,6 +26,10 @@ pub struct Chunk {
     blocks: [[[Block; 16]; 16]; 16],
     start: Vec3,
     version: u32,
+    // How many of the blocks are lava, so meshing only looks for its light
+    // near chunks that have some. Counted again whenever a chunk is loaded.
+    #[serde(skip)]
+    lava: u32,
 }
 
 impl Chunk {
@@ -39,17 +45,55 @@ impl Chunk {
         self.version
     }
 
+    /// Whether any of the blocks are lava.
+    pub fn has_lava(&self) -> bool {
+        self.lava > 0
+    }
+
+    /// The blocks, for filling in as the chunk is generated. The lava in
+    /// them is only counted once `count_lava` is called.
+    pub(crate) fn blocks_mut(&mut self) -> &mut [[[Block; 16]; 16]; 16] {
+        &mut self.blocks
+    }
+
+    pub(crate) fn count_lava(&mut self) {
+        self.lava = self
+            .blocks
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|block| block.ty() == block::Type::Lava)
+            .count() as u32;
+    }
+
+    /// Changes one block, keeping the lava count up to date.
+    fn set(&mut self, coords: &LocalBlockCoords, ty: block::Type, level: u8, is_source: bool) {
+        let block = &mut self.blocks[coords.lx][coords.lz][coords.ly];
+        let was_lava = block.ty() == block::Type::Lava;
+        block.set_type(ty);
+        block.set_level(level);
+        block.set_source(is_source);
+        match (was_lava, ty == block::Type::Lava) {
+            (true, false) => self.lava -= 1,
+            (false, true) => self.lava += 1,
+            _ => {}
+        }
+        self.increment_version();
+    }
+
     fn increment_version(&mut self) {
         self.version = self.version.wrapping_add(1);
     }
 
-    #[cfg(test)]
     pub fn new(start: Vec3, blocks: [[[Block; 16]; 16]; 16]) -> Self {
-        Self {
+        let mut chunk = Self {
             blocks,
             start,
             version: 1,
-        }
+            lava: 0,
+        };
+        chunk.count_lava();
+        chunk
     }
 }
 
@@ -65,7 +109,7 @@ pub struct Chunks {
 
     load_radius: i32,
     world_name: String,
-    terrain: WorldTerrain,
+    terrain: Arc<dyn TerrainGenerator>,
 
     water_queue: Arc<Mutex<HashSet<IVec3>>>,
     sim_thread: Option<JoinHandle<()>>,
@@ -116,14 +160,19 @@ fn block_to_local_coords(pos: IVec3) -> Option<LocalBlockCoords> {
 }
 
 impl Chunks {
-    pub fn new(world_name: String, seed: u32, load_radius: u32, sim_rate_ms: u64) -> Self {
+    pub fn new(
+        world_name: String,
+        terrain: Arc<dyn TerrainGenerator>,
+        load_radius: u32,
+        sim_rate_ms: u64,
+        voxel_trees: Option<VoxelTrees>,
+    ) -> Self {
         let _ = std::fs::create_dir_all(format!("worlds/{}", world_name));
 
         // TODO: shut these down correctly.
         let (loader_tx, loader_rx) = mpsc::channel();
 
-        let terrain = WorldTerrain::new(seed);
-        let terrain_clone = terrain.clone();
+        let terrain_clone = Arc::clone(&terrain);
 
         // Create a thread that will load chunks when requested.
         let loading = Arc::new(Mutex::new(HashSet::new()));
@@ -138,7 +187,12 @@ impl Chunks {
             .name(String::from("chunk loader"))
             .spawn(move || {
                 for key in loader_rx {
-                    let chunks = load_chunks(&world_name_clone, &terrain_clone, key);
+                    let chunks = load_chunks(
+                        &world_name_clone,
+                        terrain_clone.as_ref(),
+                        voxel_trees.as_ref(),
+                        key,
+                    );
                     log::debug!("completed loading of chunk {key}");
                     let mut loaded = loaded_clone.lock().expect("locked loaded");
                     loaded.insert(key, chunks);
@@ -280,7 +334,7 @@ impl Chunks {
 
     pub fn height_at(&self, position: &Vec3) -> f32 {
         let point = Vec2::new(position.x, position.z);
-        self.terrain.get(point).height
+        self.terrain.surface_height(point)
     }
 
     pub fn set_block(&self, pos: glam::IVec3, block_type: block::Type) {
@@ -308,11 +362,7 @@ impl Chunks {
         if let Ok(mut loaded) = self.loaded.lock() {
             if let Some(col) = loaded.get_mut(&coords.chunk_key) {
                 if coords.chunk_y < col.len() {
-                    let block = &mut col[coords.chunk_y].blocks[coords.lx][coords.lz][coords.ly];
-                    block.set_type(block_type);
-                    block.set_level(level);
-                    block.set_source(is_source);
-                    col[coords.chunk_y].increment_version();
+                    col[coords.chunk_y].set(&coords, block_type, level, is_source);
 
                     let path = format!(
                         "worlds/{}/chunk_{}_{}.bin",
@@ -379,6 +429,24 @@ impl Chunks {
         0
     }
 
+    /// Lets rain collect in the column at `column` (x, z). Water is only added
+    /// where the surface is open to the sky and enclosed on all sides, so rain
+    /// fills basins rather than spilling across open ground. Returns true if
+    /// water was placed.
+    pub fn add_rain_water(&self, column: IVec2) -> bool {
+        let target = match self.loaded.lock() {
+            Ok(loaded) => rain_basin_target(&loaded, column),
+            Err(_) => None,
+        };
+        match target {
+            Some(pos) => {
+                self.set_block(pos, block::Type::Water);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn is_chunk_loaded(&self, chunk_pos: glam::UVec2) -> bool {
         if let Ok(loaded) = self.loaded.lock() {
             return loaded.contains_key(&chunk_pos);
@@ -386,111 +454,69 @@ impl Chunks {
         false
     }
 
-    pub fn terrain(&self) -> &WorldTerrain {
+    pub fn terrain(&self) -> &Arc<dyn TerrainGenerator> {
         &self.terrain
     }
 }
 
-fn load_chunks(world_name: &str, terrain: &WorldTerrain, key: UVec2) -> Vec<Chunk> {
+fn load_chunks(
+    world_name: &str,
+    terrain: &dyn TerrainGenerator,
+    voxel_trees: Option<&VoxelTrees>,
+    key: UVec2,
+) -> Vec<Chunk> {
     log::debug!("loading chunk {key}");
     let path = format!("worlds/{}/chunk_{}_{}.bin", world_name, key.x, key.y);
-    if let Ok(data) = std::fs::read(&path) {
-        if let Ok(chunks) = bincode::deserialize(&data) {
+    let mut chunks = match std::fs::read(&path).map(|data| bincode::deserialize(&data)) {
+        Ok(Ok(chunks)) => {
             log::debug!("loaded chunk {} from disk", key);
-            return chunks;
+            chunks
         }
+        _ => {
+            let mut chunks = terrain.generate_column(key);
+            if let Some(voxel_trees) = voxel_trees {
+                voxel_trees.build(terrain, key, &mut chunks);
+            }
+            chunks
+        }
+    };
+    for chunk in &mut chunks {
+        chunk.count_lava();
     }
+    chunks
+}
 
-    let mut chunks = Vec::new();
-    let num_chunks_y = (MAX_HEIGHT / 16) as u32;
-    for chunky in 0..num_chunks_y {
-        let mut chunk = Chunk {
-            blocks: [[[Block::new(); 16]; 16]; 16],
-            start: Vec3::new(
-                16.0 * (key.x as f32),
-                16.0 * (chunky as f32),
-                16.0 * (key.y as f32),
-            ),
-            version: 1,
-        };
-        for (x, row) in chunk.blocks.iter_mut().enumerate() {
-            for (z, col) in row.iter_mut().enumerate() {
-                let blockx = (x as u32) + (16 * key.x);
-                let blockz = (z as u32) + (16 * key.y);
-                let point = glam::Vec2::new(blockx as f32, blockz as f32);
-                let tdata = terrain.get(point);
-                for (y, block) in col.iter_mut().enumerate() {
-                    let blocky = (y as u32) + (16 * chunky);
-                    let blockyf32 = blocky as f32;
-                    let height = tdata.height;
-
-                    let point3d = glam::Vec3::new(blockx as f32, blocky as f32, blockz as f32);
-
-                    if terrain.is_cave(point3d, tdata.height) {
-                        continue;
-                    }
+/// Whether a world's wild trees are built from blocks. Chunks saved to disk
+/// keep whichever trees they were generated with, so once a world has saved
+/// any the choice it was played with stands, and a later change to
+/// `requested` is ignored.
+pub(crate) fn settle_voxel_trees(world_name: &str, requested: bool) -> bool {
+    let dir = format!("worlds/{}", world_name);
+    let marker = format!("{}/voxel_trees", dir);
+    if std::path::Path::new(&marker).exists() {
+        if !requested {
+            log::warn!("{world_name} was created with voxel trees, so it keeps them");
+        }
+        return true;
+    }
 
-                    if blockyf32 < WATER_LEVEL && blockyf32 >= height {
-                        block.set_type(block::Type::Water);
-                    } else if blockyf32 < height {
-                        let hash = (blockx.wrapping_mul(31)
-                            ^ blocky.wrapping_mul(17)
-                            ^ blockz.wrapping_mul(23))
-                            % 10;
-                        let dither = (hash as f32) - 5.0;
-
-                        let btype = match tdata.biome {
-                            Biome::Desert => {
-                                if blockyf32 > height - 4.0 + (dither * 0.5) {
-                                    block::Type::Sand
-                                } else {
-                                    block::Type::Rock
-                                }
-                            }
-                            Biome::Ocean => {
-                                if blockyf32 > height - 2.0 + (dither * 0.5) {
-                                    block::Type::Sand
-                                } else {
-                                    block::Type::Rock
-                                }
-                            }
-                            Biome::Plains | Biome::Hills => {
-                                if blockyf32 > height - 1.0 {
-                                    if height < WATER_LEVEL {
-                                        block::Type::Sand
-                                    } else {
-                                        block::Type::Grass
-                                    }
-                                } else if blockyf32 > height - 4.0 + dither {
-                                    block::Type::Sand
-                                } else {
-                                    block::Type::Rock
-                                }
-                            }
-                            Biome::Mountains => {
-                                if blockyf32 > 180.0 + dither {
-                                    block::Type::Ice
-                                } else if blockyf32 > 120.0 + dither {
-                                    block::Type::Rock
-                                } else if (blockyf32) > height - 1.0 {
-                                    if height < WATER_LEVEL {
-                                        block::Type::Sand
-                                    } else {
-                                        block::Type::Grass
-                                    }
-                                } else {
-                                    block::Type::Rock
-                                }
-                            }
-                        };
-                        block.set_type(btype);
-                    }
-                }
-            }
+    let saved_chunks = std::fs::read_dir(&dir).is_ok_and(|mut entries| {
+        entries.any(|entry| {
+            entry.is_ok_and(|entry| entry.file_name().to_string_lossy().starts_with("chunk_"))
+        })
+    });
+    if saved_chunks {
+        if requested {
+            log::warn!("{world_name} was created without voxel trees, so it keeps drawn trees");
         }
-        chunks.push(chunk);
+        return false;
     }
-    chunks
+
+    if requested {
+        let _ = std::fs::create_dir_all(&dir);
+        let _ = std::fs::write(&marker, "");
+    }
+    requested
 }
 
 fn get_block_at(loaded: &HashMap<UVec2, Vec<Chunk>>, pos: IVec3) -> Option<Block> {
@@ -503,6 +529,42 @@ fn get_block_at(loaded: &HashMap<UVec2, Vec<Chunk>>, pos: IVec3) -> Option<Block
     None
 }
 
+/// Finds where rain falling on `column` would pool: either topping up shallow
+/// water at the surface, or filling the air block above the surface if all four
+/// horizontal neighbours would hold it in.
+fn rain_basin_target(loaded: &HashMap<UVec2, Vec<Chunk>>, column: IVec2) -> Option<IVec3> {
+    let top = (0..MAX_HEIGHT).rev().find_map(|y| {
+        let pos = IVec3::new(column.x, y, column.y);
+        get_block_at(loaded, pos)
+            .filter(|b| b.is_active())
+            .map(|b| (pos, b))
+    })?;
+
+    let (top_pos, top_block) = top;
+    if top_block.ty() == block::Type::Water && !top_block.is_source() {
+        return Some(top_pos);
+    }
+
+    // Full pools may only rise while they are still enclosed.
+
+    let target = top_pos + IVec3::Y;
+    if target.y >= MAX_HEIGHT {
+        return None;
+    }
+
+    let enclosed = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z]
+        .iter()
+        .all(|dir| match get_block_at(loaded, target + *dir) {
+            Some(b) => b.is_solid() || b.ty() == block::Type::Water,
+            None => false,
+        });
+    if enclosed {
+        Some(target)
+    } else {
+        None
+    }
+}
+
 fn set_block_in_sim(
     loaded: &mut HashMap<UVec2, Vec<Chunk>>,
     pos: IVec3,
@@ -522,13 +584,10 @@ fn set_block_in_sim(
 
     if let Some(col) = loaded.get_mut(&coords.chunk_key) {
         if coords.chunk_y < col.len() {
-            let block = &mut col[coords.chunk_y].blocks[coords.lx][coords.lz][coords.ly];
+            let block = col[coords.chunk_y].blocks[coords.lx][coords.lz][coords.ly];
             if block.ty() != block_type || block.level() != level || block.is_source() != is_source
             {
-                block.set_type(block_type);
-                block.set_level(level);
-                block.set_source(is_source);
-                col[coords.chunk_y].increment_version();
+                col[coords.chunk_y].set(&coords, block_type, level, is_source);
                 modified_chunks.insert(coords.chunk_key);
             }
         }
@@ -720,12 +779,96 @@ fn tick_water_simulation(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ores::Ore;
+    use crate::terrain::{Biome, WorldTerrain};
+
+    #[test]
+    fn test_surface_height_matches_density_blocks() {
+        let terrain = WorldTerrain::new(12345).with_density(1.0);
+        let hills = terrain
+            .find_closest_pure_biome(Vec2::ZERO, Biome::Hills)
+            .expect("Should find hills");
+        let key = UVec2::new(hills.x as u32 / 16, hills.y as u32 / 16);
+        let chunks = load_chunks("test_density", &terrain, None, key);
+
+        let mut moved = 0;
+        for x in 0..16 {
+            for z in 0..16 {
+                let top = chunks
+                    .iter()
+                    .flat_map(|chunk| {
+                        chunk.blocks[x][z]
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, b)| b.is_solid())
+                            .map(move |(y, _)| chunk.start.y as i32 + y as i32)
+                    })
+                    .max()
+                    .expect("Column should have ground");
+
+                let world = Vec2::new(
+                    (key.x * 16) as f32 + x as f32,
+                    (key.y * 16) as f32 + z as f32,
+                );
+                let surface = terrain.surface_height(world);
+                assert_eq!(
+                    top + 1,
+                    surface.ceil() as i32,
+                    "Surface at {} doesn't match the generated blocks",
+                    world
+                );
+                if surface.ceil() != terrain.get(world).height.ceil() {
+                    moved += 1;
+                }
+            }
+        }
+        assert!(moved > 0, "Density should move some of the ground");
+    }
+
+    #[test]
+    fn test_ore_veins_in_chunk() {
+        let terrain = WorldTerrain::new(999);
+        let hills = terrain
+            .find_closest_pure_biome(Vec2::ZERO, Biome::Hills)
+            .expect("Should find hills");
+        let key = UVec2::new(hills.x as u32 / 16, hills.y as u32 / 16);
+        let chunks = load_chunks("test_ores", &terrain, None, key);
+        let ore_blocks = Ore::ALL.map(|ore| ore.block());
+
+        let mut ores = HashSet::new();
+        for chunk in chunks.iter() {
+            for (x, row) in chunk.blocks.iter().enumerate() {
+                for (z, col) in row.iter().enumerate() {
+                    let world = Vec2::new(
+                        (key.x * 16) as f32 + x as f32,
+                        (key.y * 16) as f32 + z as f32,
+                    );
+                    let height = terrain.get(world).height;
+                    for (y, block) in col.iter().enumerate() {
+                        if ore_blocks.contains(&block.ty()) {
+                            // Ores only ever replace rock, which is below the topsoil.
+                            assert!(chunk.start.y + (y as f32) < height - 1.0);
+                            ores.insert(block.material_id());
+                        }
+                    }
+                }
+            }
+        }
+        assert!(
+            ores.contains(&(block::Type::Coal as u32)),
+            "Should find coal"
+        );
+        assert!(
+            ores.contains(&(block::Type::Iron as u32)),
+            "Should find iron"
+        );
+    }
 
     #[test]
     fn test_cave_generation_in_chunk() {
         let terrain = WorldTerrain::new(999);
         let key = UVec2::new(0, 0);
-        let chunks = load_chunks("test_caves", &terrain, key);
+        let chunks = load_chunks("test_caves", &terrain, None, key);
 
         let mut solid_underground = 0;
         let mut cave_air = 0;
@@ -863,8 +1006,8 @@ mod tests {
         let mut blocks = [[[Block::new(); 16]; 16]; 16];
 
         // Create a platform of solid blocks at Y=5 (Z=5 in internal array)
-        for x in 5..=10 {
-            blocks[x][5][5].set_type(block::Type::Rock);
+        for column in blocks.iter_mut().take(11).skip(5) {
+            column[5][5].set_type(block::Type::Rock);
         }
 
         let chunk = Chunk::new(Vec3::new(0.0, 0.0, 0.0), blocks);
@@ -938,8 +1081,8 @@ mod tests {
 
         // Create a platform of solid blocks at Y=5 (Z=5 in internal array)
         // Platform exists for x in 5..=8. So x=9 is air/hole initially.
-        for x in 5..=8 {
-            blocks[x][5][5].set_type(block::Type::Rock);
+        for column in blocks.iter_mut().take(9).skip(5) {
+            column[5][5].set_type(block::Type::Rock);
         }
 
         let chunk = Chunk::new(Vec3::new(0.0, 0.0, 0.0), blocks);
@@ -988,4 +1131,92 @@ mod tests {
             assert_eq!(falling_flow.level(), 8);
         }
     }
+
+    #[test]
+    fn test_lava_is_counted_as_it_changes() {
+        let mut blocks = [[[Block::new(); 16]; 16]; 16];
+        blocks[3][4][5].set_type(block::Type::Lava);
+        let mut loaded = HashMap::new();
+        loaded.insert(
+            UVec2::new(0, 0),
+            vec![Chunk::new(Vec3::new(0.0, 0.0, 0.0), blocks)],
+        );
+        assert!(loaded[&UVec2::new(0, 0)][0].has_lava());
+
+        // Lava flowing on and cooling where it was keeps the count right
+        let mut modified = HashSet::new();
+        let m = &mut modified;
+        let flowed = IVec3::new(8, 8, 8);
+        let cooled = IVec3::new(3, 5, 4);
+        set_block_in_sim(&mut loaded, flowed, block::Type::Lava, 0, false, m);
+        set_block_in_sim(&mut loaded, cooled, block::Type::Rock, 0, false, m);
+        assert!(loaded[&UVec2::new(0, 0)][0].has_lava());
+        set_block_in_sim(&mut loaded, flowed, block::Type::Water, 8, true, m);
+        assert!(!loaded[&UVec2::new(0, 0)][0].has_lava());
+    }
+
+    #[test]
+    fn test_rain_fills_enclosed_basin() {
+        let mut loaded = HashMap::new();
+
+        // Flat ground up to Y=5 with a one block deep pit at (5, 5) and an open
+        // surface everywhere else.
+        let mut blocks = [[[Block::new(); 16]; 16]; 16];
+        for row in blocks.iter_mut() {
+            for column in row.iter_mut() {
+                for block in column.iter_mut().take(6) {
+                    block.set_type(block::Type::Rock);
+                }
+            }
+        }
+        blocks[5][5][5].set_type(block::Type::Inactive);
+
+        loaded.insert(
+            UVec2::new(0, 0),
+            vec![Chunk::new(Vec3::new(0.0, 0.0, 0.0), blocks)],
+        );
+
+        // The pit is enclosed on all four sides, so rain collects in it.
+        assert_eq!(
+            rain_basin_target(&loaded, IVec2::new(5, 5)),
+            Some(IVec3::new(5, 5, 5))
+        );
+
+        // Open flat ground has nothing to hold the water.
+        assert_eq!(rain_basin_target(&loaded, IVec2::new(10, 10)), None);
+
+        // Columns that aren't loaded are ignored.
+        assert_eq!(rain_basin_target(&loaded, IVec2::new(40, 40)), None);
+
+        // Shallow flowing water is topped up in place.
+        {
+            let col = loaded.get_mut(&UVec2::new(0, 0)).unwrap();
+            col[0].blocks[5][5][5].set_type(block::Type::Water);
+            col[0].blocks[5][5][5].set_level(3);
+            col[0].blocks[5][5][5].set_source(false);
+        }
+        assert_eq!(
+            rain_basin_target(&loaded, IVec2::new(5, 5)),
+            Some(IVec3::new(5, 5, 5))
+        );
+    }
+
+    #[test]
+    fn test_voxel_trees_are_settled_when_chunks_are_saved() {
+        let fresh = "test_voxel_trees_fresh";
+        let _ = std::fs::remove_dir_all(format!("worlds/{fresh}"));
+        assert!(!settle_voxel_trees(fresh, false));
+        assert!(settle_voxel_trees(fresh, true));
+        // Turning them off again would leave its columns without trees.
+        assert!(settle_voxel_trees(fresh, false));
+
+        let saved = "test_voxel_trees_saved";
+        let _ = std::fs::remove_dir_all(format!("worlds/{saved}"));
+        std::fs::create_dir_all(format!("worlds/{saved}")).unwrap();
+        std::fs::write(format!("worlds/{saved}/chunk_0_0.bin"), []).unwrap();
+        assert!(!settle_voxel_trees(saved, true));
+
+        let _ = std::fs::remove_dir_all(format!("worlds/{fresh}"));
+        let _ = std::fs::remove_dir_all(format!("worlds/{saved}"));
+    }
 }
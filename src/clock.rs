@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use crate::config::{CelestialConfig, ClockConfig};
+
+pub const TICKS_PER_DAY: u64 = 24000;
+
+/// Clock speed is clamped to this range so a typo can't skip whole years.
+const MIN_SPEED: f32 = 0.0;
+const MAX_SPEED: f32 = 100.0;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    /// Seasons split the year into quarters, starting at the spring equinox.
+    pub fn from_year_fraction(t: f32) -> Self {
+        match (t.rem_euclid(1.0) * 4.0) as u32 {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+}
+
+impl std::fmt::Display for Season {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Counts the ticks that have passed in a world. Everything that depends on
+/// the date (sun, moon, seasons) is derived from the tick count so that it can
+/// be saved and restored exactly.
+pub struct WorldClock {
+    // Fractional ticks are kept so that slow clock speeds still make progress.
+    ticks: f64,
+    paused: bool,
+    speed: f32,
+    day_length_secs: f32,
+    year_length_days: f32,
+}
+
+impl WorldClock {
+    pub fn new(state: &ClockConfig, celestial: &CelestialConfig) -> Self {
+        Self {
+            ticks: state.ticks as f64,
+            paused: state.paused,
+            speed: state.speed.clamp(MIN_SPEED, MAX_SPEED),
+            day_length_secs: celestial.day_length_secs.max(1.0),
+            year_length_days: celestial.year_length_days.max(1.0),
+        }
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        if self.paused {
+            return;
+        }
+        let ticks_per_sec = TICKS_PER_DAY as f64 / self.day_length_secs as f64;
+        self.ticks += dt.as_secs_f64() * ticks_per_sec * self.speed as f64;
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks as u64
+    }
+
+    pub fn set_ticks(&mut self, ticks: u64) {
+        self.ticks = ticks as f64;
+    }
+
+    /// Moves the clock forwards, or backwards for negative values. The clock
+    /// never goes back past the creation of the world.
+    pub fn add_ticks(&mut self, ticks: i64) {
+        self.ticks = (self.ticks + ticks as f64).max(0.0);
+    }
+
+    /// Days elapsed since the world was created, including the fraction of
+    /// the current day.
+    pub fn days(&self) -> f64 {
+        self.ticks / TICKS_PER_DAY as f64
+    }
+
+    pub fn day(&self) -> u64 {
+        self.ticks() / TICKS_PER_DAY
+    }
+
+    /// Fraction of the day in [0, 1), where 0 is midnight and 0.5 is noon.
+    pub fn time_of_day(&self) -> f32 {
+        self.days().fract() as f32
+    }
+
+    /// Moves the clock to the given fraction of the current day.
+    pub fn set_time_of_day(&mut self, t: f32) {
+        let day_start = (self.day() * TICKS_PER_DAY) as f64;
+        self.ticks = day_start + t.rem_euclid(1.0) as f64 * TICKS_PER_DAY as f64;
+    }
+
+    /// Fraction of the year in [0, 1), starting at the spring equinox.
+    pub fn year_fraction(&self) -> f32 {
+        (self.days() / self.year_length_days as f64).fract() as f32
+    }
+
+    pub fn season(&self) -> Season {
+        Season::from_year_fraction(self.year_fraction())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn save_state(&self, config: &mut crate::config::WorldConfig) {
+        config.clock = ClockConfig {
+            ticks: self.ticks(),
+            paused: self.paused,
+            speed: self.speed,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(ticks: u64) -> WorldClock {
+        WorldClock::new(
+            &ClockConfig {
+                ticks,
+                ..Default::default()
+            },
+            &CelestialConfig {
+                day_length_secs: 100.0,
+                year_length_days: 8.0,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_clock_advances_with_day_length() {
+        let mut c = clock(0);
+        c.advance(Duration::from_secs(25));
+        assert_eq!(c.ticks(), TICKS_PER_DAY / 4);
+        assert!((c.time_of_day() - 0.25).abs() < 1e-4);
+
+        c.set_speed(2.0);
+        c.advance(Duration::from_secs(25));
+        assert_eq!(c.ticks(), TICKS_PER_DAY * 3 / 4);
+    }
+
+    #[test]
+    fn test_paused_clock_stands_still() {
+        let mut c = clock(1000);
+        c.set_paused(true);
+        c.advance(Duration::from_secs(60));
+        assert_eq!(c.ticks(), 1000);
+
+        // Explicit changes still apply while paused
+        c.add_ticks(500);
+        assert_eq!(c.ticks(), 1500);
+    }
+
+    #[test]
+    fn test_add_ticks_never_goes_negative() {
+        let mut c = clock(1000);
+        c.add_ticks(-5000);
+        assert_eq!(c.ticks(), 0);
+    }
+
+    #[test]
+    fn test_set_time_of_day_keeps_the_day() {
+        let mut c = clock(TICKS_PER_DAY * 3 + 100);
+        c.set_time_of_day(0.5);
+        assert_eq!(c.day(), 3);
+        assert_eq!(c.ticks(), TICKS_PER_DAY * 3 + TICKS_PER_DAY / 2);
+    }
+
+    #[test]
+    fn test_seasons_follow_the_year() {
+        // 8 day year, so each season lasts 2 days.
+        assert_eq!(clock(0).season(), Season::Spring);
+        assert_eq!(clock(TICKS_PER_DAY * 2).season(), Season::Summer);
+        assert_eq!(clock(TICKS_PER_DAY * 5).season(), Season::Autumn);
+        assert_eq!(clock(TICKS_PER_DAY * 7).season(), Season::Winter);
+        assert_eq!(clock(TICKS_PER_DAY * 8).season(), Season::Spring);
+    }
+}
@@ -0,0 +1,374 @@
+use glam::{IVec2, Vec3};
+use std::collections::HashMap;
+
+/// How close a ray has to get to a shape to count as hitting it.
+const RAY_EPSILON: f32 = 1e-3;
+/// The most steps a ray takes towards a shape before giving up on it.
+const RAY_STEPS: usize = 64;
+/// Segments in each ring of an outline.
+const OUTLINE_SEGMENTS: usize = 12;
+
+/// A simplified stand-in for the geometry of something that isn't made of
+/// blocks, for walking into and looking at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CollisionShape {
+    /// A trunk, rounded off at either end.
+    Capsule { start: Vec3, end: Vec3, radius: f32 },
+    /// A bush, or anything else that's round all over.
+    Sphere { centre: Vec3, radius: f32 },
+}
+
+impl CollisionShape {
+    /// The shape with every point moved by `place` and the radius scaled to
+    /// match.
+    pub(crate) fn transformed(&self, place: impl Fn(Vec3) -> Vec3, scale: f32) -> Self {
+        match *self {
+            Self::Capsule { start, end, radius } => Self::Capsule {
+                start: place(start),
+                end: place(end),
+                radius: radius * scale,
+            },
+            Self::Sphere { centre, radius } => Self::Sphere {
+                centre: place(centre),
+                radius: radius * scale,
+            },
+        }
+    }
+
+    /// The corners of the box around the shape.
+    fn bounds(&self) -> (Vec3, Vec3) {
+        match *self {
+            Self::Capsule { start, end, radius } => {
+                (start.min(end) - radius, start.max(end) + radius)
+            }
+            Self::Sphere { centre, radius } => (centre - radius, centre + radius),
+        }
+    }
+
+    /// How far a point is from the surface, negative inside.
+    fn distance(&self, point: Vec3) -> f32 {
+        match *self {
+            Self::Capsule { start, end, radius } => distance_to_segment(point, start, end) - radius,
+            Self::Sphere { centre, radius } => point.distance(centre) - radius,
+        }
+    }
+
+    /// Whether the shape overlaps a capsule `radius` around the segment from
+    /// `start` to `end`.
+    pub(crate) fn touches(&self, start: Vec3, end: Vec3, radius: f32) -> bool {
+        match *self {
+            Self::Capsule {
+                start: a,
+                end: b,
+                radius: r,
+            } => distance_between_segments(a, b, start, end) < r + radius,
+            Self::Sphere { centre, radius: r } => {
+                distance_to_segment(centre, start, end) < r + radius
+            }
+        }
+    }
+
+    /// How far along a ray from `origin` the shape is hit, if it is within
+    /// `max_distance`. `dir` should be normalised.
+    pub(crate) fn raycast(&self, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<f32> {
+        // Both shapes are convex, so stepping by the distance to the surface
+        // never overshoots it
+        let mut t = 0.0;
+        for _ in 0..RAY_STEPS {
+            let distance = self.distance(origin + dir * t);
+            if distance < RAY_EPSILON {
+                return Some(t);
+            }
+            t += distance;
+            if t > max_distance {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Pairs of points on the surface that, joined up, sketch the shape: a
+    /// ring around either end of a capsule with lines running between them,
+    /// or three rings around a sphere.
+    pub(crate) fn outline(&self) -> Vec<(Vec3, Vec3)> {
+        let ring = |centre: Vec3, u: Vec3, v: Vec3, radius: f32| {
+            let point = move |i: usize| {
+                let angle = i as f32 / OUTLINE_SEGMENTS as f32 * std::f32::consts::TAU;
+                centre + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            (0..OUTLINE_SEGMENTS).map(move |i| (point(i), point(i + 1)))
+        };
+        match *self {
+            Self::Capsule { start, end, radius } => {
+                let (u, v) = (end - start).normalize_or_zero().any_orthonormal_pair();
+                let mut lines: Vec<_> = ring(start, u, v, radius)
+                    .chain(ring(end, u, v, radius))
+                    .collect();
+                for side in [u, v, -u, -v] {
+                    lines.push((start + side * radius, end + side * radius));
+                }
+                lines
+            }
+            Self::Sphere { centre, radius } => ring(centre, Vec3::X, Vec3::Y, radius)
+                .chain(ring(centre, Vec3::Y, Vec3::Z, radius))
+                .chain(ring(centre, Vec3::Z, Vec3::X, radius))
+                .collect(),
+        }
+    }
+}
+
+/// Shapes bucketed by the block columns they stand in, so only the few near
+/// the player are tested against.
+#[derive(Default)]
+pub(crate) struct CollisionIndex {
+    shapes: Vec<CollisionShape>,
+    // Where each body's shapes start in `shapes`
+    bodies: Vec<usize>,
+    columns: HashMap<IVec2, Vec<u32>>,
+}
+
+impl CollisionIndex {
+    /// Indexes the shapes of each body, which is everything one thing, such
+    /// as a tree, is made up of.
+    pub(crate) fn new(bodies: Vec<Vec<CollisionShape>>) -> Self {
+        let mut shapes = Vec::new();
+        let mut starts = Vec::with_capacity(bodies.len());
+        for body in bodies {
+            starts.push(shapes.len());
+            shapes.extend(body);
+        }
+
+        let mut columns: HashMap<IVec2, Vec<u32>> = HashMap::new();
+        for (i, shape) in shapes.iter().enumerate() {
+            let (min, max) = shape.bounds();
+            for x in min.x.floor() as i32..=max.x.floor() as i32 {
+                for z in min.z.floor() as i32..=max.z.floor() as i32 {
+                    columns.entry(IVec2::new(x, z)).or_default().push(i as u32);
+                }
+            }
+        }
+        Self {
+            shapes,
+            bodies: starts,
+            columns,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// The shapes of the body the shape at `index` belongs to.
+    fn body_of(&self, index: usize) -> &[CollisionShape] {
+        let body = self.bodies.partition_point(|&start| start <= index) - 1;
+        let end = self
+            .bodies
+            .get(body + 1)
+            .copied()
+            .unwrap_or(self.shapes.len());
+        &self.shapes[self.bodies[body]..end]
+    }
+
+    /// The shapes whose boxes overlap the one from `min` to `max`, each once,
+    /// along with where they are in `shapes`.
+    fn near(&self, min: Vec3, max: Vec3) -> impl Iterator<Item = (usize, &CollisionShape)> {
+        let mut found = Vec::new();
+        for x in min.x.floor() as i32..=max.x.floor() as i32 {
+            for z in min.z.floor() as i32..=max.z.floor() as i32 {
+                if let Some(column) = self.columns.get(&IVec2::new(x, z)) {
+                    found.extend_from_slice(column);
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+            .into_iter()
+            .map(|i| (i as usize, &self.shapes[i as usize]))
+            .filter(move |(_, shape)| {
+                let (low, high) = shape.bounds();
+                low.cmple(max).all() && high.cmpge(min).all()
+            })
+    }
+
+    /// Whether any shape overlaps a capsule `radius` around the segment from
+    /// `start` to `end`.
+    pub(crate) fn touches(&self, start: Vec3, end: Vec3, radius: f32) -> bool {
+        self.near(start.min(end) - radius, start.max(end) + radius)
+            .any(|(_, shape)| shape.touches(start, end, radius))
+    }
+
+    /// How far along a ray from `origin` the nearest shape is hit, if any is
+    /// within `max_distance`, and the shapes of the body it belongs to.
+    pub(crate) fn raycast(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_distance: f32,
+    ) -> Option<(f32, &[CollisionShape])> {
+        let end = origin + dir * max_distance;
+        self.near(origin.min(end), origin.max(end))
+            .filter_map(|(i, shape)| Some((shape.raycast(origin, dir, max_distance)?, i)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(t, i)| (t, self.body_of(i)))
+    }
+}
+
+pub(crate) fn distance_to_segment(point: Vec3, start: Vec3, end: Vec3) -> f32 {
+    let along = end - start;
+    let t = (point - start).dot(along) / along.length_squared().max(f32::EPSILON);
+    point.distance(start + along * t.clamp(0.0, 1.0))
+}
+
+/// The closest any point on one segment comes to any point on the other.
+fn distance_between_segments(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> f32 {
+    let u = b - a;
+    let v = d - c;
+    let w = a - c;
+    let uu = u.length_squared();
+    let vv = v.length_squared();
+    if uu <= f32::EPSILON {
+        return distance_to_segment(a, c, d);
+    }
+    if vv <= f32::EPSILON {
+        return distance_to_segment(c, a, b);
+    }
+
+    let uv = u.dot(v);
+    let uw = u.dot(w);
+    let vw = v.dot(w);
+    // Parallel segments are as close at the start of the first as anywhere
+    let denom = uu * vv - uv * uv;
+    let mut s = if denom > f32::EPSILON {
+        ((uv * vw - vv * uw) / denom).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut t = (uv * s + vw) / vv;
+    if t < 0.0 {
+        t = 0.0;
+        s = (-uw / uu).clamp(0.0, 1.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = ((uv - uw) / uu).clamp(0.0, 1.0);
+    }
+    (a + u * s).distance(c + v * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trunk(x: f32, z: f32) -> CollisionShape {
+        CollisionShape::Capsule {
+            start: Vec3::new(x, 0.0, z),
+            end: Vec3::new(x, 6.0, z),
+            radius: 0.4,
+        }
+    }
+
+    #[test]
+    fn test_shapes_touch_what_comes_near() {
+        let trunk = trunk(0.0, 0.0);
+        let player = |x: f32| (Vec3::new(x, 1.0, 0.0), Vec3::new(x, 2.5, 0.0));
+        let (start, end) = player(0.6);
+        assert!(trunk.touches(start, end, 0.3));
+        let (start, end) = player(0.8);
+        assert!(!trunk.touches(start, end, 0.3));
+        // Standing on top of it
+        assert!(trunk.touches(Vec3::new(0.0, 6.5, 0.0), Vec3::new(0.0, 8.0, 0.0), 0.3));
+        assert!(!trunk.touches(Vec3::new(0.0, 7.0, 0.0), Vec3::new(0.0, 8.0, 0.0), 0.3));
+
+        let bush = CollisionShape::Sphere {
+            centre: Vec3::new(0.0, 1.0, 0.0),
+            radius: 1.0,
+        };
+        assert!(bush.touches(Vec3::new(1.2, 0.5, 0.0), Vec3::new(1.2, 2.0, 0.0), 0.3));
+        assert!(!bush.touches(Vec3::new(1.4, 0.5, 0.0), Vec3::new(1.4, 2.0, 0.0), 0.3));
+    }
+
+    #[test]
+    fn test_rays_stop_at_the_surface() {
+        let trunk = trunk(0.0, 0.0);
+        let hit = trunk
+            .raycast(Vec3::new(-3.0, 2.0, 0.0), Vec3::X, 5.0)
+            .unwrap();
+        assert!((hit - 2.6).abs() < 0.01, "hit at {hit}");
+        assert_eq!(trunk.raycast(Vec3::new(-3.0, 2.0, 0.0), Vec3::X, 2.0), None);
+        assert_eq!(trunk.raycast(Vec3::new(-3.0, 2.0, 1.0), Vec3::X, 5.0), None);
+
+        let bush = CollisionShape::Sphere {
+            centre: Vec3::ZERO,
+            radius: 1.0,
+        };
+        let hit = bush
+            .raycast(Vec3::new(0.0, 4.0, 0.0), Vec3::NEG_Y, 5.0)
+            .unwrap();
+        assert!((hit - 3.0).abs() < 0.01, "hit at {hit}");
+    }
+
+    #[test]
+    fn test_index_finds_the_nearest_shape() {
+        let index = CollisionIndex::new(vec![
+            vec![trunk(0.0, 0.0)],
+            vec![trunk(3.0, 0.0), trunk(3.0, 1.0)],
+            vec![trunk(40.0, 0.0)],
+        ]);
+        let (hit, body) = index
+            .raycast(Vec3::new(-3.0, 2.0, 0.0), Vec3::X, 8.0)
+            .unwrap();
+        assert!((hit - 2.6).abs() < 0.01, "hit at {hit}");
+        assert_eq!(body, &[trunk(0.0, 0.0)]);
+        let (hit, body) = index
+            .raycast(Vec3::new(6.0, 2.0, 0.0), Vec3::NEG_X, 8.0)
+            .unwrap();
+        assert!((hit - 2.6).abs() < 0.01, "hit at {hit}");
+        assert_eq!(body, &[trunk(3.0, 0.0), trunk(3.0, 1.0)]);
+
+        assert!(index.touches(Vec3::new(40.5, 1.0, 0.0), Vec3::new(40.5, 2.5, 0.0), 0.3));
+        assert!(!index.touches(Vec3::new(20.0, 1.0, 0.0), Vec3::new(20.0, 2.5, 0.0), 0.3));
+        assert!(CollisionIndex::default().is_empty());
+    }
+
+    #[test]
+    fn test_outlines_lie_on_the_surface() {
+        let shapes = [
+            CollisionShape::Capsule {
+                start: Vec3::new(1.0, 0.0, 2.0),
+                end: Vec3::new(2.0, 5.0, 1.0),
+                radius: 0.5,
+            },
+            CollisionShape::Sphere {
+                centre: Vec3::new(0.0, 3.0, 0.0),
+                radius: 1.5,
+            },
+        ];
+        for shape in shapes {
+            let lines = shape.outline();
+            assert!(!lines.is_empty());
+            for (a, b) in lines {
+                assert!(shape.distance(a).abs() < 1e-4, "{a} is off {shape:?}");
+                assert!(shape.distance(b).abs() < 1e-4, "{b} is off {shape:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_segments_cross_at_their_closest() {
+        let d = distance_between_segments(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        );
+        assert!((d - 1.0).abs() < 1e-5);
+        // Parallel, and past the end of each other
+        let d = distance_between_segments(
+            Vec3::ZERO,
+            Vec3::Y,
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+        );
+        assert!((d - 2.0).abs() < 1e-5);
+    }
+}
@@ -1,4 +1,13 @@
-use crate::{camera::Camera, console::TimeOfDay, scene::Scene};
+use crate::{
+    camera::{Camera, Target},
+    caves::CaveKind,
+    console::{ClockAction, DebugSetting, TimeOfDay, WeatherSetting},
+    ores::Ore,
+    render_state::RenderState,
+    scene::Scene,
+    structures::StructureKind,
+    REACH_DISTANCE,
+};
 use glam::Vec3;
 
 pub(crate) fn execute_teleport(camera: &mut Camera, x: f32, y: f32, z: f32) -> String {
@@ -24,8 +33,86 @@ pub(crate) fn execute_time(scene: &mut Scene, time: Option<TimeOfDay>) -> String
             } else {
                 "evening"
             };
-            format!("Current time: {}", name)
+            let clock = scene.clock();
+            format!(
+                "Current time: {} (day {}, tick {}, {}{})",
+                name,
+                clock.day(),
+                clock.ticks(),
+                clock.season(),
+                if clock.is_paused() { ", paused" } else { "" }
+            )
+        }
+    }
+}
+
+pub(crate) fn execute_clock(scene: &mut Scene, action: ClockAction) -> String {
+    let clock = scene.clock_mut();
+    match action {
+        ClockAction::Set(ticks) => {
+            clock.set_ticks(ticks);
+            format!("Set clock to tick {}", clock.ticks())
+        }
+        ClockAction::Add(ticks) => {
+            clock.add_ticks(ticks);
+            format!("Moved clock to tick {}", clock.ticks())
+        }
+        ClockAction::Pause => {
+            clock.set_paused(true);
+            "Clock paused".to_string()
+        }
+        ClockAction::Resume => {
+            clock.set_paused(false);
+            "Clock resumed".to_string()
+        }
+        ClockAction::Speed(speed) => {
+            clock.set_speed(speed);
+            format!("Set clock speed to {}x", clock.speed())
+        }
+    }
+}
+
+pub(crate) fn execute_weather(scene: &mut Scene, setting: Option<WeatherSetting>) -> String {
+    let weather = scene.weather_mut();
+    match setting {
+        Some(WeatherSetting::Auto) => {
+            weather.force(None);
+            "Weather returned to automatic".to_string()
+        }
+        Some(WeatherSetting::Force(kind)) => {
+            weather.force(Some(kind));
+            format!("Set weather to {}", kind)
         }
+        None => match weather.forced() {
+            Some(kind) => format!("Current weather: {} (forced)", kind),
+            None => format!("Current weather: {}", weather.current()),
+        },
+    }
+}
+
+pub(crate) fn execute_debug(
+    state: &mut RenderState<'static>,
+    setting: Option<DebugSetting>,
+) -> String {
+    match setting {
+        Some(DebugSetting::View(view)) => {
+            state.set_debug_view(view);
+            format!("Set debug view to {}", view)
+        }
+        Some(DebugSetting::ChunkBorders) => {
+            let show = !state.show_chunk_borders();
+            state.set_show_chunk_borders(show);
+            format!("Chunk borders {}", if show { "on" } else { "off" })
+        }
+        None => format!(
+            "Debug view: {}, chunk borders {}",
+            state.debug_view(),
+            if state.show_chunk_borders() {
+                "on"
+            } else {
+                "off"
+            }
+        ),
     }
 }
 
@@ -34,22 +121,22 @@ pub(crate) fn execute_find_biome(scene: &mut Scene, camera: &Camera, biome_name:
     let start_pos = camera.position();
 
     if biome_name == "cave" {
-        let start_pos_2d = glam::Vec2::new(start_pos.x, start_pos.z);
-        match terrain.find_closest_cave(start_pos_2d) {
-            Some(point) => {
-                let dist = start_pos.distance(point);
-                return format!(
-                    "Found cave at {:.0} {:.0} {:.0} ({:.0} blocks away)",
-                    point.x, point.y, point.z, dist
-                );
-            }
-            None => return "Cave not found within search radius.".to_string(),
-        }
+        return execute_find_cave(scene, camera, None);
     }
 
     let target_biome = match crate::terrain::Biome::from_str(biome_name) {
         Some(b) => b,
-        None => return format!("Unknown biome: '{}'", biome_name),
+        None => {
+            let names: Vec<String> = crate::terrain::Biome::ALL
+                .iter()
+                .map(|b| b.to_string().to_lowercase())
+                .collect();
+            return format!(
+                "Unknown biome: '{}'. Expected one of: {}, cave",
+                biome_name,
+                names.join(", ")
+            );
+        }
     };
 
     let start_pos_2d = glam::Vec2::new(start_pos.x, start_pos.z);
@@ -68,13 +155,143 @@ pub(crate) fn execute_find_biome(scene: &mut Scene, camera: &Camera, biome_name:
     }
 }
 
+pub(crate) fn execute_find_cave(scene: &Scene, camera: &Camera, kind_name: Option<&str>) -> String {
+    let kind = match kind_name.map(CaveKind::from_str) {
+        None => None,
+        Some(Some(k)) => Some(k),
+        Some(None) => {
+            let names: Vec<String> = CaveKind::ALL
+                .iter()
+                .map(|k| k.to_string().to_lowercase())
+                .collect();
+            return format!(
+                "Unknown cave: '{}'. Expected one of: {}",
+                kind_name.unwrap_or_default(),
+                names.join(", ")
+            );
+        }
+    };
+
+    let start_pos = camera.position();
+    let start_pos_2d = glam::Vec2::new(start_pos.x, start_pos.z);
+    let name = kind.map_or("cave".to_string(), |k| k.to_string().to_lowercase());
+    match scene
+        .chunks()
+        .terrain()
+        .find_closest_cave(start_pos_2d, kind)
+    {
+        Some(point) => format!(
+            "Found {} at {:.0} {:.0} {:.0} ({:.0} blocks away)",
+            name,
+            point.x,
+            point.y,
+            point.z,
+            start_pos.distance(point)
+        ),
+        None => format!("No {} found within search radius.", name),
+    }
+}
+
+pub(crate) fn execute_find_ore(scene: &Scene, camera: &Camera, ore_name: &str) -> String {
+    let ore = match Ore::from_str(ore_name) {
+        Some(o) => o,
+        None => {
+            let names: Vec<String> = Ore::ALL
+                .iter()
+                .map(|o| o.to_string().to_lowercase())
+                .collect();
+            return format!(
+                "Unknown ore: '{}'. Expected one of: {}",
+                ore_name,
+                names.join(", ")
+            );
+        }
+    };
+
+    let name = ore.to_string().to_lowercase();
+    let start_pos = camera.position();
+    match scene.chunks().terrain().find_closest_ore(start_pos, ore) {
+        Some(point) => format!(
+            "Found {} vein at {:.0} {:.0} {:.0} ({:.0} blocks away)",
+            name,
+            point.x,
+            point.y,
+            point.z,
+            start_pos.distance(point)
+        ),
+        None => format!("No {} found within search radius.", name),
+    }
+}
+
+pub(crate) fn execute_find_structure(scene: &Scene, camera: &Camera, kind_name: &str) -> String {
+    let kind = match StructureKind::from_str(kind_name) {
+        Some(k) => k,
+        None => {
+            let names: Vec<String> = StructureKind::ALL
+                .iter()
+                .map(|k| k.to_string().to_lowercase())
+                .collect();
+            return format!(
+                "Unknown structure: '{}'. Expected one of: {}",
+                kind_name,
+                names.join(", ")
+            );
+        }
+    };
+
+    let start_pos = camera.position();
+    let start_pos_2d = glam::Vec2::new(start_pos.x, start_pos.z);
+    match scene
+        .chunks()
+        .terrain()
+        .find_closest_structure(start_pos_2d, kind)
+    {
+        Some(point) => format!(
+            "Found {} at {:.0} {:.0} {:.0} ({:.0} blocks away)",
+            kind_name,
+            point.x,
+            point.y,
+            point.z,
+            start_pos_2d.distance(glam::Vec2::new(point.x, point.z))
+        ),
+        None => format!("No {} found within search radius.", kind_name),
+    }
+}
+
+pub(crate) fn execute_plant(scene: &mut Scene, camera: &Camera, species_name: &str) -> String {
+    let Some(id) = scene.entity_manager().species().find(species_name) else {
+        return format!("Unknown tree species: '{}'", species_name);
+    };
+
+    match camera.raycast(scene.chunks(), scene.entity_manager(), REACH_DISTANCE) {
+        Some(Target::Block(hit_pos, normal)) if normal == glam::IVec3::Y => {
+            let position = hit_pos.as_vec3() + Vec3::new(0.5, 1.0, 0.5);
+            scene.entity_manager_mut().plant(position, id);
+            format!(
+                "Planted a {} sapling at {:.0} {:.0} {:.0}",
+                scene.entity_manager().species().get(id).name,
+                position.x,
+                position.y,
+                position.z
+            )
+        }
+        _ => "Look at the top of a nearby block to plant a sapling there.".to_string(),
+    }
+}
+
 pub(crate) fn execute_help(command: Option<String>) -> String {
     match command.as_deref() {
-        None => "Available commands: help, tp/teleport, time, fb/find_biome".to_string(),
+        None => "Available commands: help, tp/teleport, time, w/weather, debug, fb/find_biome, fc/find_cave, fo/find_ore, fs/find_structure, plant".to_string(),
         Some("help") => "help [command] - Lists all available commands, or provides help for a specific command.".to_string(),
         Some("tp") | Some("teleport") => "teleport <x> <y> <z> - Teleports the player to the specified coordinates.".to_string(),
-        Some("time") => "time [time_of_day] - Sets the time (morning, day, evening, night). If empty, prints current time.".to_string(),
+        Some("t") | Some("time") => "time [time_of_day] - Sets the time (morning, day, evening, night). If empty, prints current time, day and season. Also: time set <ticks>, time add <ticks>, time pause, time resume, time speed <x>. A day is 24000 ticks.".to_string(),
+        Some("w") | Some("weather") => "weather [clear|rain|snow|storm|auto] - Forces the weather everywhere, or 'auto' to let it change by region. If empty, prints current weather.".to_string(),
+        Some("debug") => "debug [off|normals|ao|biome|water|shadows|chunks] - Switches the debug view. 'chunks' toggles chunk borders on top of any view. If empty, prints the current view.".to_string(),
         Some("fb") | Some("find_biome") => "find_biome <biome> - Finds the nearest chunk of the specified biome (e.g. desert, plains) or 'cave'.".to_string(),
+        Some("fc") | Some("find_cave") => "find_cave [kind] - Finds the nearest cave of the specified kind (tunnel, cavern, lake, lava or ravine), or of any kind.".to_string(),
+        Some("fo") | Some("find_ore") => "find_ore <ore> - Finds the nearest vein of the specified ore (coal, iron, copper, gold or crystal).".to_string(),
+        Some("fs") | Some("find_structure") => "find_structure <kind> - Finds the nearest structure of the specified kind (ruin, village or dungeon).".to_string(),
+        Some("plant") => "plant <species> - Plants a sapling of the specified tree species (e.g. oak, palm) on the block you're looking at. It grows as the days pass.".to_string(),
         Some(cmd) => format!("Unknown command for help: {}", cmd),
     }
 }
@@ -1,3 +1,5 @@
+use crate::block;
+use crate::ores::{default_ores, OreSpec};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -10,10 +12,219 @@ pub struct CameraConfig {
     pub pitch: f32,
 }
 
+/// Describes the planet a world sits on, which decides how the sun, moon and
+/// stars move across the sky.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CelestialConfig {
+    /// Real seconds for a full day and night.
+    pub day_length_secs: f32,
+    pub year_length_days: f32,
+    pub lunar_cycle_days: f32,
+    /// Degrees north of the equator.
+    pub latitude: f32,
+    /// Degrees the planet is tilted, which sets how much the seasons change.
+    pub axial_tilt: f32,
+}
+
+impl Default for CelestialConfig {
+    fn default() -> Self {
+        Self {
+            day_length_secs: 300.0,
+            year_length_days: 32.0,
+            lunar_cycle_days: 8.0,
+            latitude: 40.0,
+            axial_tilt: 23.44,
+        }
+    }
+}
+
+/// The saved state of a world's clock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub ticks: u64,
+    pub paused: bool,
+    pub speed: f32,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            // New worlds start mid-morning on the first day of spring.
+            ticks: 9000,
+            paused: false,
+            speed: 1.0,
+        }
+    }
+}
+
+/// A tree the player planted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaplingConfig {
+    pub species: String,
+    pub position: [f32; 3],
+    /// The day it was planted, counted like the clock's days.
+    pub planted: f64,
+}
+
+/// One band of a superflat world, counted up from the bottom of the world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlatLayer {
+    pub block: block::Type,
+    pub depth: u32,
+}
+
+fn default_flat_layers() -> Vec<FlatLayer> {
+    vec![
+        FlatLayer {
+            block: block::Type::Rock,
+            depth: 28,
+        },
+        FlatLayer {
+            block: block::Type::Sand,
+            depth: 3,
+        },
+        FlatLayer {
+            block: block::Type::Grass,
+            depth: 1,
+        },
+    ]
+}
+
+/// Where a heightmap world's image comes from and how it's laid over the
+/// world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeightmapConfig {
+    /// A grayscale PNG, ideally 16-bit, where white is highest.
+    pub path: String,
+    /// A PNG painted in the biome debug colours, the same size as the
+    /// heightmap.
+    #[serde(default)]
+    pub biome_map: Option<String>,
+    /// Blocks covered by each pixel.
+    #[serde(default = "default_pixel_size")]
+    pub pixel_size: f32,
+    /// Height in blocks of a white pixel.
+    #[serde(default = "default_height_scale")]
+    pub height_scale: f32,
+    #[serde(default = "default_sea_level")]
+    pub sea_level: f32,
+    /// Where the image's top left corner sits in the world.
+    #[serde(default = "default_heightmap_origin")]
+    pub origin: [f32; 2],
+}
+
+fn default_pixel_size() -> f32 {
+    1.0
+}
+
+fn default_height_scale() -> f32 {
+    128.0
+}
+
+fn default_sea_level() -> f32 {
+    crate::terrain::WATER_LEVEL
+}
+
+fn default_heightmap_origin() -> [f32; 2] {
+    // Around where new players spawn.
+    [2000.0, 2000.0]
+}
+
+/// The overall shape of a world, chosen when it is created.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldPreset {
+    /// Continents and oceans.
+    #[default]
+    Default,
+    /// Flat layers of blocks with nothing else, for testing builds.
+    Superflat {
+        #[serde(default = "default_flat_layers")]
+        layers: Vec<FlatLayer>,
+    },
+    /// Land stretched upwards into towering hills and mountains.
+    Amplified,
+    /// A single island in an endless sea around the spawn area.
+    Island,
+    /// Scattered small islands.
+    Archipelago,
+    /// Heights read from an image, with the usual terrain around it.
+    Heightmap(HeightmapConfig),
+}
+
+/// Overrides for the noise that shapes the world. Anything left out keeps
+/// the preset's value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    pub continent_frequency: Option<f64>,
+    pub continent_octaves: Option<usize>,
+    /// Shared by the temperature and moisture noise.
+    pub climate_frequency: Option<f64>,
+    pub climate_octaves: Option<usize>,
+    pub sea_level: Option<f32>,
+    /// Blocks per unit of noise, so larger values spread everything out.
+    pub world_scale: Option<f64>,
+    /// How much land above the sea is stretched upwards.
+    pub height_scale: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorldConfig {
     pub seed: Option<u32>,
     pub camera: Option<CameraConfig>,
+    #[serde(default)]
+    pub celestial: CelestialConfig,
+    #[serde(default)]
+    pub clock: ClockConfig,
+    /// How strongly mountains and hills are worn down by running water and
+    /// rockfall, where 0 turns erosion off.
+    #[serde(default)]
+    pub erosion: f32,
+    /// How much 3D noise breaks mountains and hills into overhangs, arches
+    /// and cliffs, where 0 keeps the plain heightmap. Coasts and ground under
+    /// water are never carved.
+    #[serde(default)]
+    pub overhangs: f32,
+    #[serde(default)]
+    pub preset: WorldPreset,
+    #[serde(default)]
+    pub noise: NoiseConfig,
+    /// The ores that form underground, with their depths, how often they
+    /// form and which biomes have more of them.
+    #[serde(default = "default_ores")]
+    pub ores: Vec<OreSpec>,
+    #[serde(default)]
+    pub saplings: Vec<SaplingConfig>,
+    /// Whether wild trees are built out of log and leaf blocks that can be
+    /// climbed and cut down, rather than drawn over the world. This is
+    /// settled once the world has saved chunks, since they keep the trees they
+    /// were made with, so changing it after that does nothing.
+    #[serde(default)]
+    pub voxel_trees: bool,
+}
+
+impl WorldConfig {
+    /// A world being created now, with erosion and overhangs on. Worlds
+    /// loaded from before they existed get them off instead, from the serde
+    /// defaults.
+    pub fn new_world(seed: Option<u32>) -> Self {
+        Self {
+            seed,
+            camera: None,
+            celestial: CelestialConfig::default(),
+            clock: ClockConfig::default(),
+            erosion: 1.0,
+            overhangs: 1.0,
+            preset: WorldPreset::default(),
+            noise: NoiseConfig::default(),
+            ores: default_ores(),
+            saplings: Vec::new(),
+            voxel_trees: false,
+        }
+    }
 }
 
 fn default_sim_rate_ms() -> u64 {
@@ -24,6 +235,22 @@ fn default_max_remesh_per_frame() -> usize {
     4
 }
 
+fn default_species_dir() -> String {
+    "species".to_string()
+}
+
+fn default_decoration_distance() -> f32 {
+    48.0
+}
+
+fn default_tree_detail_distance() -> f32 {
+    64.0
+}
+
+fn default_tree_impostor_distance() -> f32 {
+    128.0
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub chunk_load_radius: u32,
@@ -37,6 +264,19 @@ pub struct Config {
     pub sim_rate_ms: u64,
     #[serde(default = "default_max_remesh_per_frame")]
     pub max_remesh_per_frame: usize,
+    /// Directory of tree species grammar files, on top of the built-in ones.
+    #[serde(default = "default_species_dir")]
+    pub species_dir: String,
+    /// How far away grass, flowers and other ground cover are drawn.
+    #[serde(default = "default_decoration_distance")]
+    pub decoration_distance: f32,
+    /// How far away trees are drawn with every branch. Past this they're
+    /// drawn with fewer.
+    #[serde(default = "default_tree_detail_distance")]
+    pub tree_detail_distance: f32,
+    /// How far away trees give way to flat pictures of themselves.
+    #[serde(default = "default_tree_impostor_distance")]
+    pub tree_impostor_distance: f32,
     #[serde(default)]
     pub worlds: HashMap<String, WorldConfig>,
 }
@@ -44,13 +284,7 @@ pub struct Config {
 impl Default for Config {
     fn default() -> Self {
         let mut worlds = HashMap::new();
-        worlds.insert(
-            "funky_town".to_string(),
-            WorldConfig {
-                seed: None,
-                camera: None,
-            },
-        );
+        worlds.insert("funky_town".to_string(), WorldConfig::new_world(None));
 
         Self {
             chunk_load_radius: 3,
@@ -62,6 +296,10 @@ impl Default for Config {
             active_world: "funky_town".to_string(),
             sim_rate_ms: default_sim_rate_ms(),
             max_remesh_per_frame: default_max_remesh_per_frame(),
+            species_dir: default_species_dir(),
+            decoration_distance: default_decoration_distance(),
+            tree_detail_distance: default_tree_detail_distance(),
+            tree_impostor_distance: default_tree_impostor_distance(),
             worlds,
         }
     }
@@ -89,10 +327,7 @@ impl Config {
             .entry(config.active_world.clone())
             .or_insert_with(|| {
                 needs_save = true;
-                WorldConfig {
-                    seed: None,
-                    camera: None,
-                }
+                WorldConfig::new_world(None)
             });
 
         if world_config.seed.is_none() {
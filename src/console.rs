@@ -1,3 +1,6 @@
+use crate::debug::DebugView;
+use crate::weather::WeatherKind;
+
 pub const PROMPT_GLYPH: &str = "~ ";
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -46,11 +49,63 @@ impl std::fmt::Display for TimeOfDay {
         write!(f, "{}", s)
     }
 }
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum WeatherSetting {
+    /// Hand control back to the regional weather simulation.
+    Auto,
+    Force(WeatherKind),
+}
+
+impl std::str::FromStr for WeatherSetting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(WeatherSetting::Auto),
+            _ => s.parse::<WeatherKind>().map(WeatherSetting::Force),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum DebugSetting {
+    View(DebugView),
+    /// Toggle the chunk border lines, which can be shown alongside any view.
+    ChunkBorders,
+}
+
+impl std::str::FromStr for DebugSetting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunks" | "borders" => Ok(DebugSetting::ChunkBorders),
+            _ => s.parse::<DebugView>().map(DebugSetting::View),
+        }
+    }
+}
+
+/// Direct control of the world clock, in ticks.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum ClockAction {
+    Set(u64),
+    Add(i64),
+    Pause,
+    Resume,
+    Speed(f32),
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum Command {
     Teleport(f32, f32, f32),
     Time(Option<TimeOfDay>),
+    Clock(ClockAction),
+    Weather(Option<WeatherSetting>),
+    Debug(Option<DebugSetting>),
     FindBiome(String),
+    FindCave(Option<String>),
+    FindOre(String),
+    FindStructure(String),
+    Plant(String),
     Help(Option<String>),
     Unknown(String),
     Error(String),
@@ -151,6 +206,38 @@ impl Console {
                 Command::Error("Invalid usage of teleport. Usage: teleport <x> <y> <z>".to_string())
             }
             "t" | "time" => {
+                match (parts.get(1).copied(), parts.len()) {
+                    (Some("set"), 3) => {
+                        return match parts[2].parse::<u64>() {
+                            Ok(ticks) => Command::Clock(ClockAction::Set(ticks)),
+                            Err(_) => Command::Error(format!(
+                                "Invalid ticks '{}'. Expected a whole number.",
+                                parts[2]
+                            )),
+                        }
+                    }
+                    (Some("add"), 3) => {
+                        return match parts[2].parse::<i64>() {
+                            Ok(ticks) => Command::Clock(ClockAction::Add(ticks)),
+                            Err(_) => Command::Error(format!(
+                                "Invalid ticks '{}'. Expected a whole number.",
+                                parts[2]
+                            )),
+                        }
+                    }
+                    (Some("speed"), 3) => {
+                        return match parts[2].parse::<f32>() {
+                            Ok(speed) if speed >= 0.0 => Command::Clock(ClockAction::Speed(speed)),
+                            _ => Command::Error(format!(
+                                "Invalid speed '{}'. Expected a positive number.",
+                                parts[2]
+                            )),
+                        }
+                    }
+                    (Some("pause"), 2) => return Command::Clock(ClockAction::Pause),
+                    (Some("resume"), 2) => return Command::Clock(ClockAction::Resume),
+                    _ => {}
+                }
                 if parts.len() == 2 {
                     match parts[1].parse::<TimeOfDay>() {
                         Ok(t_val) => return Command::Time(Some(t_val)),
@@ -160,7 +247,35 @@ impl Console {
                     return Command::Time(None);
                 }
                 Command::Error(
-                    "Invalid usage of time. Usage: time [morning|day|evening|night]".to_string(),
+                    "Invalid usage of time. Usage: time [morning|day|evening|night|set <ticks>|add <ticks>|pause|resume|speed <x>]".to_string(),
+                )
+            }
+            "w" | "weather" => {
+                if parts.len() == 2 {
+                    match parts[1].parse::<WeatherSetting>() {
+                        Ok(w) => return Command::Weather(Some(w)),
+                        Err(e) => return Command::Error(e),
+                    }
+                } else if parts.len() == 1 {
+                    return Command::Weather(None);
+                }
+                Command::Error(
+                    "Invalid usage of weather. Usage: weather [clear|rain|snow|storm|auto]"
+                        .to_string(),
+                )
+            }
+            "debug" => {
+                if parts.len() == 2 {
+                    match parts[1].parse::<DebugSetting>() {
+                        Ok(d) => return Command::Debug(Some(d)),
+                        Err(e) => return Command::Error(e),
+                    }
+                } else if parts.len() == 1 {
+                    return Command::Debug(None);
+                }
+                Command::Error(
+                    "Invalid usage of debug. Usage: debug [off|normals|ao|biome|water|shadows|chunks]"
+                        .to_string(),
                 )
             }
             "fb" | "find_biome" => {
@@ -169,6 +284,33 @@ impl Console {
                 }
                 Command::Error("Invalid usage of find_biome. Usage: find_biome <biome>".to_string())
             }
+            "fc" | "find_cave" => match parts.len() {
+                1 => Command::FindCave(None),
+                2 => Command::FindCave(Some(parts[1].to_string())),
+                _ => Command::Error(
+                    "Invalid usage of find_cave. Usage: find_cave [kind]".to_string(),
+                ),
+            },
+            "fo" | "find_ore" => {
+                if parts.len() == 2 {
+                    return Command::FindOre(parts[1].to_string());
+                }
+                Command::Error("Invalid usage of find_ore. Usage: find_ore <ore>".to_string())
+            }
+            "fs" | "find_structure" => {
+                if parts.len() == 2 {
+                    return Command::FindStructure(parts[1].to_string());
+                }
+                Command::Error(
+                    "Invalid usage of find_structure. Usage: find_structure <kind>".to_string(),
+                )
+            }
+            "plant" => {
+                if parts.len() == 2 {
+                    return Command::Plant(parts[1].to_string());
+                }
+                Command::Error("Invalid usage of plant. Usage: plant <species>".to_string())
+            }
             "help" => {
                 if parts.len() == 2 {
                     return Command::Help(Some(parts[1].to_string()));
@@ -233,6 +375,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(
+            Console::parse_command("time set 12000"),
+            Command::Clock(ClockAction::Set(12000))
+        );
+        assert_eq!(
+            Console::parse_command("t add -500"),
+            Command::Clock(ClockAction::Add(-500))
+        );
+        assert_eq!(
+            Console::parse_command("time pause"),
+            Command::Clock(ClockAction::Pause)
+        );
+        assert_eq!(
+            Console::parse_command("time resume"),
+            Command::Clock(ClockAction::Resume)
+        );
+        assert_eq!(
+            Console::parse_command("time speed 2.5"),
+            Command::Clock(ClockAction::Speed(2.5))
+        );
+        assert_eq!(
+            Console::parse_command("time set noon"),
+            Command::Error("Invalid ticks 'noon'. Expected a whole number.".to_string())
+        );
+        assert_eq!(
+            Console::parse_command("time speed -1"),
+            Command::Error("Invalid speed '-1'. Expected a positive number.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_weather() {
+        assert_eq!(Console::parse_command("weather"), Command::Weather(None));
+        assert_eq!(
+            Console::parse_command("w rain"),
+            Command::Weather(Some(WeatherSetting::Force(WeatherKind::Rain)))
+        );
+        assert_eq!(
+            Console::parse_command("weather storm"),
+            Command::Weather(Some(WeatherSetting::Force(WeatherKind::Thunderstorm)))
+        );
+        assert_eq!(
+            Console::parse_command("weather auto"),
+            Command::Weather(Some(WeatherSetting::Auto))
+        );
+        assert_eq!(
+            Console::parse_command("weather hail"),
+            Command::Error(
+                "Invalid weather 'hail'. Expected: clear, rain, snow, storm or auto.".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_debug() {
+        assert_eq!(Console::parse_command("debug"), Command::Debug(None));
+        assert_eq!(
+            Console::parse_command("debug normals"),
+            Command::Debug(Some(DebugSetting::View(DebugView::Normals)))
+        );
+        assert_eq!(
+            Console::parse_command("debug chunks"),
+            Command::Debug(Some(DebugSetting::ChunkBorders))
+        );
+        assert_eq!(
+            Console::parse_command("debug light"),
+            Command::Error(
+                "Invalid debug view 'light'. Expected: off, normals, ao, biome, water, shadows or chunks."
+                    .to_string()
+            )
+        );
+    }
+
     #[test]
     fn test_parse_find_biome() {
         assert_eq!(
@@ -245,6 +462,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_find_cave() {
+        assert_eq!(Console::parse_command("fc"), Command::FindCave(None));
+        assert_eq!(
+            Console::parse_command("find_cave lava"),
+            Command::FindCave(Some("lava".to_string()))
+        );
+        assert_eq!(
+            Console::parse_command("fc lava lake"),
+            Command::Error("Invalid usage of find_cave. Usage: find_cave [kind]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_find_ore() {
+        assert_eq!(
+            Console::parse_command("fo gold"),
+            Command::FindOre("gold".to_string())
+        );
+        assert_eq!(
+            Console::parse_command("find_ore"),
+            Command::Error("Invalid usage of find_ore. Usage: find_ore <ore>".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_find_structure() {
+        assert_eq!(
+            Console::parse_command("fs village"),
+            Command::FindStructure("village".to_string())
+        );
+        assert_eq!(
+            Console::parse_command("find_structure dungeon"),
+            Command::FindStructure("dungeon".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_plant() {
+        assert_eq!(
+            Console::parse_command("plant oak"),
+            Command::Plant("oak".to_string())
+        );
+        assert_eq!(
+            Console::parse_command("plant"),
+            Command::Error("Invalid usage of plant. Usage: plant <species>".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_help() {
         assert_eq!(Console::parse_command("help"), Command::Help(None));
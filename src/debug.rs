@@ -0,0 +1,187 @@
+use glam::{IVec2, Vec3};
+
+use crate::terrain::Biome;
+use crate::vertex::Vertex;
+
+/// Alternative ways of shading the world to help track down terrain, mesh
+/// and lighting bugs.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DebugView {
+    Off,
+    /// Face normals mapped to RGB.
+    Normals,
+    /// Ambient occlusion only, without any lighting or colour.
+    Ao,
+    /// Blocks tinted by the biome of their column.
+    Biome,
+    /// Water coloured by its level, with source blocks highlighted.
+    Water,
+    /// Normal shading with the sun and moon shadow maps shown on screen.
+    Shadows,
+}
+
+impl DebugView {
+    /// The value passed to the shaders to select this view.
+    pub fn shader_mode(self) -> u32 {
+        match self {
+            DebugView::Off | DebugView::Shadows => 0,
+            DebugView::Normals => 1,
+            DebugView::Ao => 2,
+            DebugView::Biome | DebugView::Water => 3,
+        }
+    }
+
+    /// Whether chunk meshes must be rebuilt to switch to or from this view.
+    pub fn affects_mesh(self) -> bool {
+        matches!(self, DebugView::Biome | DebugView::Water)
+    }
+}
+
+impl std::str::FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "none" => Ok(DebugView::Off),
+            "normals" => Ok(DebugView::Normals),
+            "ao" => Ok(DebugView::Ao),
+            "biome" => Ok(DebugView::Biome),
+            "water" => Ok(DebugView::Water),
+            "shadow" | "shadows" => Ok(DebugView::Shadows),
+            _ => Err(format!(
+                "Invalid debug view '{}'. Expected: off, normals, ao, biome, water, shadows or chunks.",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DebugView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DebugView::Off => "off",
+            DebugView::Normals => "normals",
+            DebugView::Ao => "ao",
+            DebugView::Biome => "biome",
+            DebugView::Water => "water",
+            DebugView::Shadows => "shadows",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+pub fn biome_color(biome: Biome) -> [u8; 4] {
+    match biome {
+        Biome::Ocean => [30, 60, 200, 255],
+        Biome::Plains => [90, 200, 60, 255],
+        Biome::Hills => [30, 120, 40, 255],
+        Biome::Mountains => [150, 150, 160, 255],
+        Biome::Desert => [230, 200, 80, 255],
+        Biome::Tundra => [220, 230, 240, 255],
+        Biome::Taiga => [40, 90, 70, 255],
+        Biome::Jungle => [10, 160, 20, 255],
+        Biome::Swamp => [80, 90, 40, 255],
+        Biome::Savanna => [200, 170, 60, 255],
+        Biome::Beach => [250, 230, 160, 255],
+    }
+}
+
+/// Colours water from dark blue when nearly drained to cyan when full, with
+/// source blocks in magenta.
+pub fn water_color(level: u8, is_source: bool) -> [u8; 4] {
+    if is_source {
+        return [220, 40, 220, 200];
+    }
+    let t = level.min(8) as f32 / 8.0;
+    [20, (40.0 + 200.0 * t) as u8, (120.0 + 135.0 * t) as u8, 200]
+}
+
+/// Everything that isn't water is drawn in grey in the water view so the
+/// water stands out.
+pub fn grey(color: [u8; 4]) -> [u8; 4] {
+    let l = (color[0] as f32 * 0.299 + color[1] as f32 * 0.587 + color[2] as f32 * 0.114) * 0.6;
+    [l as u8, l as u8, l as u8, color[3]]
+}
+
+const SECTION_COLOR: [u8; 4] = [255, 220, 0, 255];
+const COLUMN_COLOR: [u8; 4] = [255, 60, 60, 255];
+const NEIGHBOUR_COLOR: [u8; 4] = [60, 120, 255, 255];
+
+/// Builds a line list outlining the chunk column the player is in, with a
+/// ring at every section boundary, and the corners of the neighbouring
+/// columns.
+pub fn chunk_border_lines(player_position: Vec3, height: f32) -> Vec<Vertex> {
+    let column = IVec2::new(
+        (player_position.x.floor() as i32).div_euclid(16),
+        (player_position.z.floor() as i32).div_euclid(16),
+    );
+    let mut lines = Vec::new();
+    let mut line = |a: Vec3, b: Vec3, color: [u8; 4]| {
+        lines.push(Vertex::new(a.into(), 0, color, [0, 127, 0, 127]));
+        lines.push(Vertex::new(b.into(), 0, color, [0, 127, 0, 127]));
+    };
+
+    for dx in -1..=2 {
+        for dz in -1..=2 {
+            let x = ((column.x + dx) * 16) as f32;
+            let z = ((column.y + dz) * 16) as f32;
+            let is_own = (0..=1).contains(&dx) && (0..=1).contains(&dz);
+            let color = if is_own {
+                COLUMN_COLOR
+            } else {
+                NEIGHBOUR_COLOR
+            };
+            line(Vec3::new(x, 0.0, z), Vec3::new(x, height, z), color);
+        }
+    }
+
+    let min = Vec3::new((column.x * 16) as f32, 0.0, (column.y * 16) as f32);
+    let mut y = 0.0;
+    while y <= height {
+        let corners = [
+            Vec3::new(min.x, y, min.z),
+            Vec3::new(min.x + 16.0, y, min.z),
+            Vec3::new(min.x + 16.0, y, min.z + 16.0),
+            Vec3::new(min.x, y, min.z + 16.0),
+        ];
+        for i in 0..4 {
+            line(corners[i], corners[(i + 1) % 4], SECTION_COLOR);
+        }
+        y += 16.0;
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_debug_view() {
+        assert_eq!("ao".parse::<DebugView>(), Ok(DebugView::Ao));
+        assert_eq!("shadow".parse::<DebugView>(), Ok(DebugView::Shadows));
+        assert_eq!("none".parse::<DebugView>(), Ok(DebugView::Off));
+        assert!("lighting".parse::<DebugView>().is_err());
+    }
+
+    #[test]
+    fn test_chunk_border_lines_surround_player() {
+        let player = Vec3::new(-3.5, 70.0, 20.0);
+        let lines = chunk_border_lines(player, 256.0);
+        assert_eq!(lines.len() % 2, 0);
+
+        let own: Vec<[f32; 3]> = lines
+            .iter()
+            .filter(|v| v.color() == COLUMN_COLOR)
+            .map(|v| v.position())
+            .collect();
+        // Column -1, 1 runs from x -16..0 and z 16..32
+        assert!(own.iter().all(|p| p[0] == -16.0 || p[0] == 0.0));
+        assert!(own.iter().all(|p| p[2] == 16.0 || p[2] == 32.0));
+
+        // 17 section rings of 4 lines each
+        let rings = lines.iter().filter(|v| v.color() == SECTION_COLOR).count();
+        assert_eq!(rings, 17 * 4 * 2);
+    }
+}
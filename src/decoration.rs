@@ -0,0 +1,624 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, UVec2, Vec2, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::block;
+use crate::chunks::Chunk;
+use crate::generator::TerrainGenerator;
+use crate::terrain::{Biome, TerrainData};
+use crate::vertex::SimpleVertex;
+
+/// Decorations are drawn in full out to this fraction of the decoration
+/// distance, then shrink into the ground.
+pub const FADE_START: f32 = 0.75;
+
+/// Reeds grow on ground at most this far above the water beside it.
+const REED_SHORE: f32 = 2.0;
+/// How far from a column to look for water for reeds.
+const REED_REACH: f32 = 3.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum DecorationKind {
+    Grass,
+    /// The stalk of a flower.
+    Stem,
+    /// The head of a flower, sitting on top of a stem.
+    Blossom,
+    Reed,
+    Rock,
+}
+
+impl DecorationKind {
+    pub(crate) const ALL: [DecorationKind; 5] = [
+        DecorationKind::Grass,
+        DecorationKind::Stem,
+        DecorationKind::Blossom,
+        DecorationKind::Reed,
+        DecorationKind::Rock,
+    ];
+
+    /// Picks the texture noise the block shader gives it.
+    fn material(self) -> block::Type {
+        match self {
+            DecorationKind::Grass | DecorationKind::Stem | DecorationKind::Reed => {
+                block::Type::Grass
+            }
+            DecorationKind::Blossom => block::Type::Inactive,
+            DecorationKind::Rock => block::Type::Rock,
+        }
+    }
+
+    /// Whether it can stand on a block of `ground`.
+    fn grows_on(self, ground: block::Type) -> bool {
+        use block::Type::*;
+        match self {
+            DecorationKind::Grass | DecorationKind::Stem | DecorationKind::Blossom => {
+                matches!(ground, Grass | DryGrass | Mud)
+            }
+            DecorationKind::Reed => matches!(ground, Grass | DryGrass | Mud | Sand),
+            DecorationKind::Rock => !matches!(ground, Inactive | Water | Lava | Ice | Snow),
+        }
+    }
+}
+
+/// One decoration as the GPU draws it: a copy of its kind's mesh, turned,
+/// scaled and tinted.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct DecorationInstance {
+    position: [f32; 3],
+    yaw: f32,
+    scale: f32,
+    material: u32,
+    color: [u8; 4],
+}
+
+impl DecorationInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        2 => Float32x3,
+        3 => Float32,
+        4 => Float32,
+        5 => Uint32,
+        6 => Unorm8x4
+    ];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DecorationInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Decoration {
+    pub(crate) kind: DecorationKind,
+    instance: DecorationInstance,
+}
+
+impl Decoration {
+    fn new(kind: DecorationKind, position: Vec3, yaw: f32, scale: f32, color: [u8; 3]) -> Self {
+        let [r, g, b] = color;
+        Self {
+            kind,
+            instance: DecorationInstance {
+                position: position.into(),
+                yaw,
+                scale,
+                material: kind.material() as u32,
+                color: [r, g, b, 255],
+            },
+        }
+    }
+
+    pub(crate) fn position(&self) -> Vec3 {
+        Vec3::from(self.instance.position)
+    }
+
+    /// The block it takes up, which has to stay empty for it to be drawn.
+    fn cell(&self) -> IVec3 {
+        // Nudged up so a decoration resting exactly on a block's top face
+        // counts as standing in the block above.
+        (self.position() + Vec3::Y * 0.01).floor().as_ivec3()
+    }
+}
+
+/// How thickly a biome is covered, and in what.
+struct Cover {
+    /// Chance of a grass tuft in each column, before moisture is counted.
+    grass: f32,
+    grass_color: [u8; 3],
+    flowers: &'static [[u8; 3]],
+    /// Chance of a flower in each column.
+    flower: f32,
+    /// Chance of a boulder in each column.
+    rock: f32,
+}
+
+const RED: [u8; 3] = [200, 40, 40];
+const YELLOW: [u8; 3] = [235, 200, 40];
+const WHITE: [u8; 3] = [235, 235, 225];
+const BLUE: [u8; 3] = [70, 90, 210];
+const PURPLE: [u8; 3] = [140, 60, 180];
+const MAGENTA: [u8; 3] = [210, 50, 150];
+const ORANGE: [u8; 3] = [235, 120, 30];
+
+const LUSH_GRASS: [u8; 3] = [50, 130, 40];
+const GRASS: [u8; 3] = [80, 150, 50];
+const DRY_GRASS: [u8; 3] = [170, 160, 70];
+const STEM: [u8; 3] = [60, 120, 40];
+const REED: [u8; 3] = [120, 140, 60];
+const ROCK: [u8; 3] = [110, 110, 105];
+
+fn cover(biome: Biome) -> Cover {
+    let (grass, grass_color, flowers, flower, rock): (_, _, &'static [[u8; 3]], _, _) = match biome
+    {
+        Biome::Ocean | Biome::Beach => (0.0, GRASS, &[], 0.0, 0.01),
+        Biome::Plains => (0.6, GRASS, &[RED, YELLOW, WHITE, BLUE], 0.05, 0.005),
+        Biome::Hills => (0.5, GRASS, &[YELLOW, WHITE, PURPLE], 0.03, 0.02),
+        Biome::Mountains => (0.3, GRASS, &[WHITE, BLUE], 0.02, 0.05),
+        Biome::Desert => (0.0, DRY_GRASS, &[], 0.0, 0.02),
+        Biome::Tundra => (0.2, DRY_GRASS, &[WHITE], 0.01, 0.03),
+        Biome::Taiga => (0.3, GRASS, &[BLUE, PURPLE], 0.02, 0.02),
+        Biome::Jungle => (0.9, LUSH_GRASS, &[MAGENTA, ORANGE, RED], 0.06, 0.005),
+        Biome::Swamp => (0.7, LUSH_GRASS, &[WHITE, PURPLE], 0.03, 0.0),
+        Biome::Savanna => (0.5, DRY_GRASS, &[YELLOW, ORANGE], 0.02, 0.01),
+    };
+    Cover {
+        grass,
+        grass_color,
+        flowers,
+        flower,
+        rock,
+    }
+}
+
+fn jitter(color: [u8; 3], rng: &mut StdRng, amount: i32) -> [u8; 3] {
+    let shift = rng.gen_range(-amount..=amount);
+    color.map(|c| (c as i32 + shift + rng.gen_range(-4..=4)).clamp(0, 255) as u8)
+}
+
+/// Whether there's open water within reach of a column on the shore.
+fn near_water(point: Vec2, tdata: &TerrainData, terrain: &dyn TerrainGenerator) -> bool {
+    if tdata.height - tdata.water_level > REED_SHORE {
+        return false;
+    }
+    [Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y]
+        .iter()
+        .any(|dir| terrain.get(point + *dir * REED_REACH).is_water())
+}
+
+/// Scatters grass, flowers, reeds and boulders over the chunk column at
+/// `key`. Where each one lands only depends on the seed and its column.
+pub(crate) fn decorate_chunk(
+    seed: u32,
+    key: UVec2,
+    terrain: &dyn TerrainGenerator,
+) -> Vec<Decoration> {
+    let mut decorations = Vec::new();
+    for x in 0..16 {
+        for z in 0..16 {
+            let (wx, wz) = (key.x * 16 + x, key.y * 16 + z);
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            wx.hash(&mut hasher);
+            wz.hash(&mut hasher);
+            let mut rng = StdRng::seed_from_u64(hasher.finish());
+
+            // Blocks are generated from the terrain at their corner.
+            let column = Vec2::new(wx as f32, wz as f32);
+            let tdata = terrain.get(column);
+            if tdata.is_water() {
+                continue;
+            }
+            let ground = terrain.surface_height(column).ceil();
+            let cover = cover(tdata.biome);
+            let spot = |rng: &mut StdRng| {
+                Vec3::new(
+                    wx as f32 + rng.gen_range(0.15..0.85),
+                    ground,
+                    wz as f32 + rng.gen_range(0.15..0.85),
+                )
+            };
+
+            if near_water(column, &tdata, terrain) && rng.gen_bool(0.35) {
+                for _ in 0..rng.gen_range(1..=3) {
+                    let color = jitter(REED, &mut rng, 10);
+                    let position = spot(&mut rng);
+                    let (yaw, scale) = (
+                        rng.gen_range(0.0..std::f32::consts::TAU),
+                        rng.gen_range(1.2..2.2),
+                    );
+                    decorations.push(Decoration::new(
+                        DecorationKind::Reed,
+                        position,
+                        yaw,
+                        scale,
+                        color,
+                    ));
+                }
+                continue;
+            }
+
+            let roll: f32 = rng.gen();
+            let grass = cover.grass * (0.5 + tdata.moisture).min(1.0);
+            let yaw = rng.gen_range(0.0..std::f32::consts::TAU);
+            if roll < cover.rock {
+                let color = jitter(ROCK, &mut rng, 15);
+                let scale = rng.gen_range(0.3..0.9);
+                decorations.push(Decoration::new(
+                    DecorationKind::Rock,
+                    spot(&mut rng),
+                    yaw,
+                    scale,
+                    color,
+                ));
+            } else if roll < cover.rock + cover.flower && !cover.flowers.is_empty() {
+                let blossom = cover.flowers[rng.gen_range(0..cover.flowers.len())];
+                let blossom = jitter(blossom, &mut rng, 10);
+                let stem = jitter(STEM, &mut rng, 8);
+                let position = spot(&mut rng);
+                let scale = rng.gen_range(0.5..0.8);
+                decorations.push(Decoration::new(
+                    DecorationKind::Stem,
+                    position,
+                    yaw,
+                    scale,
+                    stem,
+                ));
+                decorations.push(Decoration::new(
+                    DecorationKind::Blossom,
+                    position,
+                    yaw,
+                    scale,
+                    blossom,
+                ));
+            } else if roll < cover.rock + cover.flower + grass {
+                // Lush ground gets a second tuft to fill it out.
+                let tufts = if roll < grass * 0.5 { 2 } else { 1 };
+                for _ in 0..tufts {
+                    let color = jitter(cover.grass_color, &mut rng, 12);
+                    let position = spot(&mut rng);
+                    let (yaw, scale) = (
+                        rng.gen_range(0.0..std::f32::consts::TAU),
+                        rng.gen_range(0.4..0.9),
+                    );
+                    decorations.push(Decoration::new(
+                        DecorationKind::Grass,
+                        position,
+                        yaw,
+                        scale,
+                        color,
+                    ));
+                }
+            }
+        }
+    }
+    decorations
+}
+
+/// The decorations of a chunk column that are still standing, grouped by
+/// kind so each kind is one instanced draw.
+pub(crate) struct DecorationBatch {
+    pub(crate) instances: Vec<DecorationInstance>,
+    pub(crate) ranges: [Range<u32>; DecorationKind::ALL.len()],
+}
+
+fn block_at(column: &[Chunk], pos: IVec3) -> Option<block::Type> {
+    if pos.y < 0 {
+        return None;
+    }
+    let section = column.get(pos.y as usize / 16)?;
+    let [x, y, z] = [pos.x, pos.y, pos.z].map(|c| c.rem_euclid(16) as usize);
+    Some(section.blocks()[x][z][y].ty())
+}
+
+/// Drops the decorations whose block has been filled in or whose ground has
+/// gone or changed, like where a player has built or dug.
+pub(crate) fn cull(decorations: &[Decoration], column: &[Chunk]) -> DecorationBatch {
+    let standing = |d: &&Decoration| {
+        let cell = d.cell();
+        block_at(column, cell) == Some(block::Type::Inactive)
+            && block_at(column, cell - IVec3::Y).is_some_and(|ground| d.kind.grows_on(ground))
+    };
+
+    let mut instances = Vec::new();
+    let ranges = DecorationKind::ALL.map(|kind| {
+        let start = instances.len() as u32;
+        instances.extend(
+            decorations
+                .iter()
+                .filter(|d| d.kind == kind)
+                .filter(standing)
+                .map(|d| d.instance),
+        );
+        start..instances.len() as u32
+    });
+    DecorationBatch { instances, ranges }
+}
+
+/// Adds a pair of crossed upright quads, `width` wide and running from
+/// `bottom` to `top`. They're lit as if they faced up so they match the
+/// ground they grow from.
+fn add_cross(
+    vertices: &mut Vec<SimpleVertex>,
+    indices: &mut Vec<u16>,
+    width: f32,
+    bottom: f32,
+    top: f32,
+) {
+    let h = width / 2.0;
+    for (dx, dz) in [(h, 0.0), (0.0, h)] {
+        let base = vertices.len() as u16;
+        for [x, y, z] in [
+            [-dx, bottom, -dz],
+            [dx, bottom, dz],
+            [dx, top, dz],
+            [-dx, top, -dz],
+        ] {
+            vertices.push(SimpleVertex::new([x, y, z], [0, 127, 0, 0]));
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+fn add_box(vertices: &mut Vec<SimpleVertex>, indices: &mut Vec<u16>, half: f32, height: f32) {
+    for v in crate::vertex::CUBE_VERTICES {
+        let [x, y, z] = v.position();
+        // The cube is z-up, so swap it round to stand on the ground.
+        vertices.push(SimpleVertex::new(
+            [(x - 0.5) * 2.0 * half, z * height, (y - 0.5) * 2.0 * half],
+            {
+                let [nx, ny, nz, w] = v.normal();
+                [nx, nz, ny, w]
+            },
+        ));
+    }
+    let base = (vertices.len() - crate::vertex::CUBE_VERTICES.len()) as u16;
+    indices.extend(crate::vertex::CUBE_INDICES.iter().map(|i| base + i));
+}
+
+/// The mesh every decoration kind is drawn from, all in one buffer, with the
+/// range of indices for each kind. Meshes are a unit tall and stand on the
+/// origin.
+pub(crate) fn meshes() -> (
+    Vec<SimpleVertex>,
+    Vec<u16>,
+    [Range<u32>; DecorationKind::ALL.len()],
+) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let ranges = DecorationKind::ALL.map(|kind| {
+        let start = indices.len() as u32;
+        match kind {
+            DecorationKind::Grass => {
+                add_cross(&mut vertices, &mut indices, 0.9, 0.0, 1.0);
+            }
+            DecorationKind::Stem => add_cross(&mut vertices, &mut indices, 0.08, 0.0, 0.7),
+            DecorationKind::Blossom => add_cross(&mut vertices, &mut indices, 0.35, 0.55, 0.85),
+            DecorationKind::Reed => add_cross(&mut vertices, &mut indices, 0.25, 0.0, 1.0),
+            DecorationKind::Rock => add_box(&mut vertices, &mut indices, 0.5, 0.6),
+        }
+        start..indices.len() as u32
+    });
+    (vertices, indices, ranges)
+}
+
+pub(crate) struct DecorationManager {
+    loaded_cells: HashMap<UVec2, Vec<Decoration>>,
+    task_tx: Sender<UVec2>,
+    result_rx: Receiver<(UVec2, Vec<Decoration>)>,
+    in_flight: HashSet<UVec2>,
+}
+
+impl DecorationManager {
+    pub fn new(seed: u32, terrain: Arc<dyn TerrainGenerator>) -> Self {
+        let (task_tx, task_rx) = mpsc::channel::<UVec2>();
+        let (result_tx, result_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            while let Ok(key) = task_rx.recv() {
+                let decorations = decorate_chunk(seed, key, &*terrain);
+                if result_tx.send((key, decorations)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            loaded_cells: HashMap::new(),
+            task_tx,
+            result_rx,
+            in_flight: HashSet::new(),
+        }
+    }
+
+    pub fn loaded_cells(&self) -> &HashMap<UVec2, Vec<Decoration>> {
+        &self.loaded_cells
+    }
+
+    pub(crate) fn update(&mut self, player_position: &Vec3, load_radius: u32) {
+        while let Ok((key, decorations)) = self.result_rx.try_recv() {
+            if self.in_flight.remove(&key) {
+                self.loaded_cells.insert(key, decorations);
+            }
+        }
+
+        let chunk_x = (player_position.x.floor().max(0.0) as u32) / 16;
+        let chunk_z = (player_position.z.floor().max(0.0) as u32) / 16;
+        let start_x = chunk_x.saturating_sub(load_radius);
+        let end_x = chunk_x.saturating_add(load_radius);
+        let start_z = chunk_z.saturating_sub(load_radius);
+        let end_z = chunk_z.saturating_add(load_radius);
+
+        for cx in start_x..=end_x {
+            for cz in start_z..=end_z {
+                let key = UVec2::new(cx, cz);
+                if !self.loaded_cells.contains_key(&key) && self.in_flight.insert(key) {
+                    let _ = self.task_tx.send(key);
+                }
+            }
+        }
+
+        let outside =
+            |key: &UVec2| key.x < start_x || key.x > end_x || key.y < start_z || key.y > end_z;
+        self.loaded_cells.retain(|key, _| !outside(key));
+        self.in_flight.retain(|key| !outside(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::WorldTerrain;
+
+    fn find_chunk(terrain: &WorldTerrain, biome: Biome) -> UVec2 {
+        let point = terrain
+            .find_closest_pure_biome(Vec2::new(4000.0, 4000.0), biome)
+            .expect("biome nearby");
+        UVec2::new(point.x as u32 / 16, point.y as u32 / 16)
+    }
+
+    #[test]
+    fn test_decorations_are_deterministic() {
+        let terrain = WorldTerrain::new(42);
+        let key = find_chunk(&terrain, Biome::Plains);
+        let a = decorate_chunk(42, key, &terrain);
+        let b = decorate_chunk(42, key, &terrain);
+        assert!(!a.is_empty());
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!(a.kind, b.kind);
+            assert_eq!(a.position(), b.position());
+        }
+    }
+
+    #[test]
+    fn test_biomes_get_their_own_cover() {
+        let terrain = WorldTerrain::new(42);
+        let count = |biome, kind| {
+            let key = find_chunk(&terrain, biome);
+            decorate_chunk(42, key, &terrain)
+                .iter()
+                .filter(|d| d.kind == kind)
+                .count()
+        };
+        assert!(
+            count(Biome::Jungle, DecorationKind::Grass)
+                > count(Biome::Desert, DecorationKind::Grass)
+        );
+        assert_eq!(count(Biome::Desert, DecorationKind::Blossom), 0);
+
+        // Every flower has a stem.
+        let key = find_chunk(&terrain, Biome::Plains);
+        let decorations = decorate_chunk(42, key, &terrain);
+        let stems: Vec<Vec3> = decorations
+            .iter()
+            .filter(|d| d.kind == DecorationKind::Stem)
+            .map(|d| d.position())
+            .collect();
+        for blossom in decorations
+            .iter()
+            .filter(|d| d.kind == DecorationKind::Blossom)
+        {
+            assert!(stems.contains(&blossom.position()));
+        }
+    }
+
+    #[test]
+    fn test_reeds_grow_by_water() {
+        let terrain = WorldTerrain::new(42);
+        let shore = find_chunk(&terrain, Biome::Beach);
+        let mut reeds = 0;
+        for dx in 0..5 {
+            for dz in 0..5 {
+                let key = shore + UVec2::new(dx, dz) - UVec2::splat(2);
+                for reed in decorate_chunk(42, key, &terrain)
+                    .iter()
+                    .filter(|d| d.kind == DecorationKind::Reed)
+                {
+                    let p = reed.position();
+                    let tdata = terrain.get(Vec2::new(p.x, p.z));
+                    assert!(
+                        tdata.height - tdata.water_level <= REED_SHORE,
+                        "reed at {:?}",
+                        p
+                    );
+                    reeds += 1;
+                }
+            }
+        }
+        assert!(reeds > 0, "no reeds on the shore");
+    }
+
+    #[test]
+    fn test_cull_where_blocks_change() {
+        let terrain = WorldTerrain::new(42);
+        let key = find_chunk(&terrain, Biome::Plains);
+        let decorations = decorate_chunk(42, key, &terrain);
+        let mut column = terrain.generate_column(key);
+
+        let batch = cull(&decorations, &column);
+        assert!(!batch.instances.is_empty());
+        assert_eq!(
+            batch.ranges.last().unwrap().end as usize,
+            batch.instances.len()
+        );
+        let standing: Vec<&Decoration> = decorations
+            .iter()
+            .filter(|d| {
+                batch
+                    .instances
+                    .iter()
+                    .any(|i| i.position == d.instance.position)
+            })
+            .collect();
+        for d in &standing {
+            let ground = block_at(&column, d.cell() - IVec3::Y).unwrap();
+            assert!(d.kind.grows_on(ground), "{:?} on {:?}", d.kind, ground);
+        }
+
+        // Building over one and digging out the ground under another clears both.
+        let built = standing[0].cell();
+        let dug = standing.last().unwrap().cell() - IVec3::Y;
+        let set = |column: &mut Vec<Chunk>, pos: IVec3, ty| {
+            let [x, y, z] = [pos.x, pos.y, pos.z].map(|c| c.rem_euclid(16) as usize);
+            column[pos.y as usize / 16].blocks_mut()[x][z][y].set_type(ty);
+        };
+        set(&mut column, built, block::Type::Rock);
+        set(&mut column, dug, block::Type::Inactive);
+        let culled = cull(&decorations, &column);
+        let at = |cell: IVec3| {
+            culled
+                .instances
+                .iter()
+                .filter(|i| (Vec3::from(i.position) + Vec3::Y * 0.01).floor().as_ivec3() == cell)
+                .count()
+        };
+        assert_eq!(at(built), 0);
+        assert_eq!(at(dug + IVec3::Y), 0);
+        assert!(culled.instances.len() < batch.instances.len());
+    }
+
+    #[test]
+    fn test_meshes_stand_on_the_ground() {
+        let (vertices, indices, ranges) = meshes();
+        assert_eq!(ranges.last().unwrap().end as usize, indices.len());
+        for range in &ranges {
+            assert!(!range.is_empty());
+            assert_eq!(range.len() % 3, 0);
+        }
+        for v in &vertices {
+            let [_, y, _] = v.position();
+            assert!((0.0..=1.0).contains(&y));
+        }
+    }
+}
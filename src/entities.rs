@@ -1,36 +1,520 @@
+use bytemuck::{Pod, Zeroable};
 use glam::{UVec2, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 
+use crate::clock::Season;
+use crate::collision::{CollisionIndex, CollisionShape};
+use crate::config::SaplingConfig;
+use crate::generator::TerrainGenerator;
 use crate::lsystem;
 use crate::poisson::AdaptivePoisson;
-use crate::terrain::{WorldTerrain, WATER_LEVEL};
-use crate::trees::{self, TreeType};
+use crate::trees::{SpeciesId, SpeciesRegistry};
+use crate::vertex::Vertex;
+
+/// How many different trees are grown for each species. Every tree in the
+/// world is one of these, turned, scaled and tinted.
+pub(crate) const TREE_VARIANTS: usize = 4;
+
+/// The most a tree is scaled up from the variant it was grown from.
+pub(crate) const MAX_TREE_SCALE: f32 = 1.15;
+
+/// Saplings grow to full size in this many steps, each drawn with its own
+/// prototype and scale.
+pub(crate) const GROWTH_STEPS: u32 = 8;
+
+/// How big a fresh sapling is next to the tree it grows into.
+const SAPLING_SCALE: f32 = 0.3;
+
+/// Wild trees were planted up to this many growth periods before the world
+/// began, so most are long grown but some are still catching up.
+const WILD_AGES: f64 = 4.0;
+
+/// Trees are drawn with less detail the further away their cell is. A cell
+/// only changes tier once it's this much past the distance where the tiers
+/// meet, so trees don't flicker between them as the player walks along it.
+const LOD_HYSTERESIS: f32 = 8.0;
+
+/// How much detail a cell's trees are drawn with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum TreeLod {
+    /// Every branch the species grows.
+    Full,
+    /// The tree as it was a stage of growth earlier, stretched to full size.
+    Reduced,
+    /// A billboard with a picture of the tree on it.
+    Impostor,
+}
+
+impl TreeLod {
+    /// The tier for trees `distance` away, with full detail out to `detail`
+    /// and impostors from `impostor` on.
+    pub(crate) fn for_distance(distance: f32, detail: f32, impostor: f32) -> Self {
+        if distance < detail {
+            TreeLod::Full
+        } else if distance < impostor {
+            TreeLod::Reduced
+        } else {
+            TreeLod::Impostor
+        }
+    }
+
+    /// The tier for trees now `distance` away that were drawn with this one.
+    pub(crate) fn at_distance(self, distance: f32, detail: f32, impostor: f32) -> Self {
+        // Each boundary is pushed away from the tier the trees are in now
+        let detail = match self {
+            TreeLod::Full => detail + LOD_HYSTERESIS,
+            _ => detail - LOD_HYSTERESIS,
+        };
+        let impostor = match self {
+            TreeLod::Impostor => impostor - LOD_HYSTERESIS,
+            _ => impostor + LOD_HYSTERESIS,
+        };
+        Self::for_distance(distance, detail, impostor)
+    }
+}
+
+/// The space a prototype takes up around its root.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub(crate) struct TreeExtent {
+    /// How far it reaches out from the trunk in any direction.
+    pub(crate) radius: f32,
+    pub(crate) bottom: f32,
+    pub(crate) top: f32,
+}
+
+impl TreeExtent {
+    fn of(vertices: &[Vertex]) -> Self {
+        if vertices.is_empty() {
+            return TreeExtent::default();
+        }
+        let mut extent = TreeExtent {
+            radius: 0.0,
+            bottom: f32::INFINITY,
+            top: f32::NEG_INFINITY,
+        };
+        for vertex in vertices {
+            let [x, y, z] = vertex.position();
+            extent.radius = extent.radius.max(x.hypot(z));
+            extent.bottom = extent.bottom.min(y);
+            extent.top = extent.top.max(y);
+        }
+        extent
+    }
+}
+
+/// Stretches a tree grown a stage short of `to` out to the same size, so it
+/// can stand in for it at a distance with far fewer branches.
+fn reduced_tree(mut tree: lsystem::EntityMesh, to: TreeExtent) -> lsystem::EntityMesh {
+    let from = TreeExtent::of(&tree.vertices);
+    let across = to.radius / from.radius.max(f32::EPSILON);
+    let up = to.top / from.top.max(f32::EPSILON);
+    for vertex in &mut tree.vertices {
+        let [x, y, z] = vertex.position();
+        *vertex = vertex.with_position([x * across, y * up, z * across]);
+    }
+    tree
+}
+
+/// Where a tree stands and how it differs from the variant it was grown from.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct TreeInstance {
+    position: [f32; 3],
+    yaw: f32,
+    scale: f32,
+    tint: [f32; 3],
+}
+
+impl TreeInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        5 => Float32x3,
+        6 => Float32,
+        7 => Float32,
+        8 => Float32x3,
+    ];
+
+    #[cfg(test)]
+    pub fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+
+    #[cfg(test)]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Where a point of the prototype ends up in the world, once it's scaled
+    /// and turned.
+    fn place(&self, local: Vec3) -> Vec3 {
+        let (s, c) = self.yaw.sin_cos();
+        let local = local * self.scale;
+        let turned = Vec3::new(
+            local.x * c - local.z * s,
+            local.y,
+            local.x * s + local.z * c,
+        );
+        Vec3::from(self.position) + turned
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TreeInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A tree standing in the world, and everything needed to draw it at any age.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tree {
+    position: Vec3,
+    species: SpeciesId,
+    variant: usize,
+    yaw: f32,
+    scale: f32,
+    tint: [f32; 3],
+    /// The day the tree was planted, counted like the clock's days.
+    planted: f64,
+}
+
+impl Tree {
+    /// A tree planted at `position` on the given day. Its variant and how
+    /// it's turned, scaled and tinted all come from where it stands.
+    pub(crate) fn sapling(position: Vec3, species: SpeciesId, planted: f64) -> Self {
+        Self::planted(position, species, planted, &mut Self::rng(position))
+    }
+
+    /// A tree that grew up on its own, planted some time before the world
+    /// began.
+    fn wild(position: Vec3, species: SpeciesId, growth_days: f32) -> Self {
+        let mut rng = Self::rng(position);
+        let mut tree = Self::planted(position, species, 0.0, &mut rng);
+        tree.planted = -rng.gen_range(0.0..WILD_AGES) * growth_days as f64;
+        tree
+    }
+
+    pub(crate) fn species(&self) -> SpeciesId {
+        self.species
+    }
+
+    pub(crate) fn variant(&self) -> usize {
+        self.variant
+    }
+
+    /// Where a point of the fully grown tree's prototype ends up in the
+    /// world, once the tree is scaled and turned.
+    pub(crate) fn place(&self, local: Vec3) -> Vec3 {
+        TreeInstance {
+            scale: self.scale,
+            ..self.instance(GROWTH_STEPS)
+        }
+        .place(local)
+    }
+
+    fn rng(position: Vec3) -> StdRng {
+        // Packed rather than xored so x == z and mirrored spots don't share a
+        // seed, then hashed so neighbouring trees don't get neighbouring seeds
+        let packed = (position.x.to_bits() as u64) << 32 | position.z.to_bits() as u64;
+        let mut hasher = DefaultHasher::new();
+        packed.hash(&mut hasher);
+        StdRng::seed_from_u64(hasher.finish())
+    }
+
+    fn planted(position: Vec3, species: SpeciesId, planted: f64, rng: &mut StdRng) -> Self {
+        let variant = rng.gen_range(0..TREE_VARIANTS);
+        let brightness = rng.gen_range(0.9..1.1);
+        let mut tint = [0.0; 3];
+        for channel in &mut tint {
+            *channel = brightness * rng.gen_range(0.97..1.03);
+        }
+        Self {
+            position,
+            species,
+            variant,
+            yaw: rng.gen_range(0.0..std::f32::consts::TAU),
+            scale: rng.gen_range(0.85..MAX_TREE_SCALE),
+            tint,
+            planted,
+        }
+    }
+
+    /// The growth step the tree has reached on `day`, from 1 for a fresh
+    /// sapling to `GROWTH_STEPS` once it's fully grown, and the days that
+    /// step lasts.
+    fn growth(&self, day: f64, growth_days: f32) -> (u32, Range<f64>) {
+        let step_days = growth_days.max(f32::EPSILON) as f64 / GROWTH_STEPS as f64;
+        let steps = ((day - self.planted) / step_days).floor();
+        let step = steps.clamp(0.0, (GROWTH_STEPS - 1) as f64) as u32 + 1;
+        let start = match step {
+            1 => f64::NEG_INFINITY,
+            _ => self.planted + (step - 1) as f64 * step_days,
+        };
+        let end = match step {
+            GROWTH_STEPS => f64::INFINITY,
+            _ => self.planted + step as f64 * step_days,
+        };
+        (step, start..end)
+    }
+
+    fn instance(&self, step: u32) -> TreeInstance {
+        let grown = step as f32 / GROWTH_STEPS as f32;
+        TreeInstance {
+            position: self.position.to_array(),
+            yaw: self.yaw,
+            scale: self.scale * (SAPLING_SCALE + (1.0 - SAPLING_SCALE) * grown),
+            tint: self.tint,
+        }
+    }
+}
+
+/// A few trees of every species at every stage of growth, grown once per
+/// season and shared by every tree in the world. Their meshes are joined so
+/// they can live in one pair of buffers, with each prototype's indices in
+/// its own range. Each prototype also has a reduced mesh for drawing it at a
+/// distance, and the shapes it collides with.
+pub(crate) struct TreePrototypes {
+    season: Season,
+    mesh: lsystem::EntityMesh,
+    ranges: Vec<Range<u32>>,
+    reduced_ranges: Vec<Range<u32>>,
+    extents: Vec<TreeExtent>,
+    shapes: Vec<Vec<CollisionShape>>,
+    // Where each species' prototypes start in `ranges`, and how many stages
+    // each of its variants grows through
+    species: Vec<(usize, usize)>,
+}
+
+impl TreePrototypes {
+    pub(crate) fn new(species: &SpeciesRegistry, season: Season) -> Self {
+        let mut mesh = lsystem::EntityMesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+        let mut add = |tree: lsystem::EntityMesh| {
+            let base_idx = mesh.vertices.len() as u32;
+            let start = mesh.indices.len() as u32;
+            mesh.vertices.extend(tree.vertices);
+            mesh.indices
+                .extend(tree.indices.into_iter().map(|i| i + base_idx));
+            start..mesh.indices.len() as u32
+        };
+        let mut ranges = Vec::new();
+        let mut reduced_ranges = Vec::new();
+        let mut extents = Vec::new();
+        let mut shapes = Vec::new();
+        let mut offsets = Vec::new();
+        for id in species.ids() {
+            let species = species.get(id);
+            let grammar = &species.grammar;
+            // Each stage expands the grammar once more than the last
+            let stages = grammar.iterations.max(1);
+            offsets.push((ranges.len(), stages));
+            for variant in 0..TREE_VARIANTS {
+                let mut previous: Option<lsystem::EntityMesh> = None;
+                for stage in 1..=stages {
+                    let iterations = stage.min(grammar.iterations);
+                    let tree =
+                        lsystem::generate_young_tree(grammar, variant as u32, iterations, season);
+                    let parts = lsystem::generate_tree_parts(grammar, variant as u32, iterations);
+                    let extent = TreeExtent::of(&tree.vertices);
+                    let next = tree.clone();
+                    let range = add(tree);
+                    // A sapling has no earlier stage to fall back on
+                    let reduced = match previous.replace(next) {
+                        Some(earlier) => add(reduced_tree(earlier, extent)),
+                        None => range.clone(),
+                    };
+                    ranges.push(range);
+                    reduced_ranges.push(reduced);
+                    extents.push(extent);
+                    // Understory plants are all bush, with no trunk to speak of
+                    shapes.push(lsystem::collision_shapes(&parts, species.understory));
+                }
+            }
+        }
+        Self {
+            season,
+            mesh,
+            ranges,
+            reduced_ranges,
+            extents,
+            shapes,
+            species: offsets,
+        }
+    }
+
+    /// The index in `ranges` of a species' variant at a step of growth.
+    fn index(&self, id: SpeciesId, variant: usize, step: u32) -> usize {
+        let (offset, stages) = self.species[id.index()];
+        let stage = (step as usize * stages)
+            .div_ceil(GROWTH_STEPS as usize)
+            .max(1);
+        offset + variant * stages + stage - 1
+    }
+
+    pub(crate) fn season(&self) -> Season {
+        self.season
+    }
+
+    pub(crate) fn mesh(&self) -> &lsystem::EntityMesh {
+        &self.mesh
+    }
+
+    /// The indices of each prototype, in the order instances are grouped by.
+    pub(crate) fn ranges(&self) -> &[Range<u32>] {
+        &self.ranges
+    }
+
+    /// The indices of each prototype's reduced mesh, in the same order.
+    pub(crate) fn reduced_ranges(&self) -> &[Range<u32>] {
+        &self.reduced_ranges
+    }
+
+    /// How big each prototype is, in the same order.
+    pub(crate) fn extents(&self) -> &[TreeExtent] {
+        &self.extents
+    }
+}
+
+/// The trees in one chunk cell, grouped by the prototype they're drawn with.
+pub(crate) struct TreeBatch {
+    pub(crate) instances: Vec<TreeInstance>,
+    pub(crate) ranges: Vec<Range<u32>>,
+}
+
+impl TreeBatch {
+    /// Groups the trees as they stand on `day`, along with the shapes they
+    /// collide with and the days until one of them grows enough to need a
+    /// new batch.
+    fn new(
+        trees: &[Tree],
+        day: f64,
+        species: &SpeciesRegistry,
+        prototypes: &TreePrototypes,
+    ) -> (Self, CollisionIndex, Range<f64>) {
+        let mut grouped = vec![Vec::new(); prototypes.ranges().len()];
+        let mut shapes = Vec::new();
+        let mut growing = f64::NEG_INFINITY..f64::INFINITY;
+        for tree in trees {
+            let (step, lasts) = tree.growth(day, species.get(tree.species).growth_days);
+            growing.start = growing.start.max(lasts.start);
+            growing.end = growing.end.min(lasts.end);
+            let index = prototypes.index(tree.species, tree.variant, step);
+            let instance = tree.instance(step);
+            shapes.push(
+                prototypes.shapes[index]
+                    .iter()
+                    .map(|shape| shape.transformed(|p| instance.place(p), instance.scale))
+                    .collect(),
+            );
+            grouped[index].push(instance);
+        }
+
+        let mut instances = Vec::new();
+        let mut ranges = Vec::with_capacity(grouped.len());
+        for group in grouped {
+            let start = instances.len() as u32;
+            instances.extend(group);
+            ranges.push(start..instances.len() as u32);
+        }
+        (
+            Self { instances, ranges },
+            CollisionIndex::new(shapes),
+            growing,
+        )
+    }
+}
+
+/// The trees in one chunk cell and how they're drawn and collided with
+/// today.
+pub(crate) struct TreeCell {
+    trees: Vec<Tree>,
+    batch: TreeBatch,
+    colliders: CollisionIndex,
+    // The days `batch` holds for, until a tree grows another step
+    growing: Range<f64>,
+    // Bumped whenever the batch is rebuilt so renderers know to re-upload it
+    revision: u32,
+}
+
+impl TreeCell {
+    pub(crate) fn batch(&self) -> &TreeBatch {
+        &self.batch
+    }
+
+    pub(crate) fn revision(&self) -> u32 {
+        self.revision
+    }
+}
 
 pub(crate) struct EntityManager {
-    loaded_cells: HashMap<UVec2, lsystem::EntityMesh>,
+    loaded_cells: HashMap<UVec2, TreeCell>,
+    // Trees the player planted, in the order they were planted
+    saplings: Vec<Tree>,
+    species: Arc<SpeciesRegistry>,
+    prototypes: TreePrototypes,
+    // Whether wild trees are drawn, or left to the blocks they're built from
+    wild: bool,
+    day: f64,
     pub(crate) version: u32,
     task_tx: Sender<(u32, u32)>,
-    result_rx: Receiver<(UVec2, lsystem::EntityMesh)>,
+    result_rx: Receiver<(UVec2, Vec<Tree>)>,
     in_flight: HashSet<UVec2>,
 }
 
+/// The chunk cell a position falls in.
+fn cell_of(position: Vec3) -> UVec2 {
+    let block_x = std::cmp::max(position.x.floor() as i32, 0) as u32;
+    let block_z = std::cmp::max(position.z.floor() as i32, 0) as u32;
+    UVec2::new(block_x / 16, block_z / 16)
+}
+
+/// The trees that grow wild in a chunk cell.
+pub(crate) fn wild_trees(
+    seed: u32,
+    chunk_x: u32,
+    chunk_z: u32,
+    terrain: &dyn TerrainGenerator,
+    species: &SpeciesRegistry,
+) -> Vec<Tree> {
+    generate_entities_for_chunk(seed, chunk_x, chunk_z, terrain, species)
+        .into_iter()
+        .map(|(position, id)| Tree::wild(position, id, species.get(id).growth_days))
+        .collect()
+}
+
 fn generate_entities_for_chunk(
     seed: u32,
     chunk_x: u32,
     chunk_z: u32,
-    terrain: &WorldTerrain,
-) -> Vec<(Vec3, TreeType)> {
+    terrain: &dyn TerrainGenerator,
+    species: &SpeciesRegistry,
+) -> Vec<(Vec3, SpeciesId)> {
     let mut entities = Vec::new();
     let poisson = AdaptivePoisson::new(seed);
 
     let points = poisson.generate_for_chunk(chunk_x, chunk_z, &|p| terrain.get(p));
+    // Trees don't grow through buildings.
+    let structures = terrain.structures_near(glam::IVec2::new(chunk_x as i32, chunk_z as i32));
 
     for pt in points {
         let tdata = terrain.get(pt);
+        let column = pt.floor().as_ivec2();
+        let built_on = structures.iter().any(|s| {
+            s.pieces
+                .iter()
+                .any(|p| p.foundation && p.contains_column(column))
+        });
 
-        if tdata.height > WATER_LEVEL {
+        if !tdata.is_water() && !built_on {
             let temp = tdata.temperature;
             let moist = tdata.moisture;
             let height = tdata.height;
@@ -38,7 +522,7 @@ fn generate_entities_for_chunk(
             // Altitude jitter adds organic variation to the treeline
             let altitude_jitter = (pt.x * 12.9898 + pt.y * 78.233).sin() * 10.0;
 
-            let suitable_trees = trees::get_suitable_trees(temp, moist, height, altitude_jitter);
+            let suitable_trees = species.get_suitable_trees(temp, moist, height, altitude_jitter);
 
             let tree_type = if suitable_trees.is_empty() {
                 None
@@ -54,7 +538,7 @@ fn generate_entities_for_chunk(
             };
 
             if let Some(tree_type) = tree_type {
-                let position = Vec3::new(pt.x, tdata.height, pt.y);
+                let position = Vec3::new(pt.x, terrain.surface_height(pt), pt.y);
                 entities.push((position, tree_type));
             }
         }
@@ -64,29 +548,23 @@ fn generate_entities_for_chunk(
 }
 
 impl EntityManager {
-    pub fn new(seed: u32, terrain: WorldTerrain) -> Self {
+    pub fn new(
+        seed: u32,
+        terrain: Arc<dyn TerrainGenerator>,
+        species: Arc<SpeciesRegistry>,
+        season: Season,
+    ) -> Self {
         let (task_tx, task_rx) = mpsc::channel::<(u32, u32)>();
         let (result_tx, result_rx) = mpsc::channel();
 
+        let prototypes = TreePrototypes::new(&species, season);
+        let worker_species = species.clone();
         thread::spawn(move || {
             while let Ok((chunk_x, chunk_z)) = task_rx.recv() {
-                let mut mesh = lsystem::EntityMesh {
-                    vertices: Vec::new(),
-                    indices: Vec::new(),
-                };
-
-                let entities = generate_entities_for_chunk(seed, chunk_x, chunk_z, &terrain);
-
-                for (position, tree_type) in entities {
-                    let tree_mesh = lsystem::generate_l_system_tree(tree_type, position);
-                    let base_idx = mesh.vertices.len() as u32;
-                    mesh.vertices.extend(tree_mesh.vertices);
-                    mesh.indices
-                        .extend(tree_mesh.indices.into_iter().map(|i| i + base_idx));
-                }
+                let trees = wild_trees(seed, chunk_x, chunk_z, &*terrain, &worker_species);
 
                 if result_tx
-                    .send((UVec2::new(chunk_x, chunk_z), mesh))
+                    .send((UVec2::new(chunk_x, chunk_z), trees))
                     .is_err()
                 {
                     break;
@@ -96,6 +574,11 @@ impl EntityManager {
 
         Self {
             loaded_cells: HashMap::new(),
+            saplings: Vec::new(),
+            species,
+            prototypes,
+            wild: true,
+            day: 0.0,
             version: 0,
             task_tx,
             result_rx,
@@ -103,40 +586,177 @@ impl EntityManager {
         }
     }
 
-    pub fn loaded_cells(&self) -> &HashMap<UVec2, lsystem::EntityMesh> {
+    /// Leaves wild trees out, for worlds that build them from blocks. Only
+    /// saplings the player plants are drawn.
+    pub(crate) fn without_wild_trees(mut self) -> Self {
+        self.wild = false;
+        self
+    }
+
+    pub fn loaded_cells(&self) -> &HashMap<UVec2, TreeCell> {
         &self.loaded_cells
     }
 
+    pub(crate) fn prototypes(&self) -> &TreePrototypes {
+        &self.prototypes
+    }
+
+    pub(crate) fn species(&self) -> &SpeciesRegistry {
+        &self.species
+    }
+
+    /// Changes the season used for foliage colours. Only the prototypes are
+    /// regrown; the trees in each cell stay where they are.
+    pub(crate) fn set_season(&mut self, season: Season) {
+        if season == self.prototypes.season() {
+            return;
+        }
+        self.prototypes = TreePrototypes::new(&self.species, season);
+    }
+
+    /// Moves the trees on to the given day. Cells with a tree that has
+    /// grown since are rebuilt on the next update.
+    pub(crate) fn set_day(&mut self, day: f64) {
+        self.day = day;
+    }
+
+    /// Plants a sapling today. It's kept, and grows, whether or not its cell
+    /// is loaded.
+    pub(crate) fn plant(&mut self, position: Vec3, species: SpeciesId) {
+        let tree = Tree::sapling(position, species, self.day);
+        self.saplings.push(tree);
+        if let Some(cell) = self.loaded_cells.get_mut(&cell_of(position)) {
+            cell.trees.push(tree);
+            // Rebuild on the next update
+            cell.growing = self.day..self.day;
+        }
+    }
+
+    /// Every tree the player has planted, for saving with the world.
+    pub(crate) fn saplings(&self) -> Vec<SaplingConfig> {
+        self.saplings
+            .iter()
+            .map(|tree| SaplingConfig {
+                species: self.species.get(tree.species).name.clone(),
+                position: tree.position.to_array(),
+                planted: tree.planted,
+            })
+            .collect()
+    }
+
+    /// Replants saplings saved with the world. Any of a species that's no
+    /// longer known are dropped.
+    pub(crate) fn restore_saplings(&mut self, saplings: &[SaplingConfig]) {
+        for sapling in saplings {
+            match self.species.find(&sapling.species) {
+                Some(id) => self.saplings.push(Tree::sapling(
+                    Vec3::from(sapling.position),
+                    id,
+                    sapling.planted,
+                )),
+                None => log::warn!("Dropping a sapling of unknown species {}", sapling.species),
+            }
+        }
+    }
+
+    /// Whether a tree stands within `radius` of the segment from `start` to
+    /// `end`.
+    pub(crate) fn collides(&self, start: Vec3, end: Vec3, radius: f32) -> bool {
+        self.cells_near(start.min(end) - radius, start.max(end) + radius)
+            .any(|cell| cell.colliders.touches(start, end, radius))
+    }
+
+    /// How far along a ray from `origin` the nearest tree is hit, if any is
+    /// within `max_distance`, and the shapes the tree collides with. `dir`
+    /// should be normalised.
+    pub(crate) fn raycast(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_distance: f32,
+    ) -> Option<(f32, &[CollisionShape])> {
+        let end = origin + dir * max_distance;
+        self.cells_near(origin.min(end), origin.max(end))
+            .filter_map(|cell| cell.colliders.raycast(origin, dir, max_distance))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// The loaded cells with trees that could reach into the box from `min`
+    /// to `max`. Nothing a tree collides with reaches past the next cell
+    /// over.
+    fn cells_near(&self, min: Vec3, max: Vec3) -> impl Iterator<Item = &TreeCell> {
+        let low = cell_of(min - 16.0);
+        let high = cell_of(max + 16.0);
+        (low.x..=high.x)
+            .flat_map(move |x| (low.y..=high.y).map(move |z| UVec2::new(x, z)))
+            .filter_map(|key| self.loaded_cells.get(&key))
+            .filter(|cell| !cell.colliders.is_empty())
+    }
+
     fn queue_cell(&mut self, chunk_x: u32, chunk_z: u32) {
         let key = UVec2::new(chunk_x, chunk_z);
-        if self.loaded_cells.contains_key(&key) || self.in_flight.contains(&key) {
+        if self.in_flight.contains(&key) || self.loaded_cells.contains_key(&key) {
             return; // Already loaded or in flight
         }
 
+        if !self.wild {
+            self.receive_cell(key, Vec::new());
+            return;
+        }
         self.in_flight.insert(key);
         let _ = self.task_tx.send((chunk_x, chunk_z));
     }
 
-    pub(crate) fn update(&mut self, player_position: &Vec3, load_radius: u32) {
-        // Process results
-        while let Ok((key, mesh)) = self.result_rx.try_recv() {
-            self.in_flight.remove(&key);
+    fn receive_cell(&mut self, key: UVec2, mut trees: Vec<Tree>) {
+        self.in_flight.remove(&key);
 
-            let is_empty = mesh.vertices.is_empty();
-            self.loaded_cells.insert(key, mesh);
+        trees.extend(self.saplings.iter().filter(|t| cell_of(t.position) == key));
+        let (batch, colliders, growing) =
+            TreeBatch::new(&trees, self.day, &self.species, &self.prototypes);
+        let is_empty = batch.instances.is_empty();
+        self.loaded_cells.insert(
+            key,
+            TreeCell {
+                trees,
+                batch,
+                colliders,
+                growing,
+                revision: 0,
+            },
+        );
 
-            if !is_empty {
-                // TODO: In the future, if trees can grow or meshes can change dynamically,
-                // this check will need to be updated to detect structural changes,
-                // not just whether the mesh is non-empty.
-                self.version = self.version.wrapping_add(1);
+        if !is_empty {
+            self.version = self.version.wrapping_add(1);
+        }
+    }
+
+    /// Rebuilds the batches of cells where a tree has grown a step.
+    fn grow_cells(&mut self) {
+        for cell in self.loaded_cells.values_mut() {
+            if cell.growing.contains(&self.day) {
+                continue;
             }
+            let (batch, colliders, growing) =
+                TreeBatch::new(&cell.trees, self.day, &self.species, &self.prototypes);
+            cell.batch = batch;
+            cell.colliders = colliders;
+            cell.growing = growing;
+            cell.revision = cell.revision.wrapping_add(1);
+            self.version = self.version.wrapping_add(1);
+        }
+    }
+
+    pub(crate) fn update(&mut self, player_position: &Vec3, load_radius: u32) {
+        // Process results
+        while let Ok((key, trees)) = self.result_rx.try_recv() {
+            self.receive_cell(key, trees);
         }
+        self.grow_cells();
 
-        let block_x = std::cmp::max(player_position.x.floor() as i32, 0) as u32;
-        let block_z = std::cmp::max(player_position.z.floor() as i32, 0) as u32;
-        let chunk_x = block_x / 16;
-        let chunk_z = block_z / 16;
+        let UVec2 {
+            x: chunk_x,
+            y: chunk_z,
+        } = cell_of(*player_position);
 
         let start_x = chunk_x.saturating_sub(load_radius);
         let end_x = chunk_x.saturating_add(load_radius);
@@ -176,14 +796,8 @@ impl EntityManager {
     #[cfg(test)]
     pub(crate) fn wait_for_all_in_flight(&mut self) {
         while !self.in_flight.is_empty() {
-            if let Ok((key, mesh)) = self.result_rx.recv() {
-                self.in_flight.remove(&key);
-                let is_empty = mesh.vertices.is_empty();
-                self.loaded_cells.insert(key, mesh);
-
-                if !is_empty {
-                    self.version = self.version.wrapping_add(1);
-                }
+            if let Ok((key, trees)) = self.result_rx.recv() {
+                self.receive_cell(key, trees);
             } else {
                 break; // Channel disconnected
             }
@@ -194,11 +808,17 @@ impl EntityManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terrain::{WorldTerrain, WATER_LEVEL};
 
     #[test]
     fn test_entity_manager_caching_empty_chunk() {
         let terrain = WorldTerrain::new(12345);
-        let mut em = EntityManager::new(12345, terrain.clone());
+        let mut em = EntityManager::new(
+            12345,
+            Arc::new(terrain),
+            Arc::new(SpeciesRegistry::builtin()),
+            Season::Summer,
+        );
 
         let player_pos = Vec3::new(0.0, 0.0, 0.0);
         em.update(&player_pos, 0);
@@ -216,7 +836,12 @@ mod tests {
     #[test]
     fn test_entity_manager_version_bump() {
         let terrain = WorldTerrain::new(12345);
-        let mut em = EntityManager::new(12345, terrain.clone());
+        let mut em = EntityManager::new(
+            12345,
+            Arc::new(terrain),
+            Arc::new(SpeciesRegistry::builtin()),
+            Season::Summer,
+        );
 
         let player_pos = Vec3::new(0.0, 0.0, 0.0);
         em.update(&player_pos, 0);
@@ -224,8 +849,8 @@ mod tests {
         em.wait_for_all_in_flight();
 
         let key = UVec2::new(0, 0);
-        if let Some(mesh) = em.loaded_cells.get(&key) {
-            if mesh.vertices.is_empty() {
+        if let Some(cell) = em.loaded_cells.get(&key) {
+            if cell.batch.instances.is_empty() {
                 assert_eq!(em.version, 0, "Version should not bump for empty chunks");
             } else {
                 assert_eq!(em.version, 1, "Version should bump for populated chunks");
@@ -235,6 +860,289 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_entity_manager_recolours_on_season_change() {
+        let terrain = WorldTerrain::new(12345);
+        let mut em = EntityManager::new(
+            12345,
+            Arc::new(terrain),
+            Arc::new(SpeciesRegistry::builtin()),
+            Season::Summer,
+        );
+
+        let player_pos = Vec3::new(0.0, 0.0, 0.0);
+        em.update(&player_pos, 0);
+        em.wait_for_all_in_flight();
+
+        let key = UVec2::new(0, 0);
+        let summer: Vec<[u8; 4]> = em
+            .prototypes()
+            .mesh()
+            .vertices
+            .iter()
+            .map(|v| v.color())
+            .collect();
+
+        // Same season is a no-op
+        em.set_season(Season::Summer);
+        assert_eq!(em.prototypes().season(), Season::Summer);
+
+        em.set_season(Season::Autumn);
+        em.update(&player_pos, 0);
+        assert!(
+            em.in_flight.is_empty(),
+            "Cells shouldn't be regenerated for a new season"
+        );
+        assert!(em.loaded_cells.contains_key(&key));
+        assert_eq!(em.prototypes().season(), Season::Autumn);
+
+        let autumn = &em.prototypes().mesh().vertices;
+        assert_eq!(summer.len(), autumn.len(), "Only the colours should change");
+        assert!(summer.iter().zip(autumn).any(|(s, a)| *s != a.color()));
+    }
+
+    #[test]
+    fn test_prototypes_cover_every_species() {
+        let species = SpeciesRegistry::builtin();
+        let prototypes = TreePrototypes::new(&species, Season::Summer);
+
+        let stages: usize = species
+            .ids()
+            .map(|id| species.get(id).grammar.iterations.max(1))
+            .sum();
+        assert_eq!(prototypes.ranges().len(), stages * TREE_VARIANTS);
+        let mesh = prototypes.mesh();
+        for range in prototypes.ranges() {
+            assert!(!range.is_empty(), "Every prototype should have a mesh");
+            for &i in &mesh.indices[range.start as usize..range.end as usize] {
+                assert!((i as usize) < mesh.vertices.len());
+            }
+        }
+
+        // Variants of a species are different trees
+        let oak = species.find("oak").unwrap();
+        let grown = |variant| &prototypes.ranges()[prototypes.index(oak, variant, GROWTH_STEPS)];
+        assert!((1..TREE_VARIANTS).any(|variant| grown(variant).len() != grown(0).len()));
+
+        // Young trees have grown fewer branches
+        let sapling = &prototypes.ranges()[prototypes.index(oak, 0, 1)];
+        assert!(sapling.len() < grown(0).len());
+    }
+
+    #[test]
+    fn test_reduced_prototypes_stand_in_for_the_full_ones() {
+        let species = SpeciesRegistry::builtin();
+        let prototypes = TreePrototypes::new(&species, Season::Summer);
+        assert_eq!(prototypes.reduced_ranges().len(), prototypes.ranges().len());
+        assert_eq!(prototypes.extents().len(), prototypes.ranges().len());
+
+        let mesh = prototypes.mesh();
+        let oak = species.find("oak").unwrap();
+        for variant in 0..TREE_VARIANTS {
+            let index = prototypes.index(oak, variant, GROWTH_STEPS);
+            let full = &prototypes.ranges()[index];
+            let reduced = &prototypes.reduced_ranges()[index];
+            assert!(reduced.len() < full.len(), "Reduced trees are simpler");
+
+            // But take up the same space
+            let indices = &mesh.indices[reduced.start as usize..reduced.end as usize];
+            let vertices: Vec<_> = indices.iter().map(|&i| mesh.vertices[i as usize]).collect();
+            let extent = TreeExtent::of(&vertices);
+            let expected = prototypes.extents()[index];
+            assert!((extent.radius - expected.radius).abs() < 0.01);
+            assert!((extent.top - expected.top).abs() < 0.01);
+        }
+
+        // Saplings are simple enough already
+        let i = prototypes.index(oak, 0, 1);
+        assert_eq!(prototypes.reduced_ranges()[i], prototypes.ranges()[i]);
+    }
+
+    #[test]
+    fn test_tree_lod_hysteresis() {
+        use TreeLod::*;
+        let (near, far) = (64.0, 128.0);
+        assert_eq!(TreeLod::for_distance(10.0, near, far), Full);
+        assert_eq!(TreeLod::for_distance(100.0, near, far), Reduced);
+        assert_eq!(TreeLod::for_distance(200.0, near, far), Impostor);
+
+        // Just past a boundary keeps the tier the trees are drawn with
+        assert_eq!(Full.at_distance(near + 4.0, near, far), Full);
+        assert_eq!(Reduced.at_distance(near - 4.0, near, far), Reduced);
+        assert_eq!(Reduced.at_distance(far + 4.0, near, far), Reduced);
+        assert_eq!(Impostor.at_distance(far - 4.0, near, far), Impostor);
+
+        // Well past it they change
+        assert_eq!(Full.at_distance(near + 10.0, near, far), Reduced);
+        assert_eq!(Reduced.at_distance(near - 10.0, near, far), Full);
+        assert_eq!(Reduced.at_distance(far + 10.0, near, far), Impostor);
+        assert_eq!(Impostor.at_distance(far - 10.0, near, far), Reduced);
+        assert_eq!(Full.at_distance(300.0, near, far), Impostor);
+        assert_eq!(Impostor.at_distance(0.0, near, far), Full);
+    }
+
+    #[test]
+    fn test_instances_reference_their_prototypes() {
+        let terrain = WorldTerrain::new(12345);
+        let species = SpeciesRegistry::builtin();
+        let prototypes = TreePrototypes::new(&species, Season::Summer);
+
+        let mut count = 0;
+        for cx in 96..112 {
+            let trees: Vec<Tree> = generate_entities_for_chunk(12345, cx, 384, &terrain, &species)
+                .into_iter()
+                .map(|(position, id)| Tree::wild(position, id, species.get(id).growth_days))
+                .collect();
+            let day = 3.0;
+            let (batch, _, growing) = TreeBatch::new(&trees, day, &species, &prototypes);
+            assert_eq!(batch.instances.len(), trees.len());
+            assert_eq!(batch.ranges.len(), prototypes.ranges().len());
+            assert!(growing.contains(&day));
+
+            for (index, range) in batch.ranges.iter().enumerate() {
+                for instance in &batch.instances[range.start as usize..range.end as usize] {
+                    let tree = trees
+                        .iter()
+                        .find(|t| t.position == instance.position())
+                        .expect("Every instance should be one of the cell's trees");
+                    let growth_days = species.get(tree.species).growth_days;
+                    let (step, _) = tree.growth(day, growth_days);
+                    assert_eq!(prototypes.index(tree.species, tree.variant, step), index);
+                }
+            }
+            count += trees.len();
+        }
+        assert!(count > 0, "Expected some trees to test");
+    }
+
+    #[test]
+    fn test_trees_grow_over_time() {
+        let species = SpeciesRegistry::builtin();
+        let oak = species.find("oak").unwrap();
+        let growth_days = species.get(oak).growth_days;
+        let tree = Tree::sapling(Vec3::new(10.0, 40.0, 10.0), oak, 2.0);
+
+        let (step, lasts) = tree.growth(2.0, growth_days);
+        assert_eq!(step, 1);
+        assert!(lasts.contains(&2.0));
+        assert_eq!(tree.growth(1.0, growth_days).0, 1, "Not planted yet");
+
+        let mut last = 0;
+        for day in 0..=20 {
+            let day = 2.0 + growth_days as f64 * day as f64 / 20.0;
+            let (step, lasts) = tree.growth(day, growth_days);
+            assert!(step >= last, "Trees don't shrink");
+            assert!(lasts.contains(&day));
+            last = step;
+        }
+        assert_eq!(last, GROWTH_STEPS);
+        assert_eq!(tree.growth(1000.0, growth_days).1.end, f64::INFINITY);
+        assert!(tree.instance(1).scale() < tree.instance(GROWTH_STEPS).scale());
+
+        // Most wild trees are long grown
+        let grown = (0..100)
+            .map(|i| Tree::wild(Vec3::new(i as f32 * 7.0, 40.0, 3.0), oak, growth_days))
+            .filter(|tree| tree.growth(0.0, growth_days).0 == GROWTH_STEPS)
+            .count();
+        assert!(grown > 50 && grown < 100, "{} of 100 are grown", grown);
+    }
+
+    #[test]
+    fn test_saplings_grow_and_are_saved() {
+        let species = Arc::new(SpeciesRegistry::builtin());
+        let new_manager = || {
+            EntityManager::new(
+                12345,
+                Arc::new(WorldTerrain::new(12345)),
+                species.clone(),
+                Season::Summer,
+            )
+        };
+        let mut em = new_manager();
+        let player_pos = Vec3::new(0.0, 0.0, 0.0);
+        em.set_day(5.0);
+        em.update(&player_pos, 0);
+        em.wait_for_all_in_flight();
+
+        let key = UVec2::new(0, 0);
+        let before = em.loaded_cells[&key].batch.instances.len();
+        let oak = species.find("oak").unwrap();
+        em.plant(Vec3::new(8.5, 40.0, 8.5), oak);
+        em.update(&player_pos, 0);
+        let cell = &em.loaded_cells[&key];
+        assert_eq!(cell.batch.instances.len(), before + 1);
+        assert_eq!(cell.revision, 1);
+
+        // Growing a step rebuilds the cell
+        em.set_day(5.0 + species.get(oak).growth_days as f64);
+        em.update(&player_pos, 0);
+        assert_eq!(em.loaded_cells[&key].revision, 2);
+        em.update(&player_pos, 0);
+        assert_eq!(em.loaded_cells[&key].revision, 2);
+
+        let saved = em.saplings();
+        assert_eq!(
+            saved,
+            vec![SaplingConfig {
+                species: "oak".to_string(),
+                position: [8.5, 40.0, 8.5],
+                planted: 5.0,
+            }]
+        );
+
+        // Saplings come back, and show up when their cell loads
+        let mut restored = new_manager();
+        restored.restore_saplings(&saved);
+        restored.restore_saplings(&[SaplingConfig {
+            species: "triffid".to_string(),
+            position: [1.0, 40.0, 1.0],
+            planted: 0.0,
+        }]);
+        assert_eq!(restored.saplings(), saved);
+        restored.set_day(5.0);
+        restored.update(&player_pos, 0);
+        restored.wait_for_all_in_flight();
+        assert_eq!(
+            restored.loaded_cells[&key].batch.instances.len(),
+            before + 1
+        );
+    }
+
+    #[test]
+    fn test_trees_block_the_player() {
+        let species = Arc::new(SpeciesRegistry::builtin());
+        let mut em = EntityManager::new(
+            12345,
+            Arc::new(WorldTerrain::new(12345)),
+            species.clone(),
+            Season::Summer,
+        )
+        .without_wild_trees();
+        let oak = species.find("oak").unwrap();
+        let root = Vec3::new(8.5, 40.0, 8.5);
+        em.plant(root, oak);
+        em.update(&root, 0);
+
+        // Walking into the trunk, but not beside it
+        let body = |x: f32| (Vec3::new(x, 40.5, 8.5), Vec3::new(x, 42.0, 8.5));
+        let (start, end) = body(8.5);
+        assert!(em.collides(start, end, 0.3));
+        let (start, end) = body(12.0);
+        assert!(!em.collides(start, end, 0.3));
+        let (hit, shapes) = em.raycast(Vec3::new(4.5, 41.0, 8.5), Vec3::X, 8.0).unwrap();
+        assert!(hit > 3.0 && hit < 4.0, "hit at {hit}");
+        assert!(!shapes.is_empty());
+        let behind = em.raycast(Vec3::new(4.5, 41.0, 8.5), Vec3::NEG_X, 8.0);
+        assert_eq!(behind, None);
+
+        // The trunk thickens as the sapling grows
+        let (sapling, _) = em.raycast(Vec3::new(4.5, 40.2, 8.5), Vec3::X, 8.0).unwrap();
+        em.set_day(species.get(oak).growth_days as f64);
+        em.update(&root, 0);
+        let (grown, _) = em.raycast(Vec3::new(4.5, 40.2, 8.5), Vec3::X, 8.0).unwrap();
+        assert!(grown < sapling, "{grown} vs {sapling}");
+    }
+
     #[test]
     fn test_entity_generation_bitmap() {
         use rayon::prelude::*;
@@ -249,17 +1157,17 @@ mod tests {
             .par_chunks_mut(width as usize)
             .enumerate()
             .for_each(|(z, row)| {
-                for x in 0..(width as usize) {
+                for (x, pixel) in row.iter_mut().enumerate() {
                     let tdata = terrain.get(glam::Vec2::new(x as f32, z as f32));
                     if tdata.height <= WATER_LEVEL {
                         let depth = (WATER_LEVEL - tdata.height).clamp(0.0, 30.0);
                         let b = 255 - (depth as u8 * 4);
-                        row[x] = image::Rgb([0, 0, b]);
+                        *pixel = image::Rgb([0, 0, b]);
                     } else {
                         let intensity = ((tdata.height - WATER_LEVEL) / 80.0
                             * 255.0)
                             .clamp(0.0, 255.0) as u8;
-                        row[x] = image::Rgb([
+                        *pixel = image::Rgb([
                             intensity / 2 + 30,
                             intensity / 2 + 30,
                             intensity / 2 + 30,
@@ -282,23 +1190,28 @@ mod tests {
             .flat_map(|cx| (0..chunk_count_h).map(move |cz| (cx, cz)))
             .collect();
 
-        let all_entities: Vec<Vec<(glam::Vec3, TreeType)>> = chunk_coords
+        let species = SpeciesRegistry::builtin();
+        let all_entities: Vec<Vec<(glam::Vec3, SpeciesId)>> = chunk_coords
             .par_iter()
-            .map(|&(cx, cz)| generate_entities_for_chunk(12345, cx, cz, &terrain))
+            .map(|&(cx, cz)| generate_entities_for_chunk(12345, cx, cz, &terrain, &species))
             .collect();
 
         for entities in all_entities {
-            for (p, tree_type) in entities {
+            for (p, id) in entities {
                 let px = p.x.round() as u32;
                 let pz = p.z.round() as u32;
 
                 if px < width && pz < height {
-                    let color: [u8; 3] = match tree_type {
-                        TreeType::Bush => [255, 140, 0], // Dark Orange (contrast with green grove)
-                        TreeType::Palm => [255, 255, 0], // Yellow
-                        TreeType::Pine => [200, 200, 200], // Grey/White
-                        TreeType::Birch => [255, 255, 255], // White
-                        TreeType::Oak => [100, 255, 100], // Light green
+                    let color: [u8; 3] = match species.get(id).name.as_str() {
+                        "bush" => [255, 140, 0],    // Dark Orange (contrast with green grove)
+                        "palm" => [255, 255, 0],    // Yellow
+                        "pine" => [200, 200, 200],  // Grey/White
+                        "birch" => [255, 255, 255], // White
+                        "oak" => [100, 255, 100],   // Light green
+                        "acacia" => [255, 60, 60],  // Red
+                        "jungle" => [255, 0, 255],  // Magenta
+                        "willow" => [0, 200, 255],  // Cyan
+                        _ => [255, 0, 0],
                     };
 
                     for dx in -1..=1 {
@@ -0,0 +1,369 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use glam::IVec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Blocks between tile centres. Each tile covers twice this so that its
+/// neighbours overlap it by half on every side.
+const TILE_SIZE: i32 = 64;
+/// Blocks between heightmap samples within a tile.
+const CELL_SIZE: i32 = 2;
+const CELLS: usize = (2 * TILE_SIZE / CELL_SIZE) as usize + 1;
+/// Eroded tiles are kept around for reuse until there are this many.
+const MAX_CACHED_TILES: usize = 1024;
+
+// Droplet parameters for the hydraulic pass.
+const DROPLETS_PER_CELL: f32 = 0.6;
+const DROPLET_STEPS: usize = 40;
+const INERTIA: f32 = 0.3;
+const CAPACITY: f32 = 4.0;
+const MIN_CAPACITY: f32 = 0.01;
+const ERODE_RATE: f32 = 0.3;
+const DEPOSIT_RATE: f32 = 0.3;
+const EVAPORATION: f32 = 0.02;
+const GRAVITY: f32 = 4.0;
+
+// Thermal pass: material slides downhill wherever the drop to a neighbour is
+// steeper than the talus angle.
+const THERMAL_ITERATIONS: usize = 24;
+/// Largest stable drop, in blocks, between neighbouring samples.
+const TALUS: f32 = 2.4;
+
+type Tile = Arc<Vec<f32>>;
+
+#[derive(Default)]
+struct TileCache {
+    tiles: HashMap<IVec2, Tile>,
+    order: VecDeque<IVec2>,
+}
+
+/// Runs hydraulic and thermal erosion over overlapping tiles of the
+/// heightmap and blends the results together, so the change in height at any
+/// point is smooth and doesn't depend on which tiles were eroded first.
+#[derive(Clone)]
+pub(crate) struct Erosion {
+    seed: u32,
+    strength: f32,
+    cache: Arc<Mutex<TileCache>>,
+}
+
+impl Erosion {
+    /// Erosion starts disabled until given a strength.
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            strength: 0.0,
+            cache: Arc::new(Mutex::new(TileCache::default())),
+        }
+    }
+
+    pub(crate) fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength.max(0.0);
+        self
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.strength > 0.0
+    }
+
+    /// The change in height, in blocks, erosion makes at world position
+    /// `(x, z)`. `height` gives the uneroded height at a world position.
+    pub(crate) fn offset<F>(&self, x: f64, z: f64, height: &F) -> f64
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        if !self.enabled() {
+            return 0.0;
+        }
+
+        let tx = x / TILE_SIZE as f64;
+        let tz = z / TILE_SIZE as f64;
+        let (ix, iz) = (tx.floor() as i32, tz.floor() as i32);
+        let (fx, fz) = (tx - ix as f64, tz - iz as f64);
+
+        // Tent weights over the four tiles whose centres surround the point.
+        let mut offset = 0.0;
+        for (dx, dz, w) in [
+            (0, 0, (1.0 - fx) * (1.0 - fz)),
+            (1, 0, fx * (1.0 - fz)),
+            (0, 1, (1.0 - fx) * fz),
+            (1, 1, fx * fz),
+        ] {
+            if w <= 0.0 {
+                continue;
+            }
+            let key = IVec2::new(ix + dx, iz + dz);
+            let tile = self.tile(key, height);
+            let origin = tile_origin(key);
+            offset += w * sample(
+                &tile,
+                (x - origin.x as f64) / CELL_SIZE as f64,
+                (z - origin.y as f64) / CELL_SIZE as f64,
+            );
+        }
+        offset * self.strength as f64
+    }
+
+    fn tile<F>(&self, key: IVec2, height: &F) -> Tile
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        if let Some(tile) = self.cache.lock().unwrap().tiles.get(&key) {
+            return Arc::clone(tile);
+        }
+
+        // Eroded outside the lock so loader threads can work on different
+        // tiles at once. If two threads race on the same tile they both get
+        // the same answer.
+        let tile = Arc::new(erode_tile(self.seed, key, height));
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.tiles.insert(key, Arc::clone(&tile)).is_none() {
+            cache.order.push_back(key);
+            while cache.order.len() > MAX_CACHED_TILES {
+                if let Some(old) = cache.order.pop_front() {
+                    cache.tiles.remove(&old);
+                }
+            }
+        }
+        tile
+    }
+}
+
+/// World position of the first sample in the tile centred on
+/// `key * TILE_SIZE`.
+fn tile_origin(key: IVec2) -> IVec2 {
+    key * TILE_SIZE - IVec2::splat(TILE_SIZE)
+}
+
+/// Bilinearly samples a grid of `CELLS` x `CELLS` values at a position in
+/// cells, clamping to the edges.
+fn sample(grid: &[f32], x: f64, z: f64) -> f64 {
+    let max = (CELLS - 1) as f64;
+    let x = x.clamp(0.0, max);
+    let z = z.clamp(0.0, max);
+    let (ix, iz) = ((x as usize).min(CELLS - 2), (z as usize).min(CELLS - 2));
+    let (fx, fz) = (x - ix as f64, z - iz as f64);
+    let at = |x: usize, z: usize| grid[z * CELLS + x] as f64;
+    let top = at(ix, iz) * (1.0 - fx) + at(ix + 1, iz) * fx;
+    let bottom = at(ix, iz + 1) * (1.0 - fx) + at(ix + 1, iz + 1) * fx;
+    top * (1.0 - fz) + bottom * fz
+}
+
+/// Erodes a single tile and returns the change in height at each sample.
+fn erode_tile<F>(seed: u32, key: IVec2, height: &F) -> Vec<f32>
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let origin = tile_origin(key);
+    let mut heights = vec![0.0f32; CELLS * CELLS];
+    for z in 0..CELLS {
+        for x in 0..CELLS {
+            heights[z * CELLS + x] = height(
+                (origin.x + x as i32 * CELL_SIZE) as f64,
+                (origin.y + z as i32 * CELL_SIZE) as f64,
+            ) as f32;
+        }
+    }
+    let original = heights.clone();
+
+    let tile_seed =
+        (seed as u64) << 32 ^ (key.x as u32 as u64).wrapping_mul(0x9E3779B9) ^ key.y as u32 as u64;
+    let mut rng = StdRng::seed_from_u64(tile_seed);
+    hydraulic(&mut heights, &mut rng);
+    thermal(&mut heights);
+
+    heights
+        .iter()
+        .zip(&original)
+        .map(|(eroded, original)| eroded - original)
+        .collect()
+}
+
+/// Height and gradient at a position in cells.
+fn height_and_gradient(heights: &[f32], x: f32, z: f32) -> (f32, f32, f32) {
+    let (ix, iz) = (x as usize, z as usize);
+    let (fx, fz) = (x - ix as f32, z - iz as f32);
+    let i = iz * CELLS + ix;
+    let (nw, ne, sw, se) = (
+        heights[i],
+        heights[i + 1],
+        heights[i + CELLS],
+        heights[i + CELLS + 1],
+    );
+    let gx = (ne - nw) * (1.0 - fz) + (se - sw) * fz;
+    let gz = (sw - nw) * (1.0 - fx) + (se - ne) * fx;
+    let h =
+        nw * (1.0 - fx) * (1.0 - fz) + ne * fx * (1.0 - fz) + sw * (1.0 - fx) * fz + se * fx * fz;
+    (h, gx, gz)
+}
+
+/// Spreads `amount` over the four samples around a position in cells.
+fn deposit(heights: &mut [f32], x: f32, z: f32, amount: f32) {
+    let (ix, iz) = (x as usize, z as usize);
+    let (fx, fz) = (x - ix as f32, z - iz as f32);
+    let i = iz * CELLS + ix;
+    heights[i] += amount * (1.0 - fx) * (1.0 - fz);
+    heights[i + 1] += amount * fx * (1.0 - fz);
+    heights[i + CELLS] += amount * (1.0 - fx) * fz;
+    heights[i + CELLS + 1] += amount * fx * fz;
+}
+
+/// Rolls rain droplets down the slopes. They pick up sediment while
+/// speeding downhill, cutting valleys, and drop it where they slow down,
+/// leaving fans at the foot of slopes.
+fn hydraulic(heights: &mut [f32], rng: &mut StdRng) {
+    let max = (CELLS - 1) as f32;
+    let droplets = (CELLS * CELLS) as f32 * DROPLETS_PER_CELL;
+
+    for _ in 0..droplets as usize {
+        let mut x = rng.gen_range(0.0..max);
+        let mut z = rng.gen_range(0.0..max);
+        let (mut dx, mut dz) = (0.0f32, 0.0f32);
+        let mut speed = 1.0f32;
+        let mut water = 1.0f32;
+        let mut sediment = 0.0f32;
+
+        for _ in 0..DROPLET_STEPS {
+            let (h, gx, gz) = height_and_gradient(heights, x, z);
+
+            dx = dx * INERTIA - gx * (1.0 - INERTIA);
+            dz = dz * INERTIA - gz * (1.0 - INERTIA);
+            let len = (dx * dx + dz * dz).sqrt();
+            if len < 1e-6 {
+                break;
+            }
+            dx /= len;
+            dz /= len;
+
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0.0 || nz < 0.0 || nx >= max || nz >= max {
+                break;
+            }
+
+            let (nh, _, _) = height_and_gradient(heights, nx, nz);
+            let dh = nh - h;
+            let capacity = (-dh * speed * water * CAPACITY).max(MIN_CAPACITY);
+
+            if dh > 0.0 || sediment > capacity {
+                // Fill the pit in front, or drop what can't be carried.
+                let amount = if dh > 0.0 {
+                    dh.min(sediment)
+                } else {
+                    (sediment - capacity) * DEPOSIT_RATE
+                };
+                sediment -= amount;
+                deposit(heights, x, z, amount);
+            } else {
+                let amount = ((capacity - sediment) * ERODE_RATE).min(-dh);
+                sediment += amount;
+                deposit(heights, x, z, -amount);
+            }
+
+            speed = (speed * speed - dh * GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - EVAPORATION;
+            x = nx;
+            z = nz;
+        }
+    }
+}
+
+/// Slumps slopes steeper than the talus angle until they settle into scree.
+fn thermal(heights: &mut [f32]) {
+    let mut moved = vec![0.0f32; heights.len()];
+    for _ in 0..THERMAL_ITERATIONS {
+        moved.fill(0.0);
+        for z in 0..CELLS {
+            for x in 0..CELLS {
+                let i = z * CELLS + x;
+                let h = heights[i];
+                for (nx, nz) in [(x + 1, z), (x, z + 1)] {
+                    if nx >= CELLS || nz >= CELLS {
+                        continue;
+                    }
+                    let n = nz * CELLS + nx;
+                    let drop = h - heights[n];
+                    if drop.abs() > TALUS {
+                        let amount = (drop.abs() - TALUS) * 0.25 * drop.signum();
+                        moved[i] -= amount;
+                        moved[n] += amount;
+                    }
+                }
+            }
+        }
+        for (h, m) in heights.iter_mut().zip(&moved) {
+            *h += m;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ridged mountain with steep flanks.
+    fn mountain(x: f64, z: f64) -> f64 {
+        let r = (x * x + z * z).sqrt();
+        let ridges = (x * 0.15).sin() * (z * 0.11).cos() * 6.0;
+        (150.0 - r * 1.5).max(30.0) + ridges
+    }
+
+    #[test]
+    fn test_erosion_is_deterministic() {
+        let a = Erosion::new(7).with_strength(1.0);
+        let b = Erosion::new(7).with_strength(1.0);
+        let c = Erosion::new(8).with_strength(1.0);
+        let points = [(10.0, 20.0), (-37.0, 5.0), (63.0, -64.0)];
+        for (x, z) in points {
+            assert_eq!(a.offset(x, z, &mountain), b.offset(x, z, &mountain));
+        }
+        assert!(points
+            .iter()
+            .any(|&(x, z)| a.offset(x, z, &mountain) != c.offset(x, z, &mountain)));
+        assert_eq!(
+            Erosion::new(7)
+                .with_strength(0.0)
+                .offset(10.0, 20.0, &mountain),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_erosion_is_seamless_across_tiles() {
+        let erosion = Erosion::new(3).with_strength(1.0);
+        let mut largest_step = 0.0f64;
+        let mut largest_offset = 0.0f64;
+        // Walk across several tile boundaries and centres.
+        for i in 0..(4 * TILE_SIZE) {
+            let x = (i - 2 * TILE_SIZE) as f64;
+            let a = erosion.offset(x, 17.0, &mountain);
+            let b = erosion.offset(x + 0.25, 17.0, &mountain);
+            largest_step = largest_step.max((a - b).abs());
+            largest_offset = largest_offset.max(a.abs());
+        }
+        assert!(largest_offset > 1.0, "Erosion should change the terrain");
+        assert!(
+            largest_step < 1.0,
+            "Step of {largest_step} between neighbours"
+        );
+    }
+
+    #[test]
+    fn test_erosion_cuts_slopes_and_fills_valleys() {
+        let erosion = Erosion::new(11).with_strength(1.0);
+        let (mut cut, mut filled) = (0, 0);
+        for i in 0..64 {
+            let r = i as f64 * 2.0;
+            let offset = erosion.offset(r, 3.0, &mountain);
+            if r < 70.0 && offset < -0.1 {
+                cut += 1;
+            }
+            if r > 80.0 && offset > 0.1 {
+                filled += 1;
+            }
+        }
+        assert!(cut > 0, "Flanks should be worn down");
+        assert!(filled > 0, "Sediment should collect at the foot");
+    }
+}
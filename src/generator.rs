@@ -0,0 +1,665 @@
+use crate::{
+    block::{self, Block},
+    caves::CaveKind,
+    chunks::{Chunk, MAX_HEIGHT},
+    config::FlatLayer,
+    ores::Ore,
+    structures::{Cell, Piece, Structure, StructureKind},
+    terrain::{hash2, Biome, TerrainData, WorldTerrain, BEDROCK_LEVEL},
+};
+use glam::{IVec2, IVec3, UVec2, Vec2, Vec3};
+use std::collections::HashMap;
+
+/// Ground colder than this is covered in snow and ice.
+const SNOW_COVER_TEMP: f32 = 0.12;
+/// Grass gives way to bare sand where it's drier than this.
+const BARE_GROUND_MOIST: f32 = 0.12;
+/// Ground moved by the density stage is only searched this far up for the
+/// open air it's layered down from. Anything deeper is bedrock.
+const LAYER_DEPTH: i32 = 6;
+/// Dripstone only grows in caves with at least this much ground over them.
+const DRIPSTONE_COVER: f32 = 8.0;
+/// Chance of dripstone at each open ceiling or floor block of a cave.
+const DRIPSTONE_CHANCE: f64 = 0.04;
+const DRIPSTONE_MAX_LENGTH: i32 = 3;
+
+/// Where a world's terrain comes from. Chunks are filled from it, trees and
+/// buildings are placed on it, and the console searches it.
+pub trait TerrainGenerator: Send + Sync {
+    /// The height, biome, climate and water of the column at `point`.
+    fn get(&self, point: Vec2) -> TerrainData;
+
+    /// Whether the block at `point` is ground, given the terrain of its
+    /// column.
+    fn is_solid(&self, point: Vec3, tdata: &TerrainData) -> bool {
+        point.y < tdata.height
+    }
+
+    /// The top of the highest solid block in the column.
+    fn surface_height(&self, point: Vec2) -> f32 {
+        self.get(point).height
+    }
+
+    fn biome(&self, point: Vec2) -> Biome {
+        self.get(point).biome
+    }
+
+    /// The surface of the sea.
+    fn sea_level(&self) -> f32;
+
+    /// The blocks of the chunk column at `key`, bottom section first.
+    fn generate_column(&self, key: UVec2) -> Vec<Chunk>;
+
+    /// The buildings that reach into the chunk column at `key`.
+    fn structures_near(&self, _key: IVec2) -> Vec<Structure> {
+        Vec::new()
+    }
+
+    /// How the biomes are mixed at `point`, for the debug overlay.
+    fn biome_blend_string(&self, point: Vec2) -> String {
+        format!("100% {}", self.biome(point))
+    }
+
+    fn find_closest_cave(&self, _start_pos: Vec2, _kind: Option<CaveKind>) -> Option<Vec3> {
+        None
+    }
+
+    fn find_closest_pure_biome(&self, _start_pos: Vec2, _target: Biome) -> Option<Vec2> {
+        None
+    }
+
+    fn find_closest_ore(&self, _start_pos: Vec3, _ore: Ore) -> Option<Vec3> {
+        None
+    }
+
+    fn find_closest_structure(&self, _start_pos: Vec2, _kind: StructureKind) -> Option<Vec3> {
+        None
+    }
+}
+
+impl TerrainGenerator for WorldTerrain {
+    fn get(&self, point: Vec2) -> TerrainData {
+        WorldTerrain::get(self, point)
+    }
+
+    fn is_solid(&self, point: Vec3, tdata: &TerrainData) -> bool {
+        WorldTerrain::is_solid(self, point, tdata)
+    }
+
+    fn surface_height(&self, point: Vec2) -> f32 {
+        WorldTerrain::surface_height(self, point)
+    }
+
+    fn sea_level(&self) -> f32 {
+        WorldTerrain::sea_level(self)
+    }
+
+    fn generate_column(&self, key: UVec2) -> Vec<Chunk> {
+        let columns = column_terrain(self, key);
+        let structures = WorldTerrain::structures_near(self, key.as_ivec2());
+        generate_world_column(self, key, &columns, &structures)
+    }
+
+    fn structures_near(&self, key: IVec2) -> Vec<Structure> {
+        WorldTerrain::structures_near(self, key)
+    }
+
+    fn biome_blend_string(&self, point: Vec2) -> String {
+        WorldTerrain::biome_blend_string(self, point)
+    }
+
+    fn find_closest_cave(&self, start_pos: Vec2, kind: Option<CaveKind>) -> Option<Vec3> {
+        WorldTerrain::find_closest_cave(self, start_pos, kind)
+    }
+
+    fn find_closest_pure_biome(&self, start_pos: Vec2, target: Biome) -> Option<Vec2> {
+        WorldTerrain::find_closest_pure_biome(self, start_pos, target)
+    }
+
+    fn find_closest_ore(&self, start_pos: Vec3, ore: Ore) -> Option<Vec3> {
+        WorldTerrain::find_closest_ore(self, start_pos, ore)
+    }
+
+    fn find_closest_structure(&self, start_pos: Vec2, kind: StructureKind) -> Option<Vec3> {
+        WorldTerrain::find_closest_structure(self, start_pos, kind)
+    }
+}
+
+/// The terrain of each column in the chunk column at `key`, indexed by
+/// `x * 16 + z`. It's the same for every section of the column.
+pub(crate) fn column_terrain(generator: &dyn TerrainGenerator, key: UVec2) -> Vec<TerrainData> {
+    let mut columns = Vec::with_capacity(16 * 16);
+    for x in 0..16 {
+        for z in 0..16 {
+            let point = glam::Vec2::new((x + 16 * key.x) as f32, (z + 16 * key.y) as f32);
+            columns.push(generator.get(point));
+        }
+    }
+    columns
+}
+
+/// Fills a chunk column from `columns`: water, layered ground for each biome,
+/// with caves and ores from the procedural terrain, and `structures` built on
+/// top.
+pub(crate) fn generate_world_column(
+    terrain: &WorldTerrain,
+    key: UVec2,
+    columns: &[TerrainData],
+    structures: &[Structure],
+) -> Vec<Chunk> {
+    // Veins can wander out of the column they start in, so the neighbours'
+    // veins are checked too.
+    let mut ores = HashMap::new();
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let neighbour = key.as_ivec2() + IVec2::new(dx, dz);
+            if neighbour.x < 0 || neighbour.y < 0 {
+                continue;
+            }
+            for vein in terrain.ore_veins(neighbour) {
+                for pos in vein.blocks {
+                    if pos.x.div_euclid(16) == key.x as i32 && pos.z.div_euclid(16) == key.y as i32
+                    {
+                        ores.insert(pos, vein.ore.block());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let num_chunks_y = (MAX_HEIGHT / 16) as u32;
+    for chunky in 0..num_chunks_y {
+        let mut chunk = Chunk::new(
+            Vec3::new(
+                16.0 * (key.x as f32),
+                16.0 * (chunky as f32),
+                16.0 * (key.y as f32),
+            ),
+            [[[Block::new(); 16]; 16]; 16],
+        );
+        for (x, row) in chunk.blocks_mut().iter_mut().enumerate() {
+            for (z, col) in row.iter_mut().enumerate() {
+                let blockx = (x as u32) + (16 * key.x);
+                let blockz = (z as u32) + (16 * key.y);
+                let tdata = &columns[x * 16 + z];
+                for (y, block) in col.iter_mut().enumerate() {
+                    let blocky = (y as u32) + (16 * chunky);
+                    let blockyf32 = blocky as f32;
+                    let point3d = glam::Vec3::new(blockx as f32, blocky as f32, blockz as f32);
+
+                    if let Some(cave) = terrain.cave_at(point3d, tdata) {
+                        let fill = cave.block();
+                        if fill != block::Type::Inactive {
+                            block.set_type(fill);
+                        }
+                        continue;
+                    }
+
+                    let solid = terrain.is_solid(point3d, tdata);
+                    if blockyf32 < tdata.water_level && !solid {
+                        block.set_type(block::Type::Water);
+                    } else if solid {
+                        let height = layer_height(terrain, point3d, tdata);
+                        let hash = (blockx.wrapping_mul(31)
+                            ^ blocky.wrapping_mul(17)
+                            ^ blockz.wrapping_mul(23))
+                            % 10;
+                        let dither = (hash as f32) - 5.0;
+                        let is_surface = blockyf32 > height - 1.0 && height >= tdata.water_level;
+
+                        let btype = match tdata.biome {
+                            _ if is_surface
+                                && tdata.biome != Biome::Ocean
+                                && tdata.temperature < SNOW_COVER_TEMP + dither * 0.01 =>
+                            {
+                                block::Type::Snow
+                            }
+                            Biome::Desert => {
+                                if blockyf32 > height - 4.0 + (dither * 0.5) {
+                                    block::Type::Sand
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Ocean => {
+                                if blockyf32 > height - 2.0 + (dither * 0.5) {
+                                    block::Type::Sand
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Beach => {
+                                if blockyf32 > height - 5.0 + (dither * 0.5) {
+                                    block::Type::Sand
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Tundra => {
+                                if blockyf32 > height - 1.0 {
+                                    if height < tdata.water_level {
+                                        block::Type::Sand
+                                    } else {
+                                        block::Type::Snow
+                                    }
+                                } else if blockyf32 > height - 3.0 + (dither * 0.3) {
+                                    block::Type::DryGrass
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Swamp => {
+                                if blockyf32 > height - 1.0 {
+                                    if height < tdata.water_level + 0.5 || dither > 2.0 {
+                                        block::Type::Mud
+                                    } else {
+                                        block::Type::Grass
+                                    }
+                                } else if blockyf32 > height - 5.0 + dither {
+                                    block::Type::Mud
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Savanna => {
+                                if blockyf32 > height - 1.0 {
+                                    if height < tdata.water_level {
+                                        block::Type::Sand
+                                    } else {
+                                        block::Type::DryGrass
+                                    }
+                                } else if blockyf32 > height - 4.0 + dither {
+                                    block::Type::Sand
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Plains | Biome::Hills | Biome::Taiga | Biome::Jungle => {
+                                if blockyf32 > height - 1.0 {
+                                    if height < tdata.water_level
+                                        || tdata.moisture < BARE_GROUND_MOIST + dither * 0.01
+                                    {
+                                        block::Type::Sand
+                                    } else {
+                                        block::Type::Grass
+                                    }
+                                } else if blockyf32 > height - 4.0 + dither {
+                                    block::Type::Sand
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                            Biome::Mountains => {
+                                if blockyf32 > 180.0 + dither {
+                                    block::Type::Ice
+                                } else if blockyf32 > 120.0 + dither {
+                                    block::Type::Rock
+                                } else if (blockyf32) > height - 1.0 {
+                                    if height < tdata.water_level {
+                                        block::Type::Sand
+                                    } else {
+                                        block::Type::Grass
+                                    }
+                                } else {
+                                    block::Type::Rock
+                                }
+                            }
+                        };
+                        let btype = match ores.get(&IVec3::new(
+                            blockx as i32,
+                            blocky as i32,
+                            blockz as i32,
+                        )) {
+                            Some(&ore) if btype == block::Type::Rock => ore,
+                            _ => btype,
+                        };
+                        block.set_type(btype);
+                    }
+                }
+            }
+        }
+        chunks.push(chunk);
+    }
+
+    grow_dripstone(&mut chunks, key, columns);
+    for structure in structures {
+        for piece in &structure.pieces {
+            stamp_piece(&mut chunks, key, piece, terrain);
+        }
+    }
+    chunks
+}
+
+/// Hangs dripstone from cave ceilings and stands it up from cave floors,
+/// anywhere deep enough under the surface to be out of the sky.
+fn grow_dripstone(chunks: &mut [Chunk], key: UVec2, columns: &[TerrainData]) {
+    let ty_at = |chunks: &[Chunk], x: usize, y: i32, z: usize| {
+        chunks[y as usize / 16].blocks()[x][z][y as usize % 16].ty()
+    };
+    for x in 0..16 {
+        for z in 0..16 {
+            let tdata = &columns[x * 16 + z];
+            let top = ((tdata.height - tdata.relief - DRIPSTONE_COVER) as i32).min(MAX_HEIGHT - 1);
+            let wx = 16 * key.x + x as u32;
+            let wz = 16 * key.y + z as u32;
+            for y in (BEDROCK_LEVEL as i32 + 1)..top {
+                if ty_at(chunks, x, y, z) != block::Type::Inactive {
+                    continue;
+                }
+                let [chance, length] = hash2(wx, wz, y as u32);
+                if chance >= DRIPSTONE_CHANCE {
+                    continue;
+                }
+                let step = if ty_at(chunks, x, y + 1, z) == block::Type::Rock {
+                    -1
+                } else if ty_at(chunks, x, y - 1, z) == block::Type::Rock {
+                    1
+                } else {
+                    continue;
+                };
+                let length = 1 + (length * DRIPSTONE_MAX_LENGTH as f64) as i32;
+                let mut y = y;
+                for _ in 0..length.min(DRIPSTONE_MAX_LENGTH) {
+                    if y <= BEDROCK_LEVEL as i32
+                        || y >= MAX_HEIGHT
+                        || ty_at(chunks, x, y, z) != block::Type::Inactive
+                    {
+                        break;
+                    }
+                    chunks[y as usize / 16].blocks_mut()[x][z][y as usize % 16]
+                        .set_type(block::Type::Dripstone);
+                    y += step;
+                }
+            }
+        }
+    }
+}
+
+/// Writes the part of `piece` that falls in the chunk column at `key`,
+/// building a foundation down to the ground under it if it needs one.
+fn stamp_piece(chunks: &mut [Chunk], key: UVec2, piece: &Piece, terrain: &WorldTerrain) {
+    let mut set = |pos: IVec3, ty: block::Type| {
+        if pos.y <= BEDROCK_LEVEL as i32 || pos.y >= MAX_HEIGHT {
+            return;
+        }
+        let chunk = &mut chunks[(pos.y / 16) as usize];
+        let block = &mut chunk.blocks_mut()[pos.x as usize][pos.z as usize][(pos.y % 16) as usize];
+        block.set_type(ty);
+    };
+
+    let size = piece.schematic.size();
+    for x in 0..16 {
+        for z in 0..16 {
+            let column = IVec2::new((key.x * 16 + x) as i32, (key.y * 16 + z) as i32);
+            if !piece.contains_column(column) {
+                continue;
+            }
+            let local_x = column.x - piece.origin.x;
+            let local_z = column.y - piece.origin.z;
+
+            for y in 0..size.y {
+                let pos = IVec3::new(x as i32, piece.origin.y + y, z as i32);
+                match piece.schematic.cell(IVec3::new(local_x, y, local_z)) {
+                    Cell::Keep => {}
+                    Cell::Air => set(pos, block::Type::Inactive),
+                    Cell::Block(ty) => set(pos, ty),
+                }
+            }
+
+            let base = piece.schematic.cell(IVec3::new(local_x, 0, local_z));
+            if piece.foundation && base != Cell::Keep {
+                // The top of the ground, after the density stage has had its
+                // way with it.
+                let ground = terrain.surface_height(column.as_vec2()).floor() as i32;
+                for y in ground..piece.origin.y {
+                    set(IVec3::new(x as i32, y, z as i32), block::Type::Rock);
+                }
+            }
+        }
+    }
+}
+
+/// The height of the ground that the solid block at `point` is layered down
+/// from. Where the density stage has moved the ground this is the first gap
+/// above the block, so overhangs and floating rocks get their own topsoil.
+fn layer_height(terrain: &WorldTerrain, point: Vec3, tdata: &TerrainData) -> f32 {
+    if tdata.relief <= 0.0 || point.y + (LAYER_DEPTH as f32) < tdata.height - tdata.relief {
+        return tdata.height;
+    }
+    for depth in 1..=LAYER_DEPTH {
+        if !terrain.is_solid(point + Vec3::Y * depth as f32, tdata) {
+            // Just inside the block below the gap, like a heightmap surface.
+            return point.y + depth as f32 - 0.5;
+        }
+    }
+    point.y + LAYER_DEPTH as f32 + 0.5
+}
+
+/// Flat layers of blocks stacked up from the bottom of the world, with no
+/// water, caves, ores or buildings.
+pub struct FlatTerrain {
+    layers: Vec<FlatLayer>,
+}
+
+impl FlatTerrain {
+    pub fn new(layers: Vec<FlatLayer>) -> Self {
+        Self { layers }
+    }
+
+    fn height(&self) -> u32 {
+        self.layers.iter().map(|l| l.depth).sum()
+    }
+
+    /// The block at height `y`.
+    fn block(&self, y: i32) -> block::Type {
+        let mut top = 0;
+        for layer in &self.layers {
+            top += layer.depth as i32;
+            if y < top {
+                return layer.block;
+            }
+        }
+        block::Type::Inactive
+    }
+}
+
+impl TerrainGenerator for FlatTerrain {
+    fn get(&self, _point: Vec2) -> TerrainData {
+        TerrainData {
+            height: self.height() as f32,
+            biome: Biome::Plains,
+            moisture: 0.5,
+            temperature: 0.5,
+            water_level: self.sea_level(),
+            relief: 0.0,
+        }
+    }
+
+    fn sea_level(&self) -> f32 {
+        // There is no sea, so nothing floods.
+        0.0
+    }
+
+    fn generate_column(&self, key: UVec2) -> Vec<Chunk> {
+        (0..MAX_HEIGHT / 16)
+            .map(|chunky| {
+                let mut chunk = Chunk::new(
+                    Vec3::new(
+                        16.0 * (key.x as f32),
+                        16.0 * (chunky as f32),
+                        16.0 * (key.y as f32),
+                    ),
+                    [[[Block::new(); 16]; 16]; 16],
+                );
+                for row in chunk.blocks_mut().iter_mut() {
+                    for col in row.iter_mut() {
+                        for (y, block) in col.iter_mut().enumerate() {
+                            block.set_type(self.block(y as i32 + 16 * chunky));
+                        }
+                    }
+                }
+                chunk
+            })
+            .collect()
+    }
+
+    fn find_closest_pure_biome(&self, start_pos: Vec2, target: Biome) -> Option<Vec2> {
+        (target == Biome::Plains).then_some(start_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_terrain() {
+        let config: crate::config::WorldConfig = toml::from_str(
+            r#"
+            seed = 1
+            [preset.superflat]
+            layers = [{ block = "Rock", depth = 4 }, { block = "Grass", depth = 1 }]
+            "#,
+        )
+        .unwrap();
+        let crate::config::WorldPreset::Superflat { layers } = config.preset else {
+            panic!("Expected a superflat preset");
+        };
+        let terrain = FlatTerrain::new(layers);
+
+        let tdata = terrain.get(Vec2::new(1234.0, 5678.0));
+        assert_eq!(tdata.height, 5.0);
+        assert_eq!(tdata.biome, Biome::Plains);
+        assert!(!tdata.is_water());
+        assert_eq!(terrain.surface_height(Vec2::new(10.0, 10.0)), 5.0);
+
+        let chunks = terrain.generate_column(UVec2::new(3, 7));
+        assert_eq!(chunks.len(), (MAX_HEIGHT / 16) as usize);
+        assert_eq!(chunks[0].start(), Vec3::new(48.0, 0.0, 112.0));
+        let column = &chunks[0].blocks()[5][9];
+        for (y, ty) in [
+            (0, block::Type::Rock),
+            (3, block::Type::Rock),
+            (4, block::Type::Grass),
+            (5, block::Type::Inactive),
+        ] {
+            assert_eq!(column[y].ty(), ty, "Wrong block at {}", y);
+        }
+        assert!(terrain.structures_near(IVec2::new(3, 7)).is_empty());
+    }
+
+    #[test]
+    fn test_world_terrain_generator() {
+        let terrain = WorldTerrain::new(12345);
+        let generator: &dyn TerrainGenerator = &terrain;
+        let point = Vec2::new(2500.0, 2500.0);
+        assert_eq!(generator.get(point), terrain.get(point));
+        assert_eq!(generator.biome(point), terrain.get(point).biome);
+        assert_eq!(generator.sea_level(), crate::terrain::WATER_LEVEL);
+
+        // The column is filled up to the surface and open above it.
+        let chunks = generator.generate_column(UVec2::new(156, 156));
+        let tdata = terrain.get(Vec2::new(2496.0, 2496.0));
+        assert!(!tdata.is_water());
+        let top = tdata.height.ceil() as usize;
+        let block = |y: usize| chunks[y / 16].blocks()[0][0][y % 16];
+        assert!(block(top - 1).is_active());
+        assert!(!block(top + 1).is_active());
+    }
+
+    #[test]
+    fn test_caves_hold_lava_and_dripstone() {
+        let terrain = WorldTerrain::new(12345);
+        let mut lava = 0;
+        let mut dripstone = 0;
+        for key in [
+            UVec2::new(120, 134),
+            UVec2::new(121, 134),
+            UVec2::new(120, 135),
+        ] {
+            for chunk in terrain.generate_column(key) {
+                for (x, slice_x) in chunk.blocks().iter().enumerate() {
+                    for (z, slice_z) in slice_x.iter().enumerate() {
+                        for (y, block) in slice_z.iter().enumerate() {
+                            let y = chunk.start().y + y as f32;
+                            match block.ty() {
+                                block::Type::Lava => {
+                                    assert!(y as f64 <= crate::caves::LAVA_LEVEL);
+                                    lava += 1;
+                                }
+                                block::Type::Dripstone => {
+                                    let point = Vec2::new(
+                                        chunk.start().x + x as f32,
+                                        chunk.start().z + z as f32,
+                                    );
+                                    assert!(y < terrain.get(point).height - DRIPSTONE_COVER);
+                                    dripstone += 1;
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        assert!(lava > 0, "No lava near bedrock");
+        assert!(dripstone > 0, "No dripstone in the caves");
+    }
+
+    #[test]
+    fn test_structures_stamp_across_chunk_columns() {
+        let terrain = WorldTerrain::new(12345);
+        let anchor = terrain
+            .find_closest_structure(Vec2::new(2048.0, 2048.0), StructureKind::Village)
+            .expect("Should find a village");
+        let anchor_key = IVec2::new(anchor.x as i32, anchor.z as i32) / 16;
+
+        // A building on the ground that runs over into the next chunk column.
+        let structures = terrain.structures_near(anchor_key);
+        let piece = structures
+            .iter()
+            .flat_map(|s| &s.pieces)
+            .find(|p| {
+                let last = p.origin + p.schematic.size() - 1;
+                p.foundation && p.origin.x.div_euclid(16) != last.x.div_euclid(16)
+            })
+            .expect("Should find a building across a chunk column edge");
+
+        let size = piece.schematic.size();
+        let mut keys = HashMap::new();
+        for x in 0..size.x {
+            for z in 0..size.z {
+                let column = IVec2::new(piece.origin.x + x, piece.origin.z + z);
+                let key = (column / 16).as_uvec2();
+                let chunks = keys
+                    .entry(key)
+                    .or_insert_with(|| terrain.generate_column(key));
+                let local = (column % 16).as_uvec2();
+                let ty = |y: i32| {
+                    chunks[y as usize / 16].blocks()[local.x as usize][local.y as usize]
+                        [y as usize % 16]
+                        .ty()
+                };
+
+                for y in 0..size.y {
+                    let expected = match piece.schematic.cell(IVec3::new(x, y, z)) {
+                        Cell::Keep => continue,
+                        Cell::Air => block::Type::Inactive,
+                        Cell::Block(ty) => ty,
+                    };
+                    assert_eq!(ty(piece.origin.y + y), expected, "At {column} y {y}");
+                }
+
+                // The foundation meets the ground as it is, overhangs and all.
+                if piece.schematic.cell(IVec3::new(x, 0, z)) != Cell::Keep {
+                    let ground = terrain.surface_height(column.as_vec2()).floor() as i32;
+                    for y in ground..piece.origin.y {
+                        assert_eq!(ty(y), block::Type::Rock, "Foundation at {column} y {y}");
+                    }
+                }
+            }
+        }
+        assert!(keys.len() > 1);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::{
+    caves::CaveKind,
+    chunks::Chunk,
+    config::HeightmapConfig,
+    debug,
+    generator::{column_terrain, generate_world_column, TerrainGenerator},
+    ores::Ore,
+    structures::{Structure, StructureKind},
+    terrain::{Biome, TerrainData, WorldTerrain, BEDROCK_LEVEL},
+};
+use glam::{IVec2, UVec2, Vec2, Vec3};
+use std::fs::File;
+
+/// Blocks over which the image's heights fade into the procedural terrain
+/// around it, so there are no cliffs at its edges.
+const EDGE_BLEND: f32 = 32.0;
+/// Land this close above the sea is beach, when there is no biome map.
+const BEACH_HEIGHT: f32 = 2.0;
+
+/// A decoded PNG, with every sample widened to 16 bits.
+struct Image {
+    width: usize,
+    height: usize,
+    channels: usize,
+    samples: Vec<u16>,
+}
+
+impl Image {
+    fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let mut decoder = png::Decoder::new(file);
+        // Palettes and packed low bit depths come out as plain 8-bit samples.
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|e| format!("Failed to decode {}: {}", path, e))?;
+        buf.truncate(info.buffer_size());
+
+        let samples = match info.bit_depth {
+            png::BitDepth::Sixteen => buf
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect(),
+            _ => buf.iter().map(|&b| b as u16 * 257).collect(),
+        };
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            channels: info.color_type.samples(),
+            samples,
+        })
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> &[u16] {
+        let start = (y * self.width + x) * self.channels;
+        &self.samples[start..start + self.channels]
+    }
+}
+
+/// The biome painted in the debug colour nearest to `pixel`.
+fn biome_for_colour(pixel: &[u16]) -> Biome {
+    let rgb = if pixel.len() >= 3 {
+        [pixel[0], pixel[1], pixel[2]]
+    } else {
+        [pixel[0]; 3]
+    };
+    let distance = |biome: &Biome| -> i32 {
+        let colour = debug::biome_color(*biome);
+        (0..3)
+            .map(|i| (colour[i] as i32 - (rgb[i] / 257) as i32).pow(2))
+            .sum()
+    };
+    *Biome::ALL.iter().min_by_key(|b| distance(b)).unwrap()
+}
+
+/// Heights, and optionally biomes, read from images laid over the world,
+/// with procedural terrain everywhere else.
+pub struct HeightmapTerrain {
+    fallback: WorldTerrain,
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+    biomes: Option<Vec<Biome>>,
+    origin: Vec2,
+    pixel_size: f32,
+    sea_level: f32,
+}
+
+impl HeightmapTerrain {
+    pub fn load(config: &HeightmapConfig, fallback: WorldTerrain) -> Result<Self, String> {
+        let heightmap = Image::load(&config.path)?;
+        let biome_map = config.biome_map.as_deref().map(Image::load).transpose()?;
+        Self::from_images(config, &heightmap, biome_map.as_ref(), fallback)
+    }
+
+    fn from_images(
+        config: &HeightmapConfig,
+        heightmap: &Image,
+        biome_map: Option<&Image>,
+        fallback: WorldTerrain,
+    ) -> Result<Self, String> {
+        if heightmap.width == 0 || heightmap.height == 0 {
+            return Err(format!("{} is empty", config.path));
+        }
+
+        let floor = BEDROCK_LEVEL as f32 + 1.0;
+        let mut heights = Vec::with_capacity(heightmap.width * heightmap.height);
+        for y in 0..heightmap.height {
+            for x in 0..heightmap.width {
+                let value = heightmap.pixel(x, y)[0] as f32 / u16::MAX as f32;
+                heights.push((value * config.height_scale).max(floor));
+            }
+        }
+
+        let biomes = match biome_map {
+            Some(map) if map.width != heightmap.width || map.height != heightmap.height => {
+                return Err(format!(
+                    "The biome map is {}x{} but the heightmap is {}x{}",
+                    map.width, map.height, heightmap.width, heightmap.height
+                ));
+            }
+            Some(map) => Some(
+                (0..map.height)
+                    .flat_map(|y| (0..map.width).map(move |x| (x, y)))
+                    .map(|(x, y)| biome_for_colour(map.pixel(x, y)))
+                    .collect(),
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            fallback,
+            width: heightmap.width,
+            depth: heightmap.height,
+            heights,
+            biomes,
+            origin: Vec2::from(config.origin),
+            pixel_size: config.pixel_size.max(0.01),
+            sea_level: config.sea_level,
+        })
+    }
+
+    /// How much the image decides the terrain at `point`, from 0 outside it
+    /// to 1 once well inside its edges.
+    fn image_weight(&self, point: Vec2) -> f32 {
+        let size = Vec2::new(self.width as f32, self.depth as f32) * self.pixel_size;
+        let near = point - self.origin;
+        let far = self.origin + size - point;
+        let edge = near.min(far).min_element();
+        if edge <= 0.0 {
+            return 0.0;
+        }
+        let t = (edge / EDGE_BLEND).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Where `point` falls in the image, in pixels, with pixel centres on
+    /// whole numbers.
+    fn pixel_coords(&self, point: Vec2) -> Vec2 {
+        ((point - self.origin) / self.pixel_size - 0.5).clamp(
+            Vec2::ZERO,
+            Vec2::new(self.width as f32 - 1.0, self.depth as f32 - 1.0),
+        )
+    }
+
+    fn sample_height(&self, point: Vec2) -> f32 {
+        let p = self.pixel_coords(point);
+        let (x0, y0) = (p.x.floor() as usize, p.y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.depth - 1));
+        let (tx, ty) = (p.x.fract(), p.y.fract());
+        let h = |x: usize, y: usize| self.heights[y * self.width + x];
+        let top = h(x0, y0) + (h(x1, y0) - h(x0, y0)) * tx;
+        let bottom = h(x0, y1) + (h(x1, y1) - h(x0, y1)) * tx;
+        top + (bottom - top) * ty
+    }
+
+    fn sample_biome(&self, point: Vec2) -> Option<Biome> {
+        let biomes = self.biomes.as_ref()?;
+        let p = self.pixel_coords(point).round();
+        Some(biomes[p.y as usize * self.width + p.x as usize])
+    }
+
+    /// Whether the image reaches any of the columns `structure` would be
+    /// built on, going by the corners of its pieces.
+    fn covers_structure(&self, structure: &Structure) -> bool {
+        structure.pieces.iter().any(|piece| {
+            let size = piece.schematic.size();
+            let min = Vec2::new(piece.origin.x as f32, piece.origin.z as f32);
+            let max = min + Vec2::new(size.x as f32, size.z as f32);
+            [min, max, Vec2::new(min.x, max.y), Vec2::new(max.x, min.y)]
+                .iter()
+                .any(|&corner| self.image_weight(corner) > 0.0)
+        })
+    }
+}
+
+impl TerrainGenerator for HeightmapTerrain {
+    fn get(&self, point: Vec2) -> TerrainData {
+        let mut tdata = self.fallback.get(point);
+        let weight = self.image_weight(point);
+        if weight <= 0.0 {
+            return tdata;
+        }
+
+        let height = self.sample_height(point);
+        tdata.height += (height - tdata.height) * weight;
+        tdata.relief = 0.0;
+        // The water blends with the ground, so two different sea levels meet
+        // in a slope rather than a wall.
+        tdata.water_level += (self.sea_level - tdata.water_level) * weight;
+        if weight >= 0.5 {
+            tdata.biome = match self.sample_biome(point) {
+                Some(biome) => biome,
+                None if tdata.height < self.sea_level => Biome::Ocean,
+                None if tdata.height < self.sea_level + BEACH_HEIGHT => Biome::Beach,
+                None => WorldTerrain::land_biome(tdata.temperature, tdata.moisture),
+            };
+        }
+        tdata
+    }
+
+    fn is_solid(&self, point: Vec3, tdata: &TerrainData) -> bool {
+        self.fallback.is_solid(point, tdata)
+    }
+
+    fn surface_height(&self, point: Vec2) -> f32 {
+        // Blocks are generated from the terrain at their corner.
+        let column = point.floor();
+        if self.image_weight(column) > 0.0 {
+            self.get(column).height
+        } else {
+            self.fallback.surface_height(point)
+        }
+    }
+
+    fn sea_level(&self) -> f32 {
+        self.sea_level
+    }
+
+    fn generate_column(&self, key: UVec2) -> Vec<Chunk> {
+        let columns = column_terrain(self, key);
+        let structures = self.structures_near(key.as_ivec2());
+        generate_world_column(&self.fallback, key, &columns, &structures)
+    }
+
+    fn structures_near(&self, key: IVec2) -> Vec<Structure> {
+        // Buildings are planned on the procedural ground, so none go where
+        // the image has moved it.
+        let mut structures = self.fallback.structures_near(key);
+        structures.retain(|s| !self.covers_structure(s));
+        structures
+    }
+
+    fn biome_blend_string(&self, point: Vec2) -> String {
+        if self.image_weight(point) > 0.0 {
+            format!("100% {} (heightmap)", self.get(point).biome)
+        } else {
+            self.fallback.biome_blend_string(point)
+        }
+    }
+
+    fn find_closest_cave(&self, start_pos: Vec2, kind: Option<CaveKind>) -> Option<Vec3> {
+        self.fallback.find_closest_cave(start_pos, kind)
+    }
+
+    fn find_closest_pure_biome(&self, start_pos: Vec2, target: Biome) -> Option<Vec2> {
+        self.fallback.find_closest_pure_biome(start_pos, target)
+    }
+
+    fn find_closest_ore(&self, start_pos: Vec3, ore: Ore) -> Option<Vec3> {
+        self.fallback.find_closest_ore(start_pos, ore)
+    }
+
+    fn find_closest_structure(&self, start_pos: Vec2, kind: StructureKind) -> Option<Vec3> {
+        self.fallback
+            .find_closest_structure(start_pos, kind)
+            .filter(|p| self.image_weight(Vec2::new(p.x, p.z)) <= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(path: &str) -> HeightmapConfig {
+        HeightmapConfig {
+            path: path.to_string(),
+            biome_map: None,
+            pixel_size: 4.0,
+            height_scale: 100.0,
+            sea_level: 40.0,
+            origin: [1000.0, 1000.0],
+        }
+    }
+
+    fn write_png(
+        path: &std::path::Path,
+        width: u32,
+        height: u32,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        data: &[u8],
+    ) {
+        let file = File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_heightmap_from_16_bit_png() {
+        // A 64x64 ramp rising to the east, in 16-bit grayscale.
+        let size = 64;
+        let mut data = Vec::new();
+        for _ in 0..size {
+            for x in 0..size {
+                let value = (x as f32 / (size - 1) as f32 * u16::MAX as f32) as u16;
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        let path = std::env::temp_dir().join("ruxel_test_heightmap_16.png");
+        write_png(
+            &path,
+            size,
+            size,
+            png::ColorType::Grayscale,
+            png::BitDepth::Sixteen,
+            &data,
+        );
+
+        let fallback = WorldTerrain::new(12345);
+        let terrain = HeightmapTerrain::load(&config(path.to_str().unwrap()), fallback.clone())
+            .expect("Should load the heightmap");
+        assert_eq!(terrain.sea_level(), 40.0);
+
+        // Well inside the image the heights follow the ramp exactly.
+        let row = 1000.0 + 128.0;
+        for x in [16, 32, 48] {
+            let point = Vec2::new(1000.0 + x as f32 * 4.0 + 2.0, row);
+            let expected = x as f32 / (size - 1) as f32 * 100.0;
+            let tdata = terrain.get(point);
+            assert!(
+                (tdata.height - expected).abs() < 0.01,
+                "Expected {} at pixel {}, got {}",
+                expected,
+                x,
+                tdata.height
+            );
+            assert_eq!(tdata.water_level, 40.0);
+        }
+
+        // Across the edge the water level eases from the procedural sea to
+        // the image's.
+        let mut last = fallback.get(Vec2::new(1000.0, row)).water_level;
+        for step in 1..=80 {
+            let point = Vec2::new(1000.0 + step as f32 * 0.5, row);
+            let water_level = terrain.get(point).water_level;
+            assert!(
+                (water_level - last).abs() < 1.0,
+                "Water jumps from {} to {} at {}",
+                last,
+                water_level,
+                point
+            );
+            last = water_level;
+        }
+        assert_eq!(last, 40.0);
+
+        // Outside it the procedural terrain takes over.
+        for point in [Vec2::new(900.0, 1100.0), Vec2::new(1300.0, 1100.0)] {
+            assert_eq!(terrain.get(point), fallback.get(point));
+        }
+        assert!(HeightmapTerrain::load(&config("/nonexistent/heightmap.png"), fallback).is_err());
+    }
+
+    #[test]
+    fn test_heightmap_biome_map() {
+        // Left half desert, right half jungle.
+        let size = 64;
+        let desert = debug::biome_color(Biome::Desert);
+        let jungle = debug::biome_color(Biome::Jungle);
+        let mut heights = Vec::new();
+        let mut colours = Vec::new();
+        for _ in 0..size {
+            for x in 0..size {
+                heights.push(200);
+                colours.extend_from_slice(&if x < size / 2 { desert } else { jungle }[..3]);
+            }
+        }
+        let dir = std::env::temp_dir();
+        let height_path = dir.join("ruxel_test_heightmap_8.png");
+        let biome_path = dir.join("ruxel_test_biome_map.png");
+        write_png(
+            &height_path,
+            size,
+            size,
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            &heights,
+        );
+        write_png(
+            &biome_path,
+            size,
+            size,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            &colours,
+        );
+
+        let mut config = config(height_path.to_str().unwrap());
+        config.biome_map = Some(biome_path.to_str().unwrap().to_string());
+        let terrain = HeightmapTerrain::load(&config, WorldTerrain::new(12345)).unwrap();
+
+        let desert_point = Vec2::new(1000.0 + 40.0, 1128.0);
+        let jungle_point = Vec2::new(1000.0 + 200.0, 1128.0);
+        assert_eq!(terrain.get(desert_point).biome, Biome::Desert);
+        assert_eq!(terrain.get(jungle_point).biome, Biome::Jungle);
+        assert!((terrain.surface_height(jungle_point) - 200.0 / 255.0 * 100.0).abs() < 0.01);
+
+        // Nothing is built on the image, since it has moved the ground.
+        for x in 55..90 {
+            for z in 55..90 {
+                for structure in terrain.structures_near(IVec2::new(x, z)) {
+                    assert!(!terrain.covers_structure(&structure));
+                }
+            }
+        }
+    }
+}
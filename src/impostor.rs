@@ -0,0 +1,203 @@
+use bytemuck::{Pod, Zeroable};
+use std::ops::Range;
+
+use crate::entities::TreeExtent;
+
+/// How many pixels across each prototype's picture is in the atlas.
+pub(crate) const TILE_SIZE: u32 = 128;
+
+/// A corner of the billboard a distant tree is drawn on, in the tree's own
+/// space, and where it falls in the atlas.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ImpostorVertex {
+    corner: [f32; 2],
+    uv: [f32; 2],
+}
+
+impl ImpostorVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+    ];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ImpostorVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// The box a prototype's picture is taken of, given once per prototype as
+/// it's drawn into its tile.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ImpostorTile {
+    // radius, bottom, top, unused
+    extent: [f32; 4],
+}
+
+impl ImpostorTile {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![5 => Float32x4];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ImpostorTile>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A billboard for every tree prototype, each showing its own tile of one
+/// square picture of them all. The billboards are joined into one mesh, in
+/// the same order as the prototypes, so far trees are drawn just like near
+/// ones.
+pub(crate) struct ImpostorAtlas {
+    columns: u32,
+    vertices: Vec<ImpostorVertex>,
+    indices: Vec<u32>,
+    ranges: Vec<Range<u32>>,
+    tiles: Vec<ImpostorTile>,
+}
+
+impl ImpostorAtlas {
+    pub(crate) fn new(extents: &[TreeExtent]) -> Self {
+        let columns = (extents.len() as f32).sqrt().ceil().max(1.0) as u32;
+        let size = (columns * TILE_SIZE) as f32;
+
+        let mut atlas = Self {
+            columns,
+            vertices: Vec::with_capacity(extents.len() * 4),
+            indices: Vec::with_capacity(extents.len() * 6),
+            ranges: Vec::with_capacity(extents.len()),
+            tiles: Vec::with_capacity(extents.len()),
+        };
+        for (i, extent) in extents.iter().enumerate() {
+            let (x, y) = atlas.viewport(i);
+            // Half a texel in from the edges so filtering doesn't bleed in
+            // from the neighbouring tiles
+            let left = (x + 0.5) / size;
+            let right = (x + TILE_SIZE as f32 - 0.5) / size;
+            let top = (y + 0.5) / size;
+            let bottom = (y + TILE_SIZE as f32 - 0.5) / size;
+            let r = extent.radius;
+
+            let base_idx = atlas.vertices.len() as u32;
+            atlas.vertices.extend([
+                ImpostorVertex {
+                    corner: [-r, extent.bottom],
+                    uv: [left, bottom],
+                },
+                ImpostorVertex {
+                    corner: [r, extent.bottom],
+                    uv: [right, bottom],
+                },
+                ImpostorVertex {
+                    corner: [r, extent.top],
+                    uv: [right, top],
+                },
+                ImpostorVertex {
+                    corner: [-r, extent.top],
+                    uv: [left, top],
+                },
+            ]);
+            let start = atlas.indices.len() as u32;
+            atlas
+                .indices
+                .extend([0, 1, 2, 0, 2, 3].map(|i| i + base_idx));
+            atlas.ranges.push(start..atlas.indices.len() as u32);
+            atlas.tiles.push(ImpostorTile {
+                extent: [r, extent.bottom, extent.top, 0.0],
+            });
+        }
+        atlas
+    }
+
+    /// How many pixels across the whole picture is.
+    pub(crate) fn size(&self) -> u32 {
+        self.columns * TILE_SIZE
+    }
+
+    /// The top left corner of a prototype's tile, in pixels.
+    pub(crate) fn viewport(&self, index: usize) -> (f32, f32) {
+        let column = index as u32 % self.columns;
+        let row = index as u32 / self.columns;
+        ((column * TILE_SIZE) as f32, (row * TILE_SIZE) as f32)
+    }
+
+    pub(crate) fn vertices(&self) -> &[ImpostorVertex] {
+        &self.vertices
+    }
+
+    pub(crate) fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// The indices of each prototype's billboard.
+    pub(crate) fn ranges(&self) -> &[Range<u32>] {
+        &self.ranges
+    }
+
+    pub(crate) fn tiles(&self) -> &[ImpostorTile] {
+        &self.tiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extents(count: usize) -> Vec<TreeExtent> {
+        (0..count)
+            .map(|i| TreeExtent {
+                radius: 1.0 + i as f32,
+                bottom: -0.5,
+                top: 4.0 + i as f32,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_prototype_has_its_own_tile() {
+        let extents = extents(10);
+        let atlas = ImpostorAtlas::new(&extents);
+        assert_eq!(atlas.size(), 4 * TILE_SIZE);
+        assert_eq!(atlas.ranges().len(), extents.len());
+        assert_eq!(atlas.tiles().len(), extents.len());
+
+        let size = atlas.size() as f32;
+        let tile = TILE_SIZE as f32;
+        let mut corners = Vec::new();
+        for (i, extent) in extents.iter().enumerate() {
+            let (x, y) = atlas.viewport(i);
+            assert!(x + tile <= size && y + tile <= size);
+            assert!(!corners.contains(&(x, y)), "Tiles should not overlap");
+            corners.push((x, y));
+
+            let range = &atlas.ranges()[i];
+            assert_eq!(range.len(), 6);
+            for &index in &atlas.indices()[range.start as usize..range.end as usize] {
+                let vertex = atlas.vertices()[index as usize];
+                let [u, v] = vertex.uv;
+                assert!(u * size > x && u * size < x + tile);
+                assert!(v * size > y && v * size < y + tile);
+
+                let [across, up] = vertex.corner;
+                assert_eq!(across.abs(), extent.radius);
+                assert!(up == extent.bottom || up == extent.top);
+                // The top of the tree is at the top of its tile
+                assert_eq!(up == extent.top, v * size < y + tile / 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_atlas() {
+        let atlas = ImpostorAtlas::new(&[]);
+        assert_eq!(atlas.size(), TILE_SIZE);
+        assert!(atlas.vertices().is_empty());
+    }
+}
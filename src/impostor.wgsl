@@ -0,0 +1,43 @@
+// Takes a picture of each tree prototype from the side, filling its own tile
+// of the impostor atlas. The viewport is set to the tile, so the prototype
+// only needs fitting to the box it stands in.
+
+struct BakeInput {
+  @location(0) position: vec3<f32>,
+  @location(2) color: vec4<f32>,
+  @location(3) normal_and_ao: vec4<f32>,
+  // radius, bottom, top, unused
+  @location(5) extent: vec4<f32>,
+}
+
+struct BakeOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_bake(model: BakeInput) -> BakeOutput {
+  var out: BakeOutput;
+  let radius = max(model.extent.x, 0.0001);
+  let height = max(model.extent.z - model.extent.y, 0.0001);
+
+  // Looking along -z, with the nearest branches in front
+  out.clip_position = vec4<f32>(
+    model.position.x / radius,
+    (model.position.y - model.extent.y) / height * 2.0 - 1.0,
+    0.5 - model.position.z / (2.0 * radius),
+    1.0,
+  );
+  out.color = model.color;
+  out.normal = model.normal_and_ao.xyz;
+  return out;
+}
+
+@fragment
+fn fs_bake(in: BakeOutput) -> @location(0) vec4<f32> {
+  // Just enough shading to keep the shape of the crown once the billboard
+  // is lit as a whole
+  let shade = 0.7 + 0.3 * abs(dot(normalize(in.normal), normalize(vec3<f32>(0.3, 1.0, 0.5))));
+  return vec4<f32>(in.color.rgb * shade, 1.0);
+}
@@ -1,22 +1,37 @@
 mod block;
 mod camera;
+mod caves;
+mod celestial;
 mod chunks;
+mod clock;
+mod collision;
 mod commands;
 pub mod config;
 mod console;
+mod debug;
+mod decoration;
 mod entities;
+mod erosion;
+mod generator;
+mod heightmap;
+mod impostor;
 mod light;
 mod lsystem;
 mod mesh;
+mod ores;
+mod player;
 mod poisson;
 mod render_state;
 mod scene;
 mod sky;
+mod structures;
 mod terrain;
 mod texture;
 mod trees;
 mod ui;
 mod vertex;
+mod voxel_trees;
+mod weather;
 
 use std::time::{Duration, Instant};
 
@@ -32,7 +47,7 @@ use winit::{
     window::{CursorGrabMode, Window, WindowBuilder},
 };
 
-const REACH_DISTANCE: f32 = 6.0;
+pub(crate) const REACH_DISTANCE: f32 = 6.0;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -122,7 +137,8 @@ impl Ruxel {
             let mut px = rng.gen_range(2000.0..4000.0);
             let mut pz = rng.gen_range(2000.0..4000.0);
 
-            while scene.chunks().height_at(&glam::Vec3::new(px, 0.0, pz)) <= 32.0 {
+            let sea_level = scene.chunks().terrain().sea_level();
+            while scene.chunks().height_at(&glam::Vec3::new(px, 0.0, pz)) <= sea_level {
                 px = rng.gen_range(2000.0..4000.0);
                 pz = rng.gen_range(2000.0..4000.0);
             }
@@ -183,7 +199,12 @@ impl Ruxel {
     }
 
     fn interact(&mut self, place: bool, block_type: crate::block::Type) {
-        if let Some((hit_pos, normal)) = self.camera.raycast(self.scene.chunks(), REACH_DISTANCE) {
+        let target = self.camera.raycast(
+            self.scene.chunks(),
+            self.scene.entity_manager(),
+            REACH_DISTANCE,
+        );
+        if let Some(camera::Target::Block(hit_pos, normal)) = target {
             if place {
                 let p = hit_pos + normal;
                 self.scene.chunks().set_block(p, block_type);
@@ -236,6 +257,13 @@ impl Ruxel {
                         KeyCode::Digit3 => self.selected_block_type = block::Type::Rock,
                         KeyCode::Digit4 => self.selected_block_type = block::Type::Ice,
                         KeyCode::Digit5 => self.selected_block_type = block::Type::Water,
+                        KeyCode::Digit6 => self.selected_block_type = block::Type::Snow,
+                        KeyCode::Digit7 => self.selected_block_type = block::Type::Mud,
+                        KeyCode::Digit8 => self.selected_block_type = block::Type::DryGrass,
+                        KeyCode::Digit9 => self.selected_block_type = block::Type::Lava,
+                        KeyCode::F5 => {
+                            self.camera.cycle_view_mode();
+                        }
                         _ => {}
                     }
                 }
@@ -291,9 +319,24 @@ impl Ruxel {
                     commands::execute_teleport(&mut self.camera, x, y, z)
                 }
                 console::Command::Time(t) => commands::execute_time(&mut self.scene, t),
+                console::Command::Clock(a) => commands::execute_clock(&mut self.scene, a),
+                console::Command::Weather(w) => commands::execute_weather(&mut self.scene, w),
+                console::Command::Debug(d) => commands::execute_debug(&mut self.state, d),
                 console::Command::FindBiome(b) => {
                     commands::execute_find_biome(&mut self.scene, &self.camera, &b)
                 }
+                console::Command::FindCave(c) => {
+                    commands::execute_find_cave(&self.scene, &self.camera, c.as_deref())
+                }
+                console::Command::FindOre(o) => {
+                    commands::execute_find_ore(&self.scene, &self.camera, &o)
+                }
+                console::Command::FindStructure(s) => {
+                    commands::execute_find_structure(&self.scene, &self.camera, &s)
+                }
+                console::Command::Plant(species) => {
+                    commands::execute_plant(&mut self.scene, &self.camera, &species)
+                }
                 console::Command::Help(cmd) => commands::execute_help(cmd),
                 console::Command::Unknown(cmd) => format!("Unknown command: {}", cmd),
                 console::Command::Error(err) => format!("Error: {}", err),
@@ -308,13 +351,20 @@ impl Ruxel {
         } else {
             self.camera_controller.update_camera(&mut self.camera, dt);
         }
-        self.camera.update_physics(self.scene.chunks(), dt);
+        self.camera
+            .update_physics(self.scene.chunks(), self.scene.entity_manager(), dt);
+        self.camera.update_view(self.scene.chunks(), dt);
         self.scene.update(dt, &self.camera);
 
-        let selected_block = self
-            .camera
-            .raycast(self.scene.chunks(), REACH_DISTANCE)
-            .map(|(pos, _)| pos);
+        let (selected_block, selected_tree) = match self.camera.raycast(
+            self.scene.chunks(),
+            self.scene.entity_manager(),
+            REACH_DISTANCE,
+        ) {
+            Some(camera::Target::Block(pos, _)) => (Some(pos), Vec::new()),
+            Some(camera::Target::Tree(_, shapes)) => (None, shapes),
+            None => (None, Vec::new()),
+        };
 
         let player_pos = self.camera.position();
         let blend_str = self.scene.chunks().terrain().biome_blend_string(glam::Vec2::new(player_pos.x, player_pos.z));
@@ -329,8 +379,13 @@ impl Ruxel {
             console: &self.console,
         });
 
-        self.state
-            .update(dt, &self.camera, &self.scene, selected_block);
+        self.state.update(
+            dt,
+            &self.camera,
+            &self.scene,
+            selected_block,
+            &selected_tree,
+        );
 
         if self.last_save_time.elapsed() > Duration::from_secs(5) {
             self.save_config();
@@ -340,6 +395,7 @@ impl Ruxel {
     fn save_config(&mut self) {
         if let Some(world_config) = self.config.worlds.get_mut(&self.config.active_world) {
             self.camera.save_state(world_config);
+            self.scene.save_state(world_config);
         }
         self.config.save();
         self.last_save_time = Instant::now();
@@ -424,11 +480,12 @@ impl Ruxel {
                         }
                     }
                     WindowEvent::Focused(focused) => {
-                        if !focused {
-                            self.mouse_pressed = false;
-                            if self.mouse_grabbed {
-                                self.ungrab_mouse();
-                            }
+                        if *focused {
+                            return;
+                        }
+                        self.mouse_pressed = false;
+                        if self.mouse_grabbed {
+                            self.ungrab_mouse();
                         }
                     }
                     WindowEvent::Resized(physical_size) => {
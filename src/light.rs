@@ -29,6 +29,11 @@ impl Light {
         Self { position, color }
     }
 
+    /// The alpha channel of the colour is used as the light's intensity.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.color.a = intensity as f64;
+    }
+
     pub fn to_raw(&self) -> RawLight {
         RawLight {
             position: [self.position.x, self.position.y, self.position.z],
@@ -2,14 +2,33 @@ use crate::vertex::Vertex;
 use glam::{Quat, Vec3};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde::Deserializer;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
 
-use crate::trees::TreeType;
+use crate::clock::Season;
+use crate::collision::CollisionShape;
 
+#[derive(Clone)]
 pub struct EntityMesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
 }
 
+/// A branch or leaf of a tree, from where it grows to its tip.
+#[derive(Clone, Copy, Debug)]
+pub struct TreePart {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub thickness: f32,
+    pub leaf: bool,
+    /// How many branchings out from the trunk it grows, 0 for the trunk.
+    pub depth: usize,
+}
+
 #[derive(Clone, Copy)]
 struct TurtleState {
     pos: Vec3,
@@ -18,26 +37,496 @@ struct TurtleState {
     right: Vec3,
     thickness: f32,
     length: f32,
+    // How far the turtle is pushed around by the wind, from 0 at the roots
+    sway: f32,
 }
 
-pub fn generate_l_system_string(axiom: &str, iterations: usize) -> String {
-    let mut string = String::from(axiom);
-    for _ in 0..iterations {
-        let mut next = String::new();
-        for c in string.chars() {
-            match c {
-                'A' => next.push_str("TT[&&&B][////&&&B][\\\\&&&B]TT[//&&&B][//////&&&B][\\&&&B]A"),
-                'B' => next.push_str("TT[++L]L"),
-                'L' => next.push_str("TT[--B]&B"),
-                'X' => next.push_str("F[+X][-X][^X][&X]X"),
-                'O' => next.push_str("F[+^O]T[-^O]T[+&O]T[-&O]"),
-                'P' => {
-                    next.push_str("FFFF[Y][\\^Y][\\\\^Y][\\\\\\^Y][\\\\\\\\^Y][/^Y][//^Y][///^Y]")
+/// How much each block of trunk adds to the sway of everything above it.
+const TRUNK_SWAY: f32 = 0.02;
+/// How much more each block of branch adds for every level it is from the
+/// trunk, so twigs swing further than the boughs they grow from.
+const BRANCH_SWAY: f32 = 0.08;
+/// How much further the tip of a leaf sways than its stem.
+const LEAF_SWAY: f32 = 0.25;
+
+/// The sway added by a segment `length` long, `depth` branches from the trunk.
+fn segment_sway(length: f32, depth: usize) -> f32 {
+    length * (TRUNK_SWAY + BRANCH_SWAY * depth as f32)
+}
+
+/// What a leaf symbol draws.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeafShape {
+    /// A stubby block at the end of a bud.
+    Block,
+    /// A long frond that droops under its own weight.
+    Frond,
+}
+
+/// How a species grows, read from its grammar file. `F` and `T` draw
+/// segments of trunk or branch, `+-^&\/` turn and roll the turtle by `angle`
+/// and `[]` push and pop its state. Symbols without a production are left
+/// as they are, so `T` is a trunk segment that never grows.
+///
+/// `F(l,w)` and `T(l,w)` draw a segment `l` long and `w` wide in place of
+/// the species' `length` and `thickness`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Grammar {
+    #[serde(deserialize_with = "deserialize_axiom")]
+    pub axiom: Vec<Module>,
+    pub iterations: usize,
+    /// What each symbol is replaced with on every iteration.
+    #[serde(default)]
+    pub rules: Rules,
+    /// Symbols drawn as leaves, and the shape of each.
+    #[serde(default)]
+    pub leaves: HashMap<char, LeafShape>,
+    /// How far the turtle turns, in degrees.
+    pub angle: f32,
+    pub thickness: f32,
+    /// Length of a segment.
+    pub length: f32,
+    pub bark: [u8; 3],
+    pub leaf: [u8; 3],
+    /// Evergreens keep their leaves through autumn and winter.
+    #[serde(default)]
+    pub evergreen: bool,
+    /// Colour the leaves turn in autumn.
+    #[serde(default = "default_autumn")]
+    pub autumn: [u8; 3],
+}
+
+fn default_autumn() -> [u8; 3] {
+    [170, 110, 30] // Amber
+}
+
+fn deserialize_axiom<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Module>, D::Error> {
+    parse_axiom(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn parse_axiom(axiom: &str) -> Result<Vec<Module>, String> {
+    let word = Parser::new(axiom, &[]).word()?;
+    Ok(word.iter().map(|t| t.instantiate(&[])).collect())
+}
+
+/// The most parameters a module can carry.
+const MAX_PARAMS: usize = 4;
+
+/// Symbols the turtle only turns on. Context matching looks straight past them.
+const TURN_SYMBOLS: &str = "+-^&\\/";
+
+/// A symbol in an expanded string, along with any parameters it carries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Module {
+    pub symbol: char,
+    params: [f32; MAX_PARAMS],
+    len: usize,
+}
+
+impl Module {
+    pub fn params(&self) -> &[f32] {
+        &self.params[..self.len]
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if self.len > 0 {
+            let params: Vec<String> = self.params().iter().map(|p| p.to_string()).collect();
+            write!(f, "({})", params.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Arithmetic on the parameters of a rule's predecessor, such as `l*0.8`.
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f32),
+    Param(usize),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, params: &[f32]) -> f32 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Param(i) => params[*i],
+            Expr::Neg(e) => -e.eval(params),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(params), b.eval(params));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    _ => a / b,
                 }
-                'Y' => next.push('Y'), // Fronds don't recursively branch
-                'T' => next.push('T'), // Non-expanding trunk segment
-                'F' => next.push_str("FF"),
-                _ => next.push(c),
+            }
+        }
+    }
+}
+
+/// A module in a production, with expressions for its parameters.
+#[derive(Clone, Debug)]
+struct Template {
+    symbol: char,
+    args: Vec<Expr>,
+}
+
+impl Template {
+    fn instantiate(&self, params: &[f32]) -> Module {
+        let mut module = Module {
+            symbol: self.symbol,
+            params: [0.0; MAX_PARAMS],
+            len: self.args.len(),
+        };
+        for (p, arg) in module.params.iter_mut().zip(&self.args) {
+            *p = arg.eval(params);
+        }
+        module
+    }
+}
+
+/// Reads words like `F(l*2,w)[+X(l,w*0.7)]`. Parameter names are looked up
+/// in `params`, so an axiom can only use numbers.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    params: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str, params: &'a [&'a str]) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            params,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}'", expected)),
+        }
+    }
+
+    fn word(&mut self) -> Result<Vec<Template>, String> {
+        let mut word = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let Some(symbol) = self.chars.next() else {
+                return Ok(word);
+            };
+            if "(),".contains(symbol) {
+                return Err(format!("unexpected '{}'", symbol));
+            }
+            let mut args = Vec::new();
+            if self.chars.next_if_eq(&'(').is_some() {
+                loop {
+                    args.push(self.sum()?);
+                    self.skip_whitespace();
+                    if self.chars.next_if_eq(&',').is_none() {
+                        break;
+                    }
+                }
+                self.expect(')')?;
+                if args.len() > MAX_PARAMS {
+                    return Err(format!(
+                        "'{}' has more than {} parameters",
+                        symbol, MAX_PARAMS
+                    ));
+                }
+            }
+            word.push(Template { symbol, args });
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.next_if(|&c| c == '+' || c == '-') {
+                Some(op) => expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?)),
+                None => return Ok(expr),
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.next_if(|&c| c == '*' || c == '/') {
+                Some(op) => expr = Expr::Binary(op, Box::new(expr), Box::new(self.factor()?)),
+                None => return Ok(expr),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some('-') => {
+                self.chars.next();
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("bad number '{}'", number))
+            }
+            Some(c) if c.is_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                self.params
+                    .iter()
+                    .position(|p| *p == name)
+                    .map(Expr::Param)
+                    .ok_or_else(|| format!("unknown parameter '{}'", name))
+            }
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err("expression ended early".to_string()),
+        }
+    }
+}
+
+/// One way a rule can rewrite its symbol, and how likely it is to be picked.
+#[derive(Deserialize)]
+struct WeightedProduction {
+    #[serde(default = "default_weight")]
+    weight: f32,
+    production: String,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// A rule's right hand side: a single production, or weighted alternatives
+/// to pick between at random.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Productions {
+    One(String),
+    Weighted(Vec<WeightedProduction>),
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    symbol: char,
+    /// How many parameters the symbol must carry.
+    params: usize,
+    /// Symbols that must come before and after the predecessor on its
+    /// branch, if any.
+    left: Option<char>,
+    right: Option<char>,
+    productions: Vec<(f32, Vec<Template>)>,
+}
+
+impl Rule {
+    /// Parses a rule from a predecessor like `F`, `X(l,w)` or `T<O>F` and
+    /// its productions.
+    fn parse(predecessor: &str, productions: Productions) -> Result<Self, String> {
+        let (left, rest) = match predecessor.split_once('<') {
+            Some((left, rest)) => (Some(left), rest),
+            None => (None, predecessor),
+        };
+        let (strict, right) = match rest.split_once('>') {
+            Some((strict, right)) => (strict, Some(right)),
+            None => (rest, None),
+        };
+        let context = |s: Option<&str>| -> Result<Option<char>, String> {
+            let Some(s) = s else { return Ok(None) };
+            let mut chars = s.trim().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Some(c)),
+                _ => Err(format!("context '{}' must be a single symbol", s)),
+            }
+        };
+
+        let strict = strict.trim();
+        let (symbol, names) = match strict.split_once('(') {
+            Some((symbol, names)) => {
+                let names = names
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("unclosed parameters in '{}'", predecessor))?;
+                (symbol, names.split(',').map(str::trim).collect())
+            }
+            None => (strict, Vec::new()),
+        };
+        let mut chars = symbol.chars();
+        let (Some(symbol), None) = (chars.next(), chars.next()) else {
+            return Err(format!("'{}' must rewrite a single symbol", predecessor));
+        };
+
+        let productions = match productions {
+            Productions::One(production) => vec![WeightedProduction {
+                weight: 1.0,
+                production,
+            }],
+            Productions::Weighted(productions) => productions,
+        };
+        if productions.is_empty() || productions.iter().any(|p| p.weight <= 0.0) {
+            return Err(format!(
+                "'{}' needs productions with positive weights",
+                predecessor
+            ));
+        }
+        let productions = productions
+            .iter()
+            .map(|p| Ok((p.weight, Parser::new(&p.production, &names).word()?)))
+            .collect::<Result<_, String>>()
+            .map_err(|e| format!("in rule for '{}': {}", predecessor, e))?;
+
+        Ok(Self {
+            symbol,
+            params: names.len(),
+            left: context(left)?,
+            right: context(right)?,
+            productions,
+        })
+    }
+
+    fn applies(&self, modules: &[Module], i: usize) -> bool {
+        self.symbol == modules[i].symbol
+            && self.params == modules[i].len
+            && self
+                .left
+                .is_none_or(|c| left_context(modules, i) == Some(c))
+            && self
+                .right
+                .is_none_or(|c| right_context(modules, i) == Some(c))
+    }
+
+    /// How much context the rule needs. More specific rules win.
+    fn specificity(&self) -> usize {
+        self.left.is_some() as usize + self.right.is_some() as usize
+    }
+
+    fn choose(&self, rng: &mut StdRng) -> &[Template] {
+        if let [(_, production)] = self.productions.as_slice() {
+            return production;
+        }
+        let total: f32 = self.productions.iter().map(|(w, _)| w).sum();
+        let mut pick = rng.gen_range(0.0..total.max(f32::EPSILON));
+        for (weight, production) in &self.productions {
+            if pick < *weight {
+                return production;
+            }
+            pick -= weight;
+        }
+        &self.productions[self.productions.len() - 1].1
+    }
+}
+
+/// The symbol before `modules[i]` on its branch, skipping turns and any
+/// side branches in between.
+fn left_context(modules: &[Module], i: usize) -> Option<char> {
+    let mut depth = 0;
+    for m in modules[..i].iter().rev() {
+        match m.symbol {
+            ']' => depth += 1,
+            '[' if depth > 0 => depth -= 1,
+            '[' => {}
+            c if depth > 0 || TURN_SYMBOLS.contains(c) => {}
+            c => return Some(c),
+        }
+    }
+    None
+}
+
+/// The symbol after `modules[i]` on its branch, skipping turns and any side
+/// branches in between. Nothing follows the end of a branch.
+fn right_context(modules: &[Module], i: usize) -> Option<char> {
+    let mut depth = 0;
+    for m in &modules[i + 1..] {
+        match m.symbol {
+            '[' => depth += 1,
+            ']' if depth == 0 => return None,
+            ']' => depth -= 1,
+            c if depth > 0 || TURN_SYMBOLS.contains(c) => {}
+            c => return Some(c),
+        }
+    }
+    None
+}
+
+/// Productions of a grammar, keyed by predecessor in the grammar file.
+///
+/// A predecessor like `X(l,w)` binds the parameters its symbol carries so
+/// the production can use them, and `A<X>B` only rewrites an `X` that comes
+/// after `A` and before `B` on its branch. A rule with several weighted
+/// productions picks one at random each time it's used.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, Productions>")]
+pub struct Rules(Vec<Rule>);
+
+impl TryFrom<HashMap<String, Productions>> for Rules {
+    type Error = String;
+
+    fn try_from(map: HashMap<String, Productions>) -> Result<Self, String> {
+        let mut rules = map
+            .into_iter()
+            .map(|(predecessor, productions)| Rule::parse(&predecessor, productions))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Keep the order stable so the same seed always picks the same rule.
+        rules.sort_by_key(|r| (r.symbol, r.left, r.right, r.params));
+        Ok(Rules(rules))
+    }
+}
+
+impl Rules {
+    fn find(&self, modules: &[Module], i: usize) -> Option<&Rule> {
+        let mut best: Option<&Rule> = None;
+        for rule in self.0.iter().filter(|r| r.applies(modules, i)) {
+            if best.is_none_or(|b| rule.specificity() > b.specificity()) {
+                best = Some(rule);
+            }
+        }
+        best
+    }
+}
+
+/// Rewrites `axiom` `iterations` times. Where a rule has a choice of
+/// productions, `rng` picks which.
+pub fn generate_l_system_string(
+    axiom: &[Module],
+    rules: &Rules,
+    iterations: usize,
+    rng: &mut StdRng,
+) -> Vec<Module> {
+    let mut string = axiom.to_vec();
+    for _ in 0..iterations {
+        let mut next = Vec::with_capacity(string.len() * 2);
+        for (i, module) in string.iter().enumerate() {
+            match rules.find(&string, i) {
+                Some(rule) => next.extend(
+                    rule.choose(rng)
+                        .iter()
+                        .map(|t| t.instantiate(module.params())),
+                ),
+                None => next.push(*module),
             }
         }
         string = next;
@@ -52,6 +541,10 @@ struct BranchParams {
     right: Vec3,
     thickness: f32,
     color: [u8; 4],
+    // Sway at the start and end of the branch
+    sway: [f32; 2],
+    // How many branchings out from the trunk it grows
+    depth: usize,
 }
 
 fn jitter_color(
@@ -69,70 +562,188 @@ fn jitter_color(
     color
 }
 
-pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
-    let (axiom, iterations, angle, base_thickness, base_length) = match tree_type {
-        TreeType::Palm => ("P", 2, std::f32::consts::PI / 4.0, 0.2, 1.5),
-        TreeType::Bush => (
-            "[+X][-X][^X][&X]",
-            3,
-            std::f32::consts::PI / 6.0,
-            0.05,
-            0.15,
-        ),
-        TreeType::Birch => ("X", 4, std::f32::consts::PI / 8.0, 0.15, 0.6),
-        TreeType::Oak => ("O", 5, std::f32::consts::PI / 4.0, 0.8, 0.8),
-        TreeType::Pine => ("A", 5, std::f32::consts::PI / 6.0, 0.5, 1.0),
-    };
+fn blend_color(color: [u8; 4], target: [u8; 3], t: f32) -> [u8; 4] {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    [
+        mix(color[0], target[0]),
+        mix(color[1], target[1]),
+        mix(color[2], target[2]),
+        color[3],
+    ]
+}
+
+/// Shifts a leaf colour for the season. Evergreens only darken a little in
+/// winter while broadleaf trees go through fresh green, autumn colours and
+/// dead brown.
+fn seasonal_leaf_color(color: [u8; 4], grammar: &Grammar, season: Season) -> [u8; 4] {
+    match (season, grammar.evergreen) {
+        (Season::Summer, _) => color,
+        (Season::Winter, true) => blend_color(color, [50, 60, 50], 0.25),
+        (_, true) => color,
+        (Season::Spring, false) => blend_color(color, [150, 220, 80], 0.3),
+        (Season::Autumn, false) => blend_color(color, grammar.autumn, 0.7),
+        (Season::Winter, false) => blend_color(color, [110, 90, 60], 0.7),
+    }
+}
 
-    let string = generate_l_system_string(axiom, iterations);
+/// Grows one of a handful of trees of a species, standing at the origin.
+/// Everything random about the tree, from which productions it grows to the
+/// tint of its bark, comes from the variant, so the same variant always grows
+/// into the same tree.
+#[cfg(test)]
+pub fn generate_tree_variant(grammar: &Grammar, variant: u32, season: Season) -> EntityMesh {
+    generate_young_tree(grammar, variant, grammar.iterations, season)
+}
 
+/// A variant as it looks part way through growing, expanded `iterations`
+/// times rather than the species' full count. Each stage grows on from the
+/// last, so a young tree keeps the shape and proportions of the one it
+/// becomes.
+pub fn generate_young_tree(
+    grammar: &Grammar,
+    variant: u32,
+    iterations: usize,
+    season: Season,
+) -> EntityMesh {
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
+    grow(grammar, variant, iterations, season, |params, _| {
+        add_branch(&mut vertices, &mut indices, params)
+    });
+    EntityMesh { vertices, indices }
+}
+
+/// The branches and leaves of a variant expanded `iterations` times, for
+/// building it out of blocks or colliding with it rather than drawing it.
+pub fn generate_tree_parts(grammar: &Grammar, variant: u32, iterations: usize) -> Vec<TreePart> {
+    let mut parts = Vec::new();
+    let mut add = |params: BranchParams, leaf| {
+        parts.push(TreePart {
+            start: params.start,
+            end: params.end,
+            thickness: params.thickness,
+            leaf,
+            depth: params.depth,
+        })
+    };
+    grow(grammar, variant, iterations, Season::Summer, &mut add);
+    parts
+}
+
+/// The shapes a tree grown from `parts` collides with. Branches and leaves
+/// are left out so the player can walk under the crown, leaving the trunk
+/// as a capsule for each straight run of it. A bush has no trunk to speak
+/// of, so it's rounded off into one sphere around everything it grows.
+pub fn collision_shapes(parts: &[TreePart], bush: bool) -> Vec<CollisionShape> {
+    if bush {
+        let (bottom, top, reach) = parts.iter().flat_map(|p| [p.start, p.end]).fold(
+            (0.0, 0.0, 0.0),
+            |(bottom, top, reach): (f32, f32, f32), p| {
+                (bottom.min(p.y), top.max(p.y), reach.max(p.x.hypot(p.z)))
+            },
+        );
+        if top <= bottom {
+            return Vec::new();
+        }
+        return vec![CollisionShape::Sphere {
+            centre: Vec3::new(0.0, (bottom + top) / 2.0, 0.0),
+            radius: reach.max((top - bottom) / 2.0),
+        }];
+    }
+
+    let mut shapes: Vec<CollisionShape> = Vec::new();
+    for part in parts.iter().filter(|p| p.depth == 0 && !p.leaf) {
+        let radius = part.thickness / 2.0;
+        // Carry on the last run if this part grows straight on from it
+        if let Some(CollisionShape::Capsule {
+            start,
+            end,
+            radius: r,
+        }) = shapes.last_mut()
+        {
+            let straight = (*end - *start)
+                .normalize_or_zero()
+                .dot((part.end - part.start).normalize_or_zero());
+            if *end == part.start && *r == radius && straight > 0.999 {
+                *end = part.end;
+                continue;
+            }
+        }
+        shapes.push(CollisionShape::Capsule {
+            start: part.start,
+            end: part.end,
+            radius,
+        });
+    }
+    shapes
+}
 
-    let mut rng = StdRng::seed_from_u64((origin.x.to_bits() as u64) ^ (origin.z.to_bits() as u64));
+/// Walks the turtle along the expanded string, handing each branch and leaf
+/// to `add` along with whether it's a leaf.
+fn grow(
+    grammar: &Grammar,
+    variant: u32,
+    iterations: usize,
+    season: Season,
+    mut add: impl FnMut(BranchParams, bool),
+) {
+    let mut rng = StdRng::seed_from_u64(variant as u64);
+    let angle = grammar.angle.to_radians();
+
+    // Drawn before the expansion, which takes a different number of draws
+    // at each stage.
     let height_var = rng.gen_range(0.8..1.2);
+    let thickness_var = rng.gen_range(0.8..1.2);
     let frond_var = rng.gen_range(0.8..1.2);
     let tree_color_jitter = rng.gen_range(-15..=15);
 
+    let string = generate_l_system_string(&grammar.axiom, &grammar.rules, iterations, &mut rng);
+
     let mut state = TurtleState {
-        pos: origin,
+        pos: Vec3::ZERO,
         dir: Vec3::Y,
         up: Vec3::Z,
         right: Vec3::X,
-        thickness: base_thickness * rng.gen_range(0.8..1.2),
-        length: base_length * height_var,
+        thickness: grammar.thickness * thickness_var,
+        length: grammar.length * height_var,
+        sway: 0.0,
     };
     let mut stack = Vec::new();
 
-    for c in string.chars() {
+    let [r, g, b] = grammar.bark;
+    let bark_color = [r, g, b, 255];
+    let [r, g, b] = grammar.leaf;
+    let leaf_color = seasonal_leaf_color([r, g, b, 255], grammar, season);
+
+    for module in &string {
+        let c = module.symbol;
         match c {
             'F' | 'T' => {
-                let end = state.pos + state.dir * state.length;
-
-                // Color: brown for trunk/branches
-                let base_color = match tree_type {
-                    TreeType::Palm => [210, 180, 140, 255], // Pale tan
-                    TreeType::Bush => [85, 107, 47, 255],   // Dark olive green
-                    TreeType::Birch => [200, 200, 200, 255], // White/Grey
-                    TreeType::Oak => [80, 50, 20, 255],     // Darker brown
-                    TreeType::Pine => [90, 60, 40, 255],    // Pine brown
+                let (length, thickness) = match module.params() {
+                    [l] => (l * height_var, state.thickness),
+                    [l, w, ..] => (l * height_var, w * thickness_var),
+                    [] => (state.length, state.thickness),
                 };
-                let color = jitter_color(base_color, &mut rng, tree_color_jitter, 5);
+                let end = state.pos + state.dir * length;
+                let color = jitter_color(bark_color, &mut rng, tree_color_jitter, 5);
+                let sway = state.sway + segment_sway(length, stack.len());
 
-                add_branch(
-                    &mut vertices,
-                    &mut indices,
+                add(
                     BranchParams {
                         start: state.pos,
                         end,
                         up: state.up,
                         right: state.right,
-                        thickness: state.thickness,
+                        thickness,
                         color,
+                        sway: [state.sway, sway],
+                        depth: stack.len(),
                     },
+                    false,
                 );
 
                 state.pos = end;
+                state.sway = sway;
                 // state.thickness *= 0.9;
             }
             '+' => {
@@ -171,21 +782,12 @@ pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
                     state = s;
                 }
             }
-            'X' | 'O' | 'B' | 'A' | 'L' => {
+            _ if grammar.leaves.get(&c) == Some(&LeafShape::Block) => {
                 // Draw a leaf at the end of the bud
-                let base_color = match tree_type {
-                    TreeType::Bush => [107, 142, 35, 255],  // Olive drab
-                    TreeType::Birch => [173, 255, 47, 255], // Bright leaves
-                    TreeType::Oak => [34, 110, 34, 255],    // Deep green
-                    TreeType::Pine => [20, 70, 20, 255],    // Pine needle dark green
-                    _ => [34, 139, 34, 255],                // Default green
-                };
-                let color = jitter_color(base_color, &mut rng, tree_color_jitter, 10);
+                let color = jitter_color(leaf_color, &mut rng, tree_color_jitter, 10);
                 let leaf_pos = state.pos;
                 let leaf_end = leaf_pos + state.dir * 0.5;
-                add_branch(
-                    &mut vertices,
-                    &mut indices,
+                add(
                     BranchParams {
                         start: leaf_pos,
                         end: leaf_end,
@@ -193,17 +795,20 @@ pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
                         right: state.right,
                         thickness: 0.8,
                         color,
+                        sway: [state.sway, state.sway + LEAF_SWAY],
+                        depth: stack.len(),
                     },
+                    true,
                 );
             }
-            'Y' => {
+            _ if grammar.leaves.get(&c) == Some(&LeafShape::Frond) => {
                 // Draw a long, drooping palm frond
-                let base_color = [46, 139, 87, 255]; // sea green
-                let color = jitter_color(base_color, &mut rng, tree_color_jitter, 8);
+                let color = jitter_color(leaf_color, &mut rng, tree_color_jitter, 8);
                 let mut current_pos = state.pos;
                 let mut current_dir = state.dir;
                 let mut current_up = state.up;
                 let mut current_right = state.right;
+                let mut current_sway = state.sway;
 
                 let segments = 5;
                 // Subtle variation per frond
@@ -213,10 +818,10 @@ pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
                     let end = current_pos + current_dir * segment_length;
                     // Taper the thickness of the frond towards the tip
                     let thickness = 0.6 * (1.0 - (i as f32 / segments as f32) * 0.7);
+                    // Fronds are as supple as a branch off the one they grow on
+                    let sway = current_sway + segment_sway(segment_length, stack.len() + 1);
 
-                    add_branch(
-                        &mut vertices,
-                        &mut indices,
+                    add(
                         BranchParams {
                             start: current_pos,
                             end,
@@ -224,9 +829,13 @@ pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
                             right: current_right,
                             thickness,
                             color,
+                            sway: [current_sway, sway],
+                            depth: stack.len() + 1,
                         },
+                        true,
                     );
                     current_pos = end;
+                    current_sway = sway;
 
                     // Pitch down for the next segment (gravity/droop)
                     let mut droop_axis = current_dir.cross(Vec3::NEG_Y);
@@ -245,8 +854,6 @@ pub fn generate_l_system_tree(tree_type: TreeType, origin: Vec3) -> EntityMesh {
             _ => {}
         }
     }
-
-    EntityMesh { vertices, indices }
 }
 
 fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: BranchParams) {
@@ -266,27 +873,23 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     let p6 = params.end + params.right * t + params.up * t;
     let p7 = params.end - params.right * t + params.up * t;
 
-    let push_vertex = |vts: &mut Vec<Vertex>, p: Vec3, n: Vec3| {
+    let [start_sway, end_sway] = params.sway;
+    let push_vertex = |vts: &mut Vec<Vertex>, p: Vec3, sway: f32, n: Vec3| {
         let nx = (n.x * 127.0) as i8;
         let ny = (n.y * 127.0) as i8;
         let nz = (n.z * 127.0) as i8;
         // material 0 is solid, normal_and_ao: last is AO, just use 127 (fully bright)
-        vts.push(Vertex::new(
-            [p.x, p.y, p.z],
-            0,
-            params.color,
-            [nx, ny, nz, 127],
-        ));
+        vts.push(Vertex::new([p.x, p.y, p.z], 0, params.color, [nx, ny, nz, 127]).with_sway(sway));
     };
 
     let dir = (params.end - params.start).normalize_or_zero();
 
     // Bottom face (-dir)
     let n_bottom = -dir;
-    push_vertex(vertices, p0, n_bottom);
-    push_vertex(vertices, p1, n_bottom);
-    push_vertex(vertices, p2, n_bottom);
-    push_vertex(vertices, p3, n_bottom);
+    push_vertex(vertices, p0, start_sway, n_bottom);
+    push_vertex(vertices, p1, start_sway, n_bottom);
+    push_vertex(vertices, p2, start_sway, n_bottom);
+    push_vertex(vertices, p3, start_sway, n_bottom);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 2,
@@ -299,10 +902,10 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     // Top face (+dir)
     let base_idx = vertices.len() as u32;
     let n_top = dir;
-    push_vertex(vertices, p4, n_top);
-    push_vertex(vertices, p5, n_top);
-    push_vertex(vertices, p6, n_top);
-    push_vertex(vertices, p7, n_top);
+    push_vertex(vertices, p4, end_sway, n_top);
+    push_vertex(vertices, p5, end_sway, n_top);
+    push_vertex(vertices, p6, end_sway, n_top);
+    push_vertex(vertices, p7, end_sway, n_top);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 1,
@@ -315,10 +918,10 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     // Right face (+right)
     let base_idx = vertices.len() as u32;
     let n_right = params.right;
-    push_vertex(vertices, p1, n_right);
-    push_vertex(vertices, p5, n_right);
-    push_vertex(vertices, p6, n_right);
-    push_vertex(vertices, p2, n_right);
+    push_vertex(vertices, p1, start_sway, n_right);
+    push_vertex(vertices, p5, end_sway, n_right);
+    push_vertex(vertices, p6, end_sway, n_right);
+    push_vertex(vertices, p2, start_sway, n_right);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 1,
@@ -331,10 +934,10 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     // Left face (-right)
     let base_idx = vertices.len() as u32;
     let n_left = -params.right;
-    push_vertex(vertices, p0, n_left);
-    push_vertex(vertices, p3, n_left);
-    push_vertex(vertices, p7, n_left);
-    push_vertex(vertices, p4, n_left);
+    push_vertex(vertices, p0, start_sway, n_left);
+    push_vertex(vertices, p3, start_sway, n_left);
+    push_vertex(vertices, p7, end_sway, n_left);
+    push_vertex(vertices, p4, end_sway, n_left);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 1,
@@ -347,10 +950,10 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     // Up face (+up)
     let base_idx = vertices.len() as u32;
     let n_up = params.up;
-    push_vertex(vertices, p3, n_up);
-    push_vertex(vertices, p2, n_up);
-    push_vertex(vertices, p6, n_up);
-    push_vertex(vertices, p7, n_up);
+    push_vertex(vertices, p3, start_sway, n_up);
+    push_vertex(vertices, p2, start_sway, n_up);
+    push_vertex(vertices, p6, end_sway, n_up);
+    push_vertex(vertices, p7, end_sway, n_up);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 1,
@@ -363,10 +966,10 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
     // Down face (-up)
     let base_idx = vertices.len() as u32;
     let n_down = -params.up;
-    push_vertex(vertices, p0, n_down);
-    push_vertex(vertices, p4, n_down);
-    push_vertex(vertices, p5, n_down);
-    push_vertex(vertices, p1, n_down);
+    push_vertex(vertices, p0, start_sway, n_down);
+    push_vertex(vertices, p4, end_sway, n_down);
+    push_vertex(vertices, p5, end_sway, n_down);
+    push_vertex(vertices, p1, start_sway, n_down);
     indices.extend_from_slice(&[
         base_idx,
         base_idx + 1,
@@ -380,9 +983,16 @@ fn add_branch(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, params: Branch
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::trees::SpeciesRegistry;
     use std::fs::File;
     use std::io::Write;
 
+    fn grammar(name: &str) -> Grammar {
+        let registry = SpeciesRegistry::builtin();
+        let id = registry.find(name).expect("built-in species");
+        registry.get(id).grammar.clone()
+    }
+
     fn write_ply_header(file: &mut File, num_vertices: usize, num_faces: usize) {
         writeln!(file, "ply").unwrap();
         writeln!(file, "format ascii 1.0").unwrap();
@@ -416,27 +1026,158 @@ mod tests {
         }
     }
 
-    fn write_faces(file: &mut File, indices: &Vec<u32>) {
+    fn write_faces(file: &mut File, indices: &[u32]) {
         for chunk in indices.chunks(3) {
             writeln!(file, "3 {} {} {}", chunk[0], chunk[1], chunk[2]).unwrap();
         }
     }
 
+    fn expand(axiom: &str, rules: &Rules, iterations: usize, seed: u64) -> String {
+        let axiom = parse_axiom(axiom).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+        generate_l_system_string(&axiom, rules, iterations, &mut rng)
+            .iter()
+            .map(|m| m.to_string())
+            .collect()
+    }
+
+    fn rules(toml: &str) -> Rules {
+        toml::from_str(toml).unwrap()
+    }
+
     #[test]
     fn test_lsystem_string_expansion() {
-        let axiom = "X";
-        let iter_1 = generate_l_system_string(axiom, 1);
+        let rules = grammar("bush").rules;
+        let iter_1 = expand("X", &rules, 1, 0);
         assert_eq!(iter_1, "F[+X][-X][^X][&X]X");
 
-        let iter_2 = generate_l_system_string(axiom, 2);
+        let iter_2 = expand("X", &rules, 2, 0);
         // F becomes FF
         // X becomes F[+X][-X][^X][&X]X
         assert!(iter_2.starts_with("FF[+F[+X]"));
     }
 
+    #[test]
+    fn test_parametric_rules() {
+        let rules = rules(
+            r#"
+            "X(l,w)" = "F(l,w)[+X(l*0.5,(w+1)/2)]"
+            "F(l,w)" = "F(l*2,w)"
+            "#,
+        );
+        assert_eq!(expand("X(4,0.5)", &rules, 1, 0), "F(4,0.5)[+X(2,0.75)]");
+        assert_eq!(
+            expand("X(4,0.5)", &rules, 2, 0),
+            "F(8,0.5)[+F(2,0.75)[+X(1,0.875)]]"
+        );
+        // Only modules with the right number of parameters are rewritten.
+        assert_eq!(expand("X(1)F", &rules, 1, 0), "X(1)F");
+
+        // Parameters set the length and width of a segment.
+        let mut grammar = grammar("bush");
+        grammar.axiom = parse_axiom("F(3,0.5)").unwrap();
+        grammar.iterations = 0;
+        let mesh = generate_tree_variant(&grammar, 0, Season::Summer);
+        let (min, max) = mesh.vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| {
+                let p = Vec3::from(v.position());
+                (min.min(p), max.max(p))
+            },
+        );
+        let size = max - min;
+        assert!(size.y >= 3.0 * 0.8 && size.y <= 3.0 * 1.2, "{:?}", size);
+        assert!(size.x >= 0.5 * 0.8 && size.x <= 0.5 * 1.2, "{:?}", size);
+    }
+
+    #[test]
+    fn test_context_sensitive_rules() {
+        let rules = rules(
+            r#"
+            A = "a"
+            "B<A" = "b"
+            "A>C" = "c"
+            "#,
+        );
+        // Turns and side branches don't break the context, but the start of
+        // a branch looks back down its parent.
+        assert_eq!(expand("BA", &rules, 1, 0), "Bb");
+        assert_eq!(expand("B+[C]A", &rules, 1, 0), "B+[C]b");
+        assert_eq!(expand("B[+A]", &rules, 1, 0), "B[+b]");
+        assert_eq!(expand("A[B]C", &rules, 1, 0), "c[B]C");
+        assert_eq!(expand("[A]C", &rules, 1, 0), "[a]C");
+        assert_eq!(expand("DA", &rules, 1, 0), "Da");
+    }
+
+    #[test]
+    fn test_bad_rules_are_rejected() {
+        for bad in [
+            r#""X(l" = "F""#,
+            r#"XY = "F""#,
+            r#""X(l)" = "F(k)""#,
+            r#"X = "F(1,2""#,
+            r#"X = "F(1,2,3,4,5)""#,
+            r#"X = []"#,
+            r#"X = [{ weight = 0, production = "F" }]"#,
+        ] {
+            assert!(toml::from_str::<Rules>(bad).is_err(), "{} parsed", bad);
+        }
+        assert!(parse_axiom("X(l)").is_err());
+    }
+
+    #[test]
+    fn test_stochastic_rules_follow_the_seed() {
+        let rules = rules(
+            r#"
+            X = [
+                { weight = 3, production = "FX" },
+                { production = "TX" },
+            ]
+            "#,
+        );
+        let strings: Vec<String> = (0..32).map(|seed| expand("X", &rules, 6, seed)).collect();
+        for (seed, string) in strings.iter().enumerate() {
+            assert_eq!(*string, expand("X", &rules, 6, seed as u64));
+        }
+        let fs = strings.iter().flat_map(|s| s.chars()).filter(|&c| c == 'F');
+        let ts = strings.iter().flat_map(|s| s.chars()).filter(|&c| c == 'T');
+        let (fs, ts) = (fs.count(), ts.count());
+        assert!(
+            fs > ts && ts > 0,
+            "picked F {} times and T {} times",
+            fs,
+            ts
+        );
+    }
+
+    #[test]
+    fn test_same_seed_same_tree() {
+        for name in ["oak", "acacia", "birch"] {
+            let grammar = grammar(name);
+            let a = generate_tree_variant(&grammar, 5, Season::Summer);
+            let b = generate_tree_variant(&grammar, 5, Season::Summer);
+            assert_eq!(a.indices, b.indices);
+            let positions = |m: &EntityMesh| -> Vec<[f32; 3]> {
+                m.vertices.iter().map(|v| v.position()).collect()
+            };
+            assert_eq!(positions(&a), positions(&b), "{} isn't repeatable", name);
+        }
+
+        // Stochastic species grow different shapes, not just resized ones.
+        let oak = grammar("oak");
+        let sizes: std::collections::HashSet<usize> = (0..8)
+            .map(|variant| {
+                generate_tree_variant(&oak, variant, Season::Summer)
+                    .vertices
+                    .len()
+            })
+            .collect();
+        assert!(sizes.len() > 1, "every oak has the same branches");
+    }
+
     #[test]
     fn test_generate_palm_tree() {
-        let mesh = generate_l_system_tree(TreeType::Palm, Vec3::ZERO);
+        let mesh = generate_tree_variant(&grammar("palm"), 0, Season::Summer);
         let mut file = File::create("test_outputs/lsystem_palm.ply").unwrap();
 
         let num_faces = mesh.indices.len() / 3;
@@ -448,7 +1189,7 @@ mod tests {
 
     #[test]
     fn test_generate_bush_ply() {
-        let mesh = generate_l_system_tree(TreeType::Bush, Vec3::ZERO);
+        let mesh = generate_tree_variant(&grammar("bush"), 0, Season::Summer);
         let mut file = File::create("test_outputs/lsystem_bush.ply").unwrap();
 
         let num_faces = mesh.indices.len() / 3;
@@ -460,7 +1201,7 @@ mod tests {
 
     #[test]
     fn test_generate_birch() {
-        let mesh = generate_l_system_tree(TreeType::Birch, Vec3::ZERO);
+        let mesh = generate_tree_variant(&grammar("birch"), 0, Season::Summer);
         let mut file = File::create("test_outputs/lsystem_birch.ply").unwrap();
 
         let num_faces = mesh.indices.len() / 3;
@@ -472,7 +1213,7 @@ mod tests {
 
     #[test]
     fn test_generate_oak() {
-        let mesh = generate_l_system_tree(TreeType::Oak, Vec3::ZERO);
+        let mesh = generate_tree_variant(&grammar("oak"), 0, Season::Summer);
         let mut file = File::create("test_outputs/lsystem_oak.ply").unwrap();
 
         let num_faces = mesh.indices.len() / 3;
@@ -484,7 +1225,7 @@ mod tests {
 
     #[test]
     fn test_generate_pine() {
-        let pine = generate_l_system_tree(TreeType::Pine, Vec3::ZERO);
+        let pine = generate_tree_variant(&grammar("pine"), 0, Season::Summer);
         let mut file = File::create("test_outputs/lsystem_pine.ply").unwrap();
 
         let num_faces = pine.indices.len() / 3;
@@ -492,4 +1233,137 @@ mod tests {
         write_vertices(&mut file, &pine.vertices);
         write_faces(&mut file, &pine.indices);
     }
+
+    #[test]
+    fn test_generate_biome_trees() {
+        for name in ["acacia", "jungle", "willow"] {
+            let mesh = generate_tree_variant(&grammar(name), 0, Season::Summer);
+            assert!(!mesh.vertices.is_empty(), "{} has no geometry", name);
+            assert_eq!(mesh.indices.len() % 3, 0);
+        }
+
+        let acacia = generate_tree_variant(&grammar("acacia"), 0, Season::Summer);
+        let (min, max) = acacia.vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| {
+                let p = Vec3::from(v.position());
+                (min.min(p), max.max(p))
+            },
+        );
+        let size = max - min;
+        assert!(
+            size.x.max(size.z) > size.y * 0.6,
+            "acacia crown should spread wide, got {:?}",
+            size
+        );
+    }
+
+    #[test]
+    fn test_seasonal_leaf_colors() {
+        let green = [34, 110, 34, 255];
+
+        // Broadleaf trees turn in autumn and winter but evergreens don't.
+        let oak = grammar("oak");
+        let oak_autumn = seasonal_leaf_color(green, &oak, Season::Autumn);
+        assert!(
+            oak_autumn[0] > oak_autumn[1],
+            "autumn oak should be red/brown"
+        );
+        assert_eq!(
+            seasonal_leaf_color(green, &grammar("pine"), Season::Autumn),
+            green
+        );
+        assert_eq!(seasonal_leaf_color(green, &oak, Season::Summer), green);
+
+        // Trunks stay the same, only the leaves change.
+        let summer = generate_tree_variant(&grammar("birch"), 0, Season::Summer);
+        let autumn = generate_tree_variant(&grammar("birch"), 0, Season::Autumn);
+        assert_eq!(summer.vertices.len(), autumn.vertices.len());
+        assert_eq!(summer.vertices[0].color(), autumn.vertices[0].color());
+        assert_ne!(
+            summer.vertices.last().unwrap().color(),
+            autumn.vertices.last().unwrap().color()
+        );
+    }
+
+    #[test]
+    fn test_sway_grows_towards_the_tips() {
+        let registry = SpeciesRegistry::builtin();
+        for id in registry.ids() {
+            let species = registry.get(id);
+            let mesh = generate_tree_variant(&species.grammar, 0, Season::Summer);
+            for v in &mesh.vertices {
+                let [x, y, z] = v.position();
+                if y < 0.01 && x.abs() < 0.5 && z.abs() < 0.5 {
+                    assert_eq!(v.light()[3], 0, "{} moves at the roots", species.name);
+                }
+            }
+            assert!(
+                mesh.vertices.iter().any(|v| v.light()[3] > 0),
+                "{} doesn't sway",
+                species.name
+            );
+        }
+
+        // Palm trunks bend a little, their fronds a lot
+        let palm = generate_tree_variant(&grammar("palm"), 0, Season::Summer);
+        let from_trunk = |v: &Vertex| {
+            let [x, _, z] = v.position();
+            (x * x + z * z).sqrt()
+        };
+        let trunk = palm
+            .vertices
+            .iter()
+            .filter(|v| from_trunk(v) < 0.2)
+            .map(|v| v.light()[3])
+            .max()
+            .unwrap();
+        let fronds = palm.vertices.iter().map(|v| v.light()[3]).max().unwrap();
+        assert!(trunk > 0, "palm trunks should bend");
+        assert!(fronds > trunk * 2, "{} vs {}", fronds, trunk);
+    }
+
+    #[test]
+    fn test_tree_parts_match_the_mesh() {
+        for name in ["oak", "palm"] {
+            let grammar = grammar(name);
+            let mesh = generate_tree_variant(&grammar, 1, Season::Summer);
+            let parts = generate_tree_parts(&grammar, 1, grammar.iterations);
+            // Every part is drawn as one box
+            assert_eq!(mesh.vertices.len(), parts.len() * 24);
+            assert!(parts.iter().any(|p| p.leaf), "{} has no leaves", name);
+            assert!(parts.iter().any(|p| !p.leaf), "{} has no wood", name);
+
+            let trunk = parts.iter().find(|p| !p.leaf).unwrap();
+            assert_eq!(trunk.start, Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_collision_shapes_simplify_the_tree() {
+        let oak = grammar("oak");
+        let parts = generate_tree_parts(&oak, 1, oak.iterations);
+        let shapes = collision_shapes(&parts, false);
+        let trunk = parts.iter().filter(|p| p.depth == 0 && !p.leaf).count();
+        assert!(!shapes.is_empty() && shapes.len() < trunk, "{shapes:?}");
+        match shapes[0] {
+            CollisionShape::Capsule { start, end, .. } => {
+                assert_eq!(start, Vec3::ZERO);
+                assert!(end.y > 1.0, "trunk ends at {end}");
+            }
+            shape => panic!("oak trunk is {shape:?}"),
+        }
+
+        let bush = grammar("bush");
+        let parts = generate_tree_parts(&bush, 1, bush.iterations);
+        match collision_shapes(&parts, true)[..] {
+            [CollisionShape::Sphere { centre, radius }] => {
+                assert!(centre.y > 0.0 && radius > 0.0);
+                for p in parts.iter().flat_map(|p| [p.start, p.end]) {
+                    assert!(p.distance(centre) <= radius * 1.5, "{p} sticks out");
+                }
+            }
+            ref shapes => panic!("bush is {shapes:?}"),
+        }
+    }
 }
@@ -1,6 +1,133 @@
+use crate::debug::{self, DebugView};
 use crate::vertex::Vertex;
-use crate::{chunks::Chunk, terrain::WorldTerrain};
-use glam::Vec3;
+use crate::{block::Type, chunks::Chunk, generator::TerrainGenerator};
+use glam::{IVec3, Vec3};
+use std::collections::VecDeque;
+
+/// How many blocks the glow from lava reaches.
+const LAVA_LIGHT_RANGE: i32 = 8;
+/// Colour of the light given off by lava, at full strength.
+const LAVA_LIGHT_COLOR: [f32; 3] = [1.0, 0.5, 0.15];
+
+/// Block light in and around a chunk, flooded out from lava through
+/// everything that isn't opaque and dimming a step with every block.
+struct BlockLight {
+    origin: IVec3,
+    levels: Vec<u8>,
+}
+
+impl BlockLight {
+    const SIZE: i32 = 16 + 2 * LAVA_LIGHT_RANGE;
+
+    /// Returns `None` when there's no lava close enough to light the chunk.
+    fn flood(
+        chunk: &Chunk,
+        loaded_chunks: &std::collections::HashMap<glam::UVec2, Vec<Chunk>>,
+        is_opaque: impl Fn(i32, i32, i32) -> bool,
+    ) -> Option<Self> {
+        let start = chunk.start().as_ivec3();
+
+        // The light doesn't reach past the chunks touching this one.
+        let mut sources = vec![chunk];
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let key =
+                    glam::IVec2::new(start.x.div_euclid(16) + dx, start.z.div_euclid(16) + dz);
+                if key.x < 0 || key.y < 0 {
+                    continue;
+                }
+                let Some(col) = loaded_chunks.get(&key.as_uvec2()) else {
+                    continue;
+                };
+                for dy in -1..=1 {
+                    let cy = start.y.div_euclid(16) + dy;
+                    if let Some(neighbor) = usize::try_from(cy).ok().and_then(|cy| col.get(cy)) {
+                        if neighbor.start() != chunk.start() {
+                            sources.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        // Nearly always, there's no lava anywhere near
+        sources.retain(|source| source.has_lava());
+        if sources.is_empty() {
+            return None;
+        }
+
+        let mut light = Self {
+            origin: start - IVec3::splat(LAVA_LIGHT_RANGE),
+            levels: vec![0; (Self::SIZE * Self::SIZE * Self::SIZE) as usize],
+        };
+        let mut queue = VecDeque::new();
+        for source in sources {
+            let source_start = source.start().as_ivec3();
+            for (x, slice_x) in source.blocks().iter().enumerate() {
+                for (z, slice_z) in slice_x.iter().enumerate() {
+                    for (y, block) in slice_z.iter().enumerate() {
+                        if block.ty() != Type::Lava {
+                            continue;
+                        }
+                        let pos = source_start + IVec3::new(x as i32, y as i32, z as i32);
+                        if let Some(i) = light.index(pos) {
+                            light.levels[i] = LAVA_LIGHT_RANGE as u8;
+                            queue.push_back(pos);
+                        }
+                    }
+                }
+            }
+        }
+        if queue.is_empty() {
+            return None;
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let level = light.levels[light.index(pos)?];
+            if level <= 1 {
+                continue;
+            }
+            for dir in [
+                IVec3::X,
+                IVec3::NEG_X,
+                IVec3::Y,
+                IVec3::NEG_Y,
+                IVec3::Z,
+                IVec3::NEG_Z,
+            ] {
+                let next = pos + dir;
+                let Some(i) = light.index(next) else {
+                    continue;
+                };
+                if light.levels[i] >= level - 1 || is_opaque(next.x, next.y, next.z) {
+                    continue;
+                }
+                light.levels[i] = level - 1;
+                queue.push_back(next);
+            }
+        }
+        Some(light)
+    }
+
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        let local = pos - self.origin;
+        if local.min_element() < 0 || local.max_element() >= Self::SIZE {
+            return None;
+        }
+        Some(((local.x * Self::SIZE + local.z) * Self::SIZE + local.y) as usize)
+    }
+
+    /// The light's colour at a block, for a vertex.
+    fn color(&self, pos: IVec3) -> [u8; 4] {
+        let level = self.index(pos).map_or(0, |i| self.levels[i]);
+        let strength = level as f32 / LAVA_LIGHT_RANGE as f32;
+        [
+            (LAVA_LIGHT_COLOR[0] * strength * 255.0) as u8,
+            (LAVA_LIGHT_COLOR[1] * strength * 255.0) as u8,
+            (LAVA_LIGHT_COLOR[2] * strength * 255.0) as u8,
+            0,
+        ]
+    }
+}
 
 #[derive(Debug)]
 pub struct ChunkMesh {
@@ -25,7 +152,8 @@ impl ChunkMesh {
     pub fn build(
         chunk: &Chunk,
         loaded_chunks: &std::collections::HashMap<glam::UVec2, Vec<Chunk>>,
-        terrain: &WorldTerrain,
+        terrain: &dyn TerrainGenerator,
+        view: DebugView,
     ) -> Self {
         let mut vertices = Vec::new();
         let mut opaque_indices = Vec::new();
@@ -59,8 +187,8 @@ impl ChunkMesh {
                 }
 
                 let point = glam::Vec2::new(wx as f32, wz as f32);
-                let height = terrain.get(point).height as i32;
-                wy < height
+                let tdata = terrain.get(point);
+                terrain.is_solid(glam::Vec3::new(wx as f32, wy as f32, wz as f32), &tdata)
             }
         };
 
@@ -89,11 +217,15 @@ impl ChunkMesh {
                 }
 
                 let point = glam::Vec2::new(wx as f32, wz as f32);
-                let height = terrain.get(point).height as i32;
-                wy < 32 && wy >= height
+                let tdata = terrain.get(point);
+                let solid =
+                    terrain.is_solid(glam::Vec3::new(wx as f32, wy as f32, wz as f32), &tdata);
+                (wy as f32) < tdata.water_level && !solid
             }
         };
 
+        let block_light = BlockLight::flood(chunk, loaded_chunks, is_opaque);
+
         let vertex_ao = |side1: bool, side2: bool, corner: bool| -> f32 {
             let num_solid = if side1 && side2 {
                 3
@@ -110,6 +242,8 @@ impl ChunkMesh {
 
         for (x, slice_x) in blocks.iter().enumerate().take(16) {
             for (z, slice_z) in slice_x.iter().enumerate().take(16) {
+                // Only looked up for the biome view, and then once per column.
+                let mut column_biome = None;
                 for (y, block) in slice_z.iter().enumerate().take(16) {
                     if !block.is_active() {
                         continue;
@@ -122,6 +256,24 @@ impl ChunkMesh {
                         (color.b * 255.0) as u8,
                         (color.a * 255.0) as u8,
                     ];
+                    let is_water = matches!(block.ty(), Type::Water);
+                    let mut color_arr = match view {
+                        DebugView::Biome if !is_water => {
+                            let biome = *column_biome.get_or_insert_with(|| {
+                                terrain
+                                    .get(glam::Vec2::new(start.x + x as f32, start.z + z as f32))
+                                    .biome
+                            });
+                            debug::biome_color(biome)
+                        }
+                        DebugView::Water if is_water => {
+                            debug::water_color(block.level(), block.is_source())
+                        }
+                        DebugView::Water => debug::grey(color_arr),
+                        _ => color_arr,
+                    };
+                    // Keep the block's own transparency so it stays in the same pass
+                    color_arr[3] = (color.a * 255.0) as u8;
                     let is_transparent = color.a < 1.0;
                     let pos = start + Vec3::new(x as f32, y as f32, z as f32);
 
@@ -136,20 +288,33 @@ impl ChunkMesh {
 
                     let material_id = block.material_id();
 
+                    let wx = start.x as i32 + x as i32;
+                    let wy = start.y as i32 + y as i32;
+                    let wz = start.z as i32 + z as i32;
+
                     let mut add_face = |normal: [f32; 3], vts: &[[f32; 3]; 4], aos: [f32; 4]| {
                         let idx = vertices.len() as u32;
                         let nx = (normal[0] * 127.0) as i8;
                         let ny = (normal[1] * 127.0) as i8;
                         let nz = (normal[2] * 127.0) as i8;
+                        // A face is lit by the light in the block it looks out onto
+                        let facing =
+                            IVec3::new(normal[0] as i32, normal[1] as i32, normal[2] as i32);
+                        let light = block_light
+                            .as_ref()
+                            .map_or([0; 4], |l| l.color(IVec3::new(wx, wy, wz) + facing));
 
                         for (i, v) in vts.iter().enumerate() {
                             let ao_i8 = (aos[i] * 127.0) as i8;
-                            vertices.push(Vertex::new(
-                                [pos.x + v[0], pos.y + v[1], pos.z + v[2]],
-                                material_id,
-                                color_arr,
-                                [nx, ny, nz, ao_i8],
-                            ));
+                            vertices.push(
+                                Vertex::new(
+                                    [pos.x + v[0], pos.y + v[1], pos.z + v[2]],
+                                    material_id,
+                                    color_arr,
+                                    [nx, ny, nz, ao_i8],
+                                )
+                                .with_light(light),
+                            );
                         }
 
                         let target_indices = if color_arr[3] < 255 {
@@ -180,10 +345,6 @@ impl ChunkMesh {
                         }
                     };
 
-                    let wx = start.x as i32 + x as i32;
-                    let wy = start.y as i32 + y as i32;
-                    let wz = start.z as i32 + z as i32;
-
                     // X+ (Right)
                     if should_draw_face(wx + 1, wy, wz) {
                         let a00 = is_opaque(wx + 1, wy - 1, wz - 1);
@@ -362,6 +523,7 @@ impl ChunkMesh {
 mod tests {
     use super::*;
     use crate::block::{Block, Type};
+    use crate::terrain::WorldTerrain;
     use std::collections::HashMap;
 
     #[test]
@@ -374,7 +536,7 @@ mod tests {
         let loaded_chunks = HashMap::new();
         let terrain = WorldTerrain::new(12345);
 
-        let mesh = ChunkMesh::build(&chunk, &loaded_chunks, &terrain);
+        let mesh = ChunkMesh::build(&chunk, &loaded_chunks, &terrain, DebugView::Off);
 
         // A single active grass block with nothing else in the chunk should generate a full cube (6 faces).
         // 6 faces * 4 vertices = 24 vertices
@@ -406,4 +568,35 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_lava_lights_nearby_faces() {
+        let mut blocks = [[[Block::new(); 16]; 16]; 16];
+        blocks[2][4][3].set_type(Type::Lava);
+        blocks[2][6][3].set_type(Type::Grass);
+        blocks[14][14][14].set_type(Type::Grass);
+
+        let chunk = Chunk::new(Vec3::ZERO, blocks);
+        let loaded_chunks = HashMap::new();
+        let terrain = WorldTerrain::new(12345);
+
+        let mesh = ChunkMesh::build(&chunk, &loaded_chunks, &terrain, DebugView::Off);
+
+        let grass = Type::Grass as u32;
+        let near: Vec<_> = mesh
+            .vertices()
+            .iter()
+            .filter(|v| v.material_id() == grass && v.position()[2] < 8.0)
+            .collect();
+        let far: Vec<_> = mesh
+            .vertices()
+            .iter()
+            .filter(|v| v.material_id() == grass && v.position()[2] > 8.0)
+            .collect();
+        assert!(
+            near.iter().any(|v| v.light()[0] > 0),
+            "The face looking at the lava should be lit"
+        );
+        assert!(far.iter().all(|v| v.light() == [0; 4]));
+    }
 }
@@ -16,8 +16,19 @@ struct LightUniform {
 struct LightUniforms {
   lights: array<LightUniform, 2>,
 }
-@group(1) @binding(0) var<storage, read> _unused: vec3<f32>;
-// left unbound
+struct SkyUniform {
+    color: vec4<f32>,
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
+    d: vec4<f32>,
+    e: vec4<f32>,
+    z: vec4<f32>,
+    sun_dir: vec4<f32>,
+    // sidereal angle, latitude, unused, unused
+    celestial: vec4<f32>,
+};
+@group(1) @binding(0) var<uniform> sky: SkyUniform;
 @group(1) @binding(1) var<uniform> lights: LightUniforms;
 
 struct VertexInput {
@@ -29,6 +40,8 @@ struct VertexOutput {
   @builtin(position) clip_position: vec4<f32>,
   @location(0) color: vec4<f32>,
   @location(1) world_position: vec3<f32>,
+  @location(2) centre: vec3<f32>,
+  @location(3) radius: f32,
 };
 
 @vertex
@@ -40,6 +53,8 @@ fn vs_main(model: VertexInput) -> VertexOutput {
   out.clip_position = camera.view_proj * vec4<f32>(world_pos, 1.0);
   out.color = vec4<f32>(lights.lights[1].color.xyz, 1.0);
   out.world_position = world_pos;
+  out.centre = lights.lights[1].position + vec3<f32>(0.5) * scale;
+  out.radius = 0.5 * scale;
   return out;
 }
 
@@ -48,7 +63,22 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
   if (in.world_position.y < camera.water_level) {
     discard;
   }
-  var color = in.color;
+  // Trace the sphere inside the cube so the moon is round and can be lit from
+  // the side, which gives it phases as it moves relative to the sun.
+  let ray = normalize(in.world_position - camera.view_pos.xyz);
+  let oc = camera.view_pos.xyz - in.centre;
+  let b = dot(oc, ray);
+  let c = dot(oc, oc) - in.radius * in.radius;
+  let disc = b * b - c;
+  if (disc < 0.0) {
+    discard;
+  }
+  let hit = camera.view_pos.xyz + ray * (-b - sqrt(disc));
+  let normal = normalize(hit - in.centre);
+  let lit = smoothstep(-0.05, 0.1, dot(normal, normalize(sky.sun_dir.xyz)));
+
+  // Earthshine keeps the dark side faintly visible
+  var color = vec4<f32>(in.color.xyz * mix(0.06, 1.0, lit), in.color.w);
   if (camera.is_underwater > 0.5) {
     let depth = max(0.0, camera.water_level - camera.view_pos.y);
     let water_absorption = exp(-depth * vec3<f32>(0.25, 0.07, 0.015));
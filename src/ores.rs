@@ -0,0 +1,273 @@
+use crate::{block, terrain::Biome};
+use glam::{IVec2, IVec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ore {
+    Coal,
+    Iron,
+    Copper,
+    Gold,
+    Crystal,
+}
+
+impl fmt::Display for Ore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Ore {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "coal" => Some(Ore::Coal),
+            "iron" => Some(Ore::Iron),
+            "copper" => Some(Ore::Copper),
+            "gold" => Some(Ore::Gold),
+            "crystal" | "crystals" => Some(Ore::Crystal),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [Ore; 5] = [Ore::Coal, Ore::Iron, Ore::Copper, Ore::Gold, Ore::Crystal];
+
+    pub fn block(&self) -> block::Type {
+        match self {
+            Ore::Coal => block::Type::Coal,
+            Ore::Iron => block::Type::Iron,
+            Ore::Copper => block::Type::Copper,
+            Ore::Gold => block::Type::Gold,
+            Ore::Crystal => block::Type::Crystal,
+        }
+    }
+}
+
+/// Where and how often an ore forms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OreSpec {
+    pub ore: Ore,
+    /// Veins start between these heights.
+    pub min_y: i32,
+    pub max_y: i32,
+    /// The average number of veins in a chunk column, before the biome bias.
+    pub veins_per_chunk: f32,
+    /// Blocks visited by the random walk that lays down a vein.
+    pub vein_size: usize,
+    /// Biomes that have more, or fewer, veins than the average.
+    #[serde(default)]
+    pub biome_bias: HashMap<Biome, f32>,
+}
+
+impl OreSpec {
+    fn bias(&self, biome: Biome) -> f32 {
+        self.biome_bias.get(&biome).copied().unwrap_or(1.0)
+    }
+}
+
+/// The ores every world had before they could be configured. Rarer ores form
+/// deeper down, in fewer and smaller veins.
+pub fn default_ores() -> Vec<OreSpec> {
+    vec![
+        OreSpec {
+            ore: Ore::Coal,
+            min_y: 20,
+            max_y: 120,
+            veins_per_chunk: 6.0,
+            vein_size: 12,
+            biome_bias: HashMap::from([
+                (Biome::Swamp, 1.6),
+                (Biome::Jungle, 1.3),
+                (Biome::Taiga, 1.2),
+            ]),
+        },
+        OreSpec {
+            ore: Ore::Iron,
+            min_y: 8,
+            max_y: 80,
+            veins_per_chunk: 4.0,
+            vein_size: 8,
+            biome_bias: HashMap::from([(Biome::Mountains, 1.5), (Biome::Hills, 1.3)]),
+        },
+        OreSpec {
+            ore: Ore::Copper,
+            min_y: 16,
+            max_y: 96,
+            veins_per_chunk: 3.0,
+            vein_size: 10,
+            biome_bias: HashMap::from([(Biome::Savanna, 1.6), (Biome::Desert, 1.4)]),
+        },
+        OreSpec {
+            ore: Ore::Gold,
+            min_y: 4,
+            max_y: 40,
+            veins_per_chunk: 1.0,
+            vein_size: 6,
+            biome_bias: HashMap::from([
+                (Biome::Desert, 2.0),
+                (Biome::Mountains, 1.5),
+                (Biome::Ocean, 0.5),
+            ]),
+        },
+        OreSpec {
+            ore: Ore::Crystal,
+            min_y: 4,
+            max_y: 32,
+            veins_per_chunk: 0.4,
+            vein_size: 4,
+            biome_bias: HashMap::from([(Biome::Tundra, 2.5), (Biome::Mountains, 1.5)]),
+        },
+    ]
+}
+
+/// A cluster of ore blocks, which may spill over into the neighbouring chunk
+/// columns.
+pub(crate) struct Vein {
+    pub(crate) ore: Ore,
+    pub(crate) start: IVec3,
+    pub(crate) blocks: Vec<IVec3>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Ores {
+    seed: u32,
+    specs: Vec<OreSpec>,
+}
+
+impl Ores {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            specs: default_ores(),
+        }
+    }
+
+    pub(crate) fn with_specs(mut self, specs: &[OreSpec]) -> Self {
+        self.specs = specs.to_vec();
+        self
+    }
+
+    /// The veins that start in the chunk column at `key`, which are the same
+    /// every time for the same seed.
+    pub(crate) fn veins_in_chunk(&self, key: IVec2, biome: Biome) -> Vec<Vein> {
+        let mut veins = Vec::new();
+        for (i, spec) in self.specs.iter().enumerate() {
+            let mut rng = StdRng::seed_from_u64(
+                ((self.seed as u64) << 32)
+                    ^ ((key.x as u32 as u64) << 16)
+                    ^ (key.y as u32 as u64).rotate_left(40)
+                    ^ i as u64,
+            );
+
+            let expected = (spec.veins_per_chunk * spec.bias(biome)).max(0.0);
+            let mut count = expected.floor() as usize;
+            if rng.gen::<f32>() < expected.fract() {
+                count += 1;
+            }
+
+            for _ in 0..count {
+                let start = IVec3::new(
+                    key.x * 16 + rng.gen_range(0..16),
+                    rng.gen_range(spec.min_y.min(spec.max_y)..=spec.max_y.max(spec.min_y)),
+                    key.y * 16 + rng.gen_range(0..16),
+                );
+                let mut pos = start;
+                let mut blocks = Vec::with_capacity(spec.vein_size);
+                for _ in 0..spec.vein_size {
+                    blocks.push(pos);
+                    pos += IVec3::new(
+                        rng.gen_range(-1..=1),
+                        rng.gen_range(-1..=1),
+                        rng.gen_range(-1..=1),
+                    );
+                }
+                veins.push(Vein {
+                    ore: spec.ore,
+                    start,
+                    blocks,
+                });
+            }
+        }
+        veins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_veins_are_deterministic() {
+        let ores = Ores::new(42);
+        let a = ores.veins_in_chunk(IVec2::new(3, 7), Biome::Plains);
+        let b = ores.veins_in_chunk(IVec2::new(3, 7), Biome::Plains);
+        assert!(!a.is_empty());
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(a.ore, b.ore);
+            assert_eq!(a.blocks, b.blocks);
+        }
+
+        let other = ores.veins_in_chunk(IVec2::new(4, 7), Biome::Plains);
+        assert_ne!(a[0].blocks, other[0].blocks);
+    }
+
+    #[test]
+    fn test_veins_start_in_their_depth_range() {
+        let ores = Ores::new(42);
+        let specs = default_ores();
+        for x in 0..32 {
+            for vein in ores.veins_in_chunk(IVec2::new(x, 0), Biome::Mountains) {
+                let spec = specs.iter().find(|s| s.ore == vein.ore).unwrap();
+                assert!((spec.min_y..=spec.max_y).contains(&vein.start.y));
+                assert_eq!(vein.start.x.div_euclid(16), x);
+                assert_eq!(vein.blocks[0], vein.start);
+            }
+        }
+    }
+
+    #[test]
+    fn test_configured_depth_moves_veins() {
+        let mut specs = default_ores();
+        let coal = specs.iter_mut().find(|s| s.ore == Ore::Coal).unwrap();
+        coal.min_y = 150;
+        coal.max_y = 160;
+        let ores = Ores::new(42).with_specs(&specs);
+        let default = Ores::new(42);
+
+        let coal_heights = |ores: &Ores| -> Vec<i32> {
+            (0..32)
+                .flat_map(|x| ores.veins_in_chunk(IVec2::new(x, 0), Biome::Plains))
+                .filter(|v| v.ore == Ore::Coal)
+                .map(|v| v.start.y)
+                .collect()
+        };
+        let moved = coal_heights(&ores);
+        assert!(!moved.is_empty());
+        assert!(moved.iter().all(|y| (150..=160).contains(y)));
+        assert!(coal_heights(&default).iter().all(|y| *y <= 120));
+    }
+
+    #[test]
+    fn test_biome_bias() {
+        let ores = Ores::new(42);
+        let count = |biome: Biome| -> usize {
+            (0..200)
+                .flat_map(|x| ores.veins_in_chunk(IVec2::new(x, 0), biome))
+                .filter(|v| v.ore == Ore::Gold)
+                .count()
+        };
+        assert!(count(Biome::Desert) > count(Biome::Plains));
+        assert!(count(Biome::Plains) > count(Biome::Ocean));
+    }
+
+    #[test]
+    fn test_ore_names_round_trip() {
+        for ore in Ore::ALL {
+            assert_eq!(Ore::from_str(&ore.to_string()), Some(ore));
+        }
+        assert_eq!(Ore::from_str("diamond"), None);
+    }
+}
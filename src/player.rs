@@ -0,0 +1,270 @@
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::lsystem::EntityMesh;
+use crate::vertex::Vertex;
+
+const SKIN: [u8; 4] = [224, 172, 125, 255];
+const SHIRT: [u8; 4] = [60, 110, 180, 255];
+const TROUSERS: [u8; 4] = [50, 55, 90, 255];
+const EYES: [u8; 4] = [30, 30, 40, 255];
+
+const LEG_LENGTH: f32 = 0.75;
+const ARM_LENGTH: f32 = 0.72;
+const HIP_HEIGHT: f32 = LEG_LENGTH;
+const SHOULDER_HEIGHT: f32 = 1.45;
+const HEAD_CENTRE: f32 = 1.72;
+
+/// Radians of walk cycle per block travelled.
+const STRIDE_RATE: f32 = 1.6;
+/// Largest swing of the legs away from vertical while walking.
+const MAX_SWING: f32 = 0.7;
+/// Horizontal speed at which the walk reaches its full stride.
+const FULL_STRIDE_SPEED: f32 = 6.0;
+
+/// The blocky figure drawn for the player when the camera isn't in first
+/// person. It is rebuilt every frame from the current animation pose.
+pub struct PlayerModel {
+    walk_phase: f32,
+    // 0 when standing still, 1 at a full walking stride.
+    stride: f32,
+    // 0 on the ground, 1 when fully in the jump pose.
+    airborne: f32,
+}
+
+impl PlayerModel {
+    pub fn new() -> Self {
+        Self {
+            walk_phase: 0.0,
+            stride: 0.0,
+            airborne: 0.0,
+        }
+    }
+
+    /// Advances the animation from the player's current velocity.
+    pub fn update(&mut self, dt: Duration, velocity: Vec3) {
+        let dt = dt.as_secs_f32();
+        let speed = Vec3::new(velocity.x, 0.0, velocity.z).length();
+
+        let target_stride = (speed / FULL_STRIDE_SPEED).min(1.0);
+        let blend = 1.0 - f32::exp(-10.0 * dt);
+        self.stride += (target_stride - self.stride) * blend;
+
+        if speed > 0.01 {
+            self.walk_phase = (self.walk_phase + speed * STRIDE_RATE * dt) % std::f32::consts::TAU;
+        } else {
+            // Settle back to standing rather than freezing mid-step
+            self.walk_phase *= 1.0 - blend;
+        }
+
+        // Vertical velocity is zeroed whenever the player lands on something
+        let target_airborne = if velocity.y.abs() > 0.01 { 1.0 } else { 0.0 };
+        self.airborne += (target_airborne - self.airborne) * (1.0 - f32::exp(-15.0 * dt));
+    }
+
+    fn leg_swing(&self) -> f32 {
+        let walk = self.walk_phase.sin() * MAX_SWING * self.stride;
+        walk * (1.0 - self.airborne) + 0.4 * self.airborne
+    }
+
+    fn arm_swing(&self) -> f32 {
+        -self.walk_phase.sin() * MAX_SWING * self.stride * (1.0 - self.airborne)
+    }
+
+    /// Builds the mesh for the player standing at `feet`, facing along the
+    /// camera's yaw with the head tilted by its pitch.
+    pub fn mesh(&self, feet: Vec3, yaw: f32, pitch: f32) -> EntityMesh {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        // The model is built facing +Z, then turned to face the camera yaw.
+        let root = Mat4::from_rotation_translation(Quat::from_rotation_y(FRAC_PI_2 - yaw), feet);
+
+        let legs = self.leg_swing();
+        let arms = self.arm_swing();
+        // Arms lift out to the side when jumping
+        let arm_raise = 0.5 * self.airborne;
+
+        for (side, leg, arm) in [(1.0, legs, arms), (-1.0, -legs, -arms)] {
+            let hip = root
+                * Mat4::from_translation(Vec3::new(0.12 * side, HIP_HEIGHT, 0.0))
+                * Mat4::from_rotation_x(-leg);
+            add_box(
+                &mut vertices,
+                &mut indices,
+                hip,
+                Vec3::new(-0.11, -LEG_LENGTH, -0.11),
+                Vec3::new(0.11, 0.0, 0.11),
+                TROUSERS,
+            );
+
+            let shoulder = root
+                * Mat4::from_translation(Vec3::new(0.35 * side, SHOULDER_HEIGHT, 0.0))
+                * Mat4::from_rotation_z(arm_raise * side)
+                * Mat4::from_rotation_x(-arm);
+            add_box(
+                &mut vertices,
+                &mut indices,
+                shoulder,
+                Vec3::new(-0.09, -ARM_LENGTH, -0.09),
+                Vec3::new(0.09, 0.0, 0.09),
+                SHIRT,
+            );
+        }
+
+        add_box(
+            &mut vertices,
+            &mut indices,
+            root,
+            Vec3::new(-0.25, HIP_HEIGHT, -0.13),
+            Vec3::new(0.25, 1.5, 0.13),
+            SHIRT,
+        );
+
+        let neck = root
+            * Mat4::from_translation(Vec3::new(0.0, HEAD_CENTRE, 0.0))
+            * Mat4::from_rotation_x(-pitch);
+        add_box(
+            &mut vertices,
+            &mut indices,
+            neck,
+            Vec3::splat(-0.22),
+            Vec3::splat(0.22),
+            SKIN,
+        );
+        for side in [1.0, -1.0] {
+            add_box(
+                &mut vertices,
+                &mut indices,
+                neck,
+                Vec3::new(0.08 * side - 0.04, 0.02, 0.22),
+                Vec3::new(0.08 * side + 0.04, 0.08, 0.24),
+                EYES,
+            );
+        }
+
+        EntityMesh { vertices, indices }
+    }
+}
+
+/// Adds an axis aligned box, given in the space of `transform`, to the mesh.
+fn add_box(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    transform: Mat4,
+    min: Vec3,
+    max: Vec3,
+    color: [u8; 4],
+) {
+    let centre = (min + max) * 0.5;
+    let half = (max - min) * 0.5;
+
+    // Each face is given by its normal and two tangents with u x v = n, so the
+    // corners below wind anti-clockwise when seen from outside.
+    let faces = [
+        (Vec3::X, Vec3::Y, Vec3::Z),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::Z, Vec3::X),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::Y, Vec3::X),
+    ];
+
+    for (n, u, v) in faces {
+        let base_idx = vertices.len() as u32;
+        let normal = transform.transform_vector3(n).normalize_or_zero();
+        let normal = [
+            (normal.x * 127.0) as i8,
+            (normal.y * 127.0) as i8,
+            (normal.z * 127.0) as i8,
+            127,
+        ];
+        for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let local = centre + (n + u * su + v * sv) * half;
+            let p = transform.transform_point3(local);
+            vertices.push(Vertex::new([p.x, p.y, p.z], 0, color, normal));
+        }
+        indices.extend_from_slice(&[
+            base_idx,
+            base_idx + 1,
+            base_idx + 2,
+            base_idx,
+            base_idx + 2,
+            base_idx + 3,
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_mesh_is_player_sized() {
+        let model = PlayerModel::new();
+        let feet = Vec3::new(10.0, 40.0, -5.0);
+        let mesh = model.mesh(feet, 0.3, 0.0);
+
+        // Both legs, both arms, torso, head and eyes
+        assert_eq!(mesh.vertices.len(), 8 * 24);
+        assert_eq!(mesh.indices.len(), 8 * 36);
+
+        let (mut min_y, mut max_y) = (f32::MAX, f32::MIN);
+        for v in &mesh.vertices {
+            let p = Vec3::from(v.position());
+            min_y = min_y.min(p.y);
+            max_y = max_y.max(p.y);
+            assert!((p - feet).length() < 2.5);
+        }
+        assert!((min_y - feet.y).abs() < 0.01, "feet at {min_y}");
+        assert!(
+            max_y - feet.y > 1.8 && max_y - feet.y < 2.0,
+            "head at {max_y}"
+        );
+    }
+
+    #[test]
+    fn test_player_faces_camera_yaw() {
+        let model = PlayerModel::new();
+        // Yaw of zero looks along +X, so the eyes should be on that side.
+        let mesh = model.mesh(Vec3::ZERO, 0.0, 0.0);
+        let eyes: Vec<Vec3> = mesh
+            .vertices
+            .iter()
+            .filter(|v| v.color() == EYES)
+            .map(|v| Vec3::from(v.position()))
+            .collect();
+        assert!(!eyes.is_empty());
+        assert!(eyes.iter().all(|p| p.x > 0.2), "eyes at {:?}", eyes);
+    }
+
+    #[test]
+    fn test_walking_swings_legs() {
+        let mut model = PlayerModel::new();
+        assert_eq!(model.leg_swing(), 0.0);
+
+        for _ in 0..25 {
+            model.update(Duration::from_millis(16), Vec3::new(6.0, 0.0, 0.0));
+        }
+        assert!(model.stride > 0.9);
+        assert!(model.leg_swing().abs() > 0.1);
+        assert!(model.leg_swing().signum() != model.arm_swing().signum());
+
+        // Standing still settles back to the rest pose
+        for _ in 0..200 {
+            model.update(Duration::from_millis(16), Vec3::ZERO);
+        }
+        assert!(model.leg_swing().abs() < 0.01);
+    }
+
+    #[test]
+    fn test_jump_pose() {
+        let mut model = PlayerModel::new();
+        for _ in 0..30 {
+            model.update(Duration::from_millis(16), Vec3::new(0.0, 5.0, 0.0));
+        }
+        assert!(model.airborne > 0.9);
+        assert!(model.leg_swing() > 0.3);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::terrain::{TerrainData, WATER_LEVEL};
+use crate::terrain::TerrainData;
 use crate::trees;
 use glam::Vec2;
 use std::hash::{DefaultHasher, Hash, Hasher};
@@ -20,7 +20,7 @@ impl AdaptivePoisson {
         F: Fn(glam::Vec2) -> TerrainData,
     {
         let tdata = terrain_func(point);
-        if tdata.height <= WATER_LEVEL {
+        if tdata.is_water() {
             return f32::INFINITY;
         }
 
@@ -47,10 +47,7 @@ impl AdaptivePoisson {
                 glam::Vec2::new(point.x - diag, point.y - diag),
             ];
 
-            points_to_check.iter().any(|&p| {
-                let d = terrain_func(p);
-                d.height <= WATER_LEVEL
-            })
+            points_to_check.iter().any(|&p| terrain_func(p).is_water())
         };
 
         trees::get_vegetation_radius(temp, moist, height, check_water_nearby())
@@ -161,7 +158,7 @@ impl AdaptivePoisson {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::terrain::{Biome, WorldTerrain};
+    use crate::terrain::{Biome, WorldTerrain, WATER_LEVEL};
 
     #[test]
     fn test_poisson_determinism() {
@@ -236,6 +233,12 @@ mod tests {
                             Biome::Mountains => [200, 200, 200], // White/Grey
                             Biome::Desert => [255, 255, 0], // Cactus (Yellow)
                             Biome::Ocean => [0, 0, 255],    // Blue
+                            Biome::Tundra => [255, 255, 255], // White
+                            Biome::Taiga => [0, 255, 200],  // Teal
+                            Biome::Jungle => [255, 0, 255], // Magenta
+                            Biome::Swamp => [120, 60, 0],   // Brown
+                            Biome::Savanna => [255, 60, 60], // Red
+                            Biome::Beach => [0, 200, 255],  // Cyan
                         };
 
                         // Draw a small 3x3 square for visibility
@@ -264,12 +267,14 @@ mod tests {
             Biome::Plains,
             "test_outputs/poisson_bitmap_plains.bmp",
             |p| {
-                let h = gen.get([p.x as f64 / scale as f64, p.y as f64 / scale as f64]);
+                let h = gen.get([p.x as f64 / scale, p.y as f64 / scale]);
                 TerrainData {
                     height: h as f32,
                     biome: Biome::Plains,
                     moisture: 0.5,
                     temperature: 0.5,
+                    water_level: WATER_LEVEL,
+                    relief: 0.0,
                 }
             },
         );
@@ -284,10 +289,12 @@ mod tests {
             Biome::Mountains,
             "test_outputs/poisson_bitmap_mountains.bmp",
             |p| TerrainData {
-                height: gen.get([p.x as f64 / scale as f64, p.y as f64 / scale as f64]) as f32,
+                height: gen.get([p.x as f64 / scale, p.y as f64 / scale]) as f32,
                 biome: Biome::Mountains,
                 moisture: 0.5,
                 temperature: 0.5,
+                water_level: WATER_LEVEL,
+                relief: 0.0,
             },
         );
     }
@@ -298,12 +305,14 @@ mod tests {
         let gen = crate::terrain::HillsTerrain::new(42);
         let scale = crate::terrain::WorldTerrain::WORLD_SCALE;
         generate_biome_bitmap(Biome::Hills, "test_outputs/poisson_bitmap_hills.bmp", |p| {
-            let h = gen.get([p.x as f64 / scale as f64, p.y as f64 / scale as f64]);
+            let h = gen.get([p.x as f64 / scale, p.y as f64 / scale]);
             TerrainData {
                 height: h as f32,
                 biome: Biome::Hills,
                 moisture: 0.5,
                 temperature: 0.5,
+                water_level: WATER_LEVEL,
+                relief: 0.0,
             }
         });
     }
@@ -316,10 +325,12 @@ mod tests {
             Biome::Desert,
             "test_outputs/poisson_bitmap_desert.bmp",
             |p| TerrainData {
-                height: gen.get([p.x as f64 / scale as f64, p.y as f64 / scale as f64]) as f32,
+                height: gen.get([p.x as f64 / scale, p.y as f64 / scale]) as f32,
                 biome: Biome::Desert,
                 moisture: 0.0,
                 temperature: 1.0,
+                water_level: WATER_LEVEL,
+                relief: 0.0,
             },
         );
     }
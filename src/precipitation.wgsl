@@ -0,0 +1,146 @@
+struct CameraUniform {
+  view_proj: mat4x4<f32>,
+  inv_view_proj: mat4x4<f32>,
+  view_pos: vec4<f32>,
+  water_level: f32,
+  fog_start_sq: f32,
+  fog_end_sq: f32,
+  is_underwater: f32,
+}
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct SkyUniform {
+    color: vec4<f32>,
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
+    d: vec4<f32>,
+    e: vec4<f32>,
+    z: vec4<f32>,
+    sun_dir: vec4<f32>,
+    // sidereal angle, latitude, unused, unused
+    celestial: vec4<f32>,
+};
+@group(1) @binding(0) var<uniform> sky: SkyUniform;
+
+struct WeatherUniform {
+  // intensity, is_snow, time, unused
+  precipitation: vec4<f32>,
+  // overcast, wetness, lightning, unused
+  atmosphere: vec4<f32>,
+  // wind x, wind z, unused, unused
+  wind: vec4<f32>,
+}
+@group(1) @binding(2) var<uniform> weather: WeatherUniform;
+
+// Particles live in a box around the camera and wrap around as it moves, so
+// no particle state is needed on the CPU.
+const BOX_SIZE: f32 = 32.0;
+const BOX_HEIGHT: f32 = 24.0;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+}
+
+fn hash3(n: u32) -> vec3<f32> {
+    var x = n * 747796405u + 2891336453u;
+    x = ((x >> ((x >> 28u) + 4u)) ^ x) * 277803737u;
+    x = (x >> 22u) ^ x;
+    let y = x * 1664525u + 1013904223u;
+    let z = y * 1664525u + 1013904223u;
+    return vec3<f32>(f32(x & 0xffffu), f32(y & 0xffffu), f32(z & 0xffffu)) / 65535.0;
+}
+
+fn wrap(p: f32, centre: f32, size: f32) -> f32 {
+    return centre + (fract((p - centre) / size + 0.5) - 0.5) * size;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VertexOutput {
+  var out: VertexOutput;
+
+  let is_snow = weather.precipitation.y > 0.5;
+  let t = weather.precipitation.z;
+  let wind = vec3<f32>(weather.wind.x, 0.0, weather.wind.y);
+  let seed = hash3(instance_index);
+
+  let fall_speed = select(14.0, 1.5, is_snow);
+  var velocity = vec3<f32>(0.0, -fall_speed, 0.0) + wind * select(4.0, 2.0, is_snow);
+
+  var p = seed * vec3<f32>(BOX_SIZE, BOX_HEIGHT, BOX_SIZE) + velocity * (t + seed.y * 10.0);
+  if (is_snow) {
+      // Flakes drift from side to side as they fall
+      let phase = seed.x * 6.283 + t * (0.5 + seed.z);
+      p += vec3<f32>(sin(phase), 0.0, cos(phase * 0.7)) * 0.5;
+  }
+
+  let cam = camera.view_pos.xyz;
+  let world = vec3<f32>(
+      wrap(p.x, cam.x, BOX_SIZE),
+      wrap(p.y, cam.y, BOX_HEIGHT),
+      wrap(p.z, cam.z, BOX_SIZE),
+  );
+
+  // Two triangles per particle
+  var corners = array<vec2<f32>, 6>(
+      vec2<f32>(-1.0, 0.0),
+      vec2<f32>(1.0, 0.0),
+      vec2<f32>(1.0, 1.0),
+      vec2<f32>(-1.0, 0.0),
+      vec2<f32>(1.0, 1.0),
+      vec2<f32>(-1.0, 1.0),
+  );
+  let corner = corners[vertex_index];
+  out.uv = corner;
+
+  let view_dir = normalize(cam - world);
+  var axis: vec3<f32>;
+  var width: f32;
+  var streak_length: f32;
+  if (is_snow) {
+      axis = vec3<f32>(0.0, 1.0, 0.0);
+      width = 0.05;
+      streak_length = 0.1;
+  } else {
+      // Rain streaks are stretched along their direction of travel
+      axis = -normalize(velocity);
+      width = 0.015;
+      streak_length = 0.6;
+  }
+  var right = cross(axis, view_dir);
+  if (dot(right, right) < 0.0001) {
+      right = vec3<f32>(1.0, 0.0, 0.0);
+  }
+  right = normalize(right);
+  if (is_snow) {
+      axis = normalize(cross(view_dir, right));
+  }
+
+  let pos = world + right * corner.x * width + axis * (corner.y - 0.5) * streak_length;
+  out.clip_position = camera.view_proj * vec4<f32>(pos, 1.0);
+
+  // Nothing falls underwater
+  if (camera.is_underwater > 0.5) {
+      out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+  }
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let is_snow = weather.precipitation.y > 0.5;
+
+  // Dim the particles at night along with everything else
+  let daylight = clamp(sky.sun_dir.y * 3.0 + 0.5, 0.15, 1.0);
+  let flash = weather.atmosphere.z;
+
+  if (is_snow) {
+      let d = length(vec2<f32>(in.uv.x, in.uv.y * 2.0 - 1.0));
+      let alpha = (1.0 - smoothstep(0.5, 1.0, d)) * 0.9;
+      return vec4<f32>(vec3<f32>(0.95) * daylight + flash, alpha);
+  }
+
+  let alpha = (1.0 - abs(in.uv.x)) * 0.35;
+  return vec4<f32>(vec3<f32>(0.7, 0.75, 0.85) * daylight + flash, alpha);
+}
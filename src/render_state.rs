@@ -2,13 +2,20 @@ use std::time::Duration;
 
 use crate::{
     block::Type,
-    camera::{Camera, Uniform},
+    camera::{Camera, Uniform, ViewMode},
+    clock::Season,
+    collision::CollisionShape,
     config::Config,
+    debug::DebugView,
+    decoration::{self, DecorationInstance, DecorationKind},
+    entities::{TreeInstance, TreeLod},
+    impostor::{ImpostorAtlas, ImpostorTile, ImpostorVertex},
     scene::Scene,
     sky::Sky,
     texture::Texture,
     ui::Ui,
     vertex::{SimpleVertex, Vertex},
+    weather::Weather,
 };
 use bytemuck::{Pod, Zeroable};
 use std::sync::Arc;
@@ -20,6 +27,12 @@ use wgpu_text::{
 };
 use winit::window::Window;
 
+/// Number of rain or snow particles drawn at full intensity.
+const MAX_PRECIPITATION: u32 = 8000;
+
+/// The pictures impostors show keep the colours of the trees as they are.
+const IMPOSTOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct MainShadowUniform {
@@ -62,6 +75,39 @@ struct EntityBuffers {
     num_indices: u32,
 }
 
+/// The variants every tree is drawn from, for the season they were grown in.
+struct TreePrototypeBuffers {
+    season: Season,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    ranges: Vec<std::ops::Range<u32>>,
+    reduced_ranges: Vec<std::ops::Range<u32>>,
+    impostors: ImpostorBuffers,
+}
+
+/// Billboards for the prototypes, with the pictures taken of them.
+struct ImpostorBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    ranges: Vec<std::ops::Range<u32>>,
+    bind_group: wgpu::BindGroup,
+}
+
+struct TreeBuffers {
+    instance_buffer: wgpu::Buffer,
+    // Instances of each prototype, in the same order as the prototype ranges
+    ranges: Vec<std::ops::Range<u32>>,
+    revision: u32,
+    lod: TreeLod,
+}
+
+struct DecorationBuffers {
+    instance_buffer: Option<wgpu::Buffer>,
+    ranges: [std::ops::Range<u32>; DecorationKind::ALL.len()],
+    // Summed versions of the chunk column the decorations were culled against
+    blocks_version: u32,
+}
+
 pub struct RenderState<'window> {
     pub size: winit::dpi::PhysicalSize<u32>,
     surface: wgpu::Surface<'window>,
@@ -70,12 +116,28 @@ pub struct RenderState<'window> {
     config: wgpu::SurfaceConfiguration,
     render_pipeline: wgpu::RenderPipeline,
     transparent_pipeline: wgpu::RenderPipeline,
+    decoration_pipeline: wgpu::RenderPipeline,
+    tree_pipeline: wgpu::RenderPipeline,
+    impostor_pipeline: wgpu::RenderPipeline,
+    impostor_bake_pipeline: wgpu::RenderPipeline,
+    impostor_bind_group_layout: wgpu::BindGroupLayout,
+    impostor_sampler: wgpu::Sampler,
     sun_render_pipeline: wgpu::RenderPipeline,
     moon_render_pipeline: wgpu::RenderPipeline,
     sky_render_pipeline: wgpu::RenderPipeline,
+    precipitation_pipeline: wgpu::RenderPipeline,
     wireframe_pipeline: wgpu::RenderPipeline,
     overlay_pipeline: wgpu::RenderPipeline,
+    debug_lines_pipeline: wgpu::RenderPipeline,
+    shadow_preview_pipeline: wgpu::RenderPipeline,
     selected_block: Option<glam::IVec3>,
+    // Outlines the tree being looked at, rebuilt when it changes
+    selected_tree: Option<(Vec<CollisionShape>, wgpu::Buffer, u32)>,
+
+    debug_view: DebugView,
+    show_chunk_borders: bool,
+    // Rebuilt whenever the player moves into a different chunk column
+    chunk_border_buffer: Option<(glam::IVec2, wgpu::Buffer, u32)>,
 
     ui_brush: TextBrush,
 
@@ -97,6 +159,7 @@ pub struct RenderState<'window> {
     sun_shadow_texture: Texture,
     moon_shadow_texture: Texture,
     shadow_pipeline: wgpu::RenderPipeline,
+    tree_shadow_pipeline: wgpu::RenderPipeline,
 
     main_shadow_bind_group: wgpu::BindGroup,
     main_shadow_uniform_buffer: wgpu::Buffer,
@@ -106,12 +169,23 @@ pub struct RenderState<'window> {
     sun_shadow_pass_uniform_buffer: wgpu::Buffer,
     moon_shadow_pass_uniform_buffer: wgpu::Buffer,
 
-    entity_buffers: std::collections::HashMap<glam::UVec2, EntityBuffers>,
+    tree_prototype_buffers: Option<TreePrototypeBuffers>,
+    tree_buffers: std::collections::HashMap<glam::UVec2, TreeBuffers>,
+
+    decoration_vertex_buffer: wgpu::Buffer,
+    decoration_index_buffer: wgpu::Buffer,
+    decoration_mesh_ranges: [std::ops::Range<u32>; DecorationKind::ALL.len()],
+    decoration_buffers: std::collections::HashMap<glam::UVec2, DecorationBuffers>,
+
+    player_buffers: EntityBuffers,
+    show_player: bool,
 
     chunk_buffers: std::collections::HashMap<glam::UVec2, Vec<Option<ChunkBuffers>>>,
     chunk_versions: std::collections::HashMap<glam::UVec2, Vec<u32>>,
 
     lights_buffer: wgpu::Buffer,
+    weather_buffer: wgpu::Buffer,
+    precipitation_instances: u32,
 
     sky: Sky,
     game_config: Config,
@@ -293,36 +367,67 @@ impl RenderState<'static> {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
+        let weather_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("weather buffer"),
+            contents: bytemuck::cast_slice(&[Weather::new(0).to_raw()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let shadow_pass_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("shadow pass bind group layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    // Weather, so trees cast shadows that sway with them
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
             });
 
         let sun_shadow_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("sun shadow pass bind group"),
             layout: &shadow_pass_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: sun_shadow_pass_uniform_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sun_shadow_pass_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: weather_buffer.as_entire_binding(),
+                },
+            ],
         });
         let moon_shadow_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("moon shadow pass bind group"),
             layout: &shadow_pass_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: moon_shadow_pass_uniform_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: moon_shadow_pass_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: weather_buffer.as_entire_binding(),
+                },
+            ],
         });
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -338,6 +443,23 @@ impl RenderState<'static> {
         });
         let num_indices = crate::vertex::CUBE_INDICES.len() as u32;
 
+        // The player mesh always has the same topology, so the index buffer is
+        // fixed and only the vertices are rewritten each frame.
+        let player_mesh = crate::player::PlayerModel::new().mesh(glam::Vec3::ZERO, 0.0, 0.0);
+        let player_buffers = EntityBuffers {
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("player vertex buffer"),
+                contents: bytemuck::cast_slice(&player_mesh.vertices),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("player index buffer"),
+                contents: bytemuck::cast_slice(&player_mesh.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            num_indices: player_mesh.indices.len() as u32,
+        };
+
         let wireframe_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("wireframe index buffer"),
             contents: bytemuck::cast_slice(crate::vertex::WIREFRAME_INDICES),
@@ -406,6 +528,16 @@ impl RenderState<'static> {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
         let sky = Sky::new(&device);
@@ -429,6 +561,10 @@ impl RenderState<'static> {
                     binding: 1,
                     resource: lights_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: weather_buffer.as_entire_binding(),
+                },
             ],
             label: Some("light bind group"),
         });
@@ -468,8 +604,11 @@ impl RenderState<'static> {
                 push_constant_ranges: &[],
             });
 
-            PipelineConfig::opaque(&layout, Vertex::desc(), wgpu::include_wgsl!("shader.wgsl"))
-                .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
+            PipelineConfig::opaque(&layout, Vertex::desc(), main_shader()).build(
+                &device,
+                &surface_config,
+                Some(Texture::DEPTH_FORMAT),
+            )
         };
 
         let transparent_pipeline = {
@@ -483,10 +622,168 @@ impl RenderState<'static> {
                 push_constant_ranges: &[],
             });
 
-            PipelineConfig::transparent(&layout, Vertex::desc(), wgpu::include_wgsl!("shader.wgsl"))
+            PipelineConfig::transparent(&layout, Vertex::desc(), main_shader()).build(
+                &device,
+                &surface_config,
+                Some(Texture::DEPTH_FORMAT),
+            )
+        };
+
+        let decoration_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("decoration pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &light_bind_group_layout,
+                    &shadow_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+            PipelineConfig::opaque(&layout, SimpleVertex::desc(), main_shader())
+                .with_instances("vs_decoration", DecorationInstance::desc())
+                .with_cull_mode(None)
+                .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
+        };
+
+        let tree_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("tree pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &light_bind_group_layout,
+                    &shadow_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+            PipelineConfig::opaque(&layout, Vertex::desc(), main_shader())
+                .with_instances("vs_tree", TreeInstance::desc())
+                .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
+        };
+
+        let impostor_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("impostor bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        // atlas
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        // sampler
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let impostor_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("impostor sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let impostor_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("impostor pipeline layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &light_bind_group_layout,
+                    &shadow_bind_group_layout,
+                    &impostor_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+            PipelineConfig::opaque(&layout, ImpostorVertex::desc(), main_shader())
+                .with_instances("vs_impostor", TreeInstance::desc())
+                .with_fragment("fs_impostor")
+                .with_cull_mode(None)
                 .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
         };
 
+        // Takes the pictures of the prototypes the impostors show
+        let impostor_bake_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("impostor bake pipeline layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+            let shader = device.create_shader_module(wgpu::include_wgsl!("impostor.wgsl"));
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("impostor bake pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_bake",
+                    buffers: &[Vertex::desc(), ImpostorTile::desc()],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_bake",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: IMPOSTOR_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                multiview: None,
+            })
+        };
+
+        let (decoration_vertices, decoration_indices, decoration_mesh_ranges) =
+            decoration::meshes();
+        let decoration_vertex_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("decoration vertex buffer"),
+                contents: bytemuck::cast_slice(&decoration_vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let decoration_index_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("decoration index buffer"),
+                contents: bytemuck::cast_slice(&decoration_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
         let sun_render_pipeline = {
             let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("sun pipeline layout"),
@@ -534,21 +831,21 @@ impl RenderState<'static> {
             .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
         };
 
-        let shadow_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("shadow pipeline layout"),
-                bind_group_layouts: &[&shadow_pass_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-            let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let shadow_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow pipeline layout"),
+            bind_group_layouts: &[&shadow_pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shadow_shader = device.create_shader_module(shadow_shader());
+        // Trees are drawn into the shadow maps from their prototypes too.
+        let shadow_pipeline_for = |label, entry_point, buffers: &[wgpu::VertexBufferLayout]| {
             device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("shadow render pipeline"),
-                layout: Some(&layout),
+                label: Some(label),
+                layout: Some(&shadow_layout),
                 vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
+                    module: &shadow_shader,
+                    entry_point,
+                    buffers,
                     compilation_options: wgpu::PipelineCompilationOptions::default(),
                 },
                 primitive: wgpu::PrimitiveState {
@@ -580,6 +877,13 @@ impl RenderState<'static> {
                 multiview: None,
             })
         };
+        let shadow_pipeline =
+            shadow_pipeline_for("shadow render pipeline", "vs_main", &[Vertex::desc()]);
+        let tree_shadow_pipeline = shadow_pipeline_for(
+            "tree shadow render pipeline",
+            "vs_tree",
+            &[Vertex::desc(), TreeInstance::desc()],
+        );
 
         let sky_render_pipeline = {
             let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -633,6 +937,58 @@ impl RenderState<'static> {
             })
         };
 
+        let precipitation_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("precipitation pipeline layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            let shader = device.create_shader_module(wgpu::include_wgsl!("precipitation.wgsl"));
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("precipitation render pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                multiview: None,
+            })
+        };
+
         let overlay_pipeline = {
             let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("overlay pipeline layout"),
@@ -679,6 +1035,68 @@ impl RenderState<'static> {
             })
         };
 
+        let debug_lines_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("debug lines pipeline layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            PipelineConfig::opaque(
+                &layout,
+                Vertex::desc(),
+                wgpu::include_wgsl!("debug_lines.wgsl"),
+            )
+            .with_topology(wgpu::PrimitiveTopology::LineList)
+            .build(&device, &surface_config, Some(Texture::DEPTH_FORMAT))
+        };
+
+        let shadow_preview_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("shadow preview pipeline layout"),
+                bind_group_layouts: &[&shadow_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            let shader = device.create_shader_module(wgpu::include_wgsl!("shadow_preview.wgsl"));
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("shadow preview render pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: surface_config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                multiview: None,
+            })
+        };
+
         Self {
             size,
             surface,
@@ -687,21 +1105,37 @@ impl RenderState<'static> {
             config: surface_config,
             render_pipeline,
             transparent_pipeline,
+            decoration_pipeline,
+            tree_pipeline,
+            impostor_pipeline,
+            impostor_bake_pipeline,
+            impostor_bind_group_layout,
+            impostor_sampler,
             sun_render_pipeline,
             moon_render_pipeline,
             sky_render_pipeline,
+            precipitation_pipeline,
             wireframe_pipeline,
             overlay_pipeline,
+            debug_lines_pipeline,
+            shadow_preview_pipeline,
+            debug_view: DebugView::Off,
+            show_chunk_borders: false,
+            chunk_border_buffer: None,
             chunk_buffers: std::collections::HashMap::new(),
             chunk_versions: std::collections::HashMap::new(),
 
             lights_buffer,
+            weather_buffer,
+            precipitation_instances: 0,
             sky,
 
             selected_block: None,
+            selected_tree: None,
             sun_shadow_texture,
             moon_shadow_texture,
             shadow_pipeline,
+            tree_shadow_pipeline,
             main_shadow_bind_group,
             main_shadow_uniform_buffer,
             sun_shadow_pass_bind_group,
@@ -720,19 +1154,50 @@ impl RenderState<'static> {
             num_indices,
             wireframe_index_buffer,
             wireframe_uniform_buffer,
-            entity_buffers: std::collections::HashMap::new(),
+            tree_prototype_buffers: None,
+            tree_buffers: std::collections::HashMap::new(),
+            decoration_vertex_buffer,
+            decoration_index_buffer,
+            decoration_mesh_ranges,
+            decoration_buffers: std::collections::HashMap::new(),
+            player_buffers,
+            show_player: false,
             game_config: config,
         }
     }
 
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        if view.affects_mesh() || self.debug_view.affects_mesh() {
+            // Force every loaded chunk to be remeshed with the new colours
+            for versions in self.chunk_versions.values_mut() {
+                versions.fill(u32::MAX);
+            }
+        }
+        self.debug_view = view;
+    }
+
+    pub fn show_chunk_borders(&self) -> bool {
+        self.show_chunk_borders
+    }
+
+    pub fn set_show_chunk_borders(&mut self, show: bool) {
+        self.show_chunk_borders = show;
+    }
+
     pub fn update(
         &mut self,
         dt: Duration,
         camera: &Camera,
         scene: &Scene,
         selected_block: Option<glam::IVec3>,
+        selected_tree: &[CollisionShape],
     ) {
         self.update_chunk_buffers(scene);
+        self.update_decoration_buffers(scene);
 
         self.selected_block = selected_block;
         if let Some(pos) = self.selected_block {
@@ -743,10 +1208,39 @@ impl RenderState<'static> {
                 bytemuck::cast_slice(&[offset]),
             );
         }
+        if selected_tree.is_empty() {
+            self.selected_tree = None;
+        } else if self.selected_tree.as_ref().map(|(s, _, _)| s.as_slice()) != Some(selected_tree) {
+            let lines: Vec<Vertex> = selected_tree
+                .iter()
+                // Drawn just outside the shapes, like the block wireframe, so
+                // the trunk doesn't hide it
+                .flat_map(|shape| shape.transformed(|p| p, 1.1).outline())
+                .flat_map(|(a, b)| [a, b])
+                .map(|p| Vertex::new(p.into(), 0, [255, 255, 255, 255], [0, 127, 0, 127]))
+                .collect();
+            let buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("selected tree vertex buffer"),
+                    contents: bytemuck::cast_slice(&lines),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+            self.selected_tree = Some((selected_tree.to_vec(), buffer, lines.len() as u32));
+        }
 
-        let cam_block = camera.visual_position().floor().as_ivec3();
+        let cam_block = camera.eye_position().floor().as_ivec3();
         let is_underwater = scene.chunks().block_material_at(cam_block) == Type::Water as u32;
         self.camera_uniform.update_view_proj(camera, is_underwater);
+        self.camera_uniform
+            .set_water_level(scene.chunks().terrain().sea_level());
+        self.camera_uniform
+            .set_debug_view(self.debug_view.shader_mode());
+        let decoration_distance = self.game_config.decoration_distance;
+        self.camera_uniform.set_decoration_fade(
+            decoration_distance * decoration::FADE_START,
+            decoration_distance,
+        );
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
@@ -820,43 +1314,150 @@ impl RenderState<'static> {
             bytemuck::cast_slice(&[moon_shadow_pass_uniform]),
         );
 
-        self.sky.update(dt, &scene.sun_offset());
+        let weather = scene.weather();
+        self.queue.write_buffer(
+            &self.weather_buffer,
+            0,
+            bytemuck::cast_slice(&[weather.to_raw()]),
+        );
+        self.precipitation_instances = (weather.intensity() * MAX_PRECIPITATION as f32) as u32;
+
+        self.sky.update(dt, scene.celestial(), weather.overcast());
         self.queue.write_buffer(
             self.sky.buffer(),
             0,
             bytemuck::cast_slice(&[self.sky.to_raw(self.config.format.is_srgb())]),
         );
 
-        let loaded_cells = scene.entity_manager().loaded_cells();
+        if self.show_chunk_borders {
+            let position = camera.position();
+            let column = glam::IVec2::new(
+                (position.x.floor() as i32).div_euclid(16),
+                (position.z.floor() as i32).div_euclid(16),
+            );
+            if self.chunk_border_buffer.as_ref().map(|(c, _, _)| *c) != Some(column) {
+                let lines =
+                    crate::debug::chunk_border_lines(position, crate::chunks::MAX_HEIGHT as f32);
+                let buffer = self
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("chunk border vertex buffer"),
+                        contents: bytemuck::cast_slice(&lines),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    });
+                self.chunk_border_buffer = Some((column, buffer, lines.len() as u32));
+            }
+        }
 
-        // Remove buffers for entities that are no longer loaded
-        self.entity_buffers
-            .retain(|key, _| loaded_cells.contains_key(key));
+        let player_mesh = scene
+            .player()
+            .mesh(camera.feet_position(), camera.yaw(), camera.pitch());
+        self.queue.write_buffer(
+            &self.player_buffers.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&player_mesh.vertices),
+        );
+        // The player still casts a shadow in first person, but isn't drawn
+        // since the camera is inside its head.
+        self.show_player = camera.view_mode() != ViewMode::FirstPerson;
 
-        // Create buffers for newly loaded entity chunks
-        for (key, mesh) in loaded_cells {
-            if !self.entity_buffers.contains_key(key) && !mesh.vertices.is_empty() {
-                use wgpu::util::DeviceExt;
-                let vertex_buffer =
+        self.update_tree_buffers(scene, camera.position());
+    }
+
+    fn update_tree_buffers(&mut self, scene: &Scene, camera_position: glam::Vec3) {
+        let entity_manager = scene.entity_manager();
+
+        // Regrow the shared meshes when the season turns
+        let prototypes = entity_manager.prototypes();
+        if self
+            .tree_prototype_buffers
+            .as_ref()
+            .is_none_or(|buffers| buffers.season != prototypes.season())
+        {
+            let mesh = prototypes.mesh();
+            let vertex_buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("tree prototype vertex buffer"),
+                    contents: bytemuck::cast_slice(&mesh.vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+            let index_buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("tree prototype index buffer"),
+                    contents: bytemuck::cast_slice(&mesh.indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                });
+            let atlas = ImpostorAtlas::new(prototypes.extents());
+            let bind_group =
+                self.bake_impostors(&atlas, &vertex_buffer, &index_buffer, prototypes.ranges());
+            let impostors = ImpostorBuffers {
+                vertex_buffer: self
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("impostor vertex buffer"),
+                        contents: bytemuck::cast_slice(atlas.vertices()),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                index_buffer: self
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("impostor index buffer"),
+                        contents: bytemuck::cast_slice(atlas.indices()),
+                        usage: wgpu::BufferUsages::INDEX,
+                    }),
+                ranges: atlas.ranges().to_vec(),
+                bind_group,
+            };
+            self.tree_prototype_buffers = Some(TreePrototypeBuffers {
+                season: prototypes.season(),
+                vertex_buffer,
+                index_buffer,
+                ranges: prototypes.ranges().to_vec(),
+                reduced_ranges: prototypes.reduced_ranges().to_vec(),
+                impostors,
+            });
+        }
+
+        // Remove buffers for cells that are no longer loaded or whose trees
+        // have grown since they were uploaded
+        let loaded_cells = entity_manager.loaded_cells();
+        self.tree_buffers.retain(|key, buffers| {
+            loaded_cells
+                .get(key)
+                .is_some_and(|cell| cell.revision() == buffers.revision)
+        });
+
+        // Draw cells with less detail the further away they are
+        let detail = self.game_config.tree_detail_distance;
+        let impostor = self.game_config.tree_impostor_distance;
+        let distance = |key: &glam::UVec2| {
+            let centre = (key.as_vec2() + 0.5) * 16.0;
+            centre.distance(glam::Vec2::new(camera_position.x, camera_position.z))
+        };
+        for (key, buffers) in self.tree_buffers.iter_mut() {
+            buffers.lod = buffers.lod.at_distance(distance(key), detail, impostor);
+        }
+
+        // Create instance buffers for newly loaded cells
+        for (key, cell) in loaded_cells {
+            let batch = cell.batch();
+            if !self.tree_buffers.contains_key(key) && !batch.instances.is_empty() {
+                let instance_buffer =
                     self.device
                         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("entity chunk vertex buffer"),
-                            contents: bytemuck::cast_slice(&mesh.vertices),
+                            label: Some("tree instance buffer"),
+                            contents: bytemuck::cast_slice(&batch.instances),
                             usage: wgpu::BufferUsages::VERTEX,
                         });
-                let index_buffer =
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("entity chunk index buffer"),
-                            contents: bytemuck::cast_slice(&mesh.indices),
-                            usage: wgpu::BufferUsages::INDEX,
-                        });
-                self.entity_buffers.insert(
+                self.tree_buffers.insert(
                     *key,
-                    EntityBuffers {
-                        vertex_buffer,
-                        index_buffer,
-                        num_indices: mesh.indices.len() as u32,
+                    TreeBuffers {
+                        instance_buffer,
+                        ranges: batch.ranges.clone(),
+                        revision: cell.revision(),
+                        lod: TreeLod::for_distance(distance(key), detail, impostor),
                     },
                 );
             }
@@ -894,7 +1495,8 @@ impl RenderState<'static> {
         let mut new_meshes = Vec::new();
         for (key, i, version) in dirty_chunks {
             let chunk = &locked_loaded.get(&key).unwrap()[i];
-            let mesh = crate::mesh::ChunkMesh::build(chunk, &locked_loaded, &terrain);
+            let mesh =
+                crate::mesh::ChunkMesh::build(chunk, &locked_loaded, &*terrain, self.debug_view);
             new_meshes.push((key, i, version, mesh));
         }
 
@@ -959,6 +1561,199 @@ impl RenderState<'static> {
         }
     }
 
+    /// Uploads the ground cover of newly loaded chunk columns, and re-culls
+    /// it wherever the blocks underneath have changed.
+    fn update_decoration_buffers(&mut self, scene: &Scene) {
+        let loaded = scene.chunks().loaded();
+        let locked_loaded = loaded.lock().expect("");
+        let cells = scene.decoration_manager().loaded_cells();
+
+        self.decoration_buffers
+            .retain(|key, _| cells.contains_key(key) && locked_loaded.contains_key(key));
+
+        for (key, decorations) in cells {
+            let Some(column) = locked_loaded.get(key) else {
+                continue;
+            };
+            let blocks_version = column
+                .iter()
+                .fold(0u32, |sum, chunk| sum.wrapping_add(chunk.version()));
+            if self
+                .decoration_buffers
+                .get(key)
+                .is_some_and(|buffers| buffers.blocks_version == blocks_version)
+            {
+                continue;
+            }
+
+            let batch = decoration::cull(decorations, column);
+            let instance_buffer = (!batch.instances.is_empty()).then(|| {
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("decoration instance buffer"),
+                        contents: bytemuck::cast_slice(&batch.instances),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    })
+            });
+            self.decoration_buffers.insert(
+                *key,
+                DecorationBuffers {
+                    instance_buffer,
+                    ranges: batch.ranges,
+                    blocks_version,
+                },
+            );
+        }
+    }
+
+    /// Draws the loaded trees from the prototype meshes, one instanced draw
+    /// per variant per cell. Cells far enough away for impostors are left
+    /// to `draw_impostors`, except in the shadow maps where they're drawn
+    /// reduced so far trees still cast shadows.
+    fn draw_trees<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        shadows: bool,
+    ) {
+        let Some(prototypes) = &self.tree_prototype_buffers else {
+            return;
+        };
+        pass.set_pipeline(pipeline);
+        pass.set_vertex_buffer(0, prototypes.vertex_buffer.slice(..));
+        pass.set_index_buffer(prototypes.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for buffers in self.tree_buffers.values() {
+            let meshes = match buffers.lod {
+                TreeLod::Full => &prototypes.ranges,
+                TreeLod::Reduced => &prototypes.reduced_ranges,
+                TreeLod::Impostor if shadows => &prototypes.reduced_ranges,
+                TreeLod::Impostor => continue,
+            };
+            pass.set_vertex_buffer(1, buffers.instance_buffer.slice(..));
+            for (mesh, instances) in meshes.iter().zip(&buffers.ranges) {
+                if !instances.is_empty() {
+                    pass.draw_indexed(mesh.clone(), 0, instances.clone());
+                }
+            }
+        }
+    }
+
+    /// Draws the trees in far cells as billboards, one instanced draw per
+    /// variant per cell like `draw_trees`.
+    fn draw_impostors<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        let Some(prototypes) = &self.tree_prototype_buffers else {
+            return;
+        };
+        let impostors = &prototypes.impostors;
+        pass.set_pipeline(&self.impostor_pipeline);
+        pass.set_bind_group(3, &impostors.bind_group, &[]);
+        pass.set_vertex_buffer(0, impostors.vertex_buffer.slice(..));
+        pass.set_index_buffer(impostors.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for buffers in self.tree_buffers.values() {
+            if buffers.lod != TreeLod::Impostor {
+                continue;
+            }
+            pass.set_vertex_buffer(1, buffers.instance_buffer.slice(..));
+            for (mesh, instances) in impostors.ranges.iter().zip(&buffers.ranges) {
+                if !instances.is_empty() {
+                    pass.draw_indexed(mesh.clone(), 0, instances.clone());
+                }
+            }
+        }
+    }
+
+    /// Takes a picture of every prototype from the side, each in its own
+    /// tile of the atlas, for the impostors to show.
+    fn bake_impostors(
+        &self,
+        atlas: &ImpostorAtlas,
+        vertex_buffer: &wgpu::Buffer,
+        index_buffer: &wgpu::Buffer,
+        ranges: &[std::ops::Range<u32>],
+    ) -> wgpu::BindGroup {
+        let size = atlas.size();
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("impostor atlas"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: IMPOSTOR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture =
+            Texture::new_depth_texture_with_size(&self.device, size, size, "impostor depth");
+        let tile_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("impostor tile buffer"),
+                contents: bytemuck::cast_slice(atlas.tiles()),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("impostor bake encoder"),
+            });
+        {
+            let mut bake_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("impostor bake pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            bake_pass.set_pipeline(&self.impostor_bake_pipeline);
+            bake_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            bake_pass.set_vertex_buffer(1, tile_buffer.slice(..));
+            bake_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            let tile = crate::impostor::TILE_SIZE as f32;
+            for (i, range) in ranges.iter().enumerate() {
+                let (x, y) = atlas.viewport(i);
+                bake_pass.set_viewport(x, y, tile, tile, 0.0, 1.0);
+                bake_pass.draw_indexed(range.clone(), 0, i as u32..i as u32 + 1);
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("impostor bind group"),
+            layout: &self.impostor_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.impostor_sampler),
+                },
+            ],
+        })
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
@@ -999,16 +1794,15 @@ impl RenderState<'static> {
                 timestamp_writes: None,
             });
 
-            shadow_pass.set_pipeline(&self.shadow_pipeline);
             shadow_pass.set_bind_group(0, &self.sun_shadow_pass_bind_group, &[]);
+            self.draw_trees(&mut shadow_pass, &self.tree_shadow_pipeline, true);
 
-            // draw entities in shadow pass
-            for buffers in self.entity_buffers.values() {
-                shadow_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
-                shadow_pass
-                    .set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                shadow_pass.draw_indexed(0..buffers.num_indices, 0, 0..1);
-            }
+            // draw the player in shadow pass
+            shadow_pass.set_pipeline(&self.shadow_pipeline);
+            let buffers = &self.player_buffers;
+            shadow_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            shadow_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            shadow_pass.draw_indexed(0..buffers.num_indices, 0, 0..1);
         }
 
         {
@@ -1027,16 +1821,15 @@ impl RenderState<'static> {
                 timestamp_writes: None,
             });
 
-            shadow_pass.set_pipeline(&self.shadow_pipeline);
             shadow_pass.set_bind_group(0, &self.moon_shadow_pass_bind_group, &[]);
+            self.draw_trees(&mut shadow_pass, &self.tree_shadow_pipeline, true);
 
-            // draw entities in shadow pass
-            for buffers in self.entity_buffers.values() {
-                shadow_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
-                shadow_pass
-                    .set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                shadow_pass.draw_indexed(0..buffers.num_indices, 0, 0..1);
-            }
+            // draw the player in shadow pass
+            shadow_pass.set_pipeline(&self.shadow_pipeline);
+            let buffers = &self.player_buffers;
+            shadow_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            shadow_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            shadow_pass.draw_indexed(0..buffers.num_indices, 0, 0..1);
         }
 
         {
@@ -1101,13 +1894,36 @@ impl RenderState<'static> {
                     }
                 }
 
-                for entity_buf in self.entity_buffers.values() {
-                    render_pass.set_vertex_buffer(0, entity_buf.vertex_buffer.slice(..));
+                self.draw_trees(&mut render_pass, &self.tree_pipeline, false);
+                self.draw_impostors(&mut render_pass);
+
+                render_pass.set_pipeline(&self.decoration_pipeline);
+                render_pass.set_vertex_buffer(0, self.decoration_vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.decoration_index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint16,
+                );
+                for buffers in self.decoration_buffers.values() {
+                    let Some(instance_buffer) = &buffers.instance_buffer else {
+                        continue;
+                    };
+                    render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                    for (mesh, instances) in self.decoration_mesh_ranges.iter().zip(&buffers.ranges)
+                    {
+                        if !instances.is_empty() {
+                            render_pass.draw_indexed(mesh.clone(), 0, instances.clone());
+                        }
+                    }
+                }
+
+                render_pass.set_pipeline(&self.render_pipeline);
+                if self.show_player {
+                    render_pass.set_vertex_buffer(0, self.player_buffers.vertex_buffer.slice(..));
                     render_pass.set_index_buffer(
-                        entity_buf.index_buffer.slice(..),
+                        self.player_buffers.index_buffer.slice(..),
                         wgpu::IndexFormat::Uint32,
                     );
-                    render_pass.draw_indexed(0..entity_buf.num_indices, 0, 0..1);
+                    render_pass.draw_indexed(0..self.player_buffers.num_indices, 0, 0..1);
                 }
             }
 
@@ -1138,6 +1954,14 @@ impl RenderState<'static> {
                 }
             }
 
+            // draw rain and snow
+            if self.precipitation_instances > 0 {
+                render_pass.set_pipeline(&self.precipitation_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.light_bind_group, &[]);
+                render_pass.draw(0..6, 0..self.precipitation_instances);
+            }
+
             // draw wireframe
             if self.selected_block.is_some() {
                 render_pass.set_pipeline(&self.wireframe_pipeline);
@@ -1150,6 +1974,22 @@ impl RenderState<'static> {
                 );
                 render_pass.draw_indexed(0..24, 0, 0..1);
             }
+            if let Some((_, buffer, num_vertices)) = &self.selected_tree {
+                render_pass.set_pipeline(&self.debug_lines_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, buffer.slice(..));
+                render_pass.draw(0..*num_vertices, 0..1);
+            }
+
+            // draw chunk borders
+            if self.show_chunk_borders {
+                if let Some((_, buffer, num_vertices)) = &self.chunk_border_buffer {
+                    render_pass.set_pipeline(&self.debug_lines_pipeline);
+                    render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(0..*num_vertices, 0..1);
+                }
+            }
         }
 
         {
@@ -1168,6 +2008,12 @@ impl RenderState<'static> {
                 timestamp_writes: None,
             });
 
+            if self.debug_view == DebugView::Shadows {
+                ui_pass.set_pipeline(&self.shadow_preview_pipeline);
+                ui_pass.set_bind_group(0, &self.main_shadow_bind_group, &[]);
+                ui_pass.draw(0..6, 0..2);
+            }
+
             if ui.is_console_open {
                 ui_pass.set_pipeline(&self.overlay_pipeline);
                 ui_pass.draw(0..6, 0..1);
@@ -1278,10 +2124,33 @@ impl RenderState<'static> {
     }
 }
 
+/// shader.wgsl, after the tree sway it shares with the shadow pass.
+fn main_shader() -> wgpu::ShaderModuleDescriptor<'static> {
+    wgpu::ShaderModuleDescriptor {
+        label: Some("shader.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(include_str!("wind.wgsl"), include_str!("shader.wgsl")).into(),
+        ),
+    }
+}
+
+/// shadow.wgsl, after the tree sway it shares with the main shader.
+fn shadow_shader() -> wgpu::ShaderModuleDescriptor<'static> {
+    wgpu::ShaderModuleDescriptor {
+        label: Some("shadow.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(include_str!("wind.wgsl"), include_str!("shadow.wgsl")).into(),
+        ),
+    }
+}
+
 struct PipelineConfig<'a> {
     layout: &'a wgpu::PipelineLayout,
     vertex_buffer_layout: wgpu::VertexBufferLayout<'a>,
+    instance_buffer_layout: Option<wgpu::VertexBufferLayout<'a>>,
     shader: wgpu::ShaderModuleDescriptor<'a>,
+    vertex_entry_point: &'a str,
+    fragment_entry_point: &'a str,
     depth_write_enabled: bool,
     blend_state: wgpu::BlendState,
     cull_mode: Option<wgpu::Face>,
@@ -1297,7 +2166,10 @@ impl<'a> PipelineConfig<'a> {
         Self {
             layout,
             vertex_buffer_layout,
+            instance_buffer_layout: None,
             shader,
+            vertex_entry_point: "vs_main",
+            fragment_entry_point: "fs_main",
             depth_write_enabled: true,
             blend_state: wgpu::BlendState::REPLACE,
             cull_mode: Some(wgpu::Face::Back),
@@ -1313,7 +2185,10 @@ impl<'a> PipelineConfig<'a> {
         Self {
             layout,
             vertex_buffer_layout,
+            instance_buffer_layout: None,
             shader,
+            vertex_entry_point: "vs_main",
+            fragment_entry_point: "fs_main",
             depth_write_enabled: false,
             blend_state: wgpu::BlendState::ALPHA_BLENDING,
             cull_mode: None,
@@ -1331,6 +2206,28 @@ impl<'a> PipelineConfig<'a> {
         self
     }
 
+    fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Draws copies of the mesh with `entry_point`, which also reads a
+    /// buffer of per-instance data laid out as `layout`.
+    fn with_instances(
+        mut self,
+        entry_point: &'a str,
+        layout: wgpu::VertexBufferLayout<'a>,
+    ) -> Self {
+        self.vertex_entry_point = entry_point;
+        self.instance_buffer_layout = Some(layout);
+        self
+    }
+
+    fn with_fragment(mut self, entry_point: &'a str) -> Self {
+        self.fragment_entry_point = entry_point;
+        self
+    }
+
     fn build(
         self,
         device: &wgpu::Device,
@@ -1338,14 +2235,17 @@ impl<'a> PipelineConfig<'a> {
         depth_format: Option<wgpu::TextureFormat>,
     ) -> wgpu::RenderPipeline {
         let shader = device.create_shader_module(self.shader);
+        let buffers: Vec<_> = std::iter::once(self.vertex_buffer_layout)
+            .chain(self.instance_buffer_layout)
+            .collect();
 
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("render pipeline"),
             layout: Some(self.layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main",
-                buffers: &[self.vertex_buffer_layout],
+                entry_point: self.vertex_entry_point,
+                buffers: &buffers,
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             primitive: wgpu::PrimitiveState {
@@ -1371,7 +2271,7 @@ impl<'a> PipelineConfig<'a> {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
+                entry_point: self.fragment_entry_point,
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.format,
                     blend: Some(self.blend_state),
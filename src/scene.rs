@@ -1,14 +1,28 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::{
     camera::Camera,
-    chunks::Chunks,
-    config::Config,
+    celestial::{self, Celestial},
+    chunks::{settle_voxel_trees, Chunks},
+    clock::WorldClock,
+    config::{Config, WorldConfig, WorldPreset},
+    decoration::DecorationManager,
     entities::EntityManager,
+    generator::{FlatTerrain, TerrainGenerator},
+    heightmap::HeightmapTerrain,
     light::{Light, RawLight},
+    player::PlayerModel,
+    terrain::WorldTerrain,
+    trees::SpeciesRegistry,
+    voxel_trees::VoxelTrees,
+    weather::Weather,
 };
 use bytemuck::{Pod, Zeroable};
-use glam::{Quat, Vec3};
+use glam::Vec3;
+
+/// Brightness of the full moon relative to the sun.
+const MOONLIGHT: f32 = 0.25;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
@@ -38,23 +52,70 @@ impl Lights {
 
 pub struct Scene {
     chunks: Chunks,
-    sun_offset: Vec3,
-    moon_offset: Vec3,
+    clock: WorldClock,
+    celestial: Celestial,
     lights: Lights,
     entity_manager: EntityManager,
+    decoration_manager: DecorationManager,
+    weather: Weather,
+    player: PlayerModel,
     load_radius: u32,
+    /// Ground cover is only generated this many chunks out, since it's
+    /// faded out well before the fog.
+    decoration_radius: u32,
 }
 
 impl Scene {
     pub fn new(seed: u32, config: Config) -> Self {
         let load_radius = config.chunk_load_radius;
+        let world_config = match config.worlds.get(&config.active_world) {
+            Some(world_config) => world_config.clone(),
+            None => WorldConfig::new_world(Some(seed)),
+        };
+        let procedural = || {
+            WorldTerrain::new(seed)
+                .with_preset(&world_config.preset)
+                .with_noise(&world_config.noise)
+                .with_erosion(world_config.erosion)
+                .with_density(world_config.overhangs)
+                .with_ores(&world_config.ores)
+        };
+        let terrain: Arc<dyn TerrainGenerator> = match &world_config.preset {
+            WorldPreset::Superflat { layers } => Arc::new(FlatTerrain::new(layers.clone())),
+            WorldPreset::Heightmap(heightmap) => {
+                match HeightmapTerrain::load(heightmap, procedural()) {
+                    Ok(terrain) => Arc::new(terrain),
+                    Err(e) => {
+                        log::error!("{}. Using procedural terrain instead.", e);
+                        Arc::new(procedural())
+                    }
+                }
+            }
+            _ => Arc::new(procedural()),
+        };
+        let species = Arc::new(SpeciesRegistry::load(&config.species_dir));
+        let voxel_trees = settle_voxel_trees(&config.active_world, world_config.voxel_trees);
         let chunks = Chunks::new(
             config.active_world.clone(),
-            seed,
+            terrain,
             load_radius,
             config.sim_rate_ms,
+            voxel_trees.then(|| VoxelTrees::new(seed, Arc::clone(&species))),
         );
-        let entity_manager = EntityManager::new(seed, chunks.terrain().clone());
+        let clock = WorldClock::new(&world_config.clock, &world_config.celestial);
+        let mut celestial = Celestial::new(world_config.celestial.clone());
+        celestial.set_days(clock.days());
+        let mut entity_manager =
+            EntityManager::new(seed, Arc::clone(chunks.terrain()), species, clock.season());
+        if voxel_trees {
+            // The trees are in the blocks already
+            entity_manager = entity_manager.without_wild_trees();
+        }
+        entity_manager.set_day(clock.days());
+        entity_manager.restore_saplings(&world_config.saplings);
+        let decoration_manager = DecorationManager::new(seed, Arc::clone(chunks.terrain()));
+        let decoration_radius =
+            ((config.decoration_distance / 16.0).ceil() as u32).min(load_radius);
 
         // TODO: position sun relative to player always.
         let lights = Lights {
@@ -76,7 +137,7 @@ impl Scene {
                         r: 0.76,
                         g: 0.77,
                         b: 0.80,
-                        a: 0.25,
+                        a: MOONLIGHT as f64,
                     },
                 ),
             ],
@@ -84,11 +145,15 @@ impl Scene {
 
         Self {
             chunks,
-            sun_offset: Vec3::new(64.0, 64.0, 0.0),
-            moon_offset: Vec3::new(-64.0, -64.0, 32.0),
+            clock,
+            celestial,
             lights,
             entity_manager,
+            decoration_manager,
+            weather: Weather::new(seed),
+            player: PlayerModel::new(),
             load_radius,
+            decoration_radius,
         }
     }
 
@@ -104,8 +169,41 @@ impl Scene {
         &self.entity_manager
     }
 
-    pub(crate) fn sun_offset(&self) -> Vec3 {
-        self.sun_offset
+    pub(crate) fn entity_manager_mut(&mut self) -> &mut EntityManager {
+        &mut self.entity_manager
+    }
+
+    pub(crate) fn decoration_manager(&self) -> &DecorationManager {
+        &self.decoration_manager
+    }
+
+    pub(crate) fn weather(&self) -> &Weather {
+        &self.weather
+    }
+
+    pub(crate) fn weather_mut(&mut self) -> &mut Weather {
+        &mut self.weather
+    }
+
+    pub(crate) fn player(&self) -> &PlayerModel {
+        &self.player
+    }
+
+    pub(crate) fn celestial(&self) -> &Celestial {
+        &self.celestial
+    }
+
+    pub(crate) fn clock(&self) -> &WorldClock {
+        &self.clock
+    }
+
+    pub(crate) fn clock_mut(&mut self) -> &mut WorldClock {
+        &mut self.clock
+    }
+
+    pub(crate) fn save_state(&self, config: &mut WorldConfig) {
+        self.clock.save_state(config);
+        config.saplings = self.entity_manager.saplings();
     }
 
     pub(crate) fn lights(&self) -> &Lights {
@@ -113,7 +211,7 @@ impl Scene {
     }
 
     pub(crate) fn time(&self) -> f32 {
-        self.sun_offset.y.atan2(self.sun_offset.x)
+        celestial::time_of_day_to_radians(self.clock.time_of_day())
     }
 
     pub(crate) fn sun_position(&self) -> Vec3 {
@@ -125,30 +223,42 @@ impl Scene {
     }
 
     pub fn set_time(&mut self, t: f32) {
-        let radius =
-            (self.sun_offset.x * self.sun_offset.x + self.sun_offset.y * self.sun_offset.y).sqrt();
-        self.sun_offset.x = radius * t.cos();
-        self.sun_offset.y = radius * t.sin();
+        self.clock
+            .set_time_of_day(celestial::time_of_day_from_radians(t));
     }
 
     pub fn update(&mut self, dt: Duration, camera: &Camera) {
         let player_position = camera.position();
         self.chunks.update(&player_position);
+        self.player.update(dt, camera.velocity());
+
+        self.clock.advance(dt);
+        // move the sun and moon
+        self.celestial.set_days(self.clock.days());
+
+        // Foliage is regenerated with new colours when the season turns,
+        // and trees grow as the days pass.
+        self.entity_manager.set_season(self.clock.season());
+        self.entity_manager.set_day(self.clock.days());
         self.entity_manager
             .update(&player_position, self.load_radius);
+        self.decoration_manager
+            .update(&player_position, self.decoration_radius);
 
         let orbit_radius = camera.fog_end * 1.1;
 
-        // move the sun and moon
-        // TODO: precess slowly around Z too
-        self.sun_offset = Quat::from_axis_angle(Vec3::Z, 0.02 * dt.as_secs_f32()) * self.sun_offset;
-        self.moon_offset =
-            Quat::from_axis_angle(Vec3::Z, 0.04 * dt.as_secs_f32()) * self.moon_offset;
-
-        let current_sun_offset = self.sun_offset.normalize_or_zero() * orbit_radius;
-        let current_moon_offset = self.moon_offset.normalize_or_zero() * orbit_radius;
+        let current_sun_offset = self.celestial.sun_direction() * orbit_radius;
+        let current_moon_offset = self.celestial.moon_direction() * orbit_radius;
 
         self.lights.lights[0].position = player_position + current_sun_offset;
         self.lights.lights[1].position = player_position + current_moon_offset;
+        // Moonlight follows the phase, so new moon nights are properly dark.
+        self.lights.lights[1].set_intensity(MOONLIGHT * self.celestial.moon_illumination());
+
+        self.weather
+            .update(dt, &player_position, self.chunks.terrain().as_ref());
+        for column in self.weather.take_rain_puddles(&player_position) {
+            self.chunks.add_rain_water(column);
+        }
     }
 }
@@ -7,6 +7,10 @@ struct CameraUniform {
   fog_start_sq: f32,
   fog_end_sq: f32,
   is_underwater: f32,
+  // debug view, unused, unused, unused
+  debug: vec4<u32>,
+  // decoration fade start, decoration fade end, unused, unused
+  decoration_fade: vec4<f32>,
 }
 @group(0) @binding(0) var<uniform> camera: CameraUniform;
 
@@ -26,10 +30,22 @@ struct SkyUniform {
     e: vec4<f32>,
     z: vec4<f32>,
     sun_dir: vec4<f32>,
+    // sidereal angle, latitude, unused, unused
+    celestial: vec4<f32>,
 };
 @group(1) @binding(0) var<uniform> sky: SkyUniform;
 @group(1) @binding(1) var<uniform> lights: LightUniforms;
 
+struct WeatherUniform {
+  // intensity, is_snow, time, unused
+  precipitation: vec4<f32>,
+  // overcast, wetness, lightning, unused
+  atmosphere: vec4<f32>,
+  // wind x, wind z, unused, unused
+  wind: vec4<f32>,
+}
+@group(1) @binding(2) var<uniform> weather: WeatherUniform;
+
 struct MainShadowUniform {
   sun_view_proj: mat4x4<f32>,
   moon_view_proj: mat4x4<f32>,
@@ -39,6 +55,10 @@ struct MainShadowUniform {
 @group(2) @binding(2) var shadow_sampler: sampler_comparison;
 @group(2) @binding(3) var<uniform> shadow_cameras: MainShadowUniform;
 
+// Pictures of every tree prototype, for far trees drawn as billboards.
+@group(3) @binding(0) var impostor_atlas: texture_2d<f32>;
+@group(3) @binding(1) var impostor_sampler: sampler;
+
 fn perez(A: vec3<f32>, B: vec3<f32>, C: vec3<f32>, D: vec3<f32>, E: vec3<f32>, Z: vec3<f32>, sunDir: vec3<f32>, viewDir: vec3<f32>) -> vec3<f32> {
     let theta = acos(max(0.001, viewDir.y));
     let gamma = acos(clamp(dot(viewDir, sunDir), -1.0, 1.0));
@@ -72,6 +92,9 @@ fn get_sky_color(view_dir: vec3<f32>) -> vec3<f32> {
     var color = YxyToRGB(Yxy);
 
     color = vec3<f32>(1.0) - exp(-color * 1.5);
+
+    let grey = vec3<f32>(dot(color, vec3<f32>(0.299, 0.587, 0.114))) * 0.7;
+    color = mix(color, grey, weather.atmosphere.x * 0.8);
     
     let is_night = clamp(-sun_dir.y * 3.0, 0.0, 1.0);
     if (is_night > 0.0) {
@@ -88,6 +111,7 @@ struct VertexInput {
   @location(1) material: u32,
   @location(2) color: vec4<f32>,
   @location(3) normal_and_ao: vec4<f32>,
+  @location(4) light: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -97,6 +121,7 @@ struct VertexOutput {
   @location(2) world_position: vec3<f32>,
   @location(3) ao: f32,
   @location(4) @interpolate(flat) material: u32,
+  @location(5) block_light: vec3<f32>,
 }
 
 @vertex
@@ -106,6 +131,7 @@ fn vs_main(model: VertexInput) -> VertexOutput {
   out.world_normal = model.normal_and_ao.xyz;
   out.world_position = model.position;
   out.material = model.material;
+  out.block_light = model.light.xyz;
   
   // AO is mapped from 0..127 to 0.0..1.0 by the Snorm format
   // Negative values shouldn't happen, but we max with 0 just in case
@@ -115,6 +141,115 @@ fn vs_main(model: VertexInput) -> VertexOutput {
   return out;
 }
 
+struct TreeInput {
+  @location(0) position: vec3<f32>,
+  @location(1) material: u32,
+  @location(2) color: vec4<f32>,
+  @location(3) normal_and_ao: vec4<f32>,
+  @location(4) light: vec4<f32>,
+  @location(5) offset: vec3<f32>,
+  @location(6) yaw: f32,
+  @location(7) scale: f32,
+  @location(8) tint: vec3<f32>,
+}
+
+// Trees: one instance of a species' variant per tree.
+@vertex
+fn vs_tree(model: TreeInput) -> VertexOutput {
+  var out: VertexOutput;
+
+  let c = cos(model.yaw);
+  let s = sin(model.yaw);
+  let local = model.position * model.scale;
+  let turned = vec3<f32>(local.x * c - local.z * s, local.y, local.x * s + local.z * c);
+  let normal = model.normal_and_ao.xyz;
+
+  out.color = vec4<f32>(model.color.rgb * model.tint, model.color.a);
+  out.world_normal = vec3<f32>(normal.x * c - normal.z * s, normal.y, normal.x * s + normal.z * c);
+  out.world_position = model.offset + turned;
+  out.world_position += wind_sway(model.offset, out.world_position, model.light.w * model.scale);
+  out.material = model.material;
+  out.block_light = model.light.xyz;
+  out.ao = max(model.normal_and_ao.w, 0.0);
+
+  out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+  return out;
+}
+
+struct ImpostorInput {
+  @location(0) corner: vec2<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(5) offset: vec3<f32>,
+  @location(7) scale: f32,
+  @location(8) tint: vec3<f32>,
+}
+
+struct ImpostorOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) world_normal: vec3<f32>,
+  @location(2) world_position: vec3<f32>,
+  @location(3) tint: vec3<f32>,
+}
+
+// Far trees: a billboard per tree that turns about its trunk to face the
+// camera.
+@vertex
+fn vs_impostor(model: ImpostorInput) -> ImpostorOutput {
+  var out: ImpostorOutput;
+
+  let to_camera = camera.view_pos.xz - model.offset.xz;
+  let facing = normalize(vec3<f32>(to_camera.x, 0.0, to_camera.y) + vec3<f32>(0.0001, 0.0, 0.0));
+  let right = vec3<f32>(facing.z, 0.0, -facing.x);
+  let corner = model.corner * model.scale;
+
+  out.uv = model.uv;
+  // Lit as if the crown faced up towards the sky and out towards the camera
+  out.world_normal = normalize(facing + vec3<f32>(0.0, 1.0, 0.0));
+  out.world_position = model.offset + right * corner.x + vec3<f32>(0.0, corner.y, 0.0);
+  out.tint = model.tint;
+
+  out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+  return out;
+}
+
+struct DecorationInput {
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec4<f32>,
+  @location(2) offset: vec3<f32>,
+  @location(3) yaw: f32,
+  @location(4) scale: f32,
+  @location(5) material: u32,
+  @location(6) color: vec4<f32>,
+}
+
+// Ground cover: one instance of a small mesh per tuft, flower or rock.
+@vertex
+fn vs_decoration(model: DecorationInput) -> VertexOutput {
+  var out: VertexOutput;
+
+  // Shrink into the ground with distance rather than popping out
+  let dist = distance(camera.view_pos.xyz, model.offset);
+  let fade = 1.0 - smoothstep(camera.decoration_fade.x, camera.decoration_fade.y, dist);
+
+  let c = cos(model.yaw);
+  let s = sin(model.yaw);
+  let local = model.position * model.scale * fade;
+  let turned = vec3<f32>(local.x * c - local.z * s, local.y, local.x * s + local.z * c);
+  let normal = model.normal.xyz;
+
+  out.color = model.color;
+  out.world_normal = vec3<f32>(normal.x * c - normal.z * s, normal.y, normal.x * s + normal.z * c);
+  out.world_position = model.offset + turned;
+  out.material = model.material;
+  out.block_light = vec3<f32>(0.0);
+  // Darker at the roots, where the blades crowd together
+  out.ao = mix(0.6, 1.0, clamp(model.position.y, 0.0, 1.0));
+
+  out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+  return out;
+}
+
 fn light_color(light: LightUniform, pos: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
   let dir = normalize(light.position - pos);
 
@@ -172,13 +307,30 @@ fn get_texture_noise(pos: vec3<f32>, material: u32) -> f32 {
     } else if material == 4u {
         // Ice: Smooth
         return 0.5;
-    } else if material == 2u {
+    } else if material == 6u {
+        // Snow: Soft with a faint sparkle
+        return 0.5 + hash(floor(pos * 32.0)) * 0.1;
+    } else if material == 2u || material == 8u {
         // Grass: Streaky (stretch the Y coordinate)
         let streaky_pos = vec3<f32>(pos.x * 16.0, pos.y * 2.0, pos.z * 16.0);
         return smooth_noise(streaky_pos);
     } else if material == 1u {
         // Sand: Grainy (high frequency, no interpolation for pixelated look)
         return hash(floor(pos * 32.0));
+    } else if material == 14u {
+        // Lava: Slow molten swirls
+        let n1 = smooth_noise(pos * 2.0);
+        return smooth_noise(pos * 4.0 + vec3<f32>(n1 * 4.0));
+    } else if material == 16u {
+        // Log: Bark runs up the trunk
+        let bark_pos = vec3<f32>(pos.x * 12.0, pos.y * 1.5, pos.z * 12.0);
+        return smooth_noise(bark_pos);
+    } else if material == 17u {
+        // Leaves: Clumps of light and dark
+        return 0.2 + hash(floor(pos * 8.0)) * 0.8;
+    } else if material >= 9u && material <= 13u {
+        // Ores: Coarse flecks set in the rock
+        return 0.3 + hash(floor(pos * 8.0)) * 0.7;
     }
     // Default (Rock/Dirt/Wood): slightly chunky
     return smooth_noise(pos * 16.0);
@@ -187,10 +339,49 @@ fn get_texture_noise(pos: vec3<f32>, material: u32) -> f32 {
 // Fragment shader
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return shade(in);
+}
+
+@fragment
+fn fs_impostor(in: ImpostorOutput) -> @location(0) vec4<f32> {
+  let picture = textureSample(impostor_atlas, impostor_sampler, in.uv);
+  if (picture.a < 0.5) {
+      discard;
+  }
+
+  var tree: VertexOutput;
+  tree.clip_position = in.clip_position;
+  tree.color = vec4<f32>(picture.rgb * in.tint, 1.0);
+  tree.world_normal = in.world_normal;
+  tree.world_position = in.world_position;
+  tree.ao = 1.0;
+  tree.material = 0u;
+  tree.block_light = vec3<f32>(0.0);
+  return shade(tree);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+  // Debug views skip lighting entirely
+  let debug_view = camera.debug.x;
+  if (debug_view == 1u) {
+      return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, in.color.w);
+  } else if (debug_view == 2u) {
+      return vec4<f32>(vec3<f32>(in.ao), in.color.w);
+  } else if (debug_view == 3u) {
+      // Flat colours with just enough shading to make out the shape
+      let shade = 0.6 + 0.4 * abs(dot(normalize(in.world_normal), normalize(vec3<f32>(0.3, 1.0, 0.5))));
+      return vec4<f32>(in.color.xyz * shade * in.ao, in.color.w);
+  }
+
+  let overcast = weather.atmosphere.x;
+  let wetness = weather.atmosphere.y;
+  let lightning = weather.atmosphere.z;
+
   let ambient_strength = 0.1;
-  let ambient_color = sky.color.xyz * ambient_strength;
+  let ambient_color = sky.color.xyz * ambient_strength + vec3<f32>(0.6, 0.65, 0.8) * lightning * 0.5;
 
-  var total_diffuse = ambient_color;
+  // Light from glowing blocks like lava reaches where the sun doesn't
+  var total_diffuse = ambient_color + in.block_light;
 
   let lights = lights.lights;
   
@@ -214,8 +405,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
       moon_shadow_factor = textureSampleCompare(moon_shadow_map, shadow_sampler, moon_shadow_uv, moon_ndc.z - 0.0005);
   }
 
-  total_diffuse += light_color(lights[0], in.world_position, in.world_normal) * sun_shadow_factor;
-  total_diffuse += light_color(lights[1], in.world_position, in.world_normal) * moon_shadow_factor;
+  // Clouds block most of the direct light
+  let direct_strength = 1.0 - overcast * 0.7;
+  total_diffuse += light_color(lights[0], in.world_position, in.world_normal) * sun_shadow_factor * direct_strength;
+  total_diffuse += light_color(lights[1], in.world_position, in.world_normal) * moon_shadow_factor * direct_strength;
 
   let view_dir = normalize(camera.view_pos.xyz - in.world_position);
   var spec_strength = 0.0;
@@ -227,6 +420,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
   } else if (in.material == 4u) { // Ice
       spec_strength = 1.2;
       shininess = 32.0;
+  } else if (wetness > 0.0) {
+      // Rain leaves a glossy film on upward facing surfaces
+      spec_strength = wetness * 0.5 * max(in.world_normal.y, 0.0);
+      shininess = 24.0;
   }
 
   var total_specular = vec3<f32>(0.0);
@@ -241,9 +438,17 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
   // Map noise from [0, 1] to something like [0.8, 1.1] to subtly perturb color
   let color_variation = mix(0.8, 1.1, noise_val);
   
-  let base_color = in.color.xyz * color_variation;
+  var base_color = in.color.xyz * color_variation;
+  if (in.material != 5u) {
+      // Wet surfaces look darker
+      base_color *= 1.0 - wetness * 0.3;
+  }
 
   var result = total_diffuse * base_color * in.ao + total_specular;
+  if (in.material == 14u) {
+      // Lava glows by itself, whatever the light around it
+      result = base_color * 1.2;
+  }
 
   // Distance fog to blend chunks smoothly into the sky (using squared distance
   // to avoid slow sqrt)
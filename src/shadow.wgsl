@@ -3,6 +3,16 @@ struct ShadowUniform {
 }
 @group(0) @binding(0) var<uniform> shadow_camera: ShadowUniform;
 
+struct WeatherUniform {
+  // intensity, is_snow, time, unused
+  precipitation: vec4<f32>,
+  // overcast, wetness, lightning, unused
+  atmosphere: vec4<f32>,
+  // wind x, wind z, unused, unused
+  wind: vec4<f32>,
+}
+@group(0) @binding(1) var<uniform> weather: WeatherUniform;
+
 struct VertexInput {
   @location(0) position: vec3<f32>,
   @location(1) material: u32,
@@ -14,3 +24,22 @@ struct VertexInput {
 fn vs_main(model: VertexInput) -> @builtin(position) vec4<f32> {
   return shadow_camera.view_proj * vec4<f32>(model.position, 1.0);
 }
+
+struct TreeInput {
+  @location(0) position: vec3<f32>,
+  @location(4) light: vec4<f32>,
+  @location(5) offset: vec3<f32>,
+  @location(6) yaw: f32,
+  @location(7) scale: f32,
+}
+
+@vertex
+fn vs_tree(model: TreeInput) -> @builtin(position) vec4<f32> {
+  let c = cos(model.yaw);
+  let s = sin(model.yaw);
+  let local = model.position * model.scale;
+  let turned = vec3<f32>(local.x * c - local.z * s, local.y, local.x * s + local.z * c);
+  let world = model.offset + turned;
+  let swayed = world + wind_sway(model.offset, world, model.light.w * model.scale);
+  return shadow_camera.view_proj * vec4<f32>(swayed, 1.0);
+}
@@ -0,0 +1,45 @@
+@group(0) @binding(0) var sun_shadow_map: texture_depth_2d;
+@group(0) @binding(1) var moon_shadow_map: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) map: u32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VertexOutput {
+    // One quad in the bottom left corner per shadow map, sun then moon
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 0.0)
+    );
+    let corner = corners[vertex_index];
+    let size = 0.45;
+    let origin = vec2<f32>(-0.98 + f32(instance_index) * (size + 0.02), -0.98);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(origin + corner * size, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.map = instance_index;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var depth: f32;
+    if (in.map == 0u) {
+        let size = vec2<f32>(textureDimensions(sun_shadow_map));
+        depth = textureLoad(sun_shadow_map, vec2<i32>(in.uv * (size - 1.0)), 0);
+    } else {
+        let size = vec2<f32>(textureDimensions(moon_shadow_map));
+        depth = textureLoad(moon_shadow_map, vec2<i32>(in.uv * (size - 1.0)), 0);
+    }
+    // Anything drawn into the map shows up dark against the cleared background
+    let tint = select(vec3<f32>(0.6, 0.7, 1.0), vec3<f32>(1.0, 0.9, 0.6), in.map == 0u);
+    return vec4<f32>(vec3<f32>(depth) * tint, 1.0);
+}
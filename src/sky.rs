@@ -2,6 +2,8 @@ use std::time::Duration;
 
 use bytemuck::{Pod, Zeroable};
 use glam::Vec3;
+
+use crate::celestial::Celestial;
 use wgpu::util::DeviceExt;
 use std::f32::consts::PI;
 
@@ -16,6 +18,7 @@ pub struct RawSky {
     e: [f32; 4],
     z: [f32; 4], // Yz, xz, yz, padding
     sun_dir: [f32; 4],
+    celestial: [f32; 4], // sidereal angle, latitude, padding
 }
 
 pub struct Sky {
@@ -35,6 +38,7 @@ impl Sky {
             e: [0.0; 4],
             z: [0.0; 4],
             sun_dir: [0.0; 4],
+            celestial: [0.0; 4],
         };
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("sky buffer"),
@@ -56,16 +60,20 @@ impl Sky {
         self.raw
     }
 
-    pub fn update(&mut self, _dt: Duration, sun_dir: &Vec3) {
-        let sun_dir = sun_dir.normalize();
-        let turbidity = 2.5f32;
+    /// `overcast` is 0 for a clear sky and 1 for a full storm. It thickens the
+    /// atmosphere and pulls the background colour toward grey.
+    pub fn update(&mut self, _dt: Duration, celestial: &Celestial, overcast: f32) {
+        let sun_dir = celestial.sun_direction();
+        let turbidity = 2.5f32 + overcast * 5.5;
 
         let solar_zenith = sun_dir.y.clamp(-1.0, 1.0).acos();
         
         let sky_day = glam::Vec3::new(135.0 / 255.0, 206.0 / 255.0, 235.0 / 255.0);
         let sky_night = glam::Vec3::new(12.0 / 255.0, 20.0 / 255.0, 69.0 / 255.0);
         let frac = sun_dir.y.max(0.0);
-        let color = sky_day * frac + sky_night * (1.0 - frac);
+        let clear_color = sky_day * frac + sky_night * (1.0 - frac);
+        let storm_grey = Vec3::splat((clear_color.x + clear_color.y + clear_color.z) / 3.0) * 0.6;
+        let color = clear_color.lerp(storm_grey, overcast);
 
         let yz_num = (4.0453 * turbidity - 4.9710) * ((4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * solar_zenith)).tan() - 0.2155 * turbidity + 2.4192;
         let y0_den = (4.0453 * turbidity - 4.9710) * ((4.0 / 9.0 - turbidity / 120.0) * PI).tan() - 0.2155 * turbidity + 2.4192;
@@ -117,6 +125,7 @@ impl Sky {
             e: [e_y, e_x, e_y2, 0.0],
             z: [yz, xz, y_z, 0.0],
             sun_dir: [sun_dir.x, sun_dir.y, sun_dir.z, 0.0],
+            celestial: [celestial.sidereal_angle(), celestial.latitude(), 0.0, 0.0],
         };
     }
 }
@@ -19,6 +19,8 @@ struct SkyUniform {
     e: vec4<f32>,
     z: vec4<f32>,
     sun_dir: vec4<f32>,
+    // sidereal angle, latitude, unused, unused
+    celestial: vec4<f32>,
 };
 @group(1) @binding(0)
 var<uniform> sky: SkyUniform;
@@ -33,6 +35,17 @@ struct LightUniforms {
 @group(1) @binding(1)
 var<uniform> lights: LightUniforms;
 
+struct WeatherUniform {
+    // intensity, is_snow, time, unused
+    precipitation: vec4<f32>,
+    // overcast, wetness, lightning, unused
+    atmosphere: vec4<f32>,
+    // wind x, wind z, unused, unused
+    wind: vec4<f32>,
+};
+@group(1) @binding(2)
+var<uniform> weather: WeatherUniform;
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) clip_pos: vec2<f32>,
@@ -68,6 +81,38 @@ fn YxyToRGB(Yxy: vec3<f32>) -> vec3<f32> {
     return rgb;
 }
 
+fn hash(p: vec3<f32>) -> f32 {
+    let p2 = fract(p * 0.1031);
+    let p3 = p2 + dot(p2, p2.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+// Turns a view direction into fixed coordinates on the celestial sphere, so the
+// stars wheel around the pole as the night goes on.
+fn to_celestial(dir: vec3<f32>) -> vec3<f32> {
+    let latitude = sky.celestial.y;
+    let pole = vec3<f32>(0.0, sin(latitude), -cos(latitude));
+    let angle = sky.celestial.x;
+    let c = cos(angle);
+    let s = sin(angle);
+    return dir * c + cross(pole, dir) * s + pole * dot(pole, dir) * (1.0 - c);
+}
+
+fn star_field(view_dir: vec3<f32>, time: f32) -> f32 {
+    let p = to_celestial(view_dir) * 250.0;
+    let cell = floor(p);
+    let h = hash(cell);
+    if (h < 0.985) {
+        return 0.0;
+    }
+    // Place the star somewhere inside its cell and give it a soft edge
+    let centre = vec3<f32>(hash(cell + 17.0), hash(cell + 31.0), hash(cell + 47.0)) * 0.6 + 0.2;
+    let d = length(fract(p) - centre);
+    let brightness = (h - 0.985) / 0.015;
+    let twinkle = 0.75 + 0.25 * sin(time * 3.0 + hash(cell + 71.0) * 40.0);
+    return smoothstep(0.25, 0.0, d) * (0.3 + 0.7 * brightness) * twinkle;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let clip = vec4<f32>(in.clip_pos.x, in.clip_pos.y, 0.5, 1.0);
@@ -91,17 +136,30 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
     // Apply exposure/tonemapping
     color = vec3<f32>(1.0) - exp(-color * 1.5);
+
+    // Cloud cover flattens the sky toward grey and lightning briefly lights it up
+    let overcast = weather.atmosphere.x;
+    let grey = vec3<f32>(dot(color, vec3<f32>(0.299, 0.587, 0.114))) * 0.7;
+    color = mix(color, grey, overcast * 0.8);
+    color += vec3<f32>(0.6, 0.65, 0.8) * weather.atmosphere.z;
     
     // Add night sky fading and stars
     let is_night = clamp(-sun_dir.y * 3.0, 0.0, 1.0);
     if (is_night > 0.0) {
         let moon_dir = normalize(lights.lights[1].position - camera.view_pos.xyz);
         let moon_halo = max(0.0, dot(view_dir, moon_dir));
-        let moon_glow = pow(moon_halo, 400.0) * 0.15;
+        // Scale the halo with the phase, the light is 0.25 at full moon
+        let moon_glow = pow(moon_halo, 400.0) * 0.6 * lights.lights[1].color.w;
         
         let moon_height = max(0.0, moon_dir.y);
         let base_night_color = vec3<f32>(0.01, 0.02, 0.05) * (1.0 + moon_height * 0.5);
-        let night_color = base_night_color + vec3<f32>(0.05, 0.06, 0.08) * moon_glow;
+        var night_color = base_night_color + vec3<f32>(0.05, 0.06, 0.08) * moon_glow;
+
+        // Stars fade in with the night, twinkle, and are hidden by clouds and
+        // the thick air near the horizon.
+        let horizon_fade = smoothstep(0.0, 0.15, view_dir.y);
+        let stars = star_field(view_dir, weather.precipitation.z) * horizon_fade * (1.0 - weather.atmosphere.x);
+        night_color += vec3<f32>(0.9, 0.92, 1.0) * stars;
         
         color = mix(color, night_color, is_night);
     }
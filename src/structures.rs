@@ -0,0 +1,445 @@
+use crate::{
+    block,
+    terrain::{hash2, Biome, WorldTerrain, BEDROCK_LEVEL},
+};
+use glam::{IVec2, IVec3, Vec2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StructureKind {
+    Ruin,
+    Village,
+    Dungeon,
+}
+
+impl fmt::Display for StructureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl StructureKind {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "ruin" | "ruins" => Some(StructureKind::Ruin),
+            "village" => Some(StructureKind::Village),
+            "dungeon" => Some(StructureKind::Dungeon),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [StructureKind; 3] = [
+        StructureKind::Ruin,
+        StructureKind::Village,
+        StructureKind::Dungeon,
+    ];
+}
+
+/// What a schematic does to one block of the world.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Cell {
+    /// Leaves whatever the terrain put there.
+    Keep,
+    /// Clears the block, so hillsides don't poke through floors.
+    Air,
+    Block(block::Type),
+}
+
+/// A voxel template, drawn as layers from the bottom up. Each layer is a list
+/// of rows along z, with one character per block along x.
+pub(crate) struct Schematic {
+    layers: &'static [&'static [&'static str]],
+}
+
+impl Schematic {
+    pub(crate) fn size(&self) -> IVec3 {
+        let rows = self.layers[0];
+        IVec3::new(
+            rows[0].len() as i32,
+            self.layers.len() as i32,
+            rows.len() as i32,
+        )
+    }
+
+    pub(crate) fn cell(&self, pos: IVec3) -> Cell {
+        let c = self.layers[pos.y as usize][pos.z as usize].as_bytes()[pos.x as usize];
+        match c {
+            b'.' => Cell::Air,
+            b'#' => Cell::Block(block::Type::Rock),
+            b'=' => Cell::Block(block::Type::Mud),
+            b'^' => Cell::Block(block::Type::DryGrass),
+            b'~' => Cell::Block(block::Type::Water),
+            b'$' => Cell::Block(block::Type::Gold),
+            b'*' => Cell::Block(block::Type::Crystal),
+            _ => Cell::Keep,
+        }
+    }
+}
+
+const HOUSE: Schematic = Schematic {
+    layers: &[
+        &[
+            "=======", "=======", "=======", "=======", "=======", "=======", "=======",
+        ],
+        &[
+            "#######", "#.....#", "#.....#", "#......", "#.....#", "#.....#", "#######",
+        ],
+        &[
+            "###.###", "#.....#", "#.....#", ".......", "#.....#", "#.....#", "###.###",
+        ],
+        &[
+            "#######", "#.....#", "#.....#", "#.....#", "#.....#", "#.....#", "#######",
+        ],
+        &[
+            "^^^^^^^", "^^^^^^^", "^^^^^^^", "^^^^^^^", "^^^^^^^", "^^^^^^^", "^^^^^^^",
+        ],
+        &[
+            "       ", " ^^^^^ ", " ^^^^^ ", " ^^^^^ ", " ^^^^^ ", " ^^^^^ ", "       ",
+        ],
+        &[
+            "       ", "       ", "  ^^^  ", "  ^^^  ", "  ^^^  ", "       ", "       ",
+        ],
+    ],
+};
+
+const WELL: Schematic = Schematic {
+    layers: &[
+        &["#####", "#####", "##~##", "#####", "#####"],
+        &["     ", " #.# ", " ... ", " #.# ", "     "],
+        &["     ", " #.# ", " ... ", " #.# ", "     "],
+        &["     ", " ^^^ ", " ^^^ ", " ^^^ ", "     "],
+    ],
+};
+
+const RUIN: Schematic = Schematic {
+    layers: &[
+        &[
+            "## ####", "#=====#", "#=====#", "=======", "#=====#", "#====##", "### ###",
+        ],
+        &[
+            "#   # #", "#.....#", "#.....#", ".......", "#......", "#.....#", "##  ###",
+        ],
+        &[
+            "#     #", ".......", ".......", ".......", ".......", ".......", "#    ##",
+        ],
+        &[
+            "#      ", "       ", "       ", "       ", "       ", "       ", "      #",
+        ],
+    ],
+};
+
+const DUNGEON: Schematic = Schematic {
+    layers: &[
+        &[
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+        ],
+        &[
+            "#########",
+            "#$.....$#",
+            "#.......#",
+            "#.......#",
+            "#...*...#",
+            "#.......#",
+            "#.......#",
+            "#$.....$#",
+            "#########",
+        ],
+        &[
+            "#########",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#########",
+        ],
+        &[
+            "#########",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#.......#",
+            "#########",
+        ],
+        &[
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+            "#########",
+        ],
+    ],
+};
+
+/// One schematic placed in the world.
+pub(crate) struct Piece {
+    /// The lowest corner of the schematic.
+    pub(crate) origin: IVec3,
+    pub(crate) schematic: &'static Schematic,
+    /// Whether rock is built up under the piece to meet the ground.
+    pub(crate) foundation: bool,
+}
+
+impl Piece {
+    pub(crate) fn contains_column(&self, column: IVec2) -> bool {
+        let size = self.schematic.size();
+        column.x >= self.origin.x
+            && column.x < self.origin.x + size.x
+            && column.y >= self.origin.z
+            && column.y < self.origin.z + size.z
+    }
+}
+
+pub(crate) struct Structure {
+    pub(crate) kind: StructureKind,
+    pub(crate) anchor: IVec3,
+    pub(crate) pieces: Vec<Piece>,
+}
+
+/// Where village houses can go around the well, as offsets of their corner.
+const HOUSE_SLOTS: [IVec2; 6] = [
+    IVec2::new(-15, -3),
+    IVec2::new(9, -3),
+    IVec2::new(-3, -15),
+    IVec2::new(-3, 9),
+    IVec2::new(9, 9),
+    IVec2::new(-15, -15),
+];
+
+/// Houses aren't built where the ground under them varies by more than this.
+const MAX_HOUSE_SLOPE: f32 = 4.0;
+
+#[derive(Clone)]
+pub(crate) struct Structures {
+    seed: u32,
+}
+
+impl Structures {
+    /// Structures are spread out at most one to a cell of this many blocks.
+    pub(crate) const CELL_SIZE: i32 = 256;
+    const CHANCE: f64 = 0.6;
+    /// How far any piece of a structure can reach from its anchor.
+    const MAX_REACH: i32 = 24;
+
+    pub(crate) fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// The structures with a piece in the chunk column at `key`.
+    pub(crate) fn near_chunk(&self, key: IVec2, terrain: &WorldTerrain) -> Vec<Structure> {
+        let min = key * 16 - IVec2::splat(Self::MAX_REACH);
+        let max = key * 16 + IVec2::splat(16 + Self::MAX_REACH);
+
+        let mut structures = Vec::new();
+        for cx in min.x.div_euclid(Self::CELL_SIZE)..=max.x.div_euclid(Self::CELL_SIZE) {
+            for cz in min.y.div_euclid(Self::CELL_SIZE)..=max.y.div_euclid(Self::CELL_SIZE) {
+                let cell = IVec2::new(cx, cz);
+                let Some(anchor) = self.anchor(cell) else {
+                    continue;
+                };
+                if anchor.x < min.x || anchor.x >= max.x || anchor.y < min.y || anchor.y >= max.y {
+                    continue;
+                }
+                if let Some(structure) = self.plan(cell, terrain) {
+                    structures.push(structure);
+                }
+            }
+        }
+        structures
+    }
+
+    /// Where in `cell` a structure would go, if it has one at all.
+    pub(crate) fn anchor(&self, cell: IVec2) -> Option<IVec2> {
+        if cell.x < 0 || cell.y < 0 {
+            return None;
+        }
+        let roll = hash2(cell.x as u32, cell.y as u32, self.seed.wrapping_add(1));
+        if roll[0] > Self::CHANCE {
+            return None;
+        }
+
+        // Keep clear of the cell edges so neighbours never overlap.
+        let offset = hash2(cell.x as u32, cell.y as u32, self.seed);
+        let margin = Self::MAX_REACH as f64;
+        let span = Self::CELL_SIZE as f64 - 2.0 * margin;
+        Some(IVec2::new(
+            cell.x * Self::CELL_SIZE + (margin + offset[0] * span) as i32,
+            cell.y * Self::CELL_SIZE + (margin + offset[1] * span) as i32,
+        ))
+    }
+
+    /// Lays out the structure in `cell`, fitting it to the terrain.
+    pub(crate) fn plan(&self, cell: IVec2, terrain: &WorldTerrain) -> Option<Structure> {
+        let anchor = self.anchor(cell)?;
+        let roll = hash2(cell.x as u32, cell.y as u32, self.seed.wrapping_add(2));
+        let mut rng = StdRng::seed_from_u64(
+            ((self.seed as u64) << 32) ^ ((cell.x as u32 as u64) << 16) ^ cell.y as u32 as u64,
+        );
+
+        let tdata = terrain.get(anchor.as_vec2());
+        let on_land = !tdata.is_water() && tdata.biome != Biome::Beach;
+        let village_biome = matches!(
+            tdata.biome,
+            Biome::Plains | Biome::Savanna | Biome::Taiga | Biome::Desert
+        );
+
+        let kind = if roll[0] < 0.35 || !on_land {
+            StructureKind::Dungeon
+        } else if village_biome && roll[0] < 0.75 {
+            StructureKind::Village
+        } else {
+            StructureKind::Ruin
+        };
+
+        let mut pieces = Vec::new();
+        match kind {
+            StructureKind::Dungeon => {
+                let surface = terrain.surface_height(anchor.as_vec2());
+                let y = (surface - 20.0).min(40.0).max(BEDROCK_LEVEL as f32 + 4.0);
+                pieces.push(Piece {
+                    origin: IVec3::new(anchor.x - 4, y as i32, anchor.y - 4),
+                    schematic: &DUNGEON,
+                    foundation: false,
+                });
+            }
+            StructureKind::Ruin => {
+                pieces.push(surface_piece(terrain, anchor - IVec2::splat(3), &RUIN)?);
+            }
+            StructureKind::Village => {
+                pieces.push(surface_piece(terrain, anchor - IVec2::splat(2), &WELL)?);
+                for slot in HOUSE_SLOTS {
+                    if rng.gen_bool(0.3) {
+                        continue;
+                    }
+                    if let Some(house) = surface_piece(terrain, anchor + slot, &HOUSE) {
+                        pieces.push(house);
+                    }
+                }
+            }
+        }
+
+        let ground = pieces[0].origin.y;
+        Some(Structure {
+            kind,
+            anchor: IVec3::new(anchor.x, ground, anchor.y),
+            pieces,
+        })
+    }
+}
+
+/// Sits a schematic with its corner at `corner` on the ground, at the
+/// average height under it. Returns `None` on water or on ground too steep
+/// to build on.
+fn surface_piece(
+    terrain: &WorldTerrain,
+    corner: IVec2,
+    schematic: &'static Schematic,
+) -> Option<Piece> {
+    let size = schematic.size();
+    let mut heights = Vec::new();
+    for (dx, dz) in [
+        (0, 0),
+        (size.x - 1, 0),
+        (0, size.z - 1),
+        (size.x - 1, size.z - 1),
+    ] {
+        let point = Vec2::new((corner.x + dx) as f32, (corner.y + dz) as f32);
+        let tdata = terrain.get(point);
+        if tdata.is_water() {
+            return None;
+        }
+        heights.push(terrain.surface_height(point));
+    }
+
+    let lowest = heights.iter().copied().fold(f32::MAX, f32::min);
+    let highest = heights.iter().copied().fold(f32::MIN, f32::max);
+    if highest - lowest > MAX_HOUSE_SLOPE {
+        return None;
+    }
+    let ground = heights.iter().sum::<f32>() / heights.len() as f32;
+
+    Some(Piece {
+        origin: IVec3::new(corner.x, ground.round() as i32, corner.y),
+        schematic,
+        foundation: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schematics_are_rectangular() {
+        for schematic in [&HOUSE, &WELL, &RUIN, &DUNGEON] {
+            let size = schematic.size();
+            for layer in schematic.layers {
+                assert_eq!(layer.len() as i32, size.z);
+                for row in layer.iter() {
+                    assert_eq!(row.len() as i32, size.x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_anchors_stay_inside_their_cell() {
+        let structures = Structures::new(7);
+        let mut found = 0;
+        for cx in 0..20 {
+            for cz in 0..20 {
+                let cell = IVec2::new(cx, cz);
+                if let Some(anchor) = structures.anchor(cell) {
+                    found += 1;
+                    let lo = cell * Structures::CELL_SIZE + IVec2::splat(Structures::MAX_REACH);
+                    let hi =
+                        (cell + 1) * Structures::CELL_SIZE - IVec2::splat(Structures::MAX_REACH);
+                    assert!(anchor.cmpge(lo).all() && anchor.cmple(hi).all());
+                }
+            }
+        }
+        assert!(found > 100 && found < 400);
+        assert_eq!(structures.anchor(IVec2::new(-1, 0)), None);
+    }
+
+    #[test]
+    fn test_plan_is_deterministic() {
+        let terrain = WorldTerrain::new(12345);
+        let structures = Structures::new(7);
+        let cell = (0..)
+            .map(|x| IVec2::new(x, 3))
+            .find(|&cell| structures.anchor(cell).is_some())
+            .unwrap();
+
+        let a = structures.plan(cell, &terrain);
+        let b = structures.plan(cell, &terrain);
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                assert_eq!(a.kind, b.kind);
+                assert_eq!(a.anchor, b.anchor);
+                let origins = |s: &Structure| s.pieces.iter().map(|p| p.origin).collect::<Vec<_>>();
+                assert_eq!(origins(&a), origins(&b));
+            }
+            (None, None) => {}
+            _ => panic!("Planning the same cell twice should agree"),
+        }
+    }
+}
@@ -1,5 +1,13 @@
+use crate::caves::{CaveKind, CaveTerrain, RAVINE_SHORE};
+use crate::config::{NoiseConfig, WorldPreset};
+use crate::erosion::Erosion;
+use crate::ores::{Ore, OreSpec, Ores, Vein};
+use crate::structures::{Structure, StructureKind, Structures};
 use noise::{Fbm, MultiFractal, NoiseFn, Perlin, Simplex};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::sync::{Arc, Mutex};
 
 fn fbm_bound(octaves: usize, persistence: f64) -> f64 {
     (0..octaves).map(|i| persistence.powi(i as i32)).sum()
@@ -13,21 +21,100 @@ fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
 pub const WATER_LEVEL: f32 = 32.0;
 pub const BEDROCK_LEVEL: f64 = 2.0;
 
+/// The highest above the sea a river's surface climbs.
+const RIVER_RISE: f64 = 60.0;
+/// Rivers fade out where they would have to cut deeper than this below the
+/// land, which is where they spring from in the mountains.
+const RIVER_MAX_CUT: f64 = 40.0;
+/// Blocks between the points the lie of the land is sampled at for rivers.
+const RIVER_GRID: f64 = 64.0;
+/// How far below the lie of the land rivers run.
+const RIVER_SINK: f64 = 6.0;
+/// Lake surfaces and river heights are kept for reuse until there are this
+/// many of each.
+const MAX_CACHED_HEIGHTS: usize = 4096;
+/// Lakes only form below this height, and where the ground around them is
+/// within this many blocks of their surface.
+const LAKE_MAX_ALTITUDE: f64 = 150.0;
+const LAKE_MAX_RELIEF: f64 = 10.0;
+
+/// Temperature lost for every block of height above the sea.
+const LAPSE_RATE: f64 = 0.0025;
+/// The direction the prevailing wind blows towards, from the west.
+const PREVAILING_WIND: [f64; 2] = [1.0, 0.0];
+/// Distances upwind, in blocks, checked for mountains blocking the rain.
+const RAIN_SHADOW_DISTANCES: [f64; 3] = [64.0, 160.0, 320.0];
+/// Moisture lost in the lee of a full mountain range.
+const RAIN_SHADOW: f64 = 0.35;
+/// How many blocks the density stage can push rock above, or carve below,
+/// the heightmap in full mountains at a strength of 1.
+const DENSITY_RELIEF: f64 = 28.0;
+/// How much the amplified preset stretches the land above the sea.
+const AMPLIFIED_HEIGHT_SCALE: f64 = 2.0;
+/// Stretched land is squashed back down as it nears this height so it stays
+/// inside the world.
+const MAX_LAND_HEIGHT: f64 = 240.0;
+/// Where the island preset puts its island, which is around where new
+/// players spawn, and how far out its coast starts to give way to the sea.
+const ISLAND_CENTRE: [f64; 2] = [3000.0, 3000.0];
+const ISLAND_RADIUS: f64 = 1600.0;
+/// The archipelago preset samples the continents this much more finely, and
+/// lowers them by this much, to break them into small islands.
+const ARCHIPELAGO_ZOOM: f64 = 4.0;
+const ARCHIPELAGO_SINK: f64 = 0.08;
+
+/// How land and sea are laid out across the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Landmass {
+    Continents,
+    Island,
+    Archipelago,
+}
+
+/// Temperature and moisture after the terrain has fed back into them, along
+/// with the blend and land height from before, which are reused when the
+/// feedback doesn't change the blend.
+struct Climate {
+    temperature: f64,
+    moisture: f64,
+    base_weights: BiomeWeights,
+    base_height: f64,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct TerrainData {
     pub height: f32,
     pub biome: Biome,
     pub moisture: f32,
     pub temperature: f32,
+    /// Surface of the sea, river or lake over this column. This is the sea
+    /// level everywhere except in rivers and lakes, which sit above
+    /// the sea.
+    pub water_level: f32,
+    /// How far the density stage can move the ground from `height` in this
+    /// column. Where this is 0 the column is a plain heightmap.
+    pub relief: f32,
+}
+
+impl TerrainData {
+    pub fn is_water(&self) -> bool {
+        self.height <= self.water_level
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Biome {
     Ocean,
     Plains,
     Hills,
     Mountains,
     Desert,
+    Tundra,
+    Taiga,
+    Jungle,
+    Swamp,
+    Savanna,
+    Beach,
 }
 
 impl fmt::Display for Biome {
@@ -44,17 +131,82 @@ impl Biome {
             "hills" => Some(Biome::Hills),
             "mountains" => Some(Biome::Mountains),
             "desert" => Some(Biome::Desert),
+            "tundra" => Some(Biome::Tundra),
+            "taiga" => Some(Biome::Taiga),
+            "jungle" => Some(Biome::Jungle),
+            "swamp" => Some(Biome::Swamp),
+            "savanna" => Some(Biome::Savanna),
+            "beach" | "shore" => Some(Biome::Beach),
             _ => None,
         }
     }
+
+    pub const ALL: [Biome; 11] = [
+        Biome::Ocean,
+        Biome::Plains,
+        Biome::Hills,
+        Biome::Mountains,
+        Biome::Desert,
+        Biome::Tundra,
+        Biome::Taiga,
+        Biome::Jungle,
+        Biome::Swamp,
+        Biome::Savanna,
+        Biome::Beach,
+    ];
 }
 
-#[derive(Clone, Default)]
+/// Whittaker style table of where each land biome sits in temperature and
+/// moisture. Mountains aren't listed: they take everywhere cold enough,
+/// whatever the moisture.
+const BIOME_TABLE: [(Biome, f64, f64); 8] = [
+    (Biome::Plains, 0.5, 0.45),
+    (Biome::Hills, 0.65, 0.7),
+    (Biome::Desert, 1.0, 0.0),
+    (Biome::Tundra, 0.18, 0.2),
+    (Biome::Taiga, 0.3, 0.62),
+    (Biome::Jungle, 0.92, 0.88),
+    (Biome::Swamp, 0.5, 0.92),
+    (Biome::Savanna, 0.8, 0.3),
+];
+
+/// The biomes blended together on land, in the order of `BiomeWeights`.
+const LAND_BIOMES: [Biome; 9] = [
+    Biome::Plains,
+    Biome::Hills,
+    Biome::Desert,
+    Biome::Mountains,
+    Biome::Tundra,
+    Biome::Taiga,
+    Biome::Jungle,
+    Biome::Swamp,
+    Biome::Savanna,
+];
+
+/// Beaches form along the coast wherever the land is less than this high
+/// above the sea.
+const BEACH_HEIGHT: f64 = 4.0;
+
+/// How much each of `LAND_BIOMES` contributes at a point, summing to 1.
+#[derive(Clone, Default, PartialEq)]
 struct BiomeWeights {
-    plains: f64,
-    hills: f64,
-    desert: f64,
-    mountains: f64,
+    weights: [f64; LAND_BIOMES.len()],
+}
+
+impl BiomeWeights {
+    fn get(&self, biome: Biome) -> f64 {
+        LAND_BIOMES
+            .iter()
+            .position(|&b| b == biome)
+            .map_or(0.0, |i| self.weights[i])
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Biome, f64)> + '_ {
+        LAND_BIOMES
+            .iter()
+            .copied()
+            .zip(self.weights.iter().copied())
+    }
 }
 
 #[derive(Clone)]
@@ -68,6 +220,25 @@ pub struct WorldTerrain {
     hills: HillsTerrain,
     mountains: MountainTerrain,
     desert: DesertTerrain,
+    tundra: TundraTerrain,
+    taiga: TaigaTerrain,
+    jungle: JungleTerrain,
+    swamp: SwampTerrain,
+    savanna: SavannaTerrain,
+    beach: BeachTerrain,
+    rivers: RiverTerrain,
+    lakes: LakeTerrain,
+    erosion: Erosion,
+    density: DensityTerrain,
+    ores: Ores,
+    structures: Structures,
+    lake_surfaces: Arc<Mutex<HeightCache>>,
+    river_heights: Arc<Mutex<HeightCache>>,
+    landmass: Landmass,
+    climate_octaves: usize,
+    world_scale: f64,
+    sea_level: f64,
+    height_scale: f64,
 }
 
 impl WorldTerrain {
@@ -91,82 +262,243 @@ impl WorldTerrain {
             hills: HillsTerrain::new(seed.wrapping_add(300)),
             mountains: MountainTerrain::new(seed.wrapping_add(400)),
             desert: DesertTerrain::new(seed.wrapping_add(500)),
+            tundra: TundraTerrain::new(seed.wrapping_add(900)),
+            taiga: TaigaTerrain::new(seed.wrapping_add(1000)),
+            jungle: JungleTerrain::new(seed.wrapping_add(1100)),
+            swamp: SwampTerrain::new(seed.wrapping_add(1200)),
+            savanna: SavannaTerrain::new(seed.wrapping_add(1300)),
+            beach: BeachTerrain::new(seed.wrapping_add(1400)),
+            rivers: RiverTerrain::new(seed.wrapping_add(600)),
+            lakes: LakeTerrain::new(seed.wrapping_add(700)),
+            erosion: Erosion::new(seed.wrapping_add(800)),
+            density: DensityTerrain::new(seed.wrapping_add(1500)),
+            ores: Ores::new(seed.wrapping_add(1600)),
+            structures: Structures::new(seed.wrapping_add(1700)),
+            lake_surfaces: Arc::new(Mutex::new(HeightCache::default())),
+            river_heights: Arc::new(Mutex::new(HeightCache::default())),
+            landmass: Landmass::Continents,
+            climate_octaves: 4,
+            world_scale: Self::WORLD_SCALE,
+            sea_level: WATER_LEVEL as f64,
+            height_scale: 1.0,
         }
     }
 
-    fn get_land_blend(&self, point: [f64; 2]) -> (Biome, BiomeWeights) {
+    /// Shapes the world to one of the presets.
+    pub fn with_preset(mut self, preset: &WorldPreset) -> Self {
+        match preset {
+            // Superflat and heightmap worlds aren't generated from this
+            // terrain's heights.
+            WorldPreset::Default | WorldPreset::Superflat { .. } | WorldPreset::Heightmap(_) => {}
+            WorldPreset::Amplified => self.height_scale = AMPLIFIED_HEIGHT_SCALE,
+            WorldPreset::Island => self.landmass = Landmass::Island,
+            WorldPreset::Archipelago => self.landmass = Landmass::Archipelago,
+        }
+        self
+    }
+
+    /// Overrides the noise parameters, on top of any preset.
+    pub fn with_noise(mut self, noise: &NoiseConfig) -> Self {
+        if let Some(frequency) = noise.continent_frequency {
+            self.continent_noise = self.continent_noise.set_frequency(frequency);
+        }
+        if let Some(octaves) = noise.continent_octaves {
+            self.continent_noise = self.continent_noise.set_octaves(octaves.max(1));
+        }
+        if let Some(frequency) = noise.climate_frequency {
+            self.temperature_noise = self.temperature_noise.set_frequency(frequency);
+            self.moisture_noise = self.moisture_noise.set_frequency(frequency);
+        }
+        if let Some(octaves) = noise.climate_octaves {
+            self.climate_octaves = octaves.max(1);
+            self.temperature_noise = self.temperature_noise.set_octaves(self.climate_octaves);
+            self.moisture_noise = self.moisture_noise.set_octaves(self.climate_octaves);
+        }
+        if let Some(sea_level) = noise.sea_level {
+            self.sea_level = sea_level as f64;
+        }
+        if let Some(world_scale) = noise.world_scale {
+            self.world_scale = world_scale.max(1.0);
+        }
+        if let Some(height_scale) = noise.height_scale {
+            self.height_scale = height_scale.max(0.0);
+        }
+        self
+    }
+
+    /// Wears down mountains and hills with an erosion pass. A strength of 0
+    /// leaves them as generated.
+    pub fn with_erosion(mut self, strength: f32) -> Self {
+        self.erosion = self.erosion.with_strength(strength);
+        self
+    }
+
+    /// Breaks mountains and hills up with 3D noise, giving overhangs, arches,
+    /// cliffs and floating rocks. A strength of 0 leaves a plain heightmap.
+    pub fn with_density(mut self, strength: f32) -> Self {
+        self.density.strength = strength.max(0.0) as f64;
+        self
+    }
+
+    /// Replaces the ores that form underground, and where and how often
+    /// they do.
+    pub fn with_ores(mut self, ores: &[OreSpec]) -> Self {
+        self.ores = self.ores.with_specs(ores);
+        self
+    }
+
+    /// Temperature and moisture straight from the noise, roughly in [0, 1].
+    fn get_base_climate(&self, point: [f64; 2]) -> (f64, f64) {
         let temp_raw = self.temperature_noise.get(point);
         let moist_raw = self.moisture_noise.get(point);
 
         // The maximum value of 2D Perlin noise is sqrt(2)/2.
         // Therefore, the maximum of this FBM is fbm_bound * sqrt(2)/2.
-        let true_bound = fbm_bound(4, 0.5) * std::f64::consts::SQRT_2 / 2.0;
+        let true_bound = fbm_bound(self.climate_octaves, 0.5) * std::f64::consts::SQRT_2 / 2.0;
         let temp_norm = (temp_raw + true_bound) / (2.0 * true_bound);
         let moist_norm = (moist_raw + true_bound) / (2.0 * true_bound);
+        (temp_norm, moist_norm)
+    }
 
-        // Calculate distance to each biome center in the parameter space
-        // Mountains: Cold (0.0), Any Moisture
-        let d_mountains = temp_norm;
-        // Desert: Hot (1.0), Dry (0.0)
-        let d_desert = ((temp_norm - 1.0).powi(2) + (moist_norm - 0.0).powi(2)).sqrt();
-        // Plains: Moderate (0.5), Moderate (0.5)
-        let d_plains = ((temp_norm - 0.5).powi(2) + (moist_norm - 0.5).powi(2)).sqrt();
-        // Hills: Hot (1.0), Wet (1.0)
-        let d_hills = ((temp_norm - 1.0).powi(2) + (moist_norm - 1.0).powi(2)).sqrt();
-
-        // Find the closest biome
-        let min_d = d_mountains.min(d_desert).min(d_plains).min(d_hills);
+    /// Cools the air with altitude and dries it out in the lee of mountain
+    /// ranges, so the biomes follow the terrain.
+    fn get_climate(&self, point: [f64; 2]) -> Climate {
+        let (temp, moist) = self.get_base_climate(point);
+        let (_, base_weights) = Self::blend_climate(temp, moist);
+        let base_height = self.get_land_height(point, &base_weights);
 
-        let mut primary_biome = Biome::Mountains;
-        if min_d == d_hills {
-            primary_biome = Biome::Hills;
+        let shore_t = self.get_shore_t(point);
+        let elevation = (base_height - self.sea_level).max(0.0) * shore_t;
+        let temperature = temp - elevation * LAPSE_RATE;
+
+        // Air drops its rain climbing over mountains upwind, and the nearer
+        // they are the drier it is. Mountains themselves catch the rain.
+        let mut barrier = 0.0f64;
+        for (i, distance) in RAIN_SHADOW_DISTANCES.iter().enumerate() {
+            let d = distance / self.world_scale;
+            let upwind = [
+                point[0] - PREVAILING_WIND[0] * d,
+                point[1] - PREVAILING_WIND[1] * d,
+            ];
+            let (t, m) = self.get_base_climate(upwind);
+            let (_, weights) = Self::blend_climate(t, m);
+            let falloff = 1.0 - i as f64 / RAIN_SHADOW_DISTANCES.len() as f64;
+            barrier =
+                barrier.max(weights.get(Biome::Mountains) * self.get_shore_t(upwind) * falloff);
         }
-        if min_d == d_desert {
-            primary_biome = Biome::Desert;
+        let shadow = barrier * (1.0 - base_weights.get(Biome::Mountains)) * RAIN_SHADOW;
+        let moisture = (moist - shadow).max(0.0);
+
+        Climate {
+            temperature,
+            moisture,
+            base_weights,
+            base_height,
         }
-        if min_d == d_plains {
-            primary_biome = Biome::Plains;
+    }
+
+    fn get_land_blend(&self, point: [f64; 2]) -> (Biome, BiomeWeights) {
+        let climate = self.get_climate(point);
+        Self::blend_climate(climate.temperature, climate.moisture)
+    }
+
+    /// The climate, biome blend and uneroded land height at a point.
+    fn get_land(&self, point: [f64; 2]) -> (Climate, Biome, BiomeWeights, f64) {
+        let climate = self.get_climate(point);
+        let (biome, weights) = Self::blend_climate(climate.temperature, climate.moisture);
+        let height = if weights == climate.base_weights {
+            climate.base_height
+        } else {
+            self.get_land_height(point, &weights)
+        };
+        (climate, biome, weights, height)
+    }
+
+    /// The land biome that suits a climate.
+    pub(crate) fn land_biome(temperature: f32, moisture: f32) -> Biome {
+        Self::blend_climate(temperature as f64, moisture as f64).0
+    }
+
+    fn blend_climate(temp_norm: f64, moist_norm: f64) -> (Biome, BiomeWeights) {
+        // Calculate distance to each biome center in the parameter space,
+        // in the order of LAND_BIOMES.
+        let mut distances = [0.0; LAND_BIOMES.len()];
+        for (biome, d) in LAND_BIOMES.iter().zip(distances.iter_mut()) {
+            *d = match BIOME_TABLE.iter().find(|(b, _, _)| b == biome) {
+                Some(&(_, t, m)) => ((temp_norm - t).powi(2) + (moist_norm - m).powi(2)).sqrt(),
+                // Mountains: Cold (0.0), Any Moisture
+                None => temp_norm,
+            };
         }
-        if min_d == d_mountains {
-            primary_biome = Biome::Mountains;
+
+        // Find the closest biome. Ties go to the earliest in the table.
+        let mut primary = 0;
+        for (i, d) in distances.iter().enumerate() {
+            if *d < distances[primary] {
+                primary = i;
+            }
         }
+        let min_d = distances[primary];
 
         // Blend weights based on distance difference from the minimum distance
         let blend_radius = 0.1; // This defines the width of the transition zone
-        let diff_mountains = d_mountains - min_d;
-        let diff_desert = d_desert - min_d;
-        let diff_plains = d_plains - min_d;
-        let diff_hills = d_hills - min_d;
 
         // smoothstep ensures that the weight drops smoothly to 0 at the blend_radius
-        let w_mountains = smoothstep(0.0, blend_radius, blend_radius - diff_mountains);
-        let w_desert = smoothstep(0.0, blend_radius, blend_radius - diff_desert);
-        let w_plains = smoothstep(0.0, blend_radius, blend_radius - diff_plains);
-        let w_hills = smoothstep(0.0, blend_radius, blend_radius - diff_hills);
-
-        let sum = w_mountains + w_desert + w_plains + w_hills;
-
-        let weights = BiomeWeights {
-            mountains: w_mountains / sum,
-            desert: w_desert / sum,
-            plains: w_plains / sum,
-            hills: w_hills / sum,
-        };
+        let mut weights = BiomeWeights::default();
+        for (w, d) in weights.weights.iter_mut().zip(distances) {
+            *w = smoothstep(0.0, blend_radius, blend_radius - (d - min_d));
+        }
+        let sum: f64 = weights.weights.iter().sum();
+        for w in weights.weights.iter_mut() {
+            *w /= sum;
+        }
 
-        (primary_biome, weights)
+        (LAND_BIOMES[primary], weights)
     }
 
-    pub fn is_cave(&self, point: glam::Vec3, surface_height: f32) -> bool {
-        self.caves.is_cave(
+    /// What kind of cave, if any, is carved out of a column at a point.
+    pub fn cave_at(&self, point: glam::Vec3, tdata: &TerrainData) -> Option<CaveKind> {
+        // Caves stay clear of the lowest ground the density stage can carve,
+        // so they never open onto the surface, but ravines are meant to.
+        let dry = tdata.relief == 0.0 && tdata.height > tdata.water_level + RAVINE_SHORE;
+        self.caves.cave_at(
             [point.x as f64, point.y as f64, point.z as f64],
-            surface_height as f64,
+            (tdata.height - tdata.relief) as f64,
+            dry,
         )
     }
 
+    /// How continental a point is, roughly in [0, 1], where the sea covers
+    /// everything below about 0.45.
+    fn get_continent(&self, point: [f64; 2]) -> f64 {
+        match self.landmass {
+            Landmass::Continents => {
+                let val = self.continent_noise.get(point);
+                // val is roughly [-1.0, 1.0]. Map it to [0.0, 1.0]
+                ((val + 1.0) / 2.0).clamp(0.0, 1.0)
+            }
+            Landmass::Island => {
+                let val = self.continent_noise.get(point);
+                let centre = [
+                    ISLAND_CENTRE[0] / self.world_scale,
+                    ISLAND_CENTRE[1] / self.world_scale,
+                ];
+                let distance =
+                    ((point[0] - centre[0]).powi(2) + (point[1] - centre[1]).powi(2)).sqrt();
+                let falloff = distance * self.world_scale / ISLAND_RADIUS;
+                ((val + 1.0) / 2.0 + 0.5 - falloff).clamp(0.0, 1.0)
+            }
+            Landmass::Archipelago => {
+                let val = self
+                    .continent_noise
+                    .get([point[0] * ARCHIPELAGO_ZOOM, point[1] * ARCHIPELAGO_ZOOM]);
+                ((val + 1.0) / 2.0 - ARCHIPELAGO_SINK).clamp(0.0, 1.0)
+            }
+        }
+    }
+
     fn get_shore_t(&self, point: [f64; 2]) -> f64 {
-        let val = self.continent_noise.get(point);
-        // val is roughly [-1.0, 1.0]. Map it to [0.0, 1.0]
-        let t = ((val + 1.0) / 2.0).clamp(0.0, 1.0);
+        let t = self.get_continent(point);
         // Adjust threshold so that ~30-40% is ocean.
         // Let's say if t < 0.45, it is ocean (0.0).
         // The transition to full land (1.0) will happen quickly over a small margin.
@@ -179,70 +511,281 @@ impl WorldTerrain {
         }
     }
 
+    /// Blocks per unit of noise, unless the world overrides it.
     pub const WORLD_SCALE: f64 = 384.0;
 
-    pub fn get(&self, world_point: glam::Vec2) -> TerrainData {
-        let point = [
-            world_point.x as f64 / Self::WORLD_SCALE,
-            world_point.y as f64 / Self::WORLD_SCALE,
-        ];
-        let shore_t = self.get_shore_t(point);
+    /// The surface of the sea.
+    pub fn sea_level(&self) -> f32 {
+        self.sea_level as f32
+    }
 
-        let (mut primary_biome, weights) = self.get_land_blend(point);
-        if shore_t == 0.0 {
-            primary_biome = Biome::Ocean;
-        }
+    /// Where a river at `point` runs: a little below the lie of the land,
+    /// smoothed over a coarse grid so it changes gently along and across the
+    /// river. It comes down to the sea at the coast.
+    fn river_surface(&self, point: [f64; 2]) -> f64 {
+        let x = point[0] * self.world_scale / RIVER_GRID;
+        let z = point[1] * self.world_scale / RIVER_GRID;
+        let (ix, iz) = (x.floor() as i32, z.floor() as i32);
+        let (fx, fz) = (x - ix as f64, z - iz as f64);
+
+        let land = |dx: i32, dz: i32| {
+            let key = glam::IVec2::new(ix + dx, iz + dz);
+            HeightCache::get_or_find(&self.river_heights, key, || {
+                let p = [
+                    key.x as f64 * RIVER_GRID / self.world_scale,
+                    key.y as f64 * RIVER_GRID / self.world_scale,
+                ];
+                let (_, _, weights, height) = self.get_land(p);
+                Some(self.get_base_height(p, self.get_shore_t(p), &weights, height))
+            })
+            .unwrap_or(self.sea_level)
+        };
+        let near = land(0, 0) * (1.0 - fx) + land(1, 0) * fx;
+        let far = land(0, 1) * (1.0 - fx) + land(1, 1) * fx;
+        let land = near * (1.0 - fz) + far * fz;
+        (land - RIVER_SINK).clamp(self.sea_level, self.sea_level + RIVER_RISE)
+    }
 
+    /// The blended height of the land biomes, before erosion.
+    fn get_land_height(&self, point: [f64; 2], weights: &BiomeWeights) -> f64 {
         let mut land_height = 0.0;
 
-        if weights.plains > 0.0 {
-            land_height += weights.plains * self.plains.get(point);
-        }
-        if weights.hills > 0.0 {
-            land_height += weights.hills * self.hills.get(point);
+        for (biome, w) in weights.iter() {
+            if w <= 0.0 {
+                continue;
+            }
+            let height = match biome {
+                Biome::Plains => self.plains.get(point),
+                Biome::Hills => self.hills.get(point),
+                Biome::Desert => self.desert.get(point),
+                Biome::Mountains => self.mountains.get(point),
+                Biome::Tundra => self.tundra.get(point),
+                Biome::Taiga => self.taiga.get(point),
+                Biome::Jungle => self.jungle.get(point),
+                Biome::Swamp => self.swamp.get(point, self.sea_level),
+                Biome::Savanna => self.savanna.get(point),
+                Biome::Ocean | Biome::Beach => continue,
+            };
+            land_height += w * height;
         }
-        if weights.desert > 0.0 {
-            land_height += weights.desert * self.desert.get(point);
+
+        if self.height_scale != 1.0 && land_height > self.sea_level {
+            // Stretch the land above the sea, easing into the top of the
+            // world rather than running off it.
+            let sea = self.sea_level;
+            let stretched = (land_height - sea) * self.height_scale;
+            let room = MAX_LAND_HEIGHT - sea;
+            land_height = sea + room * (stretched / room).tanh();
         }
-        if weights.mountains > 0.0 {
-            land_height += weights.mountains * self.mountains.get(point);
+        land_height
+    }
+
+    /// The height of the land and sea floor before rivers and lakes are cut
+    /// into it, given the uneroded land height.
+    fn get_base_height(
+        &self,
+        point: [f64; 2],
+        shore_t: f64,
+        weights: &BiomeWeights,
+        mut land_height: f64,
+    ) -> f64 {
+        let rugged = weights.get(Biome::Mountains) + weights.get(Biome::Hills);
+        if rugged > 0.0 && self.erosion.enabled() {
+            let uneroded = |x: f64, z: f64| {
+                let (_, _, _, height) = self.get_land([x / self.world_scale, z / self.world_scale]);
+                height
+            };
+            land_height += rugged
+                * self.erosion.offset(
+                    point[0] * self.world_scale,
+                    point[1] * self.world_scale,
+                    &uneroded,
+                );
         }
 
-        let ocean_abyss = self.ocean.get(point);
-        let final_height = if shore_t >= 1.0 {
+        if shore_t >= 1.0 {
             land_height
         } else {
+            let ocean_abyss = self.ocean.get(point);
+            let beach = self.beach.get(point, self.sea_level);
+            // The shelf the beach drops to before the open ocean
+            let shelf = self.sea_level - 8.0;
             let shore_t_norm = shore_t;
             if shore_t_norm > 0.8 {
                 let local_t = (shore_t_norm - 0.8) / 0.2;
                 let w = local_t * local_t * (3.0 - 2.0 * local_t);
-                31.0 * (1.0 - w) + land_height * w
+                beach * (1.0 - w) + land_height * w
             } else if shore_t_norm > 0.3 {
                 let local_t = (shore_t_norm - 0.3) / 0.5;
                 let w = local_t * local_t * (3.0 - 2.0 * local_t);
-                24.0 * (1.0 - w) + 31.0 * w
+                shelf * (1.0 - w) + beach * w
             } else {
                 let local_t = shore_t_norm / 0.3;
                 let w = local_t * local_t * (3.0 - 2.0 * local_t);
-                ocean_abyss * (1.0 - w) + 24.0 * w
+                ocean_abyss * (1.0 - w) + shelf * w
+            }
+        }
+    }
+
+    pub fn get(&self, world_point: glam::Vec2) -> TerrainData {
+        let point = [
+            world_point.x as f64 / self.world_scale,
+            world_point.y as f64 / self.world_scale,
+        ];
+        let shore_t = self.get_shore_t(point);
+
+        let (climate, mut primary_biome, weights, land_height) = self.get_land(point);
+        if shore_t == 0.0 {
+            primary_biome = Biome::Ocean;
+        }
+
+        let mut final_height = self.get_base_height(point, shore_t, &weights, land_height);
+        let mut water_level = self.sea_level;
+
+        // The shallows and low ground along the coast are beach, while high
+        // ground runs straight into the sea as cliffs.
+        let sea = self.sea_level;
+        if shore_t > 0.0
+            && shore_t < 1.0
+            && final_height > sea - BEACH_HEIGHT
+            && final_height < sea + BEACH_HEIGHT
+        {
+            primary_biome = Biome::Beach;
+        }
+
+        if shore_t > 0.0 {
+            let trend = self.river_surface(point);
+            let (height, surface) = self.rivers.carve(point, final_height, trend, sea);
+            final_height = height;
+            if let Some(surface) = surface {
+                water_level = water_level.max(surface);
+            }
+        }
+
+        if shore_t >= 1.0 {
+            for lake in self.lakes.lakes_near(point) {
+                let Some(surface) = self.lake_surface(&lake) else {
+                    continue;
+                };
+                let (height, inside) = lake.carve(point, final_height, surface);
+                final_height = height;
+                if inside {
+                    water_level = water_level.max(surface);
+                }
             }
+        }
+
+        // A lake's rim can bank up over a river or a neighbouring lake.
+        if final_height > water_level {
+            water_level = self.sea_level;
+        }
+
+        // Only rugged land away from the coast and out of the water gets
+        // overhangs.
+        let relief = if final_height > water_level {
+            let rugged = weights.get(Biome::Mountains) + 0.5 * weights.get(Biome::Hills);
+            self.density.strength * DENSITY_RELIEF * rugged * shore_t.min(1.0)
+        } else {
+            0.0
         };
 
-        let true_bound = fbm_bound(4, 0.5) * std::f64::consts::SQRT_2 / 2.0;
         TerrainData {
             height: final_height as f32,
             biome: primary_biome,
-            moisture: ((self.moisture_noise.get(point) + true_bound) / (2.0 * true_bound)) as f32,
-            temperature: ((self.temperature_noise.get(point) + true_bound) / (2.0 * true_bound))
-                as f32,
+            moisture: climate.moisture as f32,
+            temperature: climate.temperature as f32,
+            water_level: water_level as f32,
+            relief: relief as f32,
+        }
+    }
+
+    /// Whether the block at `point` is ground, given the terrain of its
+    /// column. Below the water level the heightmap always wins so the
+    /// density stage never opens air pockets under the sea, rivers or lakes.
+    pub fn is_solid(&self, point: glam::Vec3, tdata: &TerrainData) -> bool {
+        let y = point.y;
+        if tdata.relief <= 0.0 || y < tdata.water_level {
+            return y < tdata.height;
+        }
+        if y < tdata.height - tdata.relief {
+            return true;
+        }
+        if y >= tdata.height + tdata.relief {
+            return false;
+        }
+
+        let density = (tdata.height - y) as f64
+            + tdata.relief as f64 * self.density.get([point.x as f64, y as f64, point.z as f64]);
+        density > 0.0
+    }
+
+    /// The top of the highest solid block in the column, which is the
+    /// heightmap unless the density stage has moved the ground.
+    pub fn surface_height(&self, world_point: glam::Vec2) -> f32 {
+        let tdata = self.get(world_point);
+        if tdata.relief <= 0.0 {
+            return tdata.height;
+        }
+
+        // Blocks are generated from the terrain at their corner.
+        let column = world_point.floor();
+        let tdata = self.get(column);
+        let top = (tdata.height + tdata.relief).ceil() as i32;
+        let bottom = (tdata.height - tdata.relief).floor() as i32;
+        for y in (bottom..=top).rev() {
+            let point = glam::Vec3::new(column.x, y as f32, column.y);
+            if self.is_solid(point, &tdata) {
+                return y as f32 + 1.0;
+            }
+        }
+        tdata.height
+    }
+
+    /// Lakes fill to just below the land at their centre, so long as that is
+    /// well inland and above the sea, and the ground around is flat enough to
+    /// hold them.
+    fn lake_surface(&self, lake: &Lake) -> Option<f64> {
+        HeightCache::get_or_find(&self.lake_surfaces, lake.cell, || {
+            self.find_lake_surface(lake)
+        })
+    }
+
+    fn find_lake_surface(&self, lake: &Lake) -> Option<f64> {
+        let base = |p: [f64; 2]| {
+            let shore_t = self.get_shore_t(p);
+            if shore_t < 1.0 {
+                return None;
+            }
+            let (_, _, weights, height) = self.get_land(p);
+            Some(self.get_base_height(p, shore_t, &weights, height))
+        };
+
+        let centre = base(lake.centre)?;
+        let surface = centre.floor() - 1.0;
+        if surface < self.sea_level + 4.0 || surface > LAKE_MAX_ALTITUDE {
+            return None;
         }
+
+        let r = lake.radius;
+        for offset in [[r, 0.0], [-r, 0.0], [0.0, r], [0.0, -r]] {
+            let edge = base([lake.centre[0] + offset[0], lake.centre[1] + offset[1]])?;
+            if (edge - surface).abs() > LAKE_MAX_RELIEF {
+                return None;
+            }
+        }
+        Some(surface)
     }
 
     pub fn is_pure_biome(&self, point: glam::Vec2, target: Biome) -> bool {
         let p = [point.x as f64, point.y as f64];
         let shore_t = self.get_shore_t(p);
-        if target == Biome::Ocean {
-            return shore_t == 0.0;
+        match target {
+            Biome::Ocean => return shore_t == 0.0,
+            Biome::Beach => {
+                let world = point * self.world_scale as f32;
+                return self.get(world).biome == Biome::Beach;
+            }
+            _ => {}
         }
         if shore_t < 1.0 {
             return false; // Mixed with ocean
@@ -253,15 +796,7 @@ impl WorldTerrain {
             return false;
         }
 
-        let w = match target {
-            Biome::Plains => weights.plains,
-            Biome::Hills => weights.hills,
-            Biome::Desert => weights.desert,
-            Biome::Mountains => weights.mountains,
-            Biome::Ocean => 0.0,
-        };
-
-        w > 0.99
+        weights.get(target) > 0.99
     }
 
     pub fn find_closest_pure_biome(
@@ -269,11 +804,11 @@ impl WorldTerrain {
         start_pos: glam::Vec2,
         target: Biome,
     ) -> Option<glam::Vec2> {
-        let step_size = 32.0 / Self::WORLD_SCALE;
+        let step_size = 32.0 / self.world_scale;
         let max_steps = 400 * 400;
         let start_point = glam::Vec2::new(
-            start_pos.x / Self::WORLD_SCALE as f32,
-            start_pos.y / Self::WORLD_SCALE as f32,
+            start_pos.x / self.world_scale as f32,
+            start_pos.y / self.world_scale as f32,
         );
 
         let mut x = 0;
@@ -289,8 +824,8 @@ impl WorldTerrain {
                 let point = glam::Vec2::new(px, pz);
                 if self.is_pure_biome(point, target) {
                     return Some(glam::Vec2::new(
-                        px * Self::WORLD_SCALE as f32,
-                        pz * Self::WORLD_SCALE as f32,
+                        px * self.world_scale as f32,
+                        pz * self.world_scale as f32,
                     ));
                 }
             }
@@ -307,7 +842,110 @@ impl WorldTerrain {
         None
     }
 
-    pub fn find_closest_cave(&self, start_pos: glam::Vec2) -> Option<glam::Vec3> {
+    /// The ore veins that start in the chunk column at `key`, with more or
+    /// fewer of each ore depending on the biome there.
+    pub(crate) fn ore_veins(&self, key: glam::IVec2) -> Vec<Vein> {
+        let centre = glam::Vec2::new(key.x as f32 * 16.0 + 8.0, key.y as f32 * 16.0 + 8.0);
+        self.ores.veins_in_chunk(key, self.get(centre).biome)
+    }
+
+    /// The start of the nearest vein of `ore`, searching outwards a ring of
+    /// chunk columns at a time.
+    pub fn find_closest_ore(&self, start_pos: glam::Vec3, ore: Ore) -> Option<glam::Vec3> {
+        let max_rings: i32 = 32; // Search up to ~512 blocks radius
+        let centre = glam::IVec2::new(
+            (start_pos.x / 16.0).floor() as i32,
+            (start_pos.z / 16.0).floor() as i32,
+        );
+
+        let mut closest: Option<(f32, glam::Vec3)> = None;
+        for ring in 0..=max_rings {
+            // Nothing in this ring or beyond can beat what's been found.
+            if let Some((dist, _)) = closest {
+                if dist < (ring - 1).max(0) as f32 * 16.0 {
+                    break;
+                }
+            }
+
+            for dx in -ring..=ring {
+                for dz in -ring..=ring {
+                    if dx.abs() != ring && dz.abs() != ring {
+                        continue;
+                    }
+                    let key = centre + glam::IVec2::new(dx, dz);
+                    if key.x < 0 || key.y < 0 {
+                        continue;
+                    }
+                    for vein in self.ore_veins(key) {
+                        if vein.ore != ore {
+                            continue;
+                        }
+                        let point = vein.start.as_vec3();
+                        let dist = start_pos.distance(point);
+                        if closest.is_none_or(|(best, _)| dist < best) {
+                            closest = Some((dist, point));
+                        }
+                    }
+                }
+            }
+        }
+        closest.map(|(_, point)| point)
+    }
+
+    /// The structures with a piece in the chunk column at `key`.
+    pub(crate) fn structures_near(&self, key: glam::IVec2) -> Vec<Structure> {
+        self.structures.near_chunk(key, self)
+    }
+
+    /// The anchor of the nearest structure of `kind`, searching outwards a
+    /// ring of structure cells at a time.
+    pub fn find_closest_structure(
+        &self,
+        start_pos: glam::Vec2,
+        kind: StructureKind,
+    ) -> Option<glam::Vec3> {
+        let max_rings: i32 = 8; // Search up to ~2048 blocks radius
+        let cell_size = Structures::CELL_SIZE as f32;
+        let centre = (start_pos / cell_size).floor().as_ivec2();
+
+        let mut closest: Option<(f32, glam::Vec3)> = None;
+        for ring in 0..=max_rings {
+            // Nothing in this ring or beyond can beat what's been found.
+            if let Some((dist, _)) = closest {
+                if dist < (ring - 1).max(0) as f32 * cell_size {
+                    break;
+                }
+            }
+
+            for dx in -ring..=ring {
+                for dz in -ring..=ring {
+                    if dx.abs() != ring && dz.abs() != ring {
+                        continue;
+                    }
+                    let cell = centre + glam::IVec2::new(dx, dz);
+                    let Some(structure) = self.structures.plan(cell, self) else {
+                        continue;
+                    };
+                    if structure.kind != kind {
+                        continue;
+                    }
+                    let point = structure.anchor.as_vec3();
+                    let dist = start_pos.distance(glam::Vec2::new(point.x, point.z));
+                    if closest.is_none_or(|(best, _)| dist < best) {
+                        closest = Some((dist, point));
+                    }
+                }
+            }
+        }
+        closest.map(|(_, point)| point)
+    }
+
+    /// Finds the nearest cave of a kind, or of any kind when `kind` is `None`.
+    pub fn find_closest_cave(
+        &self,
+        start_pos: glam::Vec2,
+        kind: Option<CaveKind>,
+    ) -> Option<glam::Vec3> {
         let step_size = 4.0f32;
         let max_steps = 150 * 150; // Search up to ~600 blocks radius
 
@@ -322,18 +960,17 @@ impl WorldTerrain {
 
             if px >= 0.0 && pz >= 0.0 {
                 let tdata = self.get(glam::Vec2::new(px, pz));
-                let surface_height = tdata.height;
                 let min_y = BEDROCK_LEVEL as f32 + 1.0;
-                let max_y = surface_height - 10.0;
-                if max_y > min_y {
-                    let mut y = min_y;
-                    while y <= max_y {
-                        let point = glam::Vec3::new(px, y, pz);
-                        if self.is_cave(point, surface_height) {
+                let max_y = tdata.height;
+                let mut y = min_y;
+                while y <= max_y {
+                    let point = glam::Vec3::new(px, y, pz);
+                    if let Some(found) = self.cave_at(point, &tdata) {
+                        if kind.is_none_or(|k| k == found) {
                             return Some(point);
                         }
-                        y += 4.0;
                     }
+                    y += 4.0;
                 }
             }
 
@@ -351,8 +988,8 @@ impl WorldTerrain {
 
     pub fn biome_blend_string(&self, world_point: glam::Vec2) -> String {
         let point = [
-            world_point.x as f64 / Self::WORLD_SCALE,
-            world_point.y as f64 / Self::WORLD_SCALE,
+            world_point.x as f64 / self.world_scale,
+            world_point.y as f64 / self.world_scale,
         ];
         let shore_t = self.get_shore_t(point);
 
@@ -379,10 +1016,9 @@ impl WorldTerrain {
             }
         };
 
-        add_land("Plains", weights.plains);
-        add_land("Hills", weights.hills);
-        add_land("Desert", weights.desert);
-        add_land("Mountains", weights.mountains);
+        for (biome, w) in weights.iter() {
+            add_land(&biome.to_string(), w);
+        }
 
         b_strs.join(", ")
     }
@@ -547,7 +1183,7 @@ impl MountainTerrain {
     }
 }
 
-fn hash2(x: u32, y: u32, seed: u32) -> [f64; 2] {
+pub(crate) fn hash2(x: u32, y: u32, seed: u32) -> [f64; 2] {
     let mut h = seed;
     h ^= x.wrapping_mul(0x9E3779B9);
     h ^= y.wrapping_mul(0x85EBCA6B);
@@ -664,46 +1300,410 @@ impl DesertTerrain {
     }
 }
 
+/// Flat, frozen ground broken up by frost heave mounds.
 #[derive(Clone)]
-pub(crate) struct CaveTerrain {
-    cheese_noise: Fbm<Perlin>,
-    spaghetti_noise: Fbm<Perlin>,
+pub(crate) struct TundraTerrain {
+    base_noise: Fbm<Perlin>,
+    base_bound: f64,
+    mound_noise: Fbm<Perlin>,
 }
 
-impl CaveTerrain {
+impl TundraTerrain {
     pub(crate) fn new(seed: u32) -> Self {
         Self {
-            cheese_noise: Fbm::<Perlin>::new(seed).set_frequency(0.015).set_octaves(3),
-            spaghetti_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
-                .set_frequency(0.01)
+            base_noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(0.4)
+                .set_persistence(0.5)
+                .set_octaves(3),
+            base_bound: fbm_bound(3, 0.5),
+            mound_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(12.0)
                 .set_octaves(2),
         }
     }
 
-    pub(crate) fn is_cave(&self, point: [f64; 3], surface_height: f64) -> bool {
-        // Prevent caves from eating the bottom of the world
-        if point[1] <= BEDROCK_LEVEL {
-            return false;
+    pub(crate) fn get(&self, point: [f64; 2]) -> f64 {
+        let base_norm = (self.base_noise.get(point) + self.base_bound) / (2.0 * self.base_bound);
+        // Only the peaks of the noise push up mounds, leaving flat ground between.
+        let mound = (self.mound_noise.get(point) - 0.3).max(0.0);
+        30.0 + base_norm * 14.0 + mound * 10.0
+    }
+}
+
+/// Rolling glacial hills, with hollows that dip below the sea and fill with
+/// water.
+#[derive(Clone)]
+pub(crate) struct TaigaTerrain {
+    base_noise: Fbm<Perlin>,
+    base_bound: f64,
+    detail_noise: Fbm<Perlin>,
+    detail_bound: f64,
+}
+
+impl TaigaTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            base_noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(0.8)
+                .set_persistence(0.45)
+                .set_octaves(4),
+            base_bound: fbm_bound(4, 0.45),
+            detail_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(4.0)
+                .set_octaves(2),
+            detail_bound: fbm_bound(2, 0.5),
         }
+    }
 
-        let depth = surface_height - point[1];
-        if depth < 10.0 {
-            return false;
+    pub(crate) fn get(&self, point: [f64; 2]) -> f64 {
+        let base_norm = (self.base_noise.get(point) + self.base_bound) / (2.0 * self.base_bound);
+        let detail_norm =
+            (self.detail_noise.get(point) + self.detail_bound) / (2.0 * self.detail_bound);
+        26.0 + base_norm.powf(1.3) * 44.0 + detail_norm * 4.0
+    }
+}
+
+/// Steep, ridged karst hills.
+#[derive(Clone)]
+pub(crate) struct JungleTerrain {
+    ridge_noise: Fbm<Perlin>,
+    base_noise: Fbm<Perlin>,
+    base_bound: f64,
+}
+
+impl JungleTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            ridge_noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(1.5)
+                .set_persistence(0.4)
+                .set_octaves(3),
+            base_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(0.5)
+                .set_octaves(3),
+            base_bound: fbm_bound(3, 0.5),
+        }
+    }
+
+    pub(crate) fn get(&self, point: [f64; 2]) -> f64 {
+        let ridge = (1.0 - self.ridge_noise.get(point).abs()).clamp(0.0, 1.0);
+        let base_norm = (self.base_noise.get(point) + self.base_bound) / (2.0 * self.base_bound);
+        28.0 + ridge.powi(3) * 60.0 + base_norm * 12.0
+    }
+}
+
+/// Wet ground barely above the sea, so that the lowest parts flood.
+#[derive(Clone)]
+pub(crate) struct SwampTerrain {
+    base_noise: Fbm<Perlin>,
+    base_bound: f64,
+}
+
+impl SwampTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            base_noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(3.0)
+                .set_persistence(0.5)
+                .set_octaves(3),
+            base_bound: fbm_bound(3, 0.5),
+        }
+    }
+
+    pub(crate) fn get(&self, point: [f64; 2], sea_level: f64) -> f64 {
+        let base_norm = (self.base_noise.get(point) + self.base_bound) / (2.0 * self.base_bound);
+        sea_level - 1.5 + base_norm * 4.0
+    }
+}
+
+/// Open grassland with the odd flat topped mesa.
+#[derive(Clone)]
+pub(crate) struct SavannaTerrain {
+    base_noise: Fbm<Perlin>,
+    base_bound: f64,
+    mesa_noise: Fbm<Perlin>,
+    mesa_bound: f64,
+}
+
+impl SavannaTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            base_noise: Fbm::<Perlin>::new(seed).set_frequency(0.6).set_octaves(3),
+            base_bound: fbm_bound(3, 0.5),
+            mesa_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(2.5)
+                .set_octaves(2),
+            mesa_bound: fbm_bound(2, 0.5),
+        }
+    }
+
+    pub(crate) fn get(&self, point: [f64; 2]) -> f64 {
+        let base_norm = (self.base_noise.get(point) + self.base_bound) / (2.0 * self.base_bound);
+        let mesa_norm = (self.mesa_noise.get(point) + self.mesa_bound) / (2.0 * self.mesa_bound);
+        // Steep sided with a flat top.
+        let mesa = smoothstep(0.68, 0.72, mesa_norm) * 14.0;
+        33.0 + base_norm * 8.0 + mesa
+    }
+}
+
+/// Low sand dunes along the coast.
+#[derive(Clone)]
+pub(crate) struct BeachTerrain {
+    noise: Fbm<Perlin>,
+    bound: f64,
+}
+
+impl BeachTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            noise: Fbm::<Perlin>::new(seed).set_frequency(6.0).set_octaves(2),
+            bound: fbm_bound(2, 0.5),
+        }
+    }
+
+    pub(crate) fn get(&self, point: [f64; 2], sea_level: f64) -> f64 {
+        let norm = (self.noise.get(point) + self.bound) / (2.0 * self.bound);
+        sea_level - 1.0 + norm * 3.0
+    }
+}
+
+/// Rivers follow the zero line of a meandering noise field. Their surface
+/// follows the lie of the land down to the coast, and the land either side
+/// is pulled down into a valley to meet them.
+#[derive(Clone)]
+pub(crate) struct RiverTerrain {
+    course_noise: Fbm<Perlin>,
+    width_noise: Fbm<Perlin>,
+    depth_noise: Fbm<Perlin>,
+    bound: f64,
+}
+
+impl RiverTerrain {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            course_noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(0.5)
+                .set_persistence(0.45)
+                .set_octaves(3),
+            width_noise: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_frequency(2.0)
+                .set_octaves(2),
+            depth_noise: Fbm::<Perlin>::new(seed.wrapping_add(2))
+                .set_frequency(2.0)
+                .set_octaves(2),
+            bound: fbm_bound(2, 0.5) * std::f64::consts::SQRT_2 / 2.0,
+        }
+    }
+
+    fn norm(&self, val: f64) -> f64 {
+        ((val + self.bound) / (2.0 * self.bound)).clamp(0.0, 1.0)
+    }
+
+    /// Approximate distance in blocks from `point` to the middle of the
+    /// nearest river.
+    fn distance(&self, point: [f64; 2]) -> f64 {
+        let step = 1.0 / WorldTerrain::WORLD_SCALE;
+        let n = self.course_noise.get(point);
+        let dx = self.course_noise.get([point[0] + step, point[1]]) - n;
+        let dz = self.course_noise.get([point[0], point[1] + step]) - n;
+        let gradient = (dx * dx + dz * dz).sqrt().max(1e-6);
+        n.abs() / gradient
+    }
+
+    /// Cuts the river valley and channel into `height`, returning the new
+    /// height and the river's surface if the point is in the channel. The
+    /// river runs at `trend` where the land allows, and meets the sea at
+    /// `sea_level`.
+    pub(crate) fn carve(
+        &self,
+        point: [f64; 2],
+        height: f64,
+        trend: f64,
+        sea_level: f64,
+    ) -> (f64, Option<f64>) {
+        if height <= sea_level {
+            return (height, None);
+        }
+
+        let dist = self.distance(point);
+        let half_width = 2.0 + self.norm(self.width_noise.get(point)) * 6.0;
+        let valley_width = half_width * 4.0 + 8.0;
+        if dist > valley_width {
+            return (height, None);
+        }
+
+        let cut = height - trend;
+        let strength = 1.0 - smoothstep(RIVER_MAX_CUT * 0.5, RIVER_MAX_CUT, cut);
+        if strength <= 0.0 {
+            return (height, None);
+        }
+
+        // Banks slope down to just above the water.
+        let valley_t = (1.0 - smoothstep(half_width, valley_width, dist)) * strength;
+        let mut carved = height.min(height + (trend + 1.0 - height) * valley_t);
+
+        if dist >= half_width || strength < 0.5 {
+            return (carved, None);
+        }
+
+        // Where the land is already lower than the river would be, it runs
+        // along the valley floor instead.
+        let surface = trend.min(height - 1.0).max(sea_level);
+        let depth = 2.0 + self.norm(self.depth_noise.get(point)) * 4.0;
+        let profile = 1.0 - (dist / half_width).powi(2);
+        carved = carved.min(surface - 1.0 - (depth - 1.0) * profile);
+        (carved, Some(surface))
+    }
+}
+
+/// Heights that take the base height of the land to find, like lake surfaces,
+/// kept by the lake or grid point they were found for.
+#[derive(Default)]
+struct HeightCache {
+    heights: HashMap<glam::IVec2, Option<f64>>,
+    order: VecDeque<glam::IVec2>,
+}
+
+impl HeightCache {
+    /// The height kept for `key`, found with `find` the first time.
+    fn get_or_find<F>(cache: &Mutex<Self>, key: glam::IVec2, find: F) -> Option<f64>
+    where
+        F: FnOnce() -> Option<f64>,
+    {
+        if let Some(&height) = cache.lock().unwrap().heights.get(&key) {
+            return height;
         }
 
-        let depth_factor = if depth < 50.0 {
-            (50.0 - depth) / 40.0
+        // Found outside the lock so loader threads don't wait on each other.
+        let height = find();
+        let mut cache = cache.lock().unwrap();
+        if cache.heights.insert(key, height).is_none() {
+            cache.order.push_back(key);
+            while cache.order.len() > MAX_CACHED_HEIGHTS {
+                if let Some(old) = cache.order.pop_front() {
+                    cache.heights.remove(&old);
+                }
+            }
+        }
+        height
+    }
+}
+
+/// A lake basin, with its centre and radius in world scale units.
+pub(crate) struct Lake {
+    /// The cell of the lake grid it was placed in.
+    cell: glam::IVec2,
+    centre: [f64; 2],
+    radius: f64,
+    depth: f64,
+}
+
+/// How far out, as a multiple of the radius, a lake raises a rim to hold its
+/// water in.
+const LAKE_RIM: f64 = 1.6;
+
+impl Lake {
+    /// Digs the basin into `height` and builds up a rim around it. Returns
+    /// the new height and whether the point is under the lake.
+    pub(crate) fn carve(&self, point: [f64; 2], height: f64, surface: f64) -> (f64, bool) {
+        let dx = point[0] - self.centre[0];
+        let dz = point[1] - self.centre[1];
+        let d = (dx * dx + dz * dz).sqrt() / self.radius;
+
+        if d < 1.0 {
+            let bed = surface - 1.0 - (self.depth - 1.0) * (1.0 - d * d);
+            (height.min(bed), true)
+        } else if d < LAKE_RIM {
+            let t = smoothstep(1.0, LAKE_RIM, d);
+            let rim = (surface + 1.0) * (1.0 - t) + height * t;
+            (height.max(rim), false)
         } else {
-            0.0
-        };
+            (height, false)
+        }
+    }
+}
+
+/// Scatters lakes over the land, at most one per cell of a coarse grid.
+#[derive(Clone)]
+pub(crate) struct LakeTerrain {
+    seed: u32,
+}
+
+impl LakeTerrain {
+    const CELL_SIZE: f64 = 0.75;
+    const CHANCE: f64 = 0.35;
+
+    pub(crate) fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// The lakes whose basin or rim covers `point`.
+    pub(crate) fn lakes_near(&self, point: [f64; 2]) -> Vec<Lake> {
+        let ix = (point[0] / Self::CELL_SIZE).floor();
+        let iz = (point[1] / Self::CELL_SIZE).floor();
+
+        let mut lakes = Vec::new();
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let cx = ix + dx as f64;
+                let cz = iz + dz as f64;
+                let ux = cx as i64 as u32;
+                let uz = cz as i64 as u32;
+
+                let shape = hash2(ux, uz, self.seed.wrapping_add(1));
+                if shape[0] > Self::CHANCE {
+                    continue;
+                }
+                let offset = hash2(ux, uz, self.seed);
+                let lake = Lake {
+                    cell: glam::IVec2::new(cx as i32, cz as i32),
+                    centre: [
+                        (cx + 0.2 + offset[0] * 0.6) * Self::CELL_SIZE,
+                        (cz + 0.2 + offset[1] * 0.6) * Self::CELL_SIZE,
+                    ],
+                    radius: (12.0 + shape[1] * 28.0) / WorldTerrain::WORLD_SCALE,
+                    depth: 3.0 + shape[1] * 6.0,
+                };
+
+                let ddx = point[0] - lake.centre[0];
+                let ddz = point[1] - lake.centre[1];
+                if (ddx * ddx + ddz * ddz).sqrt() < lake.radius * LAKE_RIM {
+                    lakes.push(lake);
+                }
+            }
+        }
+        lakes
+    }
+}
+
+/// 3D noise that is added to the distance below the heightmap, so the ground
+/// is wherever the sum is positive. Squashing the noise vertically favours
+/// sheer faces over gentle slopes.
+#[derive(Clone)]
+struct DensityTerrain {
+    noise: Fbm<Perlin>,
+    bound: f64,
+    strength: f64,
+}
 
-        let cheese_val = self.cheese_noise.get(point);
-        let cheese_is_cave = cheese_val > (0.4 + depth_factor * 0.4);
+impl DensityTerrain {
+    const OCTAVES: usize = 3;
 
-        let spaghetti_val = self.spaghetti_noise.get(point).abs();
-        let spaghetti_is_cave = spaghetti_val < (0.05 - depth_factor * 0.04);
+    /// The density stage starts disabled until given a strength.
+    fn new(seed: u32) -> Self {
+        Self {
+            noise: Fbm::<Perlin>::new(seed)
+                .set_frequency(1.0 / 32.0)
+                .set_octaves(Self::OCTAVES),
+            bound: fbm_bound(Self::OCTAVES, 0.5),
+            strength: 0.0,
+        }
+    }
 
-        cheese_is_cave || spaghetti_is_cave
+    /// Noise in [-1, 1] at a point in blocks.
+    fn get(&self, point: [f64; 3]) -> f64 {
+        let squashed = [point[0], point[1] * 0.8, point[2]];
+        (self.noise.get(squashed) / self.bound * 2.0).clamp(-1.0, 1.0)
     }
 }
 
@@ -712,6 +1712,193 @@ mod tests {
     use super::*;
     const TEST_GRID_SIZE: u32 = 4096;
 
+    #[test]
+    fn test_biome_names_round_trip() {
+        for biome in Biome::ALL {
+            assert_eq!(Biome::from_str(&biome.to_string()), Some(biome));
+        }
+        assert_eq!(Biome::from_str("shore"), Some(Biome::Beach));
+        assert_eq!(Biome::from_str("moon"), None);
+    }
+
+    #[test]
+    fn test_all_biomes_appear() {
+        let terrain = WorldTerrain::new(12345);
+        let mut seen = std::collections::HashSet::new();
+
+        for x in 0..128 {
+            for z in 0..128 {
+                let point = glam::Vec2::new(x as f32 * 128.0, z as f32 * 128.0);
+                seen.insert(terrain.get(point).biome);
+            }
+        }
+
+        for biome in Biome::ALL {
+            assert!(seen.contains(&biome), "{} never generated", biome);
+        }
+    }
+
+    #[test]
+    fn test_density_off_is_heightmap() {
+        let terrain = WorldTerrain::new(12345);
+        for i in 0..50 {
+            let point = glam::Vec2::new(i as f32 * 97.0, i as f32 * 61.0);
+            let tdata = terrain.get(point);
+            assert_eq!(tdata.relief, 0.0);
+            assert_eq!(terrain.surface_height(point), tdata.height);
+            for y in 0..200 {
+                let block = glam::Vec3::new(point.x, y as f32, point.y);
+                assert_eq!(terrain.is_solid(block, &tdata), (y as f32) < tdata.height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_density_overhangs() {
+        let terrain = WorldTerrain::new(12345).with_density(1.0);
+        let centre = terrain
+            .find_closest_pure_biome(glam::Vec2::ZERO, Biome::Hills)
+            .expect("Should find hills");
+
+        let mut overhangs = 0;
+        for dx in -128..128 {
+            let column = glam::Vec2::new(centre.x + dx as f32, centre.y).floor();
+            let tdata = terrain.get(column);
+            let surface = terrain.surface_height(column);
+            let mut air_below = false;
+            for y in 0..crate::chunks::MAX_HEIGHT {
+                let block = glam::Vec3::new(column.x, y as f32, column.y);
+                let solid = terrain.is_solid(block, &tdata);
+                if solid {
+                    assert!((y as f32) < surface, "Solid block above the surface");
+                    if air_below {
+                        overhangs += 1;
+                    }
+                } else if (y as f32) < tdata.water_level && (y as f32) < tdata.height {
+                    panic!("Density opened air under the water at {:?}", block);
+                }
+                air_below = !solid;
+            }
+        }
+        assert!(overhangs > 0, "Density should hang rock over open air");
+    }
+
+    #[test]
+    fn test_find_closest_ore() {
+        let terrain = WorldTerrain::new(12345);
+        let start = glam::Vec3::new(1000.0, 60.0, 1000.0);
+        let gold = terrain
+            .find_closest_ore(start, Ore::Gold)
+            .expect("Should find gold");
+        assert!(gold.y <= 40.0, "Gold should be deep, found at {}", gold);
+
+        // Nothing in the chunk columns around the start is any closer.
+        let dist = start.distance(gold);
+        for dx in -2..=2 {
+            for dz in -2..=2 {
+                let key = glam::IVec2::new(62 + dx, 62 + dz);
+                for vein in terrain.ore_veins(key) {
+                    if vein.ore == Ore::Gold {
+                        assert!(start.distance(vein.start.as_vec3()) >= dist);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_island_preset() {
+        let terrain = WorldTerrain::new(12345).with_preset(&WorldPreset::Island);
+        let centre = glam::Vec2::new(ISLAND_CENTRE[0] as f32, ISLAND_CENTRE[1] as f32);
+        assert_ne!(terrain.get(centre).biome, Biome::Ocean);
+
+        // Out past the island's radius is open sea in every direction.
+        for i in 0..16 {
+            let angle = i as f32 / 16.0 * std::f32::consts::TAU;
+            let offset = glam::Vec2::new(angle.cos(), angle.sin()) * ISLAND_RADIUS as f32 * 1.2;
+            let tdata = terrain.get(centre + offset);
+            assert_eq!(tdata.biome, Biome::Ocean, "Land at {}", centre + offset);
+        }
+    }
+
+    #[test]
+    fn test_amplified_preset() {
+        let normal = WorldTerrain::new(12345);
+        let amplified = WorldTerrain::new(12345).with_preset(&WorldPreset::Amplified);
+
+        let mut highest = (0.0f32, 0.0f32);
+        for x in 0..64 {
+            for z in 0..64 {
+                let point = glam::Vec2::new(x as f32 * 64.0, z as f32 * 64.0);
+                let a = normal.get(point).height;
+                let b = amplified.get(point).height;
+                assert!(
+                    b <= MAX_LAND_HEIGHT as f32,
+                    "Amplified land too high: {}",
+                    b
+                );
+                highest = (highest.0.max(a), highest.1.max(b));
+            }
+        }
+        assert!(
+            highest.1 > highest.0 + 20.0,
+            "Amplified peaks should tower over normal ones: {:?}",
+            highest
+        );
+    }
+
+    #[test]
+    fn test_noise_overrides() {
+        let noise = NoiseConfig {
+            sea_level: Some(50.0),
+            world_scale: Some(768.0),
+            ..Default::default()
+        };
+        let normal = WorldTerrain::new(12345);
+        let custom = WorldTerrain::new(12345).with_noise(&noise);
+        assert_eq!(custom.sea_level(), 50.0);
+
+        // Doubling the world scale spreads the same terrain twice as far.
+        for i in 0..16 {
+            let point = glam::Vec2::new(500.0 + i as f32 * 300.0, 900.0);
+            let a = normal.get(point);
+            let b = custom.get(point * 2.0);
+            assert_eq!(a.biome == Biome::Ocean, b.biome == Biome::Ocean);
+            assert!(b.water_level >= 50.0);
+        }
+
+        // Beaches, and the rivers running out across them, sit at the raised
+        // sea rather than drowning under it.
+        let sea = custom.sea_level;
+        let (mut beaches, mut rivers) = (0, 0);
+        for x in 0..2000 {
+            for z in 0..40 {
+                let point = [x as f64 * 0.01, z as f64 * 0.05];
+                let shore_t = custom.get_shore_t(point);
+                if shore_t <= 0.0 || shore_t >= 1.0 {
+                    continue;
+                }
+                let (_, _, weights, land) = custom.get_land(point);
+                let base = custom.get_base_height(point, shore_t, &weights, land);
+                if shore_t > 0.3 && shore_t < 0.8 {
+                    beaches += 1;
+                    assert!(base > sea - 8.5 && base < sea + 2.5, "beach at {base}");
+                }
+                let trend = custom.river_surface(point);
+                if let (_, Some(surface)) = custom.rivers.carve(point, base, trend, sea) {
+                    // Rivers come down to the sea at the coast
+                    assert!(surface >= sea, "river at {surface}");
+                    if trend == sea {
+                        rivers += 1;
+                        assert_eq!(surface, sea);
+                    }
+                }
+            }
+        }
+        assert!(beaches > 0, "Should cross a coast");
+        assert!(rivers > 0, "Should find a river mouth");
+    }
+
     #[test]
     fn test_terraced_noise_blend() {
         let terrain = WorldTerrain::new(12345);
@@ -720,27 +1907,16 @@ mod tests {
             let point = [i as f64 / 10.0, i as f64 / 10.0];
             let (primary, weights) = terrain.get_land_blend(point);
 
-            let sum = weights.plains + weights.hills + weights.desert + weights.mountains;
+            let sum: f64 = weights.iter().map(|(_, w)| w).sum();
             assert!(
                 (sum - 1.0).abs() < 0.001,
                 "Weights should sum to 1.0, got {}",
                 sum
             );
 
-            let mut w_array = [
-                (Biome::Plains, weights.plains),
-                (Biome::Hills, weights.hills),
-                (Biome::Desert, weights.desert),
-                (Biome::Mountains, weights.mountains),
-            ];
+            let mut w_array: Vec<(Biome, f64)> = weights.iter().collect();
             w_array.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-            let max_calculated_weight = match primary {
-                Biome::Plains => weights.plains,
-                Biome::Hills => weights.hills,
-                Biome::Desert => weights.desert,
-                Biome::Mountains => weights.mountains,
-                Biome::Ocean => 0.0,
-            };
+            let max_calculated_weight = weights.get(primary);
             assert!(
                 (max_calculated_weight - w_array[0].1).abs() < 0.001,
                 "Primary biome must have the highest weight, even on ties"
@@ -780,11 +1956,8 @@ mod tests {
                 *biome_counts.entry(final_biome).or_insert(0) += 1;
 
                 let primary_w = match final_biome {
-                    Biome::Plains => shore_t * land_weights.plains,
-                    Biome::Hills => shore_t * land_weights.hills,
-                    Biome::Desert => shore_t * land_weights.desert,
-                    Biome::Mountains => shore_t * land_weights.mountains,
                     Biome::Ocean => 1.0 - shore_t,
+                    biome => shore_t * land_weights.get(biome),
                 };
 
                 if primary_w > 0.9 {
@@ -792,28 +1965,15 @@ mod tests {
                 }
 
                 let ocean_w = 1.0 - shore_t;
-                let plains_w = shore_t * land_weights.plains;
-                let hills_w = shore_t * land_weights.hills;
-                let desert_w = shore_t * land_weights.desert;
-                let mountains_w = shore_t * land_weights.mountains;
-
-                let r = (ocean_w * 0.0
-                    + plains_w * 144.0
-                    + hills_w * 34.0
-                    + desert_w * 237.0
-                    + mountains_w * 200.0) as u8;
-                let g = (ocean_w * 105.0
-                    + plains_w * 238.0
-                    + hills_w * 139.0
-                    + desert_w * 201.0
-                    + mountains_w * 200.0) as u8;
-                let b = (ocean_w * 148.0
-                    + plains_w * 144.0
-                    + hills_w * 34.0
-                    + desert_w * 175.0
-                    + mountains_w * 200.0) as u8;
-
-                img.put_pixel(x as u32, z as u32, image::Rgb([r, g, b]));
+                let mut rgb = crate::debug::biome_color(Biome::Ocean).map(|c| c as f64 * ocean_w);
+                for (biome, w) in land_weights.iter() {
+                    let color = crate::debug::biome_color(biome);
+                    for i in 0..3 {
+                        rgb[i] += color[i] as f64 * shore_t * w;
+                    }
+                }
+
+                img.put_pixel(x, z, image::Rgb([rgb[0] as u8, rgb[1] as u8, rgb[2] as u8]));
             }
         }
 
@@ -841,7 +2001,7 @@ mod tests {
     fn test_continent_map() {
         let terrain = WorldTerrain::new(12345);
         let grid_size = 2 * TEST_GRID_SIZE;
-        let mut img = image::ImageBuffer::new(grid_size as u32, grid_size as u32);
+        let mut img = image::ImageBuffer::new(grid_size, grid_size);
 
         let mut ocean_blocks = 0;
         let mut total_blocks = 0;
@@ -863,7 +2023,7 @@ mod tests {
                 let g = (shore_t * 139.0 + (1.0 - shore_t) * 105.0) as u8;
                 let b = (shore_t * 34.0 + (1.0 - shore_t) * 148.0) as u8;
 
-                img.put_pixel(x as u32, z as u32, image::Rgb([r, g, b]));
+                img.put_pixel(x, z, image::Rgb([r, g, b]));
             }
         }
 
@@ -885,7 +2045,7 @@ mod tests {
     fn test_temperature_map() {
         let terrain = WorldTerrain::new(12345);
         let grid_size = 2 * TEST_GRID_SIZE;
-        let mut img = image::ImageBuffer::new(grid_size as u32, grid_size as u32);
+        let mut img = image::ImageBuffer::new(grid_size, grid_size);
 
         for x in 0..grid_size {
             for z in 0..grid_size {
@@ -897,7 +2057,7 @@ mod tests {
                 let b = ((1.0 - t) * 255.0) as u8;
                 let g = 0;
 
-                img.put_pixel(x as u32, z as u32, image::Rgb([r, g, b]));
+                img.put_pixel(x, z, image::Rgb([r, g, b]));
             }
         }
         img.save("test_outputs/temperature_map.bmp")
@@ -909,7 +2069,7 @@ mod tests {
     fn test_moisture_map() {
         let terrain = WorldTerrain::new(12345);
         let grid_size = 2 * TEST_GRID_SIZE;
-        let mut img = image::ImageBuffer::new(grid_size as u32, grid_size as u32);
+        let mut img = image::ImageBuffer::new(grid_size, grid_size);
 
         for x in 0..grid_size {
             for z in 0..grid_size {
@@ -921,7 +2081,7 @@ mod tests {
                 let g = (69.0f32 * (1.0f32 - m) + 139.0f32 * m) as u8;
                 let b = (19.0f32 * (1.0f32 - m) + 34.0f32 * m) as u8;
 
-                img.put_pixel(x as u32, z as u32, image::Rgb([r, g, b]));
+                img.put_pixel(x, z, image::Rgb([r, g, b]));
             }
         }
         img.save("test_outputs/moisture_map.bmp")
@@ -931,7 +2091,7 @@ mod tests {
     #[test]
     fn test_biome_heightmap_plains() {
         let plains = PlainsTerrain::new(123);
-        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE as u32, TEST_GRID_SIZE as u32);
+        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE, TEST_GRID_SIZE);
         for x in 0..TEST_GRID_SIZE {
             for z in 0..TEST_GRID_SIZE {
                 let h = plains.get([
@@ -940,10 +2100,10 @@ mod tests {
                 ]);
                 if h <= WATER_LEVEL as f64 {
                     let b = 255 - ((WATER_LEVEL as f64 - h) as u8);
-                    img.put_pixel(x as u32, z as u32, image::Rgb([0, 0, b]));
+                    img.put_pixel(x, z, image::Rgb([0, 0, b]));
                 } else {
                     let c = h as u8;
-                    img.put_pixel(x as u32, z as u32, image::Rgb([c, c, c]));
+                    img.put_pixel(x, z, image::Rgb([c, c, c]));
                 }
             }
         }
@@ -954,7 +2114,7 @@ mod tests {
     #[test]
     fn test_biome_heightmap_mountains() {
         let mountains = MountainTerrain::new(123);
-        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE as u32, TEST_GRID_SIZE as u32);
+        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE, TEST_GRID_SIZE);
         for x in 0..TEST_GRID_SIZE {
             for z in 0..TEST_GRID_SIZE {
                 let h = mountains.get([
@@ -963,10 +2123,10 @@ mod tests {
                 ]);
                 if h <= WATER_LEVEL as f64 {
                     let b = 255 - ((WATER_LEVEL as f64 - h) as u8);
-                    img.put_pixel(x as u32, z as u32, image::Rgb([0, 0, b]));
+                    img.put_pixel(x, z, image::Rgb([0, 0, b]));
                 } else {
                     let c = h as u8;
-                    img.put_pixel(x as u32, z as u32, image::Rgb([c, c, c]));
+                    img.put_pixel(x, z, image::Rgb([c, c, c]));
                 }
             }
         }
@@ -977,7 +2137,7 @@ mod tests {
     #[test]
     fn test_biome_heightmap_desert() {
         let desert = DesertTerrain::new(123);
-        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE as u32, TEST_GRID_SIZE as u32);
+        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE, TEST_GRID_SIZE);
         for x in 0..TEST_GRID_SIZE {
             for z in 0..TEST_GRID_SIZE {
                 let h = desert.get([
@@ -986,10 +2146,10 @@ mod tests {
                 ]);
                 if h <= WATER_LEVEL as f64 {
                     let b = 255 - ((WATER_LEVEL as f64 - h) as u8);
-                    img.put_pixel(x as u32, z as u32, image::Rgb([0, 0, b]));
+                    img.put_pixel(x, z, image::Rgb([0, 0, b]));
                 } else {
                     let c = h as u8;
-                    img.put_pixel(x as u32, z as u32, image::Rgb([c, c, c]));
+                    img.put_pixel(x, z, image::Rgb([c, c, c]));
                 }
             }
         }
@@ -1000,7 +2160,7 @@ mod tests {
     #[test]
     fn test_biome_heightmap_hills() {
         let hills = HillsTerrain::new(123);
-        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE as u32, TEST_GRID_SIZE as u32);
+        let mut img = image::ImageBuffer::new(TEST_GRID_SIZE, TEST_GRID_SIZE);
         for x in 0..TEST_GRID_SIZE {
             for z in 0..TEST_GRID_SIZE {
                 let h = hills.get([
@@ -1009,10 +2169,10 @@ mod tests {
                 ]);
                 if h <= WATER_LEVEL as f64 {
                     let b = 255 - ((WATER_LEVEL as f64 - h) as u8);
-                    img.put_pixel(x as u32, z as u32, image::Rgb([0, 0, b]));
+                    img.put_pixel(x, z, image::Rgb([0, 0, b]));
                 } else {
                     let c = h as u8;
-                    img.put_pixel(x as u32, z as u32, image::Rgb([c, c, c]));
+                    img.put_pixel(x, z, image::Rgb([c, c, c]));
                 }
             }
         }
@@ -1039,15 +2199,18 @@ mod tests {
                     let tdata = terrain.get(glam::Vec2::new(world_x, world_z));
                     let surface_height = tdata.height;
 
-                    if terrain.is_cave(glam::Vec3::new(world_x, y, world_z), surface_height) {
+                    if terrain
+                        .cave_at(glam::Vec3::new(world_x, y, world_z), &tdata)
+                        .is_some()
+                    {
                         // Cave -> Black
-                        img.put_pixel(x as u32, z as u32, image::Rgb([0, 0, 0]));
+                        img.put_pixel(x, z, image::Rgb([0, 0, 0]));
                     } else if y >= surface_height - 1.0 {
                         // Surface/Air -> Green
-                        img.put_pixel(x as u32, z as u32, image::Rgb([0, 255, 0]));
+                        img.put_pixel(x, z, image::Rgb([0, 255, 0]));
                     } else {
                         // Solid medium grey
-                        img.put_pixel(x as u32, z as u32, image::Rgb([128, 128, 128]));
+                        img.put_pixel(x, z, image::Rgb([128, 128, 128]));
                     }
                 }
             }
@@ -1059,13 +2222,161 @@ mod tests {
     #[test]
     fn test_find_closest_cave() {
         let terrain = WorldTerrain::new(12345);
-        let result = terrain.find_closest_cave(glam::Vec2::new(0.0, 0.0));
+        let result = terrain.find_closest_cave(glam::Vec2::new(0.0, 0.0), None);
         assert!(result.is_some(), "Should find a cave near the origin.");
         let point = result.unwrap();
         let tdata = terrain.get(glam::Vec2::new(point.x, point.z));
         assert!(
-            terrain.is_cave(point, tdata.height),
+            terrain.cave_at(point, &tdata).is_some(),
             "The returned coordinates should point to an actual cave!"
         );
     }
+
+    #[test]
+    fn test_find_closest_cave_of_each_kind() {
+        let terrain = WorldTerrain::new(12345);
+        for kind in CaveKind::ALL {
+            let point = terrain
+                .find_closest_cave(glam::Vec2::new(2000.0, 2000.0), Some(kind))
+                .unwrap_or_else(|| panic!("Should find a {} inland", kind));
+            let tdata = terrain.get(glam::Vec2::new(point.x, point.z));
+            assert_eq!(terrain.cave_at(point, &tdata), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_rivers_run_downhill_to_the_coast() {
+        let rivers = RiverTerrain::new(42);
+        let scale = WorldTerrain::WORLD_SCALE;
+        let point = (0..4096)
+            .map(|x| [x as f64 / scale, 100.0 / scale])
+            .find(|p| rivers.distance(*p) < 1.0)
+            .expect("Should cross a river");
+
+        let surfaces: Vec<f64> = [0.0, 0.5, 1.0]
+            .iter()
+            .map(|&rise| {
+                // Land a little above where the river would run
+                let trend = WATER_LEVEL as f64 + rise * RIVER_RISE;
+                let height = trend + 10.0;
+                let (carved, surface) = rivers.carve(point, height, trend, WATER_LEVEL as f64);
+                let surface = surface.expect("Should be in the channel");
+                assert!(carved < surface, "bed {carved} surface {surface}");
+                surface
+            })
+            .collect();
+        assert_eq!(surfaces[0], WATER_LEVEL as f64);
+        assert!(surfaces[0] < surfaces[1] && surfaces[1] < surfaces[2]);
+
+        // Rivers never run above the land, and never raise it.
+        let (carved, surface) = rivers.carve(point, 50.0, 92.0, WATER_LEVEL as f64);
+        assert!(surface.unwrap() < 50.0);
+        assert!(carved <= 50.0);
+        // Nor are they cut through high mountains.
+        assert_eq!(
+            rivers.carve(point, 250.0, WATER_LEVEL as f64, WATER_LEVEL as f64),
+            (250.0, None)
+        );
+    }
+
+    #[test]
+    fn test_rivers_follow_the_land() {
+        let terrain = WorldTerrain::new(12345);
+        let sea = terrain.sea_level;
+        let (mut rivers, mut upland) = (0, 0);
+        for x in (0..4096).step_by(8) {
+            for z in (0..4096).step_by(32) {
+                let point = [
+                    x as f64 / WorldTerrain::WORLD_SCALE,
+                    z as f64 / WorldTerrain::WORLD_SCALE,
+                ];
+                let shore_t = terrain.get_shore_t(point);
+                let (_, _, weights, land) = terrain.get_land(point);
+                let base = terrain.get_base_height(point, shore_t, &weights, land);
+                let trend = terrain.river_surface(point);
+                let (_, Some(surface)) = terrain.rivers.carve(point, base, trend, sea) else {
+                    continue;
+                };
+                rivers += 1;
+                assert!(surface < base && surface >= sea);
+                if surface > sea + 10.0 {
+                    upland += 1;
+                }
+            }
+        }
+        assert!(rivers > 0, "Should cross rivers");
+        assert!(upland > 0, "Rivers should climb with the land");
+    }
+
+    #[test]
+    fn test_lake_basin_holds_water() {
+        let lake = Lake {
+            cell: glam::IVec2::ZERO,
+            centre: [1.0, 1.0],
+            radius: 20.0 / WorldTerrain::WORLD_SCALE,
+            depth: 6.0,
+        };
+        let at = |blocks: f64| [1.0 + blocks / WorldTerrain::WORLD_SCALE, 1.0];
+
+        let (bed, inside) = lake.carve(at(0.0), 70.0, 60.0);
+        assert!(inside);
+        assert_eq!(bed, 54.0);
+        let (shallows, inside) = lake.carve(at(19.0), 70.0, 60.0);
+        assert!(inside && shallows < 60.0 && shallows > bed);
+
+        // The rim stands above the water even where the land falls away.
+        let (rim, inside) = lake.carve(at(21.0), 50.0, 60.0);
+        assert!(!inside && rim > 60.0);
+        assert_eq!(lake.carve(at(40.0), 50.0, 60.0), (50.0, false));
+    }
+
+    #[test]
+    fn test_inland_water() {
+        let terrain = WorldTerrain::new(12345);
+        let mut inland = 0;
+        for x in (0..4096).step_by(16) {
+            for z in (0..4096).step_by(16) {
+                let tdata = terrain.get(glam::Vec2::new(x as f32, z as f32));
+                assert!(tdata.water_level >= WATER_LEVEL);
+                if tdata.water_level > WATER_LEVEL {
+                    assert!(tdata.is_water(), "Dry land at {x},{z} has a water level");
+                    assert!(tdata.biome != Biome::Ocean);
+                    inland += 1;
+                }
+            }
+        }
+        assert!(inland > 0, "Should find rivers or lakes above the sea");
+    }
+
+    #[test]
+    fn test_climate_follows_terrain() {
+        let terrain = WorldTerrain::new(12345);
+        let (mut cooled, mut shadowed) = (0, 0);
+        for x in (0..8192).step_by(64) {
+            for z in (0..8192).step_by(64) {
+                let point = [
+                    x as f64 / WorldTerrain::WORLD_SCALE,
+                    z as f64 / WorldTerrain::WORLD_SCALE,
+                ];
+                let (temp, moist) = terrain.get_base_climate(point);
+                let climate = terrain.get_climate(point);
+                assert!(climate.temperature <= temp && climate.moisture <= moist.max(0.0));
+                assert!(climate.moisture >= 0.0, "Too dry at {x},{z}");
+
+                let elevation = climate.base_height - WATER_LEVEL as f64;
+                if terrain.get_shore_t(point) >= 1.0 && elevation > 100.0 {
+                    assert!(
+                        climate.temperature < temp - 0.2,
+                        "High ground at {x},{z} should be colder"
+                    );
+                    cooled += 1;
+                }
+                if climate.moisture < moist - 0.05 {
+                    shadowed += 1;
+                }
+            }
+        }
+        assert!(cooled > 0, "Should find high ground");
+        assert!(shadowed > 0, "Should find rain shadows");
+    }
 }
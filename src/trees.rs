@@ -1,10 +1,193 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum TreeType {
-    Oak,
-    Pine,
-    Birch,
-    Palm,
-    Bush,
+use crate::lsystem::Grammar;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Grammars that ship with the game. Files in the species directory add to
+/// these, or replace them by name.
+const BUILTIN_SPECIES: [&str; 8] = [
+    include_str!("../species/acacia.toml"),
+    include_str!("../species/birch.toml"),
+    include_str!("../species/bush.toml"),
+    include_str!("../species/jungle.toml"),
+    include_str!("../species/oak.toml"),
+    include_str!("../species/palm.toml"),
+    include_str!("../species/pine.toml"),
+    include_str!("../species/willow.toml"),
+];
+
+/// Expanded strings grow exponentially, so grammars are kept to this many
+/// iterations.
+const MAX_ITERATIONS: usize = 8;
+
+/// A range of climate a species grows in. Bounds that are left out don't
+/// limit it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ClimateEnvelope {
+    pub min_temperature: Option<f32>,
+    pub max_temperature: Option<f32>,
+    pub min_moisture: Option<f32>,
+    pub max_moisture: Option<f32>,
+    pub min_height: Option<f32>,
+    pub max_height: Option<f32>,
+}
+
+impl ClimateEnvelope {
+    fn contains(&self, temp: f32, moist: f32, height: f32) -> bool {
+        let within = |value: f32, min: Option<f32>, max: Option<f32>| {
+            min.is_none_or(|min| value >= min) && max.is_none_or(|max| value < max)
+        };
+        within(temp, self.min_temperature, self.max_temperature)
+            && within(moist, self.min_moisture, self.max_moisture)
+            && within(height, self.min_height, self.max_height)
+    }
+}
+
+fn default_growth_days() -> f32 {
+    8.0
+}
+
+/// A kind of tree, as read from a grammar file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Species {
+    pub name: String,
+    /// Where the climate suits more than one tree, the highest priority wins.
+    #[serde(default)]
+    pub priority: i32,
+    /// Understory plants grow wherever they fit, alongside the tree that won.
+    #[serde(default)]
+    pub understory: bool,
+    /// Climates the species grows in. It grows wherever any one of them fits.
+    #[serde(default)]
+    pub climate: Vec<ClimateEnvelope>,
+    /// World days a sapling takes to grow to full size.
+    #[serde(default = "default_growth_days")]
+    pub growth_days: f32,
+    #[serde(flatten)]
+    pub grammar: Grammar,
+}
+
+impl Species {
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        let species: Species = toml::from_str(contents).map_err(|e| e.to_string())?;
+        if species.grammar.iterations > MAX_ITERATIONS {
+            return Err(format!(
+                "{} has {} iterations, the most is {}",
+                species.name, species.grammar.iterations, MAX_ITERATIONS
+            ));
+        }
+        Ok(species)
+    }
+
+    fn suits(&self, temp: f32, moist: f32, height: f32) -> bool {
+        self.climate.iter().any(|c| c.contains(temp, moist, height))
+    }
+}
+
+/// Index of a species in the registry it came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpeciesId(usize);
+
+impl SpeciesId {
+    pub(crate) fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Every species trees can be grown from, highest priority first.
+pub struct SpeciesRegistry {
+    species: Vec<Species>,
+}
+
+impl SpeciesRegistry {
+    pub fn builtin() -> Self {
+        let mut registry = Self {
+            species: Vec::new(),
+        };
+        for contents in BUILTIN_SPECIES {
+            registry.insert(Species::from_toml(contents).expect("built-in species should parse"));
+        }
+        registry
+    }
+
+    /// The built-in species along with any grammar files in `dir`. Files that
+    /// can't be read are skipped with a warning.
+    pub fn load(dir: impl AsRef<Path>) -> Self {
+        let mut registry = Self::builtin();
+        let Ok(entries) = fs::read_dir(dir) else {
+            return registry;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            match fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| Species::from_toml(&contents))
+            {
+                Ok(species) => registry.insert(species),
+                Err(e) => log::warn!("Skipping tree species {}: {}", path.display(), e),
+            }
+        }
+        registry
+    }
+
+    /// Adds a species, replacing any with the same name.
+    pub fn insert(&mut self, species: Species) {
+        self.species.retain(|s| s.name != species.name);
+        self.species.push(species);
+        self.species.sort_by_key(|s| std::cmp::Reverse(s.priority));
+    }
+
+    pub fn get(&self, id: SpeciesId) -> &Species {
+        &self.species[id.0]
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = SpeciesId> {
+        (0..self.species.len()).map(SpeciesId)
+    }
+
+    pub fn find(&self, name: &str) -> Option<SpeciesId> {
+        self.species
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))
+            .map(SpeciesId)
+    }
+
+    /// Single source of truth for tree selection: the highest priority tree
+    /// that suits the climate, and any understory that does too.
+    pub fn get_suitable_trees(
+        &self,
+        temp: f32,
+        moist: f32,
+        height: f32,
+        altitude_jitter: f32,
+    ) -> Vec<SpeciesId> {
+        // Every height bound moves with the jitter, so treelines aren't flat
+        let height = height - altitude_jitter;
+        let suits = |(_, s): &(usize, &Species)| s.suits(temp, moist, height);
+
+        let canopy = self
+            .species
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !s.understory)
+            .find(suits);
+        let understory = self
+            .species
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.understory)
+            .filter(suits);
+        canopy
+            .into_iter()
+            .chain(understory)
+            .map(|(i, _)| SpeciesId(i))
+            .collect()
+    }
 }
 
 // The shared climate constants
@@ -14,35 +197,17 @@ const MOUNTAIN_TEMP_MAX: f32 = 0.35;
 const WET_HILLS_TEMP_MIN: f32 = 0.6;
 const WET_HILLS_MOIST_MIN: f32 = 0.7;
 const BIRCH_MOIST_MIN: f32 = 0.55;
+const TUNDRA_TEMP_MAX: f32 = 0.25;
+const TUNDRA_MOIST_MAX: f32 = 0.4;
+const TAIGA_TEMP_MAX: f32 = 0.4;
+const TAIGA_MOIST_MIN: f32 = 0.45;
+const JUNGLE_TEMP_MIN: f32 = 0.85;
+const JUNGLE_MOIST_MIN: f32 = 0.7;
+const SWAMP_MOIST_MIN: f32 = 0.82;
+const SAVANNA_TEMP_MIN: f32 = 0.65;
+const SAVANNA_MOIST_MAX: f32 = 0.45;
 
 const PINE_ALTITUDE: f32 = 130.0;
-const BUSH_MAX_HEIGHT: f32 = 50.0;
-
-/// Single source of truth for tree selection
-pub fn get_suitable_trees(
-    temp: f32,
-    moist: f32,
-    height: f32,
-    altitude_jitter: f32,
-) -> Vec<TreeType> {
-    let mut trees = Vec::new();
-
-    if temp > DESERT_TEMP_MIN && moist < DESERT_MOIST_MAX {
-        trees.push(TreeType::Palm);
-    } else if temp < MOUNTAIN_TEMP_MAX && height > PINE_ALTITUDE + altitude_jitter {
-        trees.push(TreeType::Pine);
-    } else if temp >= WET_HILLS_TEMP_MIN && moist >= WET_HILLS_MOIST_MIN {
-        trees.push(TreeType::Oak);
-    } else if moist > BIRCH_MOIST_MIN {
-        trees.push(TreeType::Birch);
-    }
-
-    if !(temp > DESERT_TEMP_MIN && moist < DESERT_MOIST_MAX) && height < BUSH_MAX_HEIGHT {
-        trees.push(TreeType::Bush);
-    }
-
-    trees
-}
 
 /// Single source of truth for vegetation spacing/density
 pub fn get_vegetation_radius(temp: f32, moist: f32, height: f32, near_water: bool) -> f32 {
@@ -54,10 +219,30 @@ pub fn get_vegetation_radius(temp: f32, moist: f32, height: f32, near_water: boo
         return 12.0;
     }
 
+    if temp < TUNDRA_TEMP_MAX && moist < TUNDRA_MOIST_MAX {
+        return 25.0;
+    }
+
+    if temp < TAIGA_TEMP_MAX && moist >= TAIGA_MOIST_MIN {
+        return 7.0;
+    }
+
+    if temp > JUNGLE_TEMP_MIN && moist >= JUNGLE_MOIST_MIN {
+        return 5.0;
+    }
+
+    if moist >= SWAMP_MOIST_MIN {
+        return 10.0;
+    }
+
     if temp >= WET_HILLS_TEMP_MIN && moist >= WET_HILLS_MOIST_MIN {
         return 20.0;
     }
 
+    if temp > SAVANNA_TEMP_MIN && moist < SAVANNA_MOIST_MAX {
+        return 30.0;
+    }
+
     if moist > BIRCH_MOIST_MIN {
         return 6.0;
     }
@@ -70,45 +255,153 @@ pub fn get_vegetation_radius(temp: f32, moist: f32, height: f32, near_water: boo
 mod tests {
     use super::*;
 
+    fn suitable(temp: f32, moist: f32, height: f32) -> Vec<String> {
+        let registry = SpeciesRegistry::builtin();
+        registry
+            .get_suitable_trees(temp, moist, height, 0.0)
+            .into_iter()
+            .map(|id| registry.get(id).name.clone())
+            .collect()
+    }
+
     #[test]
     fn test_desert_palms() {
-        let trees = get_suitable_trees(0.8, 0.1, 10.0, 0.0);
-        assert_eq!(trees, vec![TreeType::Palm]);
+        let trees = suitable(0.8, 0.1, 10.0);
+        assert_eq!(trees, vec!["palm"]);
     }
 
     #[test]
     fn test_high_mountains_pine() {
-        let trees = get_suitable_trees(0.2, 0.4, 200.0, 0.0);
-        assert!(trees.contains(&TreeType::Pine));
-        assert!(!trees.contains(&TreeType::Bush)); // Height > BUSH_MAX_HEIGHT
+        let trees = suitable(0.2, 0.4, 200.0);
+        assert!(trees.iter().any(|t| t == "pine"));
+        assert!(!trees.iter().any(|t| t == "bush")); // Too high for the understory
     }
 
     #[test]
     fn test_low_mountains_bush() {
-        let trees = get_suitable_trees(0.2, 0.4, 10.0, 0.0);
-        assert!(!trees.contains(&TreeType::Pine));
-        assert!(trees.contains(&TreeType::Bush));
+        let trees = suitable(0.2, 0.4, 10.0);
+        assert!(!trees.iter().any(|t| t == "pine"));
+        assert!(trees.iter().any(|t| t == "bush"));
     }
 
     #[test]
     fn test_wet_hills_oak_and_bush() {
-        let trees = get_suitable_trees(0.8, 0.8, 10.0, 0.0);
-        assert!(trees.contains(&TreeType::Oak));
-        assert!(trees.contains(&TreeType::Bush));
-        assert!(!trees.contains(&TreeType::Birch)); // Mutually exclusive with Oak
+        let trees = suitable(0.8, 0.8, 10.0);
+        assert!(trees.iter().any(|t| t == "oak"));
+        assert!(trees.iter().any(|t| t == "bush"));
+        assert!(!trees.iter().any(|t| t == "birch")); // Mutually exclusive with Oak
     }
 
     #[test]
     fn test_moderate_hills_birch_and_bush() {
-        let trees = get_suitable_trees(0.8, 0.6, 10.0, 0.0);
-        assert!(trees.contains(&TreeType::Birch));
-        assert!(trees.contains(&TreeType::Bush));
-        assert!(!trees.contains(&TreeType::Oak)); // Mutually exclusive with Birch
+        let trees = suitable(0.8, 0.6, 10.0);
+        assert!(trees.iter().any(|t| t == "birch"));
+        assert!(trees.iter().any(|t| t == "bush"));
+        assert!(!trees.iter().any(|t| t == "oak")); // Mutually exclusive with Birch
+    }
+
+    #[test]
+    fn test_tundra_scrub_only() {
+        let trees = suitable(0.15, 0.2, 40.0);
+        assert_eq!(trees, vec!["bush"]);
+        assert!(
+            get_vegetation_radius(0.15, 0.2, 40.0, false)
+                > get_vegetation_radius(0.5, 0.45, 40.0, false)
+        );
+    }
+
+    #[test]
+    fn test_lowland_taiga_pine() {
+        let trees = suitable(0.3, 0.6, 60.0);
+        assert_eq!(trees, vec!["pine"]);
+    }
+
+    #[test]
+    fn test_jungle_dense_canopy() {
+        let trees = suitable(0.95, 0.9, 40.0);
+        assert!(trees.iter().any(|t| t == "jungle"));
+        assert!(!trees.iter().any(|t| t == "oak"));
+        assert!(
+            get_vegetation_radius(0.95, 0.9, 40.0, false)
+                < get_vegetation_radius(0.8, 0.8, 40.0, false)
+        );
+    }
+
+    #[test]
+    fn test_swamp_willow() {
+        let trees = suitable(0.5, 0.9, 31.0);
+        assert!(trees.iter().any(|t| t == "willow"));
+        assert!(trees.iter().any(|t| t == "bush"));
+    }
+
+    #[test]
+    fn test_savanna_sparse_acacia() {
+        let trees = suitable(0.8, 0.3, 35.0);
+        assert!(trees.iter().any(|t| t == "acacia"));
+        assert!(!trees.iter().any(|t| t == "palm"));
+        assert!(get_vegetation_radius(0.8, 0.3, 35.0, false) >= 30.0);
     }
 
     #[test]
     fn test_plains_bush_only() {
-        let trees = get_suitable_trees(0.5, 0.5, 10.0, 0.0);
-        assert_eq!(trees, vec![TreeType::Bush]);
+        let trees = suitable(0.5, 0.5, 10.0);
+        assert_eq!(trees, vec!["bush"]);
+    }
+
+    #[test]
+    fn test_species_files_add_and_replace() {
+        let dir = std::env::temp_dir().join("ruxel_test_species");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("cactus.toml"),
+            r#"
+            name = "cactus"
+            priority = 9
+            axiom = "TTC"
+            iterations = 2
+            angle = 90.0
+            thickness = 0.6
+            length = 0.8
+            bark = [60, 140, 60]
+            leaf = [60, 140, 60]
+            evergreen = true
+
+            [rules]
+            C = "T[+T^T]C"
+
+            [leaves]
+            C = "block"
+
+            [[climate]]
+            min_temperature = 0.8
+            max_moisture = 0.2
+            "#,
+        )
+        .unwrap();
+        // Birches anywhere damp, no matter how hot.
+        let birch = include_str!("../species/birch.toml").replace("priority = 2", "priority = 10");
+        fs::write(dir.join("birch.toml"), birch).unwrap();
+        fs::write(dir.join("broken.toml"), "name = \"broken\"").unwrap();
+
+        let registry = SpeciesRegistry::load(&dir);
+        let names = |trees: Vec<SpeciesId>| -> Vec<String> {
+            trees
+                .into_iter()
+                .map(|id| registry.get(id).name.clone())
+                .collect()
+        };
+        assert_eq!(
+            names(registry.get_suitable_trees(0.9, 0.1, 10.0, 0.0)),
+            vec!["cactus"]
+        );
+        assert_eq!(
+            names(registry.get_suitable_trees(0.95, 0.9, 40.0, 0.0)),
+            vec!["birch", "bush"]
+        );
+        assert!(registry.find("broken").is_none());
+        assert_eq!(registry.species.len(), BUILTIN_SPECIES.len() + 1);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 }
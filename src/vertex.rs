@@ -8,10 +8,12 @@ pub struct Vertex {
     material: u32,
     color: [u8; 4],
     normal_and_ao: [i8; 4],
+    /// Light given off by nearby blocks, like lava, on top of the sun and moon.
+    /// The last channel is how far the vertex sways in the wind.
+    light: [u8; 4],
 }
 
 impl Vertex {
-    #[cfg(test)]
     pub fn position(&self) -> [f32; 3] {
         self.position
     }
@@ -21,8 +23,23 @@ impl Vertex {
         self.color
     }
 
-    const ATTRIBS: [wgpu::VertexAttribute; 4] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Uint32, 2 => Unorm8x4, 3 => Snorm8x4];
+    #[cfg(test)]
+    pub fn material_id(&self) -> u32 {
+        self.material
+    }
+
+    #[cfg(test)]
+    pub fn light(&self) -> [u8; 4] {
+        self.light
+    }
+
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Uint32,
+        2 => Unorm8x4,
+        3 => Snorm8x4,
+        4 => Unorm8x4
+    ];
 
     pub const fn new(position: [f32; 3], material: u32, color: [u8; 4], normal_and_ao: [i8; 4]) -> Self {
         Vertex {
@@ -30,9 +47,27 @@ impl Vertex {
             material,
             color,
             normal_and_ao,
+            light: [0; 4],
         }
     }
 
+    pub const fn with_light(mut self, light: [u8; 4]) -> Self {
+        self.light = light;
+        self
+    }
+
+    pub const fn with_position(mut self, position: [f32; 3]) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Sets how far the vertex sways in the wind, from 0 for rooted to 1 for
+    /// swinging freely.
+    pub fn with_sway(mut self, sway: f32) -> Self {
+        self.light[3] = (sway.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -57,6 +92,14 @@ impl SimpleVertex {
         SimpleVertex { position, normal }
     }
 
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn normal(&self) -> [i8; 4] {
+        self.normal
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<SimpleVertex>() as wgpu::BufferAddress,
@@ -0,0 +1,247 @@
+use glam::{IVec2, IVec3, UVec2, Vec3};
+use std::sync::Arc;
+
+use crate::block;
+use crate::chunks::{Chunk, MAX_HEIGHT};
+use crate::collision::distance_to_segment;
+use crate::entities::{self, Tree, MAX_TREE_SCALE, TREE_VARIANTS};
+use crate::generator::TerrainGenerator;
+use crate::lsystem::{self, TreePart};
+use crate::trees::SpeciesRegistry;
+
+/// Leaves fill this far around each leaf a tree grows, so they join up into
+/// a crown.
+const LEAF_RADIUS: f32 = 1.2;
+
+/// Branches fill at least the blocks they pass through the middle of, even
+/// running along the edge between them.
+const MIN_BRANCH_RADIUS: f32 = 0.75;
+
+/// Trees built out of log and leaf blocks, for worlds where they're part of
+/// the block world rather than drawn over it. Like ore veins, a tree is
+/// built into every column it reaches, not just the one it stands in.
+pub(crate) struct VoxelTrees {
+    seed: u32,
+    species: Arc<SpeciesRegistry>,
+    // The branches and leaves of every species' variants, fully grown
+    parts: Vec<Vec<Vec<TreePart>>>,
+    // How many cells out a tree can reach into a column from
+    reach: i32,
+}
+
+impl VoxelTrees {
+    pub(crate) fn new(seed: u32, species: Arc<SpeciesRegistry>) -> Self {
+        let parts: Vec<Vec<Vec<TreePart>>> = species
+            .ids()
+            .map(|id| {
+                let grammar = &species.get(id).grammar;
+                (0..TREE_VARIANTS)
+                    .map(|variant| {
+                        lsystem::generate_tree_parts(grammar, variant as u32, grammar.iterations)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let widest = parts
+            .iter()
+            .flatten()
+            .flatten()
+            .flat_map(|part| [part.start, part.end])
+            .map(|p| p.x.hypot(p.z))
+            .fold(0.0, f32::max);
+        let reach = ((widest * MAX_TREE_SCALE + LEAF_RADIUS) / 16.0).ceil() as i32;
+
+        Self {
+            seed,
+            species,
+            parts,
+            reach,
+        }
+    }
+
+    /// Builds every tree that reaches into the column at `key` into its
+    /// blocks.
+    pub(crate) fn build(&self, terrain: &dyn TerrainGenerator, key: UVec2, column: &mut [Chunk]) {
+        for dx in -self.reach..=self.reach {
+            for dz in -self.reach..=self.reach {
+                let cell = key.as_ivec2() + IVec2::new(dx, dz);
+                if cell.x < 0 || cell.y < 0 {
+                    continue;
+                }
+                let trees = entities::wild_trees(
+                    self.seed,
+                    cell.x as u32,
+                    cell.y as u32,
+                    terrain,
+                    &self.species,
+                );
+                for tree in &trees {
+                    self.build_tree(tree, key, column);
+                }
+            }
+        }
+    }
+
+    /// Builds the part of `tree` that falls in the column at `key`. Logs
+    /// grow through leaves but neither replaces the ground.
+    fn build_tree(&self, tree: &Tree, key: UVec2, column: &mut [Chunk]) {
+        let min = key.as_ivec2() * 16;
+        let max = min + IVec2::splat(15);
+        for part in &self.parts[tree.species().index()][tree.variant()] {
+            let (ty, radius) = if part.leaf {
+                (block::Type::Leaves, LEAF_RADIUS)
+            } else {
+                (
+                    block::Type::Log,
+                    (part.thickness / 2.0).max(MIN_BRANCH_RADIUS),
+                )
+            };
+            let start = tree.place(part.start);
+            let end = tree.place(part.end);
+
+            let low = (start.min(end) - radius).floor().as_ivec3();
+            let high = (start.max(end) + radius).floor().as_ivec3();
+            let low = low.max(IVec3::new(min.x, 0, min.y));
+            let high = high.min(IVec3::new(max.x, MAX_HEIGHT - 1, max.y));
+            for x in low.x..=high.x {
+                for z in low.z..=high.z {
+                    for y in low.y..=high.y {
+                        let centre = Vec3::new(x as f32, y as f32, z as f32) + 0.5;
+                        if distance_to_segment(centre, start, end) > radius {
+                            continue;
+                        }
+                        let Some(chunk) = column.get_mut(y as usize / 16) else {
+                            continue;
+                        };
+                        let block = &mut chunk.blocks_mut()[(x - min.x) as usize]
+                            [(z - min.y) as usize][y as usize % 16];
+                        let replaces = match ty {
+                            block::Type::Log => {
+                                matches!(block.ty(), block::Type::Inactive | block::Type::Leaves)
+                            }
+                            _ => block.ty() == block::Type::Inactive,
+                        };
+                        if replaces {
+                            block.set_type(ty);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::terrain::WorldTerrain;
+
+    fn empty_column(key: UVec2) -> Vec<Chunk> {
+        (0..MAX_HEIGHT / 16)
+            .map(|y| {
+                let start = Vec3::new(key.x as f32, y as f32, key.y as f32) * 16.0;
+                Chunk::new(start, [[[Block::new(); 16]; 16]; 16])
+            })
+            .collect()
+    }
+
+    fn block_at(column: &[Chunk], key: UVec2, pos: IVec3) -> block::Type {
+        let local = pos - IVec3::new(key.x as i32 * 16, 0, key.y as i32 * 16);
+        column[pos.y as usize / 16].blocks()[local.x as usize][local.z as usize]
+            [pos.y as usize % 16]
+            .ty()
+    }
+
+    fn blocks_of(column: &[Chunk], key: UVec2) -> Vec<(IVec3, block::Type)> {
+        let mut blocks = Vec::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in 0..MAX_HEIGHT {
+                    let pos = IVec3::new(key.x as i32 * 16 + x, y, key.y as i32 * 16 + z);
+                    let ty = block_at(column, key, pos);
+                    if ty != block::Type::Inactive {
+                        blocks.push((pos, ty));
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_trees_are_built_from_logs_and_leaves() {
+        let terrain = WorldTerrain::new(12345);
+        let species = Arc::new(SpeciesRegistry::builtin());
+        let voxel_trees = VoxelTrees::new(12345, species.clone());
+        assert!(voxel_trees.reach >= 1);
+
+        let mut trunks = 0;
+        for x in 96..100 {
+            let key = UVec2::new(x, 384);
+            let mut column = terrain.generate_column(key);
+            voxel_trees.build(&terrain, key, &mut column);
+
+            let blocks = blocks_of(&column, key);
+            assert!(blocks.iter().any(|(_, ty)| *ty == block::Type::Leaves));
+            for tree in entities::wild_trees(12345, x, 384, &terrain, &species) {
+                // Every tree's trunk comes up out of the ground it stands in
+                let root = tree.place(Vec3::ZERO).floor().as_ivec3();
+                assert!((0..4)
+                    .map(|dy| block_at(&column, key, root + IVec3::Y * dy))
+                    .any(|ty| ty == block::Type::Log));
+                trunks += 1;
+            }
+        }
+        assert!(trunks > 0, "Expected some trees to test");
+    }
+
+    #[test]
+    fn test_trees_reach_across_columns() {
+        let terrain = WorldTerrain::new(12345);
+        let species = Arc::new(SpeciesRegistry::builtin());
+        let voxel_trees = VoxelTrees::new(12345, species.clone());
+
+        let mut crossed = 0;
+        for x in 97..105 {
+            let key = UVec2::new(x, 384);
+            let mut column = terrain.generate_column(key);
+            voxel_trees.build(&terrain, key, &mut column);
+
+            // Trees standing in the next columns over build into this one
+            for neighbour in [x - 1, x + 1] {
+                for tree in entities::wild_trees(12345, neighbour, 384, &terrain, &species) {
+                    let mut alone = empty_column(key);
+                    voxel_trees.build_tree(&tree, key, &mut alone);
+                    for (pos, _) in blocks_of(&alone, key) {
+                        assert_ne!(block_at(&column, key, pos), block::Type::Inactive);
+                        crossed += 1;
+                    }
+                }
+            }
+        }
+        assert!(crossed > 0, "No tree reached into the next column");
+    }
+
+    #[test]
+    fn test_logs_grow_through_leaves_but_not_ground() {
+        let species = Arc::new(SpeciesRegistry::builtin());
+        let voxel_trees = VoxelTrees::new(1, species.clone());
+        let oak = species.find("oak").unwrap();
+        let key = UVec2::new(0, 0);
+        let tree = Tree::sapling(Vec3::new(8.5, 40.0, 8.5), oak, 0.0);
+
+        let mut column = empty_column(key);
+        column[2].blocks_mut()[8][8][8].set_type(block::Type::Rock);
+        voxel_trees.build_tree(&tree, key, &mut column);
+        assert_eq!(
+            block_at(&column, key, IVec3::new(8, 40, 8)),
+            block::Type::Rock
+        );
+        assert_eq!(
+            block_at(&column, key, IVec3::new(8, 41, 8)),
+            block::Type::Log
+        );
+    }
+}
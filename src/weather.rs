@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec2, Vec2, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::generator::TerrainGenerator;
+
+/// Width of a weather region in blocks. Each region runs its own state machine.
+const REGION_SIZE: f32 = 256.0;
+/// Regions further than this (in regions) from the player are forgotten.
+const REGION_KEEP_RADIUS: i32 = 2;
+
+/// Below this temperature precipitation falls as snow.
+const SNOW_TEMP_MAX: f32 = 0.35;
+/// Above this altitude precipitation always falls as snow (matches the ice line).
+const SNOW_ALTITUDE: f32 = 180.0;
+/// Thunderstorms only build in warm, wet air.
+const STORM_TEMP_MIN: f32 = 0.55;
+const STORM_MOIST_MIN: f32 = 0.5;
+
+/// How quickly the visible intensity follows the region state, per second.
+const INTENSITY_RATE: f32 = 0.1;
+/// How quickly surfaces get wet while it rains, and dry afterwards, per second.
+const WET_RATE: f32 = 0.05;
+const DRY_RATE: f32 = 0.01;
+
+/// Seconds of full-intensity rain between each attempt to pool water in a basin.
+const RAIN_PUDDLE_INTERVAL: f32 = 2.0;
+/// Rain is pooled in basins within this many blocks of the player.
+const RAIN_RADIUS: i32 = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WeatherKind {
+    Clear,
+    Rain,
+    Snow,
+    Thunderstorm,
+}
+
+impl WeatherKind {
+    fn is_precipitating(self) -> bool {
+        !matches!(self, WeatherKind::Clear)
+    }
+
+    fn overcast(self) -> f32 {
+        match self {
+            WeatherKind::Clear => 0.0,
+            WeatherKind::Rain => 0.6,
+            WeatherKind::Snow => 0.5,
+            WeatherKind::Thunderstorm => 1.0,
+        }
+    }
+
+    fn wind_strength(self) -> f32 {
+        match self {
+            WeatherKind::Clear => 0.2,
+            WeatherKind::Rain => 0.5,
+            WeatherKind::Snow => 0.3,
+            WeatherKind::Thunderstorm => 1.0,
+        }
+    }
+}
+
+impl std::str::FromStr for WeatherKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clear" | "sun" => Ok(WeatherKind::Clear),
+            "rain" => Ok(WeatherKind::Rain),
+            "snow" => Ok(WeatherKind::Snow),
+            "storm" | "thunder" | "thunderstorm" => Ok(WeatherKind::Thunderstorm),
+            _ => Err(format!(
+                "Invalid weather '{}'. Expected: clear, rain, snow, storm or auto.",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for WeatherKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Snow => "snow",
+            WeatherKind::Thunderstorm => "thunderstorm",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RegionWeather {
+    kind: WeatherKind,
+    remaining: f32,
+    // Picks where rain pools. Seeded from the world seed and the region so the
+    // same rain fills the same basins.
+    puddles: StdRng,
+}
+
+/// Chooses the next state for a region. Precipitation type comes from the
+/// climate: cold or high regions snow, warm and wet ones can build storms.
+fn next_kind(
+    current: WeatherKind,
+    temperature: f32,
+    moisture: f32,
+    height: f32,
+    rng: &mut StdRng,
+) -> WeatherKind {
+    let is_cold = temperature < SNOW_TEMP_MAX || height > SNOW_ALTITUDE;
+    let precipitation = if is_cold {
+        WeatherKind::Snow
+    } else {
+        WeatherKind::Rain
+    };
+    let can_storm = !is_cold && temperature >= STORM_TEMP_MIN && moisture >= STORM_MOIST_MIN;
+
+    match current {
+        WeatherKind::Clear => {
+            // Wetter regions are more likely to cloud over.
+            if rng.gen::<f32>() < 0.15 + moisture * 0.5 {
+                precipitation
+            } else {
+                WeatherKind::Clear
+            }
+        }
+        WeatherKind::Rain | WeatherKind::Snow => {
+            if can_storm && rng.gen::<f32>() < 0.3 {
+                WeatherKind::Thunderstorm
+            } else if rng.gen::<f32>() < 0.6 {
+                WeatherKind::Clear
+            } else {
+                precipitation
+            }
+        }
+        WeatherKind::Thunderstorm => precipitation,
+    }
+}
+
+fn duration_for(kind: WeatherKind, rng: &mut StdRng) -> f32 {
+    match kind {
+        WeatherKind::Clear => rng.gen_range(180.0..600.0),
+        WeatherKind::Rain | WeatherKind::Snow => rng.gen_range(90.0..300.0),
+        WeatherKind::Thunderstorm => rng.gen_range(60.0..180.0),
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct RawWeather {
+    // intensity, is_snow, time, unused
+    precipitation: [f32; 4],
+    // overcast, wetness, lightning, unused
+    atmosphere: [f32; 4],
+    // wind x, wind z, unused, unused
+    wind: [f32; 4],
+}
+
+pub struct Weather {
+    regions: HashMap<IVec2, RegionWeather>,
+    rng: StdRng,
+    seed: u32,
+    forced: Option<WeatherKind>,
+    current: WeatherKind,
+    // The last kind that actually fell from the sky, kept so that particles
+    // can fade out after the region clears up.
+    precipitation: WeatherKind,
+    intensity: f32,
+    overcast: f32,
+    wind_strength: f32,
+    wetness: f32,
+    lightning: f32,
+    rain_accumulator: f32,
+    time: f32,
+}
+
+impl Weather {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            regions: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed as u64),
+            seed,
+            forced: None,
+            current: WeatherKind::Clear,
+            precipitation: WeatherKind::Rain,
+            intensity: 0.0,
+            overcast: 0.0,
+            wind_strength: WeatherKind::Clear.wind_strength(),
+            wetness: 0.0,
+            lightning: 0.0,
+            rain_accumulator: 0.0,
+            time: 0.0,
+        }
+    }
+
+    pub fn current(&self) -> WeatherKind {
+        self.current
+    }
+
+    pub fn forced(&self) -> Option<WeatherKind> {
+        self.forced
+    }
+
+    /// Overrides the simulated weather everywhere. `None` returns control to
+    /// the per-region state machine.
+    pub fn force(&mut self, kind: Option<WeatherKind>) {
+        self.forced = kind;
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn overcast(&self) -> f32 {
+        self.overcast
+    }
+
+    pub fn wind(&self) -> Vec2 {
+        let angle = self.time * 0.01 + (self.seed % 360) as f32;
+        Vec2::new(angle.cos(), angle.sin()) * self.wind_strength
+    }
+
+    fn region_of(position: &Vec3) -> IVec2 {
+        IVec2::new(
+            (position.x / REGION_SIZE).floor() as i32,
+            (position.z / REGION_SIZE).floor() as i32,
+        )
+    }
+
+    fn update_regions(&mut self, dt: f32, player_region: IVec2, terrain: &dyn TerrainGenerator) {
+        self.regions.retain(|key, _| {
+            (key.x - player_region.x).abs() <= REGION_KEEP_RADIUS
+                && (key.y - player_region.y).abs() <= REGION_KEEP_RADIUS
+        });
+
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let key = player_region + IVec2::new(dx, dz);
+                let centre = (key.as_vec2() + 0.5) * REGION_SIZE;
+                let rng = &mut self.rng;
+                let seed = self.seed;
+
+                let region = self.regions.entry(key).or_insert_with(|| RegionWeather {
+                    kind: WeatherKind::Clear,
+                    remaining: rng.gen_range(0.0..120.0),
+                    puddles: StdRng::seed_from_u64(
+                        (seed as u64) << 32
+                            ^ (key.x as u32 as u64).wrapping_mul(0x9E3779B9)
+                            ^ key.y as u32 as u64,
+                    ),
+                });
+
+                region.remaining -= dt;
+                if region.remaining <= 0.0 {
+                    let tdata = terrain.get(centre);
+                    region.kind = next_kind(
+                        region.kind,
+                        tdata.temperature,
+                        tdata.moisture,
+                        tdata.height,
+                        rng,
+                    );
+                    region.remaining = duration_for(region.kind, rng);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration, player_position: &Vec3, terrain: &dyn TerrainGenerator) {
+        let dt = dt.as_secs_f32();
+        self.time += dt;
+
+        let player_region = Self::region_of(player_position);
+        self.update_regions(dt, player_region, terrain);
+
+        self.current = match self.forced {
+            Some(kind) => kind,
+            None => self
+                .regions
+                .get(&player_region)
+                .map(|r| r.kind)
+                .unwrap_or(WeatherKind::Clear),
+        };
+
+        if self.current.is_precipitating() {
+            // Only switch particle type once the old kind has faded out.
+            if self.current != self.precipitation
+                && (self.intensity < 0.05 || self.precipitation == WeatherKind::Thunderstorm)
+            {
+                self.precipitation = self.current;
+            }
+        }
+
+        let target_intensity = if self.current.is_precipitating() {
+            if self.current == WeatherKind::Thunderstorm {
+                1.0
+            } else {
+                0.7
+            }
+        } else {
+            0.0
+        };
+        self.intensity = approach(self.intensity, target_intensity, INTENSITY_RATE * dt);
+        self.overcast = approach(self.overcast, self.current.overcast(), INTENSITY_RATE * dt);
+        self.wind_strength = approach(
+            self.wind_strength,
+            self.current.wind_strength(),
+            INTENSITY_RATE * dt,
+        );
+
+        let is_wet = matches!(
+            self.precipitation,
+            WeatherKind::Rain | WeatherKind::Thunderstorm
+        );
+        if is_wet && self.intensity > 0.0 {
+            self.wetness = approach(self.wetness, self.intensity, WET_RATE * dt);
+            self.rain_accumulator += self.intensity * dt;
+        } else {
+            self.wetness = approach(self.wetness, 0.0, DRY_RATE * dt);
+        }
+
+        self.lightning *= f32::exp(-8.0 * dt);
+        if self.current == WeatherKind::Thunderstorm && self.rng.gen::<f32>() < 0.15 * dt {
+            self.lightning = 1.0;
+        }
+    }
+
+    /// Returns the columns around the player that rain should be pooled into
+    /// since the last call.
+    pub fn take_rain_puddles(&mut self, player_position: &Vec3) -> Vec<IVec2> {
+        let count = (self.rain_accumulator / RAIN_PUDDLE_INTERVAL).floor();
+        self.rain_accumulator -= count * RAIN_PUDDLE_INTERVAL;
+
+        let Some(region) = self.regions.get_mut(&Self::region_of(player_position)) else {
+            return Vec::new();
+        };
+        let centre = IVec2::new(player_position.x as i32, player_position.z as i32);
+        (0..count as usize)
+            .map(|_| {
+                centre
+                    + IVec2::new(
+                        region.puddles.gen_range(-RAIN_RADIUS..=RAIN_RADIUS),
+                        region.puddles.gen_range(-RAIN_RADIUS..=RAIN_RADIUS),
+                    )
+            })
+            .collect()
+    }
+
+    pub fn to_raw(&self) -> RawWeather {
+        let wind = self.wind();
+        RawWeather {
+            precipitation: [
+                self.intensity,
+                if self.precipitation == WeatherKind::Snow {
+                    1.0
+                } else {
+                    0.0
+                },
+                self.time,
+                0.0,
+            ],
+            atmosphere: [self.overcast, self.wetness, self.lightning, 0.0],
+            wind: [wind.x, wind.y, 0.0, 0.0],
+        }
+    }
+}
+
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::WorldTerrain;
+
+    #[test]
+    fn test_cold_regions_snow() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let kind = next_kind(WeatherKind::Clear, 0.1, 0.9, 60.0, &mut rng);
+            assert_ne!(kind, WeatherKind::Rain, "Cold regions should never rain");
+            assert_ne!(kind, WeatherKind::Thunderstorm);
+        }
+    }
+
+    #[test]
+    fn test_high_altitude_snows() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..100 {
+            let kind = next_kind(WeatherKind::Rain, 0.9, 0.9, 200.0, &mut rng);
+            assert!(matches!(kind, WeatherKind::Clear | WeatherKind::Snow));
+        }
+    }
+
+    #[test]
+    fn test_storms_calm_down() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let kind = next_kind(WeatherKind::Thunderstorm, 0.8, 0.8, 60.0, &mut rng);
+        assert_eq!(kind, WeatherKind::Rain);
+    }
+
+    #[test]
+    fn test_forced_weather_fades_in_and_pools_rain() {
+        let terrain = WorldTerrain::new(12345);
+        let mut weather = Weather::new(12345);
+        weather.force(Some(WeatherKind::Rain));
+
+        let position = Vec3::new(100.0, 60.0, 100.0);
+        for _ in 0..200 {
+            weather.update(Duration::from_millis(100), &position, &terrain);
+        }
+
+        assert_eq!(weather.current(), WeatherKind::Rain);
+        assert!(weather.intensity() > 0.5);
+        assert!(weather.overcast() > 0.5);
+        assert!(weather.wetness > 0.0);
+        assert!(!weather.take_rain_puddles(&position).is_empty());
+        assert!(weather.take_rain_puddles(&position).is_empty());
+
+        weather.force(Some(WeatherKind::Clear));
+        weather.update(Duration::from_millis(100), &position, &terrain);
+        // The wind dies down with the rain rather than dropping at once
+        assert!(weather.wind().length() > 0.45);
+        for _ in 0..200 {
+            weather.update(Duration::from_millis(100), &position, &terrain);
+        }
+        assert_eq!(weather.intensity(), 0.0);
+        assert!((weather.wind().length() - 0.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rain_pools_in_the_same_places_for_a_seed() {
+        let terrain = WorldTerrain::new(12345);
+        let position = Vec3::new(100.0, 60.0, 100.0);
+        let puddles = |seed| {
+            let mut weather = Weather::new(seed);
+            weather.force(Some(WeatherKind::Rain));
+            for _ in 0..200 {
+                weather.update(Duration::from_millis(100), &position, &terrain);
+            }
+            weather.take_rain_puddles(&position)
+        };
+
+        let first = puddles(12345);
+        assert!(!first.is_empty());
+        assert_eq!(first, puddles(12345));
+        assert_ne!(first, puddles(54321));
+    }
+
+    #[test]
+    fn test_weather_kind_from_str() {
+        assert_eq!("rain".parse::<WeatherKind>(), Ok(WeatherKind::Rain));
+        assert_eq!(
+            "storm".parse::<WeatherKind>(),
+            Ok(WeatherKind::Thunderstorm)
+        );
+        assert!("hail".parse::<WeatherKind>().is_err());
+    }
+}
@@ -0,0 +1,41 @@
+// Tree sway, shared by shader.wgsl and shadow.wgsl so that shadows sway with
+// their trees. It is put in front of each of them, which must declare a
+// `weather` uniform.
+
+// A vertex that sways freely leans this far with the wind at full strength.
+const WIND_LEAN: f32 = 1.5;
+
+fn wind_hash(p: vec2<f32>) -> f32 {
+  return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+fn gust_noise(p: vec2<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+  let a = wind_hash(i);
+  let b = wind_hash(i + vec2<f32>(1.0, 0.0));
+  let c = wind_hash(i + vec2<f32>(0.0, 1.0));
+  let d = wind_hash(i + vec2<f32>(1.0, 1.0));
+  return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// How far the wind pushes a vertex of the tree rooted at `root`. `sway` is 0
+// at the roots and grows towards the tips of branches and leaves.
+fn wind_sway(root: vec3<f32>, position: vec3<f32>, sway: f32) -> vec3<f32> {
+  let time = weather.precipitation.z;
+  let wind = weather.wind.xy;
+  let strength = length(wind);
+  let dir = vec3<f32>(wind.x, 0.0, wind.y) / max(strength, 0.0001);
+
+  // Gusts roll downwind across the world, so neighbouring trees lean together
+  let gust = gust_noise(root.xz * 0.03 - wind * time * 0.5);
+  let rock = sin(time * 1.3 + root.x * 0.37 + root.z * 0.21);
+  let lean = strength * (0.4 + gust + 0.1 * rock) * WIND_LEAN;
+
+  // Twigs and leaves flutter quickly on top of that
+  let flutter = sin(time * 8.0 + dot(position, vec3<f32>(2.1, 1.7, 2.9))) * (0.05 + 0.15 * strength);
+  let across = vec3<f32>(-dir.z, 0.5, dir.x);
+
+  return dir * lean * sway + across * flutter * sway * sway;
+}